@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use synacor_challenge_v1::parser::OutputParser;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = OutputParser::new().parse_lossy(data);
+});
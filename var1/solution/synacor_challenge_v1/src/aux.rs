@@ -1,14 +1,23 @@
+use crate::history::{HistoryEntry, HistoryFilter};
 use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub trait Commander<'b> {
+    #[allow(dead_code)]
     fn get_replay_commands(&self) -> Vec<String>;
-    fn commands_history(&self) -> &[String];
-    fn save_commands_history(&self, p: &str) -> Result<(), std::io::Error>;
+    fn commands_history(&self) -> &[HistoryEntry];
+    fn save_commands_history(
+        &self,
+        p: &Path,
+        filter: HistoryFilter,
+        overwrite: bool,
+    ) -> Result<(), std::io::Error>;
     fn show_state(&self);
-    fn dump_memory(&self, p: &Path) -> Result<(), std::io::Error>;
-    fn dump_state(&self, p: &Path) -> Result<(), std::io::Error>;
+    fn dump_memory(&self, p: &Path, overwrite: bool) -> Result<(), std::io::Error>;
+    fn dump_state(&self, p: &Path, overwrite: bool) -> Result<(), std::io::Error>;
     fn record_output(&mut self, p: &Path) -> Result<(), Box<dyn Error>>;
+    fn stop_recording(&mut self) -> Result<PathBuf, Box<dyn Error>>;
+    fn get_recording_status(&self) -> String;
     fn is_recording_active(&self) -> bool;
     fn process_command(&mut self, command: &str) -> Result<(), Box<dyn Error>>;
 }
@@ -0,0 +1,71 @@
+use crate::machine::{Machine, RomTooLarge, StepEvent};
+use log::debug;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How fast the bare machine executes the ROM, without the parser, the plugins and the terminal
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub cycles: u64,
+    pub elapsed: Duration,
+    /// The number of the characters printed by the ROM
+    pub output: usize,
+    /// Why the run has ended: halted, the input is used up or the limit of cycles is reached
+    pub ended: &'static str,
+}
+
+impl BenchReport {
+    /// Returns the millions of the instructions executed per second
+    pub fn mips(&self) -> f64 {
+        self.cycles as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON) / 1e6
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "***           Benchmark Report            ***")?;
+        writeln!(f, "{}", "=".repeat(44))?;
+        writeln!(f, "{:<9}: {}", "cycles", self.cycles)?;
+        writeln!(f, "{:<9}: {:.2?}", "elapsed", self.elapsed)?;
+        writeln!(f, "{:<9}: {:.2}", "MIPS", self.mips())?;
+        writeln!(f, "{:<9}: {}", "output", self.output)?;
+        writeln!(f, "{:<9}: {}", "ended", self.ended)?;
+        write!(f, "{}", "=".repeat(45))
+    }
+}
+
+/// This function executes the ROM feeding it the commands, until it halts, the commands are used up
+/// or the given number of instructions is executed
+pub fn run(rom: &[u8], commands: &[String], max_cycles: u64) -> Result<BenchReport, RomTooLarge> {
+    let mut machine = Machine::new();
+    machine.load_rom(rom)?;
+    // The journal of /stepback is not needed here
+    machine.set_journal_size(0);
+    let mut input: VecDeque<u8> = commands
+        .iter()
+        .flat_map(|c| c.bytes().chain(std::iter::once(b'\n')))
+        .collect();
+    let (mut cycles, mut output) = (0, 0);
+    let started = Instant::now();
+    let ended = loop {
+        if cycles >= max_cycles {
+            break "the limit of cycles is reached";
+        }
+        match machine.execute(|| input.pop_front()) {
+            StepEvent::Halted => break "halted",
+            StepEvent::AwaitingInput => break "the input is used up",
+            StepEvent::Output(_) => output += 1,
+            _ => {}
+        }
+        cycles += 1;
+    };
+    let report = BenchReport {
+        cycles,
+        elapsed: started.elapsed(),
+        output,
+        ended,
+    };
+    debug!("benchmark has executed {} instructions", report.cycles);
+    Ok(report)
+}
@@ -1,45 +1,755 @@
-use clap::Parser;
+use crate::machine::ValueStrictness;
+use crate::maze::{ExplorationStrategy, ItemAliases};
+use crate::maze::export::MapFormat;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use colored::control;
 use log::{debug, trace, warn};
 use std::error::Error;
 use std::fmt;
+use std::str::FromStr;
+use std::env;
+use std::time::Duration;
 use std::{
     ffi::OsString,
     fs::{self, File},
-    io::{BufRead, BufReader, Read},
+    io::{self, BufRead, BufReader, IsTerminal, Read},
     path::PathBuf,
 };
 
+mod profile;
+
+// The number of the latest instructions, which can be undone with /stepback
+const DEFAULT_JOURNAL_SIZE: usize = 10_000;
+// The number of the latest rooms entered, which are autosaved by default
+const DEFAULT_AUTOSAVES: usize = 10;
+// The cycles between the snapshots of /replay_last
+const DEFAULT_SNAPSHOT_INTERVAL: u64 = 100_000;
+// The moves made by the solve subcommand
+const DEFAULT_SOLVE_STEPS: u32 = 1000;
+// The moves of /solve between the progress lines
+const DEFAULT_SOLVE_PROGRESS_INTERVAL: u32 = 50;
+// The instructions executed by the bench subcommand
+const DEFAULT_BENCH_CYCLES: u64 = 100_000_000;
+// The instructions executed by the lockstep subcommand
+const DEFAULT_LOCKSTEP_CYCLES: u64 = 100_000_000;
+
 #[derive(Parser, Debug)]
-#[command(version, about)]
+#[command(version, about, args_override_self = true)]
 struct Args {
-    #[arg(short, long, default_value = "./challenge.bin")]
-    //#[arg(short, long)]
-    rom: String,
-    #[arg(short = 'R', long, help = "File with replay commands to run")]
-    replay: Option<String>,
     #[arg(
         long,
+        global = true,
         default_value = "false",
-        help = "Force color output, even if piped (Works with CLICOLOR_FORCE=1)"
+        help = "Force color output, even if piped, the same as --color always (Works with CLICOLOR_FORCE=1)",
+        env = "SYNACOR_FORCE_COLOR"
     )]
     force_color: bool,
-    #[arg(short = 's', long = "record-output", help = "Record output of the VM to file")]
+    #[arg(
+        long,
+        global = true,
+        value_name = "WHEN",
+        help = "Color the output: auto (only the terminal, unless NO_COLOR is set), always or never",
+        env = "SYNACOR_COLOR"
+    )]
+    color: Option<String>,
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Show only the game output: no banners, status line, reports or echoed replay commands",
+        env = "SYNACOR_QUIET"
+    )]
+    quiet: bool,
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Take the options of the [profile.NAME] section of the config file, the command line ones override them",
+        env = "SYNACOR_PROFILE"
+    )]
+    profile: Option<String>,
+    #[arg(long, value_name = "FILE", help = "The config file with the profiles (synacor.conf by default)", env = "SYNACOR_CONFIG", global = true)]
+    config: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILTER",
+        help = "Log filter used when RUST_LOG is not set, e.g. debug or synacor_challenge_v1::vm=trace",
+        env = "SYNACOR_LOG_LEVEL"
+    )]
+    log_level: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help = "Run with the default configuration, when the given one is invalid",
+        env = "SYNACOR_LENIENT"
+    )]
+    lenient: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Report the configuration failure as the JSON object on stderr",
+        env = "SYNACOR_JSON_ERRORS"
+    )]
+    json_errors: bool,
+    // The options of the game session without the subcommand, the same as of `run`
+    #[command(flatten)]
+    vm: VmArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+// The options of the game session, taken by the subcommands running the VM. The other subcommands
+// reject them, so their help lists only what they use
+#[derive(clap::Args, Debug)]
+struct VmArgs {
+    #[arg(short, long, default_value = "./challenge.bin", env = "SYNACOR_ROM")]
+    rom: String,
+    #[arg(short = 'R', long, help = "File with replay commands to run", env = "SYNACOR_REPLAY")]
+    replay: Option<String>,
+    #[arg(short = 's', long = "record-output", help = "Record output of the VM to file", env = "SYNACOR_RECORD_OUTPUT")]
     record_output: Option<String>,
+    #[arg(long, help = "Do not show the status line before the input prompt", env = "SYNACOR_NO_STATUS_LINE")]
+    no_status_line: bool,
+    #[arg(long, help = "Run the VM in the terminal user interface", env = "SYNACOR_TUI")]
+    tui: bool,
+    #[arg(long, help = "Rhai script with callbacks for the game events", env = "SYNACOR_SCRIPT")]
+    script: Option<String>,
+    #[arg(long, help = "File with 'pattern => command' rules answering the game output", env = "SYNACOR_RULES")]
+    rules: Option<String>,
+    #[arg(long = "async", help = "Run the main loop on the tokio runtime", env = "SYNACOR_ASYNC")]
+    async_loop: bool,
+    #[arg(long, help = "Never read stdin, end the run with the state dump once the replay commands are used up", env = "SYNACOR_BATCH")]
+    batch: bool,
+    #[arg(long, help = "Do not show the long slash command output through $PAGER", env = "SYNACOR_NO_PAGER")]
+    no_pager: bool,
+    #[arg(long, help = "Read the commands in the raw terminal mode, where the arrows or WASD go, 'i' shows the inventory and 'l' looks around", env = "SYNACOR_KEYS")]
+    keys: bool,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "Print the game output as text or as JSON lines with the parsed responses, the solver events and the codes",
+        env = "SYNACOR_OUTPUT_FORMAT"
+    )]
+    output_format: Option<String>,
+    #[arg(
+        long,
+        value_name = "char|line|prompt",
+        help = "Flush the game output on stdout and in the recording after every character, line (default) or prompt",
+        env = "SYNACOR_FLUSH"
+    )]
+    flush: Option<String>,
+    #[arg(
+        long,
+        value_name = "bell|COMMAND",
+        help = "Ring the bell or run the shell command, when the new code is found or /solve ends",
+        env = "SYNACOR_NOTIFY"
+    )]
+    notify: Option<String>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Let the plugins take over, when there is no input for the given time (async mode only)",
+        env = "SYNACOR_INPUT_TIMEOUT"
+    )]
+    input_timeout: Option<u64>,
+    #[arg(long, help = "Seed for the random choices of the map exploration (/solve)", env = "SYNACOR_SEED")]
+    seed: Option<u64>,
+    #[arg(long, help = "Save the report of every /solve run to the file", env = "SYNACOR_SOLVE_REPORT")]
+    solve_report: Option<String>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Write the states, recordings, histories and dumps of the session to the directory, listed in its index.json",
+        env = "SYNACOR_SESSION_DIR"
+    )]
+    session_dir: Option<String>,
+    #[arg(
+        long,
+        value_name = "MOVES",
+        default_value_t = DEFAULT_SOLVE_PROGRESS_INTERVAL,
+        help = "Show the progress of /solve every given number of moves (0 turns it off)",
+        env = "SYNACOR_SOLVE_PROGRESS_INTERVAL"
+    )]
+    solve_progress_interval: u32,
+    #[arg(
+        long,
+        value_name = "KEY=VALUE,...",
+        help = "Exploration priorities: prefer_unexplored, take_items, avoid_backtracking (true/false) and edge_visit_limit",
+        env = "SYNACOR_STRATEGY"
+    )]
+    strategy: Option<String>,
+    #[arg(
+        long,
+        value_name = "ALIAS=ITEM,...",
+        help = "The items counted as the other ones, so the transformed item does not change the inventory (lit lantern=lantern by default)",
+        env = "SYNACOR_ITEM_ALIASES"
+    )]
+    item_aliases: Option<String>,
+    #[arg(long, help = "Resume the session saved by /save_state", env = "SYNACOR_LOAD_STATE")]
+    load_state: Option<String>,
+    #[arg(
+        long,
+        value_name = "VALUES",
+        help = "Halt with the error once the stack holds this number of values",
+        env = "SYNACOR_STACK_LIMIT"
+    )]
+    stack_limit: Option<usize>,
+    #[arg(
+        long,
+        value_name = "MODE",
+        help = "Treat the values 32768..32775 stored as numbers: permit, warn or reject",
+        env = "SYNACOR_STRICT_VALUES"
+    )]
+    strict_values: Option<String>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Stop the VM with the state dump after executing this number of instructions",
+        env = "SYNACOR_MAX_CYCLES"
+    )]
+    max_cycles: Option<u64>,
+    #[arg(
+        long,
+        value_name = "DURATION",
+        help = "Stop the VM with the state dump after the given time, e.g. 30s, 5m or 1h",
+        env = "SYNACOR_TIMEOUT"
+    )]
+    timeout: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write every executed instruction to the file (gzip compressed for .gz)",
+        env = "SYNACOR_TRACE"
+    )]
+    trace: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Record every character read by the game together with the time it is read at",
+        env = "SYNACOR_RECORD_INPUT"
+    )]
+    record_input: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Feed the input recorded with --record-input at the same times it has been typed",
+        env = "SYNACOR_REPLAY_INPUT"
+    )]
+    replay_input: Option<String>,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_JOURNAL_SIZE,
+        help = "Keep the writes of this number of the latest instructions for /stepback (0 disables it)",
+        env = "SYNACOR_JOURNAL_SIZE"
+    )]
+    journal_size: usize,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_AUTOSAVES,
+        help = "Save the session on entering the room, keeping this number of the latest ones for /autosaves (0 disables it)",
+        env = "SYNACOR_AUTOSAVES"
+    )]
+    autosaves: usize,
+    #[arg(
+        long,
+        value_name = "CYCLES",
+        default_value_t = DEFAULT_SNAPSHOT_INTERVAL,
+        help = "Take the snapshot for /replay_last every this number of cycles (0 disables it)",
+        env = "SYNACOR_SNAPSHOT_INTERVAL"
+    )]
+    snapshot_interval: u64,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Play the game, the same as without the subcommand
+    Run(VmArgs),
+    /// Explore the map automatically without the terminal, ending once the moves are made
+    Solve {
+        #[command(flatten)]
+        vm: VmArgs,
+        #[arg(long, default_value_t = DEFAULT_SOLVE_STEPS, help = "The number of the moves to make")]
+        steps: u32,
+        #[arg(long, help = "Stop once the new code is found")]
+        until_code: bool,
+        #[arg(long, value_name = "NAME", help = "Stop once the item is taken")]
+        until_item: Option<String>,
+        #[arg(long, value_name = "TITLE", help = "Stop once the room is reached")]
+        until_room: Option<String>,
+    },
+    /// Play the whole game without the terminal: explore, solve the coins, the teleporter and the
+    /// vault, and show all the codes found
+    SolveAll(VmArgs),
+    /// Disassemble the ROM
+    Disasm {
+        #[arg(short, long, default_value = "./challenge.bin", env = "SYNACOR_ROM")]
+        rom: String,
+        #[arg(long, value_parser = parse_address, help = "The address to start at (0x for hex)")]
+        from: Option<u16>,
+        #[arg(long, value_parser = parse_address, help = "The address to stop at (the end of the ROM by default)")]
+        to: Option<u16>,
+    },
+    /// Export the map of the state saved by /save_state (or the latest one in the directory) without
+    /// starting the VM
+    Map {
+        input: String,
+        #[arg(long, help = "dot, json or html (by the extension of the output file, dot otherwise)")]
+        format: Option<String>,
+        #[arg(short, long, help = "Write the map to the file instead of stdout")]
+        output: Option<String>,
+        #[arg(long, help = "Overwrite the output file, if it exists")]
+        force: bool,
+    },
+    /// Measure how fast the bare machine executes the ROM fed with the --replay commands
+    Bench {
+        #[arg(short, long, default_value = "./challenge.bin", env = "SYNACOR_ROM")]
+        rom: String,
+        #[arg(short = 'R', long, help = "File with replay commands to run", env = "SYNACOR_REPLAY")]
+        replay: Option<String>,
+        #[arg(long, default_value_t = DEFAULT_BENCH_CYCLES, help = "Stop after this number of instructions")]
+        cycles: u64,
+    },
+    /// Host the VM behind the HTTP and WebSocket API
+    Serve {
+        #[command(flatten)]
+        vm: VmArgs,
+        #[arg(short, long, default_value = "127.0.0.1:8080", env = "SYNACOR_LISTEN", help = "Address to listen on")]
+        listen: String,
+    },
+    /// Let gdb drive the VM over the GDB remote serial protocol (the 'gdb' feature)
+    Gdb {
+        #[command(flatten)]
+        vm: VmArgs,
+        #[arg(short, long, default_value = "127.0.0.1:9001", env = "SYNACOR_LISTEN", help = "Address to listen on")]
+        listen: String,
+    },
+    /// Let the Debug Adapter Protocol client (e.g. VS Code) drive the VM (the 'dap' feature)
+    Dap {
+        #[command(flatten)]
+        vm: VmArgs,
+        #[arg(short, long, default_value = "127.0.0.1:4711", env = "SYNACOR_LISTEN", help = "Address to listen on")]
+        listen: String,
+    },
+    /// Compare two states saved by /save_state
+    DiffState { a: String, b: String },
+    /// Run the output parser over all captured game outputs in the directory
+    ParseCorpus { dir: String },
+    /// Run the synthetic ROMs checking every opcode of the spec
+    Selftest,
+    /// Run two ROMs or saved states (.json) side by side until they diverge, feeding both the --replay
+    /// commands (at most 100000000 instructions, unless --max-cycles is given)
+    Lockstep {
+        a: String,
+        b: String,
+        #[arg(short = 'R', long, help = "File with replay commands to run", env = "SYNACOR_REPLAY")]
+        replay: Option<String>,
+        #[arg(long, value_name = "N", help = "Stop after this number of instructions", env = "SYNACOR_MAX_CYCLES")]
+        max_cycles: Option<u64>,
+    },
+    /// Show the instructions of the trace written with --trace
+    #[command(alias = "trace-dump")]
+    Trace {
+        file: String,
+        #[arg(long, help = "The first cycle to show")]
+        from: Option<u64>,
+        #[arg(long, help = "The last cycle to show")]
+        to: Option<u64>,
+        #[arg(long, help = "Show every 'out' instruction instead of the collapsed 'print' lines")]
+        raw: bool,
+    },
+    /// Show where the time of the session recorded with --record-input has been spent
+    InputStats { file: String },
+    /// Compare two traces written with --trace and show where they diverge
+    DiffTrace {
+        a: String,
+        b: String,
+        #[arg(long, default_value_t = 5, help = "The number of records shown around the divergence")]
+        context: usize,
+    },
+}
+
+/// When the output is colored
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorChoice {
+    /// Color the output going to the terminal, unless NO_COLOR is set (CLICOLOR_FORCE forces it)
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Returns whether the output is colored. Both stdout and stderr are checked, as the game
+    /// output goes to the former and the messages of the VM to the latter
+    pub fn enabled(self) -> bool {
+        let set = |var: &str| env::var(var).is_ok_and(|v| !v.is_empty() && v != "0");
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto if set("CLICOLOR_FORCE") => true,
+            ColorChoice::Auto if set("NO_COLOR") => false,
+            ColorChoice::Auto => io::stdout().is_terminal() && io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            other => Err(format!("unknown color choice '{}', expected auto, always or never", other)),
+        }
+    }
+}
+
+/// How the game output is printed on stdout
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    /// Every parsed response, solver event and found code is the JSON object on its own line,
+    /// while the raw game text is not printed at all
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(format!("unknown output format '{}', expected text or json", other)),
+        }
+    }
+}
+
+/// When the game output written to stdout and to the recording is flushed
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FlushPolicy {
+    /// After every character, e.g. for the program reading the output as it comes
+    Char,
+    #[default]
+    Line,
+    /// Once the game waits for the command, so the whole response is written at once
+    Prompt,
+}
+
+impl FromStr for FlushPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "char" => Ok(FlushPolicy::Char),
+            "line" => Ok(FlushPolicy::Line),
+            "prompt" => Ok(FlushPolicy::Prompt),
+            other => Err(format!("unknown flush policy '{}', expected char, line or prompt", other)),
+        }
+    }
+}
+
+/// The error of the configuration found only when the VM is being set up, e.g. the rules file
+/// with the invalid expression
+#[derive(Debug)]
+pub struct ConfigError(pub String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration: {}", self.0)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// The parameters of the lockstep run
+#[derive(Debug, Clone)]
+pub struct LockstepConfig {
+    pub a: PathBuf,
+    pub b: PathBuf,
+    pub replay: Option<PathBuf>,
+    pub max_cycles: u64,
+}
+
+/// The trace file and the range of cycles to show
+#[derive(Debug, Clone)]
+pub struct TraceDumpConfig {
+    pub file: PathBuf,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub raw: bool,
+}
+
+/// The traces to compare and the number of records shown around the divergence
+#[derive(Debug, Clone)]
+pub struct TraceDiffConfig {
+    pub a: PathBuf,
+    pub b: PathBuf,
+    pub context: usize,
+}
+
+/// The map to export, the format and the output file (stdout, if None)
+#[derive(Debug, Clone)]
+pub struct MapConfig {
+    pub input: PathBuf,
+    pub format: MapFormat,
+    pub output: Option<PathBuf>,
+    pub force: bool,
+}
+
+/// The disassembled range of the ROM
+#[derive(Debug, Clone)]
+pub struct DisasmConfig {
+    pub from: Option<u16>,
+    pub to: Option<u16>,
+}
+
+fn parse_address(s: &str) -> Result<u16, String> {
+    let value = match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse::<u16>(),
+    };
+    value.map_err(|e| e.to_string())
+}
+
+/// This function parses the duration given as a number with the unit suffix (ms, s, m or h).
+/// The number without the suffix is taken as seconds
+fn parse_duration(s: &str) -> Result<Duration, Box<dyn Error>> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|e| format!("invalid duration '{}': {}", s, e))?;
+    match unit.trim() {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 3600)),
+        other => Err(format!("unknown unit '{}' of the duration '{}'", other, s).into()),
+    }
+}
+
+/// This function starts the logger, RUST_LOG takes precedence over the filter given by --log-level.
+/// The color of the log follows the one of the output, unless it is not decided yet (None).
+/// The logger started already, e.g. to report the configuration failure, is kept
+pub fn init_logging(filter: Option<&str>, color: Option<bool>) {
+    let env = env_logger::Env::default().default_filter_or(filter.unwrap_or("error"));
+    let style = match color {
+        Some(true) => env_logger::WriteStyle::Always,
+        Some(false) => env_logger::WriteStyle::Never,
+        None => env_logger::WriteStyle::Auto,
+    };
+    if env_logger::Builder::from_env(env).write_style(style).try_init().is_err() {
+        debug!("the logger is already started");
+    }
+}
+
+/// How the configuration failure is handled. It is taken from the raw arguments, as the failure
+/// may be the one of the arguments themselves
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ErrorPolicy {
+    /// Fall back to the default configuration instead of exiting
+    pub lenient: bool,
+    /// Report the failure as the JSON object
+    pub json: bool,
+}
+
+impl ErrorPolicy {
+    pub fn from_args() -> Self {
+        let args: Vec<OsString> = env::args_os().collect();
+        let flag = |name: &str, var: &str| {
+            args.iter().any(|a| a == name)
+                || env::var(var).is_ok_and(|v| !matches!(v.as_str(), "" | "0" | "false"))
+        };
+        ErrorPolicy {
+            lenient: flag("--lenient", "SYNACOR_LENIENT"),
+            json: flag("--json-errors", "SYNACOR_JSON_ERRORS"),
+        }
+    }
+
+    /// Returns the report of the failure, the JSON object or the plain message
+    pub fn report(&self, e: &dyn Error, exit_code: i32) -> String {
+        if !self.json {
+            return format!("Error: {}", e);
+        }
+        serde_json::json!({
+            "error": "config",
+            "message": e.to_string(),
+            "exit_code": exit_code,
+            "fallback": self.lenient,
+        })
+        .to_string()
+    }
+}
+
+/// The configuration used by --lenient in place of the invalid one: challenge.bin of the working
+/// directory without any options
+pub fn fallback_configuration() -> Result<Configuration, Box<dyn Error>> {
+    let mut conf = Configuration::default();
+    conf.read_in()?;
+    Ok(conf)
 }
 
 pub fn parse_args() -> Result<Configuration, Box<dyn Error>> {
-    let args = Args::parse();
+    let matches = match Args::command().try_get_matches_from(profile::expand_profile(env::args_os().collect(), &Args::command())?) {
+        Ok(matches) => matches,
+        Err(e) if matches!(e.kind(), ErrorKind::DisplayHelp | ErrorKind::DisplayVersion) => e.exit(),
+        Err(e) => {
+            // The usage is worth showing to the reader, but not to the program reading the report
+            if !ErrorPolicy::from_args().json {
+                e.print()?;
+            }
+            let message = e.to_string();
+            let message = message.lines().next().unwrap_or_default().trim_start_matches("error: ");
+            return Err(Box::new(ConfigError(message.to_string())));
+        }
+    };
+    if let Some((name, _)) = matches.subcommand()
+        && let Some(option) = Args::command()
+            .get_arguments()
+            .filter(|a| !a.is_global_set())
+            .find(|a| matches.value_source(a.get_id().as_str()) == Some(ValueSource::CommandLine))
+            .and_then(|a| a.get_long())
+    {
+        return Err(Box::new(ConfigError(format!(
+            "the option '--{}' goes after the subcommand '{}', if it takes it",
+            option, name
+        ))));
+    }
+    let args = Args::from_arg_matches(&matches)?;
     debug!("parsed arguments {:?}", args);
-    if args.force_color {
-        debug!("overriding color output to be always {}", args.force_color);
-        control::set_override(true);
-    }
-    let maybe_replay: Option<OsString> = args.replay.map(OsString::from);
-    let rom_file: OsString = args.rom.into();
-    let output_file: Option<OsString> = args.record_output.map(OsString::from);
-    let mut conf = Configuration::new(rom_file.into(), maybe_replay.map(PathBuf::from), output_file.map(PathBuf::from));
+    let color = match args.color {
+        _ if args.force_color => ColorChoice::Always,
+        Some(choice) => choice.parse()?,
+        None => ColorChoice::Auto,
+    };
+    debug!("the output is colored {:?}", color);
+    control::set_override(color.enabled());
+    let mut conf = Configuration {
+        log_level: args.log_level,
+        color: color.enabled(),
+        quiet: args.quiet,
+        ..Configuration::default()
+    };
+    match args.command.unwrap_or(Command::Run(args.vm)) {
+        Command::Run(vm) => conf.set_vm_options(vm)?,
+        Command::Solve {
+            vm,
+            steps,
+            until_code,
+            until_item,
+            until_room,
+        } => {
+            conf.set_vm_options(vm)?;
+            conf.batch = true;
+            let mut command = format!("/solve {}", steps);
+            if until_code {
+                command.push_str(" --until-code");
+            }
+            if let Some(item) = until_item {
+                command.push_str(&format!(" --until-item {}", item));
+            }
+            if let Some(room) = until_room {
+                command.push_str(&format!(" --until-room {}", room));
+            }
+            conf.solve_command = Some(command);
+        }
+        Command::Disasm { rom, from, to } => {
+            conf.rom_file = PathBuf::from(rom);
+            conf.disasm = Some(DisasmConfig { from, to });
+        }
+        Command::Map { input, format, output, force } => {
+            let output = output.map(PathBuf::from);
+            let format = match format {
+                Some(format) => format.parse()?,
+                None => output.as_deref().and_then(MapFormat::of_path).unwrap_or_default(),
+            };
+            conf.map = Some(MapConfig {
+                input: PathBuf::from(input),
+                format,
+                output,
+                force,
+            });
+            return Ok(conf);
+        }
+        Command::Bench { rom, replay, cycles } => {
+            conf.rom_file = PathBuf::from(rom);
+            conf.replay_file = replay.map(PathBuf::from);
+            conf.bench_cycles = Some(cycles);
+        }
+        Command::SolveAll(vm) => {
+            conf.set_vm_options(vm)?;
+            conf.solve_all = true;
+        }
+        Command::Serve { vm, listen } => {
+            conf.set_vm_options(vm)?;
+            conf.serve_address = Some(listen);
+        }
+        Command::Gdb { vm, listen } => {
+            conf.set_vm_options(vm)?;
+            conf.gdb_address = Some(listen);
+        }
+        Command::Dap { vm, listen } => {
+            conf.set_vm_options(vm)?;
+            conf.dap_address = Some(listen);
+        }
+        Command::DiffState { a, b } => {
+            // The states are compared without the ROM
+            conf.diff_states = Some((PathBuf::from(a), PathBuf::from(b)));
+            return Ok(conf);
+        }
+        Command::ParseCorpus { dir } => {
+            conf.corpus_dir = Some(PathBuf::from(dir));
+            return Ok(conf);
+        }
+        Command::Selftest => {
+            conf.selftest = true;
+            return Ok(conf);
+        }
+        Command::Lockstep { a, b, replay, max_cycles } => {
+            conf.lockstep = Some(LockstepConfig {
+                a: PathBuf::from(a),
+                b: PathBuf::from(b),
+                replay: replay.map(PathBuf::from),
+                max_cycles: max_cycles.unwrap_or(DEFAULT_LOCKSTEP_CYCLES),
+            });
+            return Ok(conf);
+        }
+        Command::Trace { file, from, to, raw } => {
+            conf.trace_dump = Some(TraceDumpConfig {
+                file: PathBuf::from(file),
+                from,
+                to,
+                raw,
+            });
+            return Ok(conf);
+        }
+        Command::InputStats { file } => {
+            conf.input_stats = Some(PathBuf::from(file));
+            return Ok(conf);
+        }
+        Command::DiffTrace { a, b, context } => {
+            conf.trace_diff = Some(TraceDiffConfig {
+                a: PathBuf::from(a),
+                b: PathBuf::from(b),
+                context,
+            });
+            return Ok(conf);
+        }
+    }
     conf.read_in()?;
+    if let Some(command) = conf.solve_command.clone() {
+        conf.replay_commands.push(command);
+    }
     Ok(conf)
 }
 #[derive(Debug)]
@@ -49,6 +759,54 @@ pub struct Configuration {
     record_file: Option<PathBuf>,
     rom: Vec<u8>,
     replay_commands: Vec<String>,
+    status_line: bool,
+    log_level: Option<String>,
+    color: bool,
+    tui: bool,
+    serve_address: Option<String>,
+    gdb_address: Option<String>,
+    dap_address: Option<String>,
+    script: Option<PathBuf>,
+    rules_file: Option<PathBuf>,
+    rules: Vec<String>,
+    async_loop: bool,
+    batch: bool,
+    pager: bool,
+    keys: bool,
+    quiet: bool,
+    output_format: OutputFormat,
+    flush_policy: FlushPolicy,
+    notify: Option<String>,
+    input_timeout: Option<Duration>,
+    seed: Option<u64>,
+    solve_report_file: Option<PathBuf>,
+    session_dir: Option<PathBuf>,
+    solve_progress_interval: u32,
+    strategy: Option<ExplorationStrategy>,
+    item_aliases: Option<ItemAliases>,
+    load_state: Option<PathBuf>,
+    stack_limit: Option<usize>,
+    max_cycles: Option<u64>,
+    timeout: Option<Duration>,
+    trace_file: Option<PathBuf>,
+    record_input: Option<PathBuf>,
+    replay_input: Option<PathBuf>,
+    input_stats: Option<PathBuf>,
+    journal_size: usize,
+    autosaves: usize,
+    snapshot_interval: u64,
+    strictness: ValueStrictness,
+    diff_states: Option<(PathBuf, PathBuf)>,
+    lockstep: Option<LockstepConfig>,
+    corpus_dir: Option<PathBuf>,
+    selftest: bool,
+    trace_dump: Option<TraceDumpConfig>,
+    trace_diff: Option<TraceDiffConfig>,
+    solve_command: Option<String>,
+    map: Option<MapConfig>,
+    disasm: Option<DisasmConfig>,
+    bench_cycles: Option<u64>,
+    solve_all: bool,
 }
 
 impl Default for Configuration {
@@ -59,6 +817,54 @@ impl Default for Configuration {
             record_file: None,
             rom: vec![],
             replay_commands: vec![],
+            status_line: true,
+            log_level: None,
+            color: false,
+            tui: false,
+            serve_address: None,
+            gdb_address: None,
+            dap_address: None,
+            script: None,
+            rules_file: None,
+            rules: vec![],
+            async_loop: false,
+            batch: false,
+            pager: true,
+            keys: false,
+            quiet: false,
+            output_format: OutputFormat::Text,
+            flush_policy: FlushPolicy::Line,
+            notify: None,
+            input_timeout: None,
+            seed: None,
+            solve_report_file: None,
+            session_dir: None,
+            solve_progress_interval: DEFAULT_SOLVE_PROGRESS_INTERVAL,
+            strategy: None,
+            item_aliases: None,
+            load_state: None,
+            stack_limit: None,
+            max_cycles: None,
+            timeout: None,
+            trace_file: None,
+            record_input: None,
+            replay_input: None,
+            input_stats: None,
+            journal_size: DEFAULT_JOURNAL_SIZE,
+            autosaves: DEFAULT_AUTOSAVES,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            strictness: ValueStrictness::Permit,
+            diff_states: None,
+            lockstep: None,
+            corpus_dir: None,
+            selftest: false,
+            trace_dump: None,
+            trace_diff: None,
+            solve_command: None,
+            map: None,
+            disasm: None,
+            bench_cycles: None,
+            solve_all: false,
         }
     }
 }
@@ -86,17 +892,46 @@ impl fmt::Display for Configuration {
 }
 
 impl Configuration {
-    fn new(rom_file: PathBuf, replay_file: Option<PathBuf>, record_file: Option<PathBuf>) -> Self {
-        Configuration {
-            record_file, 
-            rom_file,
-            replay_file,
-            rom: vec![],
-            replay_commands: vec![],
-        }
+    /// This method takes the options of the game session, shared by the subcommands running the VM
+    fn set_vm_options(&mut self, vm: VmArgs) -> Result<(), Box<dyn Error>> {
+        self.rom_file = PathBuf::from(vm.rom);
+        self.replay_file = vm.replay.map(PathBuf::from);
+        self.record_file = vm.record_output.map(PathBuf::from);
+        self.status_line = !vm.no_status_line;
+        self.tui = vm.tui;
+        self.script = vm.script.map(PathBuf::from);
+        self.rules_file = vm.rules.map(PathBuf::from);
+        self.async_loop = vm.async_loop;
+        self.batch = vm.batch;
+        self.pager = !vm.no_pager;
+        self.keys = vm.keys;
+        self.notify = vm.notify;
+        self.output_format = vm.output_format.map(|f| f.parse()).transpose()?.unwrap_or_default();
+        self.flush_policy = vm.flush.map(|f| f.parse()).transpose()?.unwrap_or_default();
+        self.input_timeout = vm.input_timeout.map(Duration::from_secs);
+        self.seed = vm.seed;
+        self.solve_report_file = vm.solve_report.map(PathBuf::from);
+        self.session_dir = vm.session_dir.map(PathBuf::from);
+        self.solve_progress_interval = vm.solve_progress_interval;
+        self.strategy = vm.strategy.map(|s| s.parse()).transpose()?;
+        self.item_aliases = vm.item_aliases.map(|s| s.parse()).transpose()?;
+        self.load_state = vm.load_state.map(PathBuf::from);
+        self.stack_limit = vm.stack_limit;
+        self.max_cycles = vm.max_cycles;
+        self.timeout = vm.timeout.map(|s| parse_duration(&s)).transpose()?;
+        self.trace_file = vm.trace.map(PathBuf::from);
+        self.record_input = vm.record_input.map(PathBuf::from);
+        self.replay_input = vm.replay_input.map(PathBuf::from);
+        self.journal_size = vm.journal_size;
+        self.autosaves = vm.autosaves;
+        self.snapshot_interval = vm.snapshot_interval;
+        self.strictness = vm.strict_values.map(|s| s.parse()).transpose()?.unwrap_or_default();
+        Ok(())
     }
+
     pub fn read_in(&mut self) -> Result<(usize, usize), Box<dyn Error>> {
-        let mut rom_file = File::open(&self.rom_file)?;
+        let mut rom_file = File::open(&self.rom_file)
+            .map_err(|e| ConfigError(format!("failed to open the ROM file {}: {}", self.rom_file.display(), e)))?;
         let mut buf: Vec<u8> = Vec::with_capacity(60 * 1024); // The size of the chanllenge binary
         // is roughly 60kb
         let was_read = rom_file.read_to_end(&mut buf)?;
@@ -130,6 +965,15 @@ impl Configuration {
             );
             self.replay_commands = lines;
         }
+        if let Some(rules_file) = &self.rules_file {
+            let reader = BufReader::new(File::open(rules_file)?);
+            self.rules = reader.lines().collect::<Result<Vec<String>, _>>()?;
+            trace!(
+                "successfully read {} rule lines from {}",
+                self.rules.len(),
+                rules_file.display()
+            );
+        }
         Ok((was_read, commands_read))
     }
     pub fn is_valid(&self) -> bool {
@@ -155,6 +999,224 @@ impl Configuration {
         self.replay_commands.clone()
     }
 
+    pub fn status_line(&self) -> bool {
+        self.status_line
+    }
+
+    pub fn log_level(&self) -> Option<String> {
+        self.log_level.clone()
+    }
+
+    pub fn color(&self) -> bool {
+        self.color
+    }
+
+    pub fn tui(&self) -> bool {
+        self.tui
+    }
+
+    pub fn serve_address(&self) -> Option<String> {
+        self.serve_address.clone()
+    }
+
+    pub fn gdb_address(&self) -> Option<String> {
+        self.gdb_address.clone()
+    }
+
+    pub fn dap_address(&self) -> Option<String> {
+        self.dap_address.clone()
+    }
+
+    pub fn script(&self) -> Option<PathBuf> {
+        self.script.clone()
+    }
+
+    pub fn rules(&self) -> Vec<String> {
+        self.rules.clone()
+    }
+
+    pub fn batch(&self) -> bool {
+        self.batch
+    }
+
+    /// The solve subcommand quits the game, once the exploration is over
+    pub fn quit_after_solve(&self) -> bool {
+        self.solve_command.is_some()
+    }
+
+    pub fn pager(&self) -> bool {
+        self.pager
+    }
+
+    pub fn keys(&self) -> bool {
+        self.keys
+    }
+
+    /// Returns the options of the game session with their values, as reported by /version, so the
+    /// session can be started again the same way
+    pub fn options(&self) -> Vec<(&'static str, String)> {
+        let path = |p: &Option<PathBuf>| p.as_ref().map_or("none".to_string(), |p| p.display().to_string());
+        let value = |v: Option<String>| v.unwrap_or("none".to_string());
+        vec![
+            ("rom", self.rom_file.display().to_string()),
+            ("replay", path(&self.replay_file)),
+            ("record", path(&self.record_file)),
+            ("load state", path(&self.load_state)),
+            ("session dir", path(&self.session_dir)),
+            ("script", path(&self.script)),
+            ("rules", path(&self.rules_file)),
+            ("seed", value(self.seed.map(|s| s.to_string()))),
+            ("strategy", value(self.strategy.as_ref().map(|s| s.to_string()))),
+            ("aliases", value(self.item_aliases.as_ref().map(|a| a.to_string()))),
+            ("format", format!("{:?}", self.output_format).to_lowercase()),
+            ("flush", format!("{:?}", self.flush_policy).to_lowercase()),
+            ("batch", self.batch.to_string()),
+            ("quiet", self.quiet.to_string()),
+            ("pager", self.pager.to_string()),
+            ("keys", self.keys.to_string()),
+            ("max cycles", value(self.max_cycles.map(|n| n.to_string()))),
+            ("timeout", value(self.timeout.map(|t| format!("{:?}", t)))),
+            ("stack limit", value(self.stack_limit.map(|n| n.to_string()))),
+            ("strictness", self.strictness.to_string()),
+        ]
+    }
+
+    pub fn quiet(&self) -> bool {
+        self.quiet
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.flush_policy
+    }
+
+    pub fn notify(&self) -> Option<String> {
+        self.notify.clone()
+    }
+
+    pub fn async_loop(&self) -> bool {
+        self.async_loop
+    }
+
+    pub fn input_timeout(&self) -> Option<Duration> {
+        self.input_timeout
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn solve_report_file(&self) -> Option<PathBuf> {
+        self.solve_report_file.clone()
+    }
+
+    pub fn session_dir(&self) -> Option<PathBuf> {
+        self.session_dir.clone()
+    }
+
+    pub fn solve_progress_interval(&self) -> u32 {
+        self.solve_progress_interval
+    }
+
+    pub fn stack_limit(&self) -> Option<usize> {
+        self.stack_limit
+    }
+
+    pub fn max_cycles(&self) -> Option<u64> {
+        self.max_cycles
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn trace_file(&self) -> Option<PathBuf> {
+        self.trace_file.clone()
+    }
+
+    pub fn record_input(&self) -> Option<PathBuf> {
+        self.record_input.clone()
+    }
+
+    pub fn replay_input(&self) -> Option<PathBuf> {
+        self.replay_input.clone()
+    }
+
+    pub fn input_stats(&self) -> Option<PathBuf> {
+        self.input_stats.clone()
+    }
+
+    pub fn journal_size(&self) -> usize {
+        self.journal_size
+    }
+
+    pub fn autosaves(&self) -> usize {
+        self.autosaves
+    }
+
+    pub fn snapshot_interval(&self) -> u64 {
+        self.snapshot_interval
+    }
+
+    pub fn strictness(&self) -> ValueStrictness {
+        self.strictness
+    }
+
+    pub fn strategy(&self) -> Option<ExplorationStrategy> {
+        self.strategy.clone()
+    }
+
+    pub fn item_aliases(&self) -> Option<ItemAliases> {
+        self.item_aliases.clone()
+    }
+
+    pub fn load_state(&self) -> Option<PathBuf> {
+        self.load_state.clone()
+    }
+
+    pub fn diff_states(&self) -> Option<(PathBuf, PathBuf)> {
+        self.diff_states.clone()
+    }
+
+    pub fn lockstep(&self) -> Option<LockstepConfig> {
+        self.lockstep.clone()
+    }
+
+    pub fn corpus_dir(&self) -> Option<PathBuf> {
+        self.corpus_dir.clone()
+    }
+
+    pub fn selftest(&self) -> bool {
+        self.selftest
+    }
+
+    pub fn trace_dump(&self) -> Option<TraceDumpConfig> {
+        self.trace_dump.clone()
+    }
+
+    pub fn trace_diff(&self) -> Option<TraceDiffConfig> {
+        self.trace_diff.clone()
+    }
+
+    pub fn map(&self) -> Option<MapConfig> {
+        self.map.clone()
+    }
+
+    pub fn disasm(&self) -> Option<DisasmConfig> {
+        self.disasm.clone()
+    }
+
+    pub fn bench_cycles(&self) -> Option<u64> {
+        self.bench_cycles
+    }
+
+    pub fn solve_all(&self) -> bool {
+        self.solve_all
+    }
+
     pub fn rom_replay_record(self) -> (Vec<u8>, Option<Vec<String>>, Option<PathBuf>) {
         if self.replay_commands.is_empty() {
             (self.rom, None, self.record_file)
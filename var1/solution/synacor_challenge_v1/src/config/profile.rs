@@ -0,0 +1,307 @@
+use clap::Command;
+use log::debug;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+/// The config file read for --profile, when --config is not given
+pub(super) const DEFAULT_CONFIG_FILE: &str = "synacor.conf";
+// The prefix of the section names holding the profiles, e.g. [profile.debug]
+const SECTION_PREFIX: &str = "profile.";
+
+// The long option of the profile with its value, None for the flag
+type ProfileOption = (String, Option<String>);
+
+/// Returns the environment variable of the long option, e.g. SYNACOR_LOG_LEVEL for log-level
+fn env_name(option: &str) -> String {
+    format!("SYNACOR_{}", option.to_uppercase().replace('-', "_"))
+}
+
+/// This function finds the value of the option among the command line arguments, given either as
+/// '--name value' or '--name=value', or in its environment variable
+fn option_value(args: &[OsString], name: &str) -> Option<String> {
+    let option = format!("--{}", name);
+    let prefix = format!("{}=", option);
+    args.iter()
+        .enumerate()
+        .find_map(|(n, arg)| {
+            let arg = arg.to_str()?;
+            if arg == option {
+                args.get(n + 1)?.to_str().map(String::from)
+            } else {
+                arg.strip_prefix(&prefix).map(String::from)
+            }
+        })
+        .or_else(|| env::var(env_name(name)).ok())
+}
+
+/// This function turns the profile section of the config file into the command line options.
+/// Every 'key = value' line of the section becomes '--key value', while 'true' and 'false' turn
+/// the flag on and off (the flag has no value). Empty lines and lines starting with '#' are
+/// ignored, as well as the options set with the environment variables, which take precedence over
+/// the profile
+fn read_profile(text: &str, name: &str) -> Result<Vec<ProfileOption>, Box<dyn Error>> {
+    let mut options = vec![];
+    let mut profiles = vec![];
+    let mut section = None;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = header.trim().strip_prefix(SECTION_PREFIX).map(String::from);
+            profiles.extend(section.clone());
+            continue;
+        }
+        if section.as_deref() != Some(name) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!(
+                "line {} of the profile '{}' is not 'key = value': {}",
+                n + 1,
+                name,
+                line
+            )
+            .into());
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if env::var_os(env_name(key)).is_some() {
+            continue;
+        }
+        match value {
+            "true" => options.push((key.to_string(), None)),
+            "false" => {}
+            value => options.push((key.to_string(), Some(value.trim_matches('"').to_string()))),
+        }
+    }
+    if !profiles.iter().any(|p| p == name) {
+        return Err(format!(
+            "there is no profile '{}', the known ones: {}",
+            name,
+            profiles.join(", ")
+        )
+        .into());
+    }
+    Ok(options)
+}
+
+/// Returns the position of the subcommand among the arguments, e.g. 1 for 'synacor solve'
+fn subcommand_at(args: &[OsString], command: &Command) -> Option<usize> {
+    args.iter()
+        .skip(1)
+        .position(|arg| {
+            arg.to_str().is_some_and(|arg| {
+                command
+                    .get_subcommands()
+                    .any(|s| s.get_name() == arg || s.get_all_aliases().any(|a| a == arg))
+            })
+        })
+        .map(|n| n + 1)
+}
+
+/// Tells whether the long option is taken by the program or by any of its subcommands
+fn is_known(command: &Command, key: &str) -> bool {
+    command
+        .get_arguments()
+        .chain(command.get_subcommands().flat_map(|s| s.get_arguments()))
+        .any(|a| a.get_long() == Some(key))
+}
+
+/// This function puts the options of the profile selected with --profile in front of the ones
+/// given on the command line, so these override them. The global options go right after the
+/// program name, the other ones right after the subcommand, and the options the subcommand does
+/// not take (e.g. the options of the game session for 'map') are left out. The key which is not
+/// an option of any subcommand is the error
+pub(super) fn expand_profile(
+    mut args: Vec<OsString>,
+    command: &Command,
+) -> Result<Vec<OsString>, Box<dyn Error>> {
+    let Some(name) = option_value(&args, "profile") else {
+        return Ok(args);
+    };
+    let path = option_value(&args, "config").unwrap_or(DEFAULT_CONFIG_FILE.to_string());
+    let text = fs::read_to_string(Path::new(&path))
+        .map_err(|e| format!("failed to read the config file {}. Error: {}", path, e))?;
+    let options = read_profile(&text, &name).map_err(|e| format!("{}: {}", path, e))?;
+    let subcommand = subcommand_at(&args, command);
+    let target = subcommand
+        .and_then(|n| command.find_subcommand(args[n].to_str()?))
+        .unwrap_or(command);
+    let is_global = |key: &str| {
+        command
+            .get_arguments()
+            .any(|a| a.is_global_set() && a.get_long() == Some(key))
+    };
+    let (mut global, mut local) = (vec![], vec![]);
+    for (key, value) in options {
+        if !is_known(command, &key) {
+            return Err(format!(
+                "{}: the profile '{}' has the unknown option '{}'",
+                path, name, key
+            )
+            .into());
+        }
+        let options = if is_global(&key) {
+            &mut global
+        } else if target
+            .get_arguments()
+            .any(|a| a.get_long() == Some(key.as_str()))
+        {
+            &mut local
+        } else {
+            debug!(
+                "the option '{}' of the profile is not taken by '{}'",
+                key,
+                target.get_name()
+            );
+            continue;
+        };
+        options.push(OsString::from(format!("--{}", key)));
+        options.extend(value.map(OsString::from));
+    }
+    if let Some(at) = subcommand {
+        args.splice(at + 1..at + 1, local);
+    } else {
+        global.append(&mut local);
+    }
+    let at = args.len().min(1);
+    args.splice(at..at, global);
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Args, Command as Subcommand};
+    use clap::{CommandFactory, Parser};
+
+    const CONFIG: &str = "
+# the profiles of the tests
+[profile.debug]
+log-level = debug
+journal-size = 100000
+no-status-line = true
+tui = false
+
+[profile.typo]
+journal-sise = 5
+
+[profile.map]
+format = \"json\"
+";
+
+    /// This function expands the profile of the arguments with the config file of the tests. The
+    /// --config option is left out of the result
+    fn expand(name: &str, args: &[&str]) -> Result<Vec<String>, String> {
+        let dir =
+            std::env::temp_dir().join(format!("synacor-profile-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("synacor.conf");
+        fs::write(&config, CONFIG).unwrap();
+        let mut all = vec![
+            "synacor".to_string(),
+            format!("--config={}", config.display()),
+        ];
+        all.extend(args.iter().map(|a| a.to_string()));
+        let expanded = expand_profile(
+            all.into_iter().map(OsString::from).collect(),
+            &Args::command(),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        expanded
+            .map(|args| {
+                args.into_iter()
+                    .map(|a| a.into_string().unwrap())
+                    .filter(|a| !a.starts_with("--config="))
+                    .collect()
+            })
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn reads_the_options_of_the_profile_section() {
+        let options = read_profile(CONFIG, "debug").unwrap();
+        assert_eq!(
+            options,
+            vec![
+                ("log-level".to_string(), Some("debug".to_string())),
+                ("journal-size".to_string(), Some("100000".to_string())),
+                ("no-status-line".to_string(), None),
+            ]
+        );
+        assert_eq!(
+            read_profile(CONFIG, "map").unwrap(),
+            vec![("format".to_string(), Some("json".to_string()))]
+        );
+    }
+
+    #[test]
+    fn rejects_the_missing_profile_and_the_malformed_line() {
+        let missing = read_profile(CONFIG, "release").unwrap_err().to_string();
+        assert_eq!(
+            missing,
+            "there is no profile 'release', the known ones: debug, typo, map"
+        );
+        let malformed = read_profile("[profile.debug]\nbatch\n", "debug")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            malformed,
+            "line 2 of the profile 'debug' is not 'key = value': batch"
+        );
+    }
+
+    #[test]
+    fn puts_the_profile_before_the_command_line_options() {
+        let args = expand(
+            "debug",
+            &["--profile", "debug", "run", "--journal-size", "5"],
+        )
+        .unwrap();
+        assert_eq!(
+            args[1..],
+            [
+                "--log-level",
+                "debug",
+                "--profile",
+                "debug",
+                "run",
+                "--journal-size",
+                "100000",
+                "--no-status-line",
+                "--journal-size",
+                "5"
+            ]
+        );
+        let parsed = Args::try_parse_from(args).unwrap();
+        assert_eq!(parsed.log_level.as_deref(), Some("debug"));
+        let Some(Subcommand::Run(vm)) = parsed.command else {
+            panic!("the run subcommand is expected");
+        };
+        assert_eq!(vm.journal_size, 5);
+        assert!(vm.no_status_line);
+    }
+
+    #[test]
+    fn leaves_out_the_options_the_subcommand_does_not_take() {
+        let args = expand("map", &["--profile", "debug", "map", "saves"]).unwrap();
+        assert_eq!(
+            args[1..],
+            ["--log-level", "debug", "--profile", "debug", "map", "saves"]
+        );
+    }
+
+    #[test]
+    fn rejects_the_unknown_option_of_the_profile() {
+        let error = expand("typo", &["--profile", "typo", "run"]).unwrap_err();
+        assert!(
+            error.ends_with("the profile 'typo' has the unknown option 'journal-sise'"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use crate::machine::{FaultKind, MAX, Machine, StepEvent};
+use log::{LevelFilter, debug, trace};
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+// Every synthetic program halts long before this number of instructions
+const MAX_CYCLES: u64 = 1_000;
+const R0: u16 = MAX;
+const R1: u16 = MAX + 1;
+
+/// The synthetic program exercising a single opcode or an edge case of the spec
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub opcode: &'static str,
+    pub name: &'static str,
+    pub program: Vec<u16>,
+    pub input: &'static str,
+    /// The expected output of the program
+    pub output: &'static str,
+    /// The expected values of the registers after the program halts
+    pub registers: Vec<(usize, u16)>,
+    /// The expected fault, which halts the program
+    pub fault: Option<FaultKind>,
+}
+
+impl Case {
+    fn new(opcode: &'static str, name: &'static str, program: Vec<u16>) -> Self {
+        Case {
+            opcode,
+            name,
+            program,
+            input: "",
+            output: "",
+            registers: vec![],
+            fault: None,
+        }
+    }
+
+    fn input(mut self, input: &'static str) -> Self {
+        self.input = input;
+        self
+    }
+
+    fn output(mut self, output: &'static str) -> Self {
+        self.output = output;
+        self
+    }
+
+    fn register(mut self, register: usize, value: u16) -> Self {
+        self.registers.push((register, value));
+        self
+    }
+
+    fn fault(mut self, fault: FaultKind) -> Self {
+        self.fault = Some(fault);
+        self
+    }
+}
+
+/// Returns the synthetic programs covering all opcodes of the spec
+pub fn cases() -> Vec<Case> {
+    vec![
+        Case::new("halt", "stops the program", vec![0, 19, 65]),
+        Case::new("set", "sets the register", vec![1, R0, 42, 0]).register(0, 42),
+        Case::new("set", "copies the register", vec![1, R1, 7, 1, R0, R1, 0]).register(0, 7),
+        Case::new("push/pop", "round trip", vec![2, 7, 2, 8, 3, R0, 3, R1, 0])
+            .register(0, 8)
+            .register(1, 7),
+        Case::new("pop", "empty stack is an error", vec![3, R0, 0]).fault(FaultKind::Underflow),
+        Case::new(
+            "eq",
+            "equal and different",
+            vec![4, R0, 5, 5, 4, R1, 5, 6, 0],
+        )
+        .register(0, 1)
+        .register(1, 0),
+        Case::new("gt", "greater and equal", vec![5, R0, 6, 5, 5, R1, 5, 5, 0])
+            .register(0, 1)
+            .register(1, 0),
+        Case::new("jmp", "jumps over the halt", vec![6, 3, 0, 1, R0, 1, 0]).register(0, 1),
+        Case::new("jt", "jumps on nonzero", vec![7, 1, 4, 0, 1, R0, 1, 0]).register(0, 1),
+        Case::new("jt", "falls through on zero", vec![7, 0, 6, 1, R0, 2, 0]).register(0, 2),
+        Case::new("jf", "jumps on zero", vec![8, 0, 4, 0, 1, R0, 1, 0]).register(0, 1),
+        Case::new("jf", "falls through on nonzero", vec![8, 1, 6, 1, R0, 2, 0]).register(0, 2),
+        Case::new("add", "modulo wraparound", vec![9, R0, 32758, 15, 0]).register(0, 5),
+        Case::new("add", "register operands", vec![9, R0, R1, 33, 19, R0, 0]).output("!"),
+        Case::new("mult", "modulo wraparound", vec![10, R0, 16384, 3, 0]).register(0, 16384),
+        Case::new("mult", "large product", vec![10, R0, 32767, 32767, 0]).register(0, 1),
+        Case::new("mod", "remainder", vec![11, R0, 17, 5, 0]).register(0, 2),
+        Case::new("and", "bitwise and", vec![12, R0, 12, 10, 0]).register(0, 8),
+        Case::new("or", "bitwise or", vec![13, R0, 12, 10, 0]).register(0, 14),
+        Case::new("not", "15-bit inverse of zero", vec![14, R0, 0, 0]).register(0, 32767),
+        Case::new("not", "15-bit inverse", vec![14, R0, 21845, 0]).register(0, 10922),
+        Case::new("rmem", "reads the memory", vec![15, R0, 4, 0, 1234]).register(0, 1234),
+        Case::new(
+            "wmem",
+            "writes the memory",
+            vec![16, 7, 99, 15, R0, 7, 0, 0],
+        )
+        .register(0, 99),
+        // The halt at address 5 is replaced by noop, so the second character is printed as well
+        Case::new(
+            "wmem",
+            "self-modification",
+            vec![16, 5, 21, 19, 65, 0, 19, 66, 0],
+        )
+        .output("AB"),
+        Case::new(
+            "call/ret",
+            "returns to the next instruction",
+            vec![17, 5, 19, 66, 0, 19, 65, 18],
+        )
+        .output("AB"),
+        Case::new(
+            "call",
+            "register target",
+            vec![1, R0, 6, 17, R0, 0, 19, 65, 18],
+        )
+        .output("A"),
+        Case::new("ret", "empty stack halts", vec![18, 19, 65]),
+        Case::new("out", "prints the character", vec![19, 72, 19, 105, 0]).output("Hi"),
+        Case::new("in", "reads the line", vec![20, R0, 20, R1, 0])
+            .input("x\n")
+            .register(0, 120)
+            .register(1, 10),
+        Case::new("noop", "does nothing", vec![21, 21, 19, 65, 0]).output("A"),
+    ]
+}
+
+/// This function packs the program words into the ROM bytes
+pub fn rom(program: &[u16]) -> Vec<u8> {
+    program.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+/// The outcome of the single case
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    pub opcode: &'static str,
+    pub name: &'static str,
+    pub problems: Vec<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// This function runs the program of the case on the fresh machine and compares the outcome with
+/// the expected one. A panic of the interpreter is reported as a problem too
+pub fn run_case(case: &Case) -> CaseResult {
+    trace!("running conformance case {}: {}", case.opcode, case.name);
+    // The machine logs the fault as the error, while here it is the expected outcome
+    let level = log::max_level();
+    if case.fault.is_some() {
+        log::set_max_level(LevelFilter::Off);
+    }
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut machine = Machine::new();
+        machine
+            .load_rom(&rom(&case.program))
+            .expect("the program of the case fits into the memory");
+        let mut input: VecDeque<u8> = case.input.bytes().collect();
+        let mut output = String::new();
+        let mut cycles = 0;
+        while !machine.is_halted() && cycles < MAX_CYCLES {
+            cycles += 1;
+            match machine.execute(|| input.pop_front()) {
+                StepEvent::Output(c) => output.push(c),
+                StepEvent::AwaitingInput => break,
+                _ => {}
+            }
+        }
+        (machine, output)
+    }));
+    log::set_max_level(level);
+    let mut problems = vec![];
+    match outcome {
+        Err(e) => {
+            let message = e
+                .downcast_ref::<&str>()
+                .map(|m| m.to_string())
+                .or_else(|| e.downcast_ref::<String>().cloned())
+                .unwrap_or("unknown reason".to_string());
+            problems.push(format!("the interpreter panicked: {}", message));
+        }
+        Ok((machine, output)) => {
+            if !machine.is_halted() {
+                problems.push(format!(
+                    "the program has not halted at {}",
+                    machine.position()
+                ));
+            }
+            if output != case.output {
+                problems.push(format!("output {:?}, expected {:?}", output, case.output));
+            }
+            for (register, expected) in &case.registers {
+                let value = machine.registers()[*register];
+                if value != *expected {
+                    problems.push(format!(
+                        "register {} is {}, expected {}",
+                        register, value, expected
+                    ));
+                }
+            }
+            let fault = machine.fault().map(|f| f.kind);
+            if fault != case.fault {
+                problems.push(format!("fault {:?}, expected {:?}", fault, case.fault));
+            }
+        }
+    }
+    CaseResult {
+        opcode: case.opcode,
+        name: case.name,
+        problems,
+    }
+}
+
+/// The outcome of all cases
+#[derive(Debug, Default)]
+pub struct ConformanceReport {
+    pub results: Vec<CaseResult>,
+}
+
+impl ConformanceReport {
+    pub fn failures(&self) -> usize {
+        self.results.iter().filter(|r| !r.passed()).count()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failures() == 0
+    }
+}
+
+/// This function runs all cases of the spec
+pub fn run_all() -> ConformanceReport {
+    // The panics are reported per case, so the default hook would only clutter the report
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let results: Vec<CaseResult> = cases().iter().map(run_case).collect();
+    panic::set_hook(hook);
+    debug!("ran {} conformance cases", results.len());
+    ConformanceReport { results }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "***           Spec Conformance            ***")?;
+        writeln!(f, "{}", "=".repeat(44))?;
+        for result in &self.results {
+            let verdict = if result.passed() { "PASS" } else { "FAIL" };
+            writeln!(f, "{} {:<9}: {}", verdict, result.opcode, result.name)?;
+            for problem in &result.problems {
+                writeln!(f, "  ! {}", problem)?;
+            }
+        }
+        writeln!(f, "{}", "_".repeat(44))?;
+        writeln!(f, "{:<9}: {}", "cases", self.results.len())?;
+        writeln!(f, "{:<9}: {}", "failures", self.failures())?;
+        write!(f, "{}", "=".repeat(45))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_all_cases_of_the_spec() {
+        let report = run_all();
+        assert!(!report.results.is_empty());
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    #[test]
+    fn reports_the_wrong_output_and_registers() {
+        let case = Case::new("out", "prints the character", vec![19, 65, 1, R0, 7, 0])
+            .output("B")
+            .register(0, 8);
+        let result = run_case(&case);
+        assert!(!result.passed());
+        assert_eq!(
+            result.problems,
+            vec![
+                r#"output "A", expected "B""#.to_string(),
+                "register 0 is 7, expected 8".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_the_program_waiting_for_the_input() {
+        let result = run_case(&Case::new("in", "reads the line", vec![20, R0, 0]));
+        assert_eq!(result.problems.len(), 1);
+        assert!(result.problems[0].starts_with("the program has not halted"));
+    }
+
+    #[test]
+    fn counts_the_failures_in_the_report() {
+        let report = ConformanceReport {
+            results: vec![
+                run_case(&Case::new("halt", "stops the program", vec![0])),
+                run_case(&Case::new("halt", "prints nothing", vec![0]).output("A")),
+            ],
+        };
+        assert_eq!(report.failures(), 1);
+        assert!(!report.is_ok());
+        assert!(
+            report
+                .to_string()
+                .contains("FAIL halt     : prints nothing")
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use crate::parser::{self, OutputParser, PROMPT};
+use log::{debug, trace};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The number of the response lines shown for every failure
+const EXCERPT_LINES: usize = 6;
+
+/// The response of the corpus, which the parser could not handle consistently
+#[derive(Debug, Clone)]
+pub struct CorpusFailure {
+    pub file: PathBuf,
+    /// The index of the response in the file, starting from 0
+    pub response: usize,
+    pub problems: Vec<String>,
+    pub excerpt: String,
+}
+
+/// The result of parsing all captured game outputs of the corpus
+#[derive(Debug, Default)]
+pub struct CorpusReport {
+    pub files: usize,
+    pub responses: usize,
+    pub failures: Vec<CorpusFailure>,
+}
+
+impl CorpusReport {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// This function parses every response of the captured game output (e.g. recorded with
+/// --record-output) and checks the parsed parts against the text
+pub fn parse_transcript(file: &Path, text: &str, report: &mut CorpusReport) {
+    let parser = OutputParser::new();
+    let mut responses = text.split_inclusive(PROMPT).peekable();
+    let mut n = 0;
+    while let Some(response) = responses.next() {
+        // The text after the last prompt is the unfinished response
+        if responses.peek().is_none() && !response.ends_with(PROMPT) {
+            trace!("skipping {} bytes after the last prompt", response.len());
+            break;
+        }
+        let parts = parser.parse_ref(response);
+        let problems = parser::validate(response, &parts);
+        if !problems.is_empty() {
+            debug!(
+                "response #{} of {} fails: {:?}",
+                n,
+                file.display(),
+                problems
+            );
+            report.failures.push(CorpusFailure {
+                file: file.to_path_buf(),
+                response: n,
+                problems,
+                excerpt: response
+                    .trim()
+                    .lines()
+                    .take(EXCERPT_LINES)
+                    .collect::<Vec<&str>>()
+                    .join("\n"),
+            });
+        }
+        n += 1;
+    }
+    report.files += 1;
+    report.responses += n;
+}
+
+/// This function runs the parser over all files of the directory
+pub fn parse_corpus(dir: &Path) -> Result<CorpusReport, Box<dyn Error>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    files.retain(|f| f.is_file());
+    files.sort();
+    debug!(
+        "parsing corpus of {} files in {}",
+        files.len(),
+        dir.display()
+    );
+    let mut report = CorpusReport::default();
+    for file in files {
+        parse_transcript(&file, &fs::read_to_string(&file)?, &mut report);
+    }
+    Ok(report)
+}
+
+impl fmt::Display for CorpusReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "***            Parser Corpus              ***")?;
+        writeln!(f, "{}", "=".repeat(44))?;
+        writeln!(f, "{:<9}: {}", "files", self.files)?;
+        writeln!(f, "{:<9}: {}", "responses", self.responses)?;
+        writeln!(f, "{:<9}: {}", "failures", self.failures.len())?;
+        for failure in &self.failures {
+            writeln!(f, "{}", "_".repeat(44))?;
+            writeln!(
+                f,
+                "{} response #{}:",
+                failure.file.display(),
+                failure.response
+            )?;
+            for problem in &failure.problems {
+                writeln!(f, "  ! {}", problem)?;
+            }
+            for line in failure.excerpt.lines() {
+                writeln!(f, "  | {}", line)?;
+            }
+        }
+        write!(f, "{}", "=".repeat(45))
+    }
+}
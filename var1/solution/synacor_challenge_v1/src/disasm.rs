@@ -0,0 +1,258 @@
+use crate::machine::{MAX, MNEMONICS, Machine};
+
+/// The opcode of the 'out' instruction
+pub const OUT_OPCODE: u16 = 19;
+// The words decoded before the listing, so the preceding instructions are found from the start
+const SYNC_WORDS: usize = 8;
+
+/// Returns the number of the operands of the opcode, or None for the word which is not an opcode
+pub fn operand_count(opcode: u16) -> Option<usize> {
+    match opcode {
+        0 | 18 | 21 => Some(0),
+        2 | 3 | 6 | 17 | 19 | 20 => Some(1),
+        1 | 7 | 8 | 14 | 15 | 16 => Some(2),
+        4 | 5 | 9..=13 => Some(3),
+        _ => None,
+    }
+}
+
+/// Returns the number of the words taken by the instruction. The word which is not an opcode is
+/// shown as data, so it takes one word
+pub fn length(opcode: u16) -> usize {
+    operand_count(opcode).map_or(1, |n| n + 1)
+}
+
+/// This function reads the words of the instruction at the address of the memory
+pub fn read(machine: &Machine, address: u16) -> Vec<u16> {
+    (0..length(machine.word(address)) as u16)
+        .map(|i| machine.word(address.wrapping_add(i) % MAX))
+        .collect()
+}
+
+fn operand(word: u16) -> String {
+    match word {
+        w if w < MAX => w.to_string(),
+        w if w < MAX + 8 => format!("r{}", w - MAX),
+        w => format!("?{}", w),
+    }
+}
+
+/// This function shows the instruction with its operands, e.g. "add r0 r1 4". The printable
+/// character of 'out' is shown as a character literal, the word which is not an opcode as data
+pub fn format(words: &[u16]) -> String {
+    let Some((&opcode, operands)) = words.split_first() else {
+        return String::new();
+    };
+    if operand_count(opcode).is_none() {
+        return format!("data {}", opcode);
+    }
+    let mut text = MNEMONICS[opcode as usize].to_string();
+    for &word in operands {
+        text.push(' ');
+        match (opcode, char::from_u32(word as u32)) {
+            (OUT_OPCODE, Some(c)) if c.is_ascii_graphic() || c == ' ' => {
+                text.push_str(&format!("'{}'", c))
+            }
+            (OUT_OPCODE, Some('\n')) => text.push_str("'\\n'"),
+            _ => text.push_str(&operand(word)),
+        }
+    }
+    text
+}
+
+/// This function decodes the instructions around the address: at most `before` ones preceding it
+/// and the one at the address followed by `after` ones. As the instructions have different
+/// lengths, the preceding ones are found by decoding from the farthest address, which reaches
+/// the given address exactly without running into the words which are not opcodes
+pub fn around(
+    machine: &Machine,
+    address: u16,
+    before: usize,
+    after: usize,
+) -> Vec<(u16, Vec<u16>)> {
+    // No instruction is longer than 4 words, the extra words let the decoding get in sync
+    let farthest = address.saturating_sub((before * 4 + SYNC_WORDS) as u16);
+    let mut preceding = None;
+    for start in farthest..address {
+        let mut position = start;
+        let mut decoded = vec![];
+        while position < address {
+            let words = read(machine, position);
+            let next = position + words.len() as u16;
+            decoded.push((position, words));
+            position = next;
+        }
+        if position != address {
+            continue;
+        }
+        decoded.drain(..decoded.len().saturating_sub(before));
+        let valid = decoded.iter().all(|(_, w)| operand_count(w[0]).is_some());
+        if valid || preceding.is_none() {
+            preceding = Some(decoded);
+        }
+        if valid {
+            break;
+        }
+    }
+    let mut listing = preceding.unwrap_or_default();
+    let mut position = address;
+    for _ in 0..=after {
+        if position >= MAX {
+            break;
+        }
+        let words = read(machine, position);
+        let next = position + words.len() as u16;
+        listing.push((position, words));
+        position = next;
+    }
+    listing
+}
+
+/// This function decodes the instructions one after another from the address `from` up to (but not
+/// including) the address `to`, e.g. the whole ROM
+pub fn range(machine: &Machine, from: u16, to: u16) -> Vec<(u16, Vec<u16>)> {
+    let mut listing = vec![];
+    let mut position = from;
+    while position < to.min(MAX) {
+        let words = read(machine, position);
+        let next = position.saturating_add(words.len() as u16);
+        listing.push((position, words));
+        position = next;
+    }
+    listing
+}
+
+/// The line of the annotated listing: the instruction or the run of the 'out' instructions
+/// printing the literal characters
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub address: u16,
+    /// The number of the words taken by the instructions of the line
+    pub length: u16,
+    pub text: String,
+}
+
+impl Line {
+    pub fn contains(&self, address: u16) -> bool {
+        (self.address..self.address + self.length).contains(&address)
+    }
+}
+
+/// This function collapses the consecutive 'out' instructions of the literal characters into the
+/// single 'print' pseudo-instruction
+pub fn annotate(listing: &[(u16, Vec<u16>)]) -> Vec<Line> {
+    let mut lines: Vec<Line> = vec![];
+    let mut run: Option<(Line, String)> = None;
+    for (address, words) in listing {
+        let literal = match words[..] {
+            [OUT_OPCODE, c] if c < MAX => char::from_u32(c as u32),
+            _ => None,
+        };
+        match (literal, run.as_mut()) {
+            (Some(c), Some((line, text))) => {
+                line.length += words.len() as u16;
+                text.push(c);
+            }
+            (Some(c), None) => {
+                let line = Line {
+                    address: *address,
+                    length: words.len() as u16,
+                    text: format(words),
+                };
+                run = Some((line, c.to_string()));
+            }
+            (None, _) => {
+                lines.extend(run.take().map(print_line));
+                lines.push(Line {
+                    address: *address,
+                    length: words.len() as u16,
+                    text: format(words),
+                });
+            }
+        }
+    }
+    lines.extend(run.map(print_line));
+    lines
+}
+
+/// The single 'out' stays as it is, the longer run becomes the 'print' of its text
+fn print_line((mut line, text): (Line, String)) -> Line {
+    if text.chars().count() > 1 {
+        line.text = format!("print {:?}", text);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::rom;
+
+    fn machine(program: &[u16]) -> Machine {
+        let mut machine = Machine::new();
+        machine.load_rom(&rom(program)).unwrap();
+        machine
+    }
+
+    #[test]
+    fn decodes_the_operands() {
+        assert_eq!(format(&[9, MAX, MAX + 1, 4]), "add r0 r1 4");
+        assert_eq!(format(&[1, MAX + 7, 32767]), "set r7 32767");
+        assert_eq!(format(&[6, MAX + 8]), "jmp ?32776");
+        assert_eq!(format(&[19, 'A' as u16]), "out 'A'");
+        assert_eq!(format(&[19, '\n' as u16]), "out '\\n'");
+        assert_eq!(format(&[19, MAX + 2]), "out r2");
+        assert_eq!(format(&[]), "");
+    }
+
+    #[test]
+    fn shows_the_invalid_opcode_as_the_data_word() {
+        let machine = machine(&[9, MAX, MAX, 1, 22, 0]);
+        let listing = range(&machine, 0, 6);
+        assert_eq!(
+            listing,
+            vec![(0, vec![9, MAX, MAX, 1]), (4, vec![22]), (5, vec![0])]
+        );
+        assert_eq!(format(&listing[1].1), "data 22");
+        assert_eq!(length(22), 1);
+        assert_eq!(operand_count(22), None);
+    }
+
+    #[test]
+    fn collapses_the_printed_characters_into_the_print() {
+        let machine = machine(&[
+            19,
+            'H' as u16,
+            19,
+            'i' as u16,
+            19,
+            '\n' as u16,
+            0,
+            19,
+            MAX,
+            19,
+            '!' as u16,
+        ]);
+        let lines = annotate(&range(&machine, 0, 11));
+        let texts: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, vec!["print \"Hi\\n\"", "halt", "out r0", "out '!'"]);
+        assert_eq!((lines[0].address, lines[0].length), (0, 6));
+        assert!(lines[0].contains(5));
+        assert!(!lines[0].contains(6));
+    }
+
+    #[test]
+    fn finds_the_instructions_before_the_address() {
+        let machine = machine(&[21, 9, MAX, MAX, 1, 1, MAX + 1, 2, 0]);
+        let listing = around(&machine, 5, 2, 1);
+        assert_eq!(
+            listing,
+            vec![
+                (0, vec![21]),
+                (1, vec![9, MAX, MAX, 1]),
+                (5, vec![1, MAX + 1, 2]),
+                (8, vec![0]),
+            ]
+        );
+    }
+}
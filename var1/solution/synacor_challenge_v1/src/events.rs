@@ -0,0 +1,106 @@
+use crate::parser::{ResponsePartsRef, UseEffect};
+use crate::plugin::VmPlugin;
+use log::{debug, trace};
+use std::any::Any;
+use std::time::Duration;
+
+/// Something which happened during the play session. The events are published by the VM and
+/// delivered to all registered plugins
+#[derive(Debug)]
+pub enum VmEvent<'a> {
+    /// The VM is about to execute the first instruction
+    Started,
+    /// The instruction at the address is about to be executed
+    InstructionExecuted(u16),
+    /// The game has read the character
+    InputConsumed(char),
+    /// The line printed by the game, including the trailing new line. The prompt line is published
+    /// without it, once the game starts reading the input
+    OutputLineCompleted(&'a str),
+    /// The player has entered the command (either a game or a slash '/' command)
+    CommandEntered(&'a str),
+    /// The game response up to the prompt has been parsed
+    ResponseParsed(&'a ResponsePartsRef<'a>),
+    /// The game has printed the prompt after the response and waits for the next command
+    Prompt,
+    /// The game has shown a room different from the previous one
+    RoomChanged(&'a ResponsePartsRef<'a>),
+    /// The player has used the item with the given effects. It is published before the response is
+    /// parsed, so the plugins know what to expect from it
+    ItemUsed(&'a str, &'a [UseEffect]),
+    /// The code for the challenge website has appeared in the output
+    CodeFound(&'a str),
+    /// The solver of /solve has issued the exploration command
+    SolverCommand(&'a str),
+    /// The /solve run has ended for the given reason
+    SolveFinished(&'a str),
+    /// The program has halted after executing the given number of instructions
+    Halted(u64),
+}
+
+/// This structure dispatches the events to the registered plugins in the order of registration
+#[derive(Default)]
+pub struct EventBus {
+    plugins: Vec<Box<dyn VmPlugin>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus::default()
+    }
+
+    pub fn register(&mut self, plugin: Box<dyn VmPlugin>) {
+        debug!("registering plugin '{}'", plugin.name());
+        self.plugins.push(plugin);
+        trace!("event bus has {} plugins now", self.plugins.len());
+    }
+
+    pub fn publish(&mut self, event: VmEvent) {
+        for plugin in self.plugins.iter_mut() {
+            match event {
+                VmEvent::Started => plugin.on_start(),
+                VmEvent::InstructionExecuted(address) => plugin.on_instruction(address),
+                VmEvent::OutputLineCompleted(line) => plugin.on_output(line),
+                VmEvent::CommandEntered(command) => plugin.on_command(command),
+                VmEvent::Prompt => plugin.on_prompt(),
+                VmEvent::Halted(cycles) => plugin.on_halt(cycles),
+                _ => {}
+            }
+            plugin.on_event(&event);
+        }
+    }
+
+    /// Returns the names of the registered plugins in the order of registration
+    pub fn names(&self) -> Vec<String> {
+        self.plugins.iter().map(|p| p.name().to_string()).collect()
+    }
+
+    /// Returns the commands of the plugins willing to take over, while the user is idle
+    pub fn idle(&mut self, idle: Duration) -> Vec<String> {
+        self.plugins
+            .iter_mut()
+            .filter_map(|p| p.on_idle(idle))
+            .collect()
+    }
+
+    /// Returns the commands the plugins have queued for the game
+    pub fn queued_input(&mut self) -> Vec<String> {
+        self.plugins
+            .iter_mut()
+            .flat_map(|p| p.queued_input())
+            .collect()
+    }
+
+    /// Returns the first registered plugin of the given type
+    pub fn plugin<T: VmPlugin>(&self) -> Option<&T> {
+        self.plugins
+            .iter()
+            .find_map(|p| (p.as_ref() as &dyn Any).downcast_ref::<T>())
+    }
+
+    pub fn plugin_mut<T: VmPlugin>(&mut self) -> Option<&mut T> {
+        self.plugins
+            .iter_mut()
+            .find_map(|p| (p.as_mut() as &mut dyn Any).downcast_mut::<T>())
+    }
+}
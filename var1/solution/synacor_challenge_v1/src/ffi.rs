@@ -0,0 +1,150 @@
+use crate::machine::{Machine, StepEvent};
+use log::{error, trace};
+use std::collections::VecDeque;
+use std::ffi::{CStr, c_char};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// The state returned by 'vm_run_until_input' when the game waits for the next command
+pub const VM_AWAITING_INPUT: i32 = 0;
+/// The state returned by 'vm_run_until_input' when the program has stopped
+pub const VM_HALTED: i32 = 1;
+/// The value returned by the functions when the arguments are invalid
+pub const VM_ERROR: i32 = -1;
+
+/// This structure is an opaque handle for the C callers. It holds the machine together with the
+/// queued input and the output, which was not read yet
+pub struct FfiVm {
+    machine: Machine,
+    input: VecDeque<u8>,
+    output: VecDeque<u8>,
+}
+
+/// This function allocates a new virtual machine with empty memory. The handle must be released
+/// with 'vm_free'
+#[unsafe(no_mangle)]
+pub extern "C" fn vm_new() -> *mut FfiVm {
+    Box::into_raw(Box::new(FfiVm {
+        machine: Machine::new(),
+        input: VecDeque::new(),
+        output: VecDeque::new(),
+    }))
+}
+
+/// This function releases the virtual machine allocated with 'vm_new'
+///
+/// # Safety
+/// The handle must be returned by 'vm_new' and must not be used after this call
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn vm_free(vm: *mut FfiVm) {
+    if !vm.is_null() {
+        drop(unsafe { Box::from_raw(vm) });
+    }
+}
+
+/// This function loads the ROM bytes into the memory of the machine, starting at address 0. The ROM
+/// larger than the memory is not loaded, VM_ERROR is returned
+///
+/// # Safety
+/// The handle must be returned by 'vm_new' and 'rom' must point to at least 'len' bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn vm_load_rom(vm: *mut FfiVm, rom: *const u8, len: usize) -> i32 {
+    let Some(vm) = (unsafe { vm.as_mut() }) else {
+        return VM_ERROR;
+    };
+    if rom.is_null() {
+        return VM_ERROR;
+    }
+    let rom = unsafe { std::slice::from_raw_parts(rom, len) };
+    if let Err(e) = vm.machine.load_rom(rom) {
+        error!("failed to load the ROM. Error: {}", e);
+        return VM_ERROR;
+    }
+    0
+}
+
+/// This function queues the command for the game. The trailing new line is added automatically
+///
+/// # Safety
+/// The handle must be returned by 'vm_new' and 'input' must be a nul terminated string
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn vm_feed_input(vm: *mut FfiVm, input: *const c_char) -> i32 {
+    let Some(vm) = (unsafe { vm.as_mut() }) else {
+        return VM_ERROR;
+    };
+    if input.is_null() {
+        return VM_ERROR;
+    }
+    let input = unsafe { CStr::from_ptr(input) }.to_bytes();
+    trace!("queueing command '{}'", String::from_utf8_lossy(input));
+    vm.input.extend(input.trim_ascii_end());
+    vm.input.push_back(b'\n');
+    0
+}
+
+/// This function executes the program until all the queued input is consumed and the game asks
+/// for more, or until the program halts. It returns VM_AWAITING_INPUT or VM_HALTED. The invalid
+/// program (e.g. the unknown opcode) panics the interpreter, which must not unwind into the C
+/// caller, so VM_ERROR is returned instead and the machine is halted
+///
+/// # Safety
+/// The handle must be returned by 'vm_new'
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn vm_run_until_input(vm: *mut FfiVm) -> i32 {
+    let Some(vm) = (unsafe { vm.as_mut() }) else {
+        return VM_ERROR;
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        loop {
+            if vm.machine.is_halted() {
+                return VM_HALTED;
+            }
+            match vm.machine.execute(|| vm.input.pop_front()) {
+                StepEvent::Output(c) => vm.output.extend(c.encode_utf8(&mut [0; 4]).bytes()),
+                StepEvent::AwaitingInput => return VM_AWAITING_INPUT,
+                StepEvent::Halted => return VM_HALTED,
+                StepEvent::Input(_) | StepEvent::None => {}
+            }
+        }
+    }));
+    result.unwrap_or_else(|_| {
+        error!("the program has crashed the interpreter");
+        vm.machine.halt = true;
+        VM_ERROR
+    })
+}
+
+/// This function returns 1, once the game has printed the prompt and all the queued input is
+/// consumed, so it is ready for the next command, or 0 otherwise
+///
+/// # Safety
+/// The handle must be returned by 'vm_new'
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn vm_is_awaiting_input(vm: *const FfiVm) -> i32 {
+    let Some(vm) = (unsafe { vm.as_ref() }) else {
+        return VM_ERROR;
+    };
+    i32::from(vm.machine.is_awaiting_input() && vm.input.is_empty())
+}
+
+/// This function moves at most 'len' bytes of the UTF-8 encoded game output into the buffer and
+/// returns the number of the copied bytes. The output is not nul terminated, and the character may
+/// be split between two calls
+///
+/// # Safety
+/// The handle must be returned by 'vm_new' and 'buf' must point to at least 'len' writable bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn vm_read_output(vm: *mut FfiVm, buf: *mut u8, len: usize) -> usize {
+    let Some(vm) = (unsafe { vm.as_mut() }) else {
+        error!("vm_read_output is called with a null handle");
+        return 0;
+    };
+    if buf.is_null() {
+        return 0;
+    }
+    let count = len.min(vm.output.len());
+    for (i, byte) in vm.output.drain(..count).enumerate() {
+        unsafe { ptr::write(buf.add(i), byte) };
+    }
+    count
+}
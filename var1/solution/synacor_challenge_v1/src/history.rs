@@ -0,0 +1,249 @@
+use std::ops::Range;
+use std::str::FromStr;
+
+/// What the response of the game to the command has shown
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommandResult {
+    /// The room shown by the response, if any
+    pub room: Option<u16>,
+    pub understood: bool,
+}
+
+/// The command entered by the player together with the context it has been entered in
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "state",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "StoredEntry")
+)]
+pub struct HistoryEntry {
+    pub command: String,
+    /// The room the command has been entered in
+    pub room: Option<u16>,
+    /// The cycle the game has read the command at
+    pub cycle: u64,
+    /// None until the response to the command is parsed
+    pub result: Option<CommandResult>,
+    /// The codes found in the response
+    pub codes: Vec<String>,
+}
+
+/// The states saved before the metadata was recorded keep the commands alone
+#[cfg(feature = "state")]
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum StoredEntry {
+    Command(String),
+    Entry {
+        command: String,
+        room: Option<u16>,
+        cycle: u64,
+        result: Option<CommandResult>,
+        #[serde(default)]
+        codes: Vec<String>,
+    },
+}
+
+#[cfg(feature = "state")]
+impl From<StoredEntry> for HistoryEntry {
+    fn from(stored: StoredEntry) -> Self {
+        match stored {
+            StoredEntry::Command(command) => HistoryEntry::new(&command, None, 0),
+            StoredEntry::Entry {
+                command,
+                room,
+                cycle,
+                result,
+                codes,
+            } => HistoryEntry {
+                command,
+                room,
+                cycle,
+                result,
+                codes,
+            },
+        }
+    }
+}
+
+impl HistoryEntry {
+    pub fn new(command: &str, room: Option<u16>, cycle: u64) -> Self {
+        HistoryEntry {
+            command: command.to_string(),
+            room,
+            cycle,
+            result: None,
+            codes: vec![],
+        }
+    }
+
+    /// Returns the room the command has taken the player to, None if the player has stayed
+    pub fn moved_to(&self) -> Option<u16> {
+        self.result
+            .as_ref()
+            .and_then(|r| r.room)
+            .filter(|to| Some(*to) != self.room)
+    }
+
+    pub fn is_move(&self) -> bool {
+        self.moved_to().is_some()
+    }
+
+    /// Whether the command takes, drops or uses the item
+    pub fn is_item_command(&self) -> bool {
+        let command = self.command.trim();
+        ["take ", "drop ", "use "]
+            .iter()
+            .any(|verb| command.starts_with(verb))
+    }
+
+    pub fn is_understood(&self) -> bool {
+        self.result.as_ref().is_none_or(|r| r.understood)
+    }
+}
+
+/// This function returns the commands alone, e.g. to replay them
+pub fn commands(history: &[HistoryEntry]) -> Vec<&str> {
+    history.iter().map(|e| e.command.as_str()).collect()
+}
+
+/// The entries of the history shown by /show_history
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HistoryFilter {
+    #[default]
+    All,
+    /// The commands which have taken the player to another room
+    Moves,
+    /// The commands taking, dropping or using the items
+    Items,
+    /// The commands the game has not understood
+    Failed,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        match self {
+            HistoryFilter::All => true,
+            HistoryFilter::Moves => entry.is_move(),
+            HistoryFilter::Items => entry.is_item_command(),
+            HistoryFilter::Failed => !entry.is_understood(),
+        }
+    }
+}
+
+impl FromStr for HistoryFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(HistoryFilter::All),
+            "moves" => Ok(HistoryFilter::Moves),
+            "items" => Ok(HistoryFilter::Items),
+            "failed" => Ok(HistoryFilter::Failed),
+            other => Err(format!(
+                "unknown filter '{}', expected all, moves, items or failed",
+                other
+            )),
+        }
+    }
+}
+
+/// The part of the history shown by /show_history
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryRange {
+    /// The given number of the latest entries
+    Last(usize),
+    /// The entries with the indices from the first one up to (but not including) the second one.
+    /// The open bounds of `5..` and `..30` are the start and the end of the history
+    Between(usize, usize),
+}
+
+impl HistoryRange {
+    /// Returns the indices of the entries in the history of the given length
+    pub fn indices(&self, len: usize) -> Range<usize> {
+        match *self {
+            HistoryRange::Last(n) => len.saturating_sub(n)..len,
+            HistoryRange::Between(start, end) => start.min(len)..end.min(len),
+        }
+    }
+}
+
+impl FromStr for HistoryRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |n: &str| {
+            if n.starts_with('-') {
+                return Err(format!(
+                    "the bound '{}' is negative, the commands are counted from 0",
+                    n
+                ));
+            }
+            n.parse::<usize>()
+                .map_err(|e| format!("invalid number '{}': {}", n, e))
+        };
+        let bound = |n: &str, open: usize| if n.is_empty() { Ok(open) } else { number(n) };
+        match s.split_once("..") {
+            Some((start, end)) => {
+                let (start, end) = (bound(start, 0)?, bound(end, usize::MAX)?);
+                if start > end {
+                    return Err(format!("the range {} is reversed", s));
+                }
+                Ok(HistoryRange::Between(start, end))
+            }
+            None => Ok(HistoryRange::Last(number(s)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(range: &str, len: usize) -> Range<usize> {
+        range.parse::<HistoryRange>().unwrap().indices(len)
+    }
+
+    #[test]
+    fn takes_the_latest_entries() {
+        assert_eq!(indices("20", 50), 30..50);
+        assert_eq!(indices("20", 5), 0..5);
+        assert_eq!(indices("0", 5), 5..5);
+    }
+
+    #[test]
+    fn takes_the_entries_between_the_bounds() {
+        assert_eq!(indices("5..30", 50), 5..30);
+        assert_eq!(indices("5..5", 50), 5..5);
+    }
+
+    #[test]
+    fn takes_the_open_bounds_from_the_ends_of_the_history() {
+        assert_eq!(indices("5..", 50), 5..50);
+        assert_eq!(indices("..30", 50), 0..30);
+        assert_eq!(indices("..", 50), 0..50);
+    }
+
+    #[test]
+    fn cuts_the_bounds_out_of_the_range_to_the_history() {
+        assert_eq!(indices("5..30", 10), 5..10);
+        assert_eq!(indices("40..60", 10), 10..10);
+        assert_eq!(indices("5..", 0), 0..0);
+    }
+
+    #[test]
+    fn rejects_the_negative_and_reversed_bounds() {
+        let error = |range: &str| range.parse::<HistoryRange>().unwrap_err();
+        assert_eq!(
+            error("-3"),
+            "the bound '-3' is negative, the commands are counted from 0"
+        );
+        assert_eq!(
+            error("-5..2"),
+            "the bound '-5' is negative, the commands are counted from 0"
+        );
+        assert_eq!(error("30..5"), "the range 30..5 is reversed");
+        assert!(error("5..x").starts_with("invalid number 'x'"));
+    }
+}
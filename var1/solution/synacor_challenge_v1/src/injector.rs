@@ -0,0 +1,50 @@
+use crate::plugin::VmPlugin;
+use log::{debug, trace};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// The handle queueing the commands for the game at runtime, e.g. from the test or the tool
+/// driving the VM started with `run_with_plugins` on another thread. It can be cloned, so several
+/// producers feed the same game
+#[derive(Debug, Clone)]
+pub struct InputQueue {
+    sender: Sender<String>,
+}
+
+impl InputQueue {
+    /// This method appends the command to the replayed ones, the same way as /inject does. The
+    /// game reads it, once it waits for the next command. The command is dropped silently, once
+    /// the VM is gone
+    pub fn queue_input(&self, command: &str) {
+        if self.sender.send(command.to_string()).is_err() {
+            trace!("input injector has been dropped");
+        }
+    }
+}
+
+/// This plugin passes the commands queued with its InputQueue to the game, so the consumers do
+/// not simulate stdin
+pub struct InputInjector {
+    receiver: Receiver<String>,
+}
+
+impl InputInjector {
+    /// Returns the injector together with the queue feeding it
+    pub fn channel() -> (Self, InputQueue) {
+        let (sender, receiver) = mpsc::channel();
+        (InputInjector { receiver }, InputQueue { sender })
+    }
+}
+
+impl VmPlugin for InputInjector {
+    fn name(&self) -> &str {
+        "input injector"
+    }
+
+    fn queued_input(&mut self) -> Vec<String> {
+        let commands: Vec<String> = self.receiver.try_iter().collect();
+        if !commands.is_empty() {
+            debug!("injecting {} queued commands", commands.len());
+        }
+        commands
+    }
+}
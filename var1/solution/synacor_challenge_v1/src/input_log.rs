@@ -0,0 +1,136 @@
+use log::debug;
+use std::cmp::Reverse;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The first line of every input record file
+pub const HEADER: &str = "# synacor input v1";
+// The number of the longest pauses shown by the report
+const LONGEST_PAUSES: usize = 10;
+
+/// The character consumed by the game and the time since the start of the session
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputEvent {
+    pub at: Duration,
+    pub byte: u8,
+}
+
+/// The line of the input record: the microseconds since the start and the character code
+/// separated with a tab
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.at.as_micros(), self.byte)
+    }
+}
+
+impl FromStr for InputEvent {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((at, byte)) = s.split_once('\t') else {
+            return Err("expected 2 fields separated with a tab".into());
+        };
+        Ok(InputEvent {
+            at: Duration::from_micros(at.parse()?),
+            byte: byte.parse()?,
+        })
+    }
+}
+
+/// This function reads the input record written with --record-input
+pub fn read(path: &Path) -> Result<Vec<InputEvent>, Box<dyn Error>> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    match lines.next().transpose()? {
+        Some(header) if header == HEADER => {}
+        _ => return Err(format!("{} is not an input record", path.display()).into()),
+    }
+    let events = lines
+        .enumerate()
+        .map(|(n, line)| {
+            line?
+                .parse()
+                .map_err(|e| format!("line {}: {}", n + 2, e).into())
+        })
+        .collect::<Result<Vec<InputEvent>, Box<dyn Error>>>()?;
+    debug!("read {} input events from {}", events.len(), path.display());
+    Ok(events)
+}
+
+/// The command typed by the player, when it has been started and how long it has been typed
+#[derive(Debug, Clone)]
+pub struct TimedCommand {
+    pub command: String,
+    pub started: Duration,
+    /// The time since the previous command has been entered
+    pub pause: Duration,
+    pub typing: Duration,
+}
+
+/// Where the time of the session has been spent
+#[derive(Debug, Clone)]
+pub struct InputStats {
+    pub characters: usize,
+    pub commands: Vec<TimedCommand>,
+}
+
+impl InputStats {
+    /// The characters are grouped into the commands ending with the new line
+    pub fn new(events: &[InputEvent]) -> Self {
+        let mut commands = vec![];
+        let mut entered = Duration::ZERO;
+        for line in events.split_inclusive(|e| e.byte == b'\n') {
+            let (first, last) = (line[0].at, line[line.len() - 1].at);
+            let command: String = line
+                .iter()
+                .map(|e| e.byte as char)
+                .filter(|c| *c != '\n')
+                .collect();
+            commands.push(TimedCommand {
+                command,
+                started: first,
+                pause: first.saturating_sub(entered),
+                typing: last.saturating_sub(first),
+            });
+            entered = last;
+        }
+        InputStats {
+            characters: events.len(),
+            commands,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.commands
+            .last()
+            .map_or(Duration::ZERO, |c| c.started + c.typing)
+    }
+}
+
+impl fmt::Display for InputStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let thinking: Duration = self.commands.iter().map(|c| c.pause).sum();
+        writeln!(f, "***          Input Timing Report          ***")?;
+        writeln!(f, "{}", "=".repeat(44))?;
+        writeln!(f, "{:<9}: {}", "commands", self.commands.len())?;
+        writeln!(f, "{:<9}: {}", "typed", self.characters)?;
+        writeln!(f, "{:<9}: {:.1?}", "elapsed", self.elapsed())?;
+        writeln!(f, "{:<9}: {:.1?}", "thinking", thinking)?;
+        writeln!(f, "{}", "_".repeat(44))?;
+        writeln!(f, "the longest pauses before the commands:")?;
+        let mut longest: Vec<&TimedCommand> = self.commands.iter().collect();
+        longest.sort_by_key(|c| Reverse(c.pause));
+        for command in longest.iter().take(LONGEST_PAUSES) {
+            writeln!(
+                f,
+                "{:>10.1?} at {:>8.1?}: {}",
+                command.pause, command.started, command.command
+            )?;
+        }
+        write!(f, "{}", "=".repeat(45))
+    }
+}
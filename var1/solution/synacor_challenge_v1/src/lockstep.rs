@@ -0,0 +1,191 @@
+use crate::machine::{Machine, StepEvent};
+use crate::state::VmState;
+use log::{debug, info, trace};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// The number of the latest output lines shown for the context of the divergence
+const CONTEXT_LINES: usize = 3;
+
+/// The first difference between two machines running in lockstep
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// The number of instructions both machines had executed before they diverged
+    pub cycle: u64,
+    pub reason: String,
+    pub positions: [u16; 2],
+    pub registers: [[u16; 8]; 2],
+    pub stack_sizes: [usize; 2],
+    /// The latest output lines of both machines
+    pub output: [String; 2],
+}
+
+/// How the lockstep run has ended
+#[derive(Debug, Clone)]
+pub enum LockstepOutcome {
+    Diverged(Box<Divergence>),
+    /// Both machines have halted identically after the given number of instructions
+    Halted(u64),
+    /// Both machines are waiting for the input, but there is nothing more to feed
+    InputExhausted(u64),
+    /// The limit of instructions has been reached without the divergence
+    CycleLimit(u64),
+}
+
+/// This structure runs two machines instruction by instruction with the same input, e.g. the
+/// patched and unpatched ROM or two saved states, and stops at the first difference in the
+/// position, registers or output
+pub struct Lockstep {
+    machines: [Machine; 2],
+    input: VecDeque<u8>,
+    output: [String; 2],
+    cycles: u64,
+}
+
+/// This function loads the machine either from the state saved by /save_state (a .json file) or
+/// from the ROM
+pub fn load_machine(path: &Path) -> Result<Machine, Box<dyn Error>> {
+    if path.extension().is_some_and(|e| e == "json") {
+        return Ok(VmState::load(path)?.machine);
+    }
+    let mut machine = Machine::new();
+    machine.load_rom(&fs::read(path)?)?;
+    Ok(machine)
+}
+
+impl Lockstep {
+    pub fn new(a: Machine, b: Machine) -> Self {
+        Lockstep {
+            machines: [a, b],
+            input: VecDeque::new(),
+            output: [String::new(), String::new()],
+            cycles: 0,
+        }
+    }
+
+    /// This method queues the command for both machines
+    pub fn feed(&mut self, command: &str) {
+        trace!("queueing lockstep input '{}'", command);
+        self.input
+            .extend(command.bytes().chain(std::iter::once(b'\n')));
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// This method executes one instruction on both machines. Both of them consume the same input
+    /// character, so it is taken from the queue only once
+    pub fn step(&mut self) -> Result<[StepEvent; 2], Box<Divergence>> {
+        let next = self.input.front().copied();
+        let mut consumed = [false, false];
+        let mut events = [StepEvent::None; 2];
+        for (n, machine) in self.machines.iter_mut().enumerate() {
+            events[n] = machine.execute(|| {
+                consumed[n] = next.is_some();
+                next
+            });
+            if let StepEvent::Output(c) = events[n] {
+                self.output[n].push(c);
+            }
+        }
+        if consumed[0] && consumed[1] {
+            self.input.pop_front();
+        }
+        self.cycles += 1;
+        match self.difference(&events) {
+            Some(reason) => Err(Box::new(self.divergence(reason))),
+            None => Ok(events),
+        }
+    }
+
+    fn difference(&self, events: &[StepEvent; 2]) -> Option<String> {
+        let [a, b] = &self.machines;
+        if events[0] != events[1] {
+            return Some(format!("events differ: {:?} vs {:?}", events[0], events[1]));
+        }
+        if a.position() != b.position() {
+            return Some("positions differ".to_string());
+        }
+        let changed: Vec<String> = (0..8)
+            .filter(|r| a.registers[*r] != b.registers[*r])
+            .map(|r| format!("reg {}", r))
+            .collect();
+        if !changed.is_empty() {
+            return Some(format!("registers differ: {}", changed.join(", ")));
+        }
+        None
+    }
+
+    fn divergence(&self, reason: String) -> Divergence {
+        info!("machines diverged after {} cycles: {}", self.cycles, reason);
+        let tail = |output: &str| {
+            let lines: Vec<&str> = output.lines().collect();
+            lines[lines.len().saturating_sub(CONTEXT_LINES)..].join("\n")
+        };
+        let [a, b] = &self.machines;
+        Divergence {
+            cycle: self.cycles,
+            reason,
+            positions: [a.position(), b.position()],
+            registers: [a.registers(), b.registers()],
+            stack_sizes: [a.stack.len(), b.stack.len()],
+            output: [tail(&self.output[0]), tail(&self.output[1])],
+        }
+    }
+
+    /// This method runs both machines until they diverge, halt, run out of the input or execute the
+    /// given number of instructions
+    pub fn run(&mut self, max_cycles: u64) -> LockstepOutcome {
+        debug!("running two machines in lockstep for {} cycles", max_cycles);
+        while self.cycles < max_cycles {
+            match self.step() {
+                Err(divergence) => return LockstepOutcome::Diverged(divergence),
+                Ok([StepEvent::Halted, _]) => return LockstepOutcome::Halted(self.cycles),
+                Ok([StepEvent::AwaitingInput, _]) => {
+                    return LockstepOutcome::InputExhausted(self.cycles);
+                }
+                Ok(_) => {}
+            }
+        }
+        LockstepOutcome::CycleLimit(self.cycles)
+    }
+}
+
+impl fmt::Display for LockstepOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "***            Lockstep Result            ***")?;
+        writeln!(f, "{}", "=".repeat(44))?;
+        match self {
+            LockstepOutcome::Halted(cycles) => writeln!(
+                f,
+                "{:<9}: both machines halted after {} cycles",
+                "result", cycles
+            )?,
+            LockstepOutcome::InputExhausted(cycles) => writeln!(
+                f,
+                "{:<9}: no divergence, the input is over after {} cycles",
+                "result", cycles
+            )?,
+            LockstepOutcome::CycleLimit(cycles) => {
+                writeln!(f, "{:<9}: no divergence in {} cycles", "result", cycles)?
+            }
+            LockstepOutcome::Diverged(d) => {
+                writeln!(f, "{:<9}: diverged after {} cycles", "result", d.cycle)?;
+                writeln!(f, "{:<9}: {}", "reason", d.reason)?;
+                for (n, name) in ["a", "b"].iter().enumerate() {
+                    writeln!(f, "{}", "_".repeat(44))?;
+                    writeln!(f, "{:<9}: {}", "machine", name)?;
+                    writeln!(f, "{:<9}: {}", "position", d.positions[n])?;
+                    writeln!(f, "{:<9}: {:?}", "registers", d.registers[n])?;
+                    writeln!(f, "{:<9}: {}", "stack", d.stack_sizes[n])?;
+                    writeln!(f, "{:<9}:\n{}", "output", d.output[n])?;
+                }
+            }
+        }
+        write!(f, "{}", "=".repeat(45))
+    }
+}
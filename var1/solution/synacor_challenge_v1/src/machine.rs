@@ -0,0 +1,1592 @@
+use crate::parser::PROMPT;
+use crate::style::Colorize;
+use log::{debug, error, info, trace, warn};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+//const MAX: u16 = 32768; // The same as 1 << 15
+pub(crate) const MAX: u16 = 1 << 15;
+/// The size of the memory in bytes, the largest ROM which can be loaded
+pub const MEMORY_SIZE: usize = 1 << 16;
+// The number of the latest calls shown in the backtrace of the fault
+const BACKTRACE_DEPTH: usize = 16;
+// At most this number of the tolerated invalid values is kept for the conformance check
+const MAX_VIOLATIONS: usize = 100;
+/// The names of the opcodes, indexed by the opcode
+pub const MNEMONICS: [&str; 22] = [
+    "halt", "set", "push", "pop", "eq", "gt", "jmp", "jt", "jf", "add", "mult", "mod", "and", "or",
+    "not", "rmem", "wmem", "call", "ret", "out", "in", "noop",
+];
+
+/*
+== binary format ==
+- each number is stored as a 16-bit little-endian pair (low byte, high byte)
+- numbers 0..32767 mean a literal value
+- numbers 32768..32775 instead mean registers 0..7
+- numbers 32776..65535 are invalid
+- programs are loaded into memory starting at address 0
+- address 0 is the first 16-bit value, address 1 is the second 16-bit value, etc
+*/
+
+// Points to the u8 data value in the memory array
+pub(crate) type Ptr = u16;
+
+impl From<&Address> for Ptr {
+    fn from(a: &Address) -> Self {
+        (a.0 * 2) as Ptr
+    }
+}
+
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Address(pub(crate) u16);
+
+impl Address {
+    pub(crate) fn new(value: u16) -> Self {
+        if value < MAX {
+            return Address(value);
+        }
+        panic!("invalid address value (value must be less than {})", MAX);
+    }
+
+    pub(crate) fn next(&self) -> Self {
+        self.add(1)
+    }
+    pub(crate) fn add(&self, n: u16) -> Self {
+        Address::new(self.0 + n)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ptr: Ptr = self.into();
+        write!(f, "addr[{} ({:#x})]", self.0, ptr)
+    }
+}
+
+impl From<Ptr> for Address {
+    // - address 0 is the first 16-bit value, address 1 is the second 16-bit value, etc
+    // In other words address points into 2 consequtive u8 values in the memory
+    fn from(p: Ptr) -> Self {
+        if p % 2 == 1 {
+            error!(
+                "provided pointer {} must be even! the value will be floored to the lesser one",
+                p
+            );
+            // For a moment just to spot the anomaly
+            panic!(
+                "provided pointer {} must be even! the value will be floored to the lesser one",
+                p
+            );
+        }
+        Address::new(p / 2)
+    }
+}
+
+pub(crate) enum Data {
+    LiteralValue(u16),
+    Register(usize),
+}
+impl Data {
+    fn is_register(&self) -> bool {
+        matches!(self, Data::Register(_))
+    }
+    #[allow(dead_code)]
+    fn is_literal(&self) -> bool {
+        matches!(self, Data::LiteralValue(_))
+    }
+}
+
+impl fmt::Display for Data {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Data::Register(r) => write!(f, "register[{}]", r),
+            Data::LiteralValue(v) => write!(f, "value[{}]", v),
+        }
+    }
+}
+impl fmt::Debug for Data {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Data::Register(r) => write!(f, "register[{}]", r),
+            Data::LiteralValue(v) => write!(f, "value[{}]", v),
+        }
+    }
+}
+
+/// This function composes u16 number from little endian byte pair of low byte and high byte
+fn compose_value(byte_pair: (u8, u8)) -> u16 {
+    // - all math is modulo 32768; 32758 + 15 => 5
+    // - each number is stored as a 16-bit little-endian pair (low byte, high byte)
+    let lb: u16 = byte_pair.0 as u16;
+    let hb: u16 = (byte_pair.1 as u16) << 8;
+    // Let's try not perform mod operation on this level
+    // let value = (hb + lb) % MAX;
+    // This was a bug preventing from getting register number!
+    // The real mod '%' operation will happen at 'pack_raw_value' function
+    let value = hb + lb;
+    trace!(
+        "  compose value {} ({:#x}) from bytes {:?} ({:#x}, {:#x})",
+        value, value, byte_pair, byte_pair.0, byte_pair.1
+    );
+    // If the value is greater than 32768 + 8 (MAX + number of registers), it will cause panic
+    // anyway, so it makes sense to log it early
+    if value > MAX + 8 {
+        trace!(
+            "  {} detected on composed value {} ({:#x})",
+            "OVERFLOW".yellow(),
+            value,
+            value
+        );
+    }
+    assert!(
+        validate_value(value),
+        "value bigger than 32768 + 8 is invalid"
+    );
+    value
+}
+
+/// This function decomposes u16 number to the little endian byte pair of low byte and high byte
+fn decompose_value(value: u16) -> (u8, u8) {
+    // - all math is modulo 32768; 32758 + 15 => 5
+    // - each number is stored as a 16-bit little-endian pair (low byte, high byte)
+    assert!(
+        validate_value(value),
+        "value bigger than 32768 + 8 is invalid"
+    );
+    let lb: u16 = value % (1 << 8);
+    let hb: u16 = value >> 8;
+    trace!("  got low byte {:#x} and high byte: {:#x}", lb, hb);
+    let byte_pair: (u8, u8) = (lb as u8, hb as u8);
+    trace!(
+        "  decompose bytes {:?} ({:#x}, {:#x}) from value {} ({:#x}) ",
+        byte_pair, byte_pair.0, byte_pair.1, value, value
+    );
+    byte_pair
+}
+
+fn validate_value(val: u16) -> bool {
+    val < MAX + 8
+}
+/// This method takes a provided value validates it and packs it to Data
+fn pack_raw_value(v: u16) -> Data {
+    match v {
+        val if v < MAX => {
+            trace!("  packing literal value '{}'", v);
+            Data::LiteralValue(val)
+        }
+        r if r % MAX < 8 => {
+            let reg = (r % MAX) as usize;
+            trace!("  packing register number value '{}' as reg: ({})", v, reg);
+            Data::Register(reg)
+        }
+        // Probably we can just return an error here
+        _ => panic!("values bigger than 32776 are invalid"),
+    }
+}
+/// This function just converts Data to raw memory address
+#[allow(dead_code)]
+fn unpack_data_to_raw_address(d: Data) -> u16 {
+    let raw = match d {
+        Data::LiteralValue(v) => v,
+        Data::Register(r) => MAX + r as u16,
+    };
+
+    assert!(
+        validate_value(raw),
+        "value bigger than 32768 + 8 is invalid"
+    );
+    raw
+}
+
+enum ArithmeticOperations {
+    Add,
+    Multiply,
+    Modulo,
+    And,
+    Or,
+    Not,
+}
+impl fmt::Display for ArithmeticOperations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticOperations::Modulo => write!(f, "%"),
+            ArithmeticOperations::And => write!(f, "&"),
+            ArithmeticOperations::Add => write!(f, "+"),
+            ArithmeticOperations::Multiply => write!(f, "*"),
+            ArithmeticOperations::Or => write!(f, "|"),
+            ArithmeticOperations::Not => write!(f, "~"),
+        }
+    }
+}
+impl ArithmeticOperations {
+    fn get_instruction_name(&self) -> &str {
+        match self {
+            ArithmeticOperations::Multiply => "mult",
+            ArithmeticOperations::Add => "add",
+            ArithmeticOperations::And => "and",
+            ArithmeticOperations::Or => "or",
+            ArithmeticOperations::Not => "not",
+            ArithmeticOperations::Modulo => "mod",
+        }
+    }
+}
+
+/// The counters of the executed instructions. They are cheap enough to be collected all the time
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionStats {
+    /// The number of the executed instructions per opcode
+    pub opcodes: [u64; MNEMONICS.len()],
+    /// The conditional jumps (jt and jf), which have jumped
+    pub branches_taken: u64,
+    pub branches_not_taken: u64,
+    /// The largest number of values the stack has ever held
+    pub stack_high_water: usize,
+}
+
+/// The state overwritten by a single instruction, so the instruction can be undone
+#[derive(Debug, Clone)]
+struct JournalEntry {
+    position: u16,
+    registers: [u16; 8],
+    halt: bool,
+    fault: Option<Fault>,
+    stack_len: usize,
+    /// The value removed by 'pop' or 'ret'
+    stack_top: Option<u16>,
+    calls_len: usize,
+    /// The backtrace before 'pop' or 'ret', which may drop its entries
+    calls: Option<Vec<u16>>,
+    /// The address written by 'wmem' and the word stored there before
+    memory: Option<(u16, u16)>,
+    /// The progress of matching the prompt, changed by 'out' and 'in'
+    prompt_matched: usize,
+    at_prompt: bool,
+    /// The instruction is 'in', which has consumed the input for good
+    read_input: bool,
+}
+
+/// The reason why the machine cannot step back any further
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepBackLimit {
+    /// The journal keeps no more instructions
+    JournalExhausted,
+    /// The latest instruction has read the input, which cannot be given back to its source
+    InputRead,
+}
+
+impl fmt::Display for StepBackLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepBackLimit::JournalExhausted => write!(f, "the journal is exhausted"),
+            StepBackLimit::InputRead => write!(f, "the input read by 'in' cannot be undone"),
+        }
+    }
+}
+
+/// Something observable, which happened during the execution of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepEvent {
+    None,
+    Output(char),
+    Input(char),
+    AwaitingInput,
+    Halted,
+}
+
+/// This decoder joins the bytes read by the 'in' instruction (StepEvent::Input carries one byte a
+/// time) into the characters of the UTF-8 input, so the echoed or recorded input is shown intact
+#[derive(Debug, Clone, Default)]
+pub struct InputDecoder {
+    pending: Vec<u8>, // the bytes of the multibyte character, which is not complete yet
+}
+
+impl InputDecoder {
+    /// This method takes the byte read by the game. The multibyte character is returned once all
+    /// of its bytes are read, the bytes, which are not valid UTF-8, are replaced
+    pub fn push(&mut self, c: char) -> Vec<char> {
+        if c.is_ascii() && self.pending.is_empty() {
+            return vec![c];
+        }
+        self.pending.push(c as u8);
+        match std::str::from_utf8(&self.pending) {
+            // The character is not complete yet
+            Err(e) if e.error_len().is_none() => vec![],
+            _ => {
+                let text = String::from_utf8_lossy(&self.pending).into_owned();
+                self.pending.clear();
+                text.chars().collect()
+            }
+        }
+    }
+}
+
+/// How the machine treats writing the values 32768..32775 to the registers and memory. They are
+/// valid operands referring to the registers, but not valid numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub enum ValueStrictness {
+    /// Store the value silently
+    #[default]
+    Permit,
+    /// Store the value, but log and record the violation
+    Warn,
+    /// Halt the machine with the fault
+    Reject,
+}
+
+impl fmt::Display for ValueStrictness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueStrictness::Permit => write!(f, "permit"),
+            ValueStrictness::Warn => write!(f, "warn"),
+            ValueStrictness::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+impl FromStr for ValueStrictness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "permit" => Ok(ValueStrictness::Permit),
+            "warn" => Ok(ValueStrictness::Warn),
+            "reject" => Ok(ValueStrictness::Reject),
+            other => Err(format!(
+                "unknown strictness '{}', expected permit, warn or reject",
+                other
+            )),
+        }
+    }
+}
+
+/// What has gone wrong during the execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// 'pop' on the empty stack
+    Underflow,
+    /// The stack has reached the configured depth limit
+    Overflow(usize),
+    /// The value, which is not a number, is written to the register
+    InvalidRegisterValue { register: usize, value: u16 },
+    /// The value, which is not a number, is written to the memory or read from it by 'rmem'
+    InvalidMemoryValue { address: u16, value: u16 },
+}
+
+/// The violation of the architecture spec by the program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    /// The address of the failed instruction
+    pub position: u16,
+    /// The addresses of the latest 'call' instructions, which have not returned yet, the innermost
+    /// first
+    pub backtrace: Vec<u16>,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            FaultKind::Underflow => write!(f, "stack underflow")?,
+            FaultKind::Overflow(limit) => {
+                write!(f, "stack overflow (the limit is {} values)", limit)?
+            }
+            FaultKind::InvalidRegisterValue { register, value } => write!(
+                f,
+                "invalid value {} written to register {}",
+                value, register
+            )?,
+            FaultKind::InvalidMemoryValue { address, value } => {
+                write!(f, "invalid value {} of memory {}", value, Address(address))?
+            }
+        }
+        write!(f, " at {}", Address(self.position))?;
+        if !self.backtrace.is_empty() {
+            let calls: Vec<String> = self
+                .backtrace
+                .iter()
+                .map(|a| Address(*a).to_string())
+                .collect();
+            write!(f, ", called from {}", calls.join(" <- "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Fault {}
+
+/// The ROM, which does not fit into the memory, with its size in bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomTooLarge(pub usize);
+
+impl fmt::Display for RomTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the ROM of {} bytes does not fit into the memory of {} bytes",
+            self.0, MEMORY_SIZE
+        )
+    }
+}
+
+impl Error for RomTooLarge {}
+
+/// This structure is the bare interpreter of the architecture: memory, registers, stack and the
+/// opcode execution. It does not touch any files, stdin or stdout, so it can be embedded anywhere
+#[derive(Clone)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub struct Machine {
+    pub(crate) halt: bool,
+    #[cfg_attr(feature = "state", serde(with = "crate::state::memory_words"))]
+    pub(crate) memory: [u8; MEMORY_SIZE], // as there is 15 bit address space, but each address points to the 2
+    // bytes, so we actually need 15 bit * 2 address space for the memory array.
+    pub(crate) registers: [u16; 8],
+    pub(crate) stack: VecDeque<u16>,
+    // - all numbers are unsigned integers 0..32767 (15-bit)
+    // - all math is modulo 32768; 32758 + 15 => 5
+    pub(crate) current_address: Address, // internal execution pointer
+    // The addresses of the 'call' instructions for the backtrace. The program may drop the return
+    // address with 'pop', so it is never longer than the stack
+    #[cfg_attr(feature = "state", serde(default))]
+    calls: Vec<u16>,
+    #[cfg_attr(feature = "state", serde(default))]
+    stack_limit: Option<usize>,
+    #[cfg_attr(feature = "state", serde(default))]
+    strictness: ValueStrictness,
+    #[cfg_attr(feature = "state", serde(skip))]
+    fault: Option<Fault>,
+    #[cfg_attr(feature = "state", serde(skip))]
+    violations: Vec<Fault>,
+    #[cfg_attr(feature = "state", serde(skip))]
+    stats: ExecutionStats,
+    // The latest instructions can be undone with step_back, while the journal is enabled
+    #[cfg_attr(feature = "state", serde(skip))]
+    journal: VecDeque<JournalEntry>,
+    #[cfg_attr(feature = "state", serde(skip))]
+    journal_size: usize,
+    // The number of the prompt characters the output ends with, and whether the whole prompt has
+    // been printed since the latest input
+    #[cfg_attr(feature = "state", serde(default))]
+    prompt_matched: usize,
+    #[cfg_attr(feature = "state", serde(default))]
+    at_prompt: bool,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Machine {
+            halt: false,
+            memory: [0; MEMORY_SIZE],
+            registers: [0; 8],
+            stack: VecDeque::new(),
+            current_address: Address::default(),
+            calls: vec![],
+            stack_limit: None,
+            strictness: ValueStrictness::default(),
+            fault: None,
+            violations: vec![],
+            stats: ExecutionStats::default(),
+            journal: VecDeque::new(),
+            journal_size: 0,
+            prompt_matched: 0,
+            at_prompt: false,
+        }
+    }
+    pub fn is_halted(&self) -> bool {
+        self.halt
+    }
+    /// Returns true, once the game has printed the prompt of the parser and has not read anything
+    /// since, so it is ready for the next command. Unlike StepEvent::AwaitingInput, it does not
+    /// depend on the queued input, and the 'in' instruction reading something else than the
+    /// command does not count
+    pub fn is_awaiting_input(&self) -> bool {
+        self.at_prompt
+    }
+    fn track_prompt(&mut self, c: char) {
+        let is_prompt_char = |n: usize| PROMPT.as_bytes().get(n).is_some_and(|p| char::from(*p) == c);
+        self.prompt_matched = if is_prompt_char(self.prompt_matched) {
+            self.prompt_matched + 1
+        } else {
+            usize::from(is_prompt_char(0))
+        };
+        if self.prompt_matched == PROMPT.len() {
+            trace!("the prompt is printed");
+            self.at_prompt = true;
+            self.prompt_matched = 0;
+        }
+    }
+    /// Limits the number of values on the stack. The machine halts with the overflow error,
+    /// instead of growing the stack without bounds
+    pub fn set_stack_limit(&mut self, limit: Option<usize>) {
+        debug!("setting stack limit to {:?}", limit);
+        self.stack_limit = limit;
+    }
+    pub fn set_strictness(&mut self, strictness: ValueStrictness) {
+        debug!("setting invalid value strictness to {}", strictness);
+        self.strictness = strictness;
+    }
+    /// Keeps the state overwritten by the given number of the latest instructions, so they can be
+    /// undone with step_back. Zero disables the journal
+    pub fn set_journal_size(&mut self, size: usize) {
+        debug!("setting journal size to {}", size);
+        self.journal_size = size;
+        self.journal.truncate(size);
+    }
+    pub fn journal_size(&self) -> usize {
+        self.journal_size
+    }
+    /// Returns the number of the instructions, which can be undone: the journaled ones after the
+    /// latest 'in'
+    pub fn journal_len(&self) -> usize {
+        self.journal.iter().rev().take_while(|e| !e.read_input).count()
+    }
+    /// Returns the copy of the machine without the journal, which is too big to be copied often
+    pub fn checkpoint(&mut self) -> Machine {
+        let journal = std::mem::take(&mut self.journal);
+        let mut copy = self.clone();
+        copy.journal_size = 0;
+        self.journal = journal;
+        copy
+    }
+    /// This method undoes the latest executed instruction, restoring the registers, the stack and
+    /// the memory written by it. The 'in' instruction is not undone, as the input it has consumed
+    /// is gone, so the machine never steps back across it
+    pub fn step_back(&mut self) -> Result<(), StepBackLimit> {
+        match self.journal.back() {
+            None => return Err(StepBackLimit::JournalExhausted),
+            Some(entry) if entry.read_input => return Err(StepBackLimit::InputRead),
+            Some(_) => {}
+        }
+        let Some(entry) = self.journal.pop_back() else {
+            return Err(StepBackLimit::JournalExhausted);
+        };
+        self.current_address = Address::new(entry.position);
+        self.registers = entry.registers;
+        self.halt = entry.halt;
+        self.fault = entry.fault;
+        self.stack.truncate(entry.stack_len);
+        if let Some(top) = entry.stack_top
+            && self.stack.len() < entry.stack_len
+        {
+            self.stack.push_back(top);
+        }
+        match entry.calls {
+            Some(calls) => self.calls = calls,
+            None => self.calls.truncate(entry.calls_len),
+        }
+        if let Some((address, word)) = entry.memory {
+            let (lb, hb) = decompose_value(word);
+            self.memory[address as usize * 2] = lb;
+            self.memory[address as usize * 2 + 1] = hb;
+        }
+        self.prompt_matched = entry.prompt_matched;
+        self.at_prompt = entry.at_prompt;
+        trace!("stepped back to {}", self.current_address);
+        Ok(())
+    }
+    /// Returns the error, which has halted the machine
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+    /// Returns the invalid values tolerated in the 'warn' strictness mode
+    pub fn violations(&self) -> &[Fault] {
+        &self.violations
+    }
+    /// Returns the counters of the instructions executed since the machine has been created
+    pub fn stats(&self) -> &ExecutionStats {
+        &self.stats
+    }
+    /// Returns the addresses of the 'call' instructions which have not returned yet, the latest last
+    pub fn calls(&self) -> &[u16] {
+        &self.calls
+    }
+    pub fn registers(&self) -> [u16; 8] {
+        self.registers
+    }
+    /// Returns the address of the instruction to be executed next
+    pub fn position(&self) -> u16 {
+        self.current_address.0
+    }
+    /// Returns the raw word stored at the address, e.g. the opcode or the operand
+    pub fn word(&self, address: u16) -> u16 {
+        self.get_value_from_addr(&Address::new(address % MAX))
+    }
+    /// This method overwrites the raw word at the address, e.g. to patch the program
+    pub fn set_word(&mut self, address: u16, value: u16) {
+        let (lb, hb) = decompose_value(value);
+        let ptr = (address % MAX) as usize * 2;
+        self.memory[ptr] = lb;
+        self.memory[ptr + 1] = hb;
+    }
+    /// Returns the value of the operand: the content of the register or the literal number
+    pub fn operand(&self, word: u16) -> u16 {
+        match word.checked_sub(MAX) {
+            Some(register) if register < 8 => self.registers[register as usize],
+            _ => word,
+        }
+    }
+    /// This method copies the ROM into the memory from address 0. The ROM larger than the memory
+    /// is refused, the memory is left as it was
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), RomTooLarge> {
+        debug!("loading program of {} bytes into memory", rom.len());
+        let Some(memory) = self.memory.get_mut(..rom.len()) else {
+            return Err(RomTooLarge(rom.len()));
+        };
+        memory.copy_from_slice(rom);
+        trace!("loading OK!");
+        Ok(())
+    }
+    /// This method gets 2 adjasent bytes from the RAM and composes a number u16 from it
+    fn get_value_from_addr(&self, addr: &Address) -> u16 {
+        trace!(" getting value from address {}", addr);
+        let ptr = addr.into();
+        let lb = self.get_byte_value_from_ptr(ptr);
+        let hb = self.get_byte_value_from_ptr(ptr + 1);
+        compose_value((lb, hb))
+    }
+    /// This method gets raw memory value by pointer
+    fn get_byte_value_from_ptr(&self, ptr: Ptr) -> u8 {
+        let b = self.memory[ptr as usize];
+        trace!(
+            "  fetched {} [{:#x}] from memory pointer {} [{:#x}] ",
+            b, b, ptr, ptr
+        );
+        b
+    }
+
+    fn get_data(&self, v: u16) -> u16 {
+        self.unpack_data(pack_raw_value(v))
+    }
+
+    fn get_data_from_addr(&self, addr: Address) -> u16 {
+        let v = self.get_value_from_addr(&addr);
+        self.get_data(v)
+    }
+
+    fn get_from_register(&self, register: usize) -> u16 {
+        if register >= 8 {
+            panic!(
+                "invalid register value {} There is 8 resisters only.",
+                register
+            );
+        }
+        let v = self.registers[register];
+        trace!(" getting value {} from register {}", v, register);
+        v
+    }
+    /// This method extracts data from both variants of Data enum
+    fn unpack_data(&self, data: Data) -> u16 {
+        let val = match data {
+            Data::LiteralValue(lv) => lv,
+            Data::Register(r) => self.get_from_register(r),
+        };
+        trace!(" unpacked value {} from {}", val, data);
+        val
+    }
+
+    fn set_position(&mut self, pos: Address) {
+        trace!("{}", format!("set position to {}", pos).yellow().italic());
+        self.current_address = pos;
+    }
+
+    fn step(&mut self) {
+        let next_address = self.current_address.next();
+        trace!(
+            "{} stepping to the next address {}",
+            &self.current_address, next_address
+        );
+        self.set_position(next_address);
+    }
+    fn step_n(&mut self, n: u16) {
+        let new_address = self.current_address.add(n);
+        trace!(
+            "{} stepping {} addresses forward to {}",
+            &self.current_address, n, &new_address
+        );
+        self.set_position(new_address);
+    }
+    // Here  ops functions go
+    fn noop(&mut self) {
+        debug!("{} {}:", &self.current_address, "noop".magenta());
+        self.step();
+    }
+    fn halt(&mut self) {
+        debug!("{} {}:", &self.current_address, "halt".magenta());
+        self.halt = true;
+        info!("VM has been halt");
+    }
+    fn out(&mut self, a: Address) -> StepEvent {
+        debug!("{} {}: {}", &self.current_address, "out".magenta(), &a);
+        // The 15-bit values are all the valid code points, the ones above ASCII are not cut down
+        let value = self.get_data_from_addr(a);
+        let character = char::from_u32(value.into()).unwrap_or(char::REPLACEMENT_CHARACTER);
+        trace!(
+            "printing character '{}' ({:#x})",
+            character.to_string().red(),
+            value
+        );
+        self.track_prompt(character);
+        self.step_n(2);
+        StepEvent::Output(character)
+    }
+
+    fn jmp(&mut self, a: Address) {
+        debug!("{} {}: {}", &self.current_address, "jmp".magenta(), &a);
+        let pos = Address::new(self.get_data_from_addr(a));
+        self.set_position(pos);
+    }
+    fn jmp_true(&mut self, a: Address, b: Address) {
+        debug!(
+            "{} {}: {} {}",
+            &self.current_address,
+            "jt".magenta(),
+            &a,
+            &b
+        );
+        if self.get_data_from_addr(a) != 0 {
+            self.stats.branches_taken += 1;
+            let pos = Address::new(self.get_data_from_addr(b));
+            self.set_position(pos);
+        } else {
+            self.stats.branches_not_taken += 1;
+            self.step_n(3);
+        }
+    }
+    fn jmp_false(&mut self, a: Address, b: Address) {
+        debug!(
+            "{} {}: {} {}",
+            &self.current_address,
+            "jf".magenta(),
+            &a,
+            &b
+        );
+        if self.get_data_from_addr(a) == 0 {
+            self.stats.branches_taken += 1;
+            let pos = Address::new(self.get_data_from_addr(b));
+            self.set_position(pos);
+        } else {
+            self.stats.branches_not_taken += 1;
+            self.step_n(3);
+        }
+    }
+    fn set_register(&mut self, a: Address, b: Address) {
+        debug!(
+            "{} {}: {} {}",
+            &self.current_address,
+            "set".magenta(),
+            &a,
+            &b
+        );
+        let reg_value = self.get_value_from_addr(&a);
+        let reg = pack_raw_value(reg_value);
+        assert!(
+            reg.is_register(),
+            "obtained value cannot be used as register"
+        );
+        let raw_value = self.get_value_from_addr(&b);
+        let val = pack_raw_value(raw_value);
+        self.set_value_to_register(reg, val);
+        self.step_n(3);
+    }
+    /// This method sets data value of the second argument to the register specified in first
+    /// argument
+    fn set_value_to_register(&mut self, reg: Data, val: Data) {
+        trace!("setting value: {} to register: {}", val, reg);
+        assert!(
+            reg.is_register(),
+            "obtained value cannot be used as register"
+        );
+        // Ensure that data is resolved, to prevent setting register to register
+        let literal = self.unpack_data(val);
+        // assert!(
+        //     val.is_literal(),
+        //     "obtained value cannot be used as a literal value"
+        // );
+        if let Data::Register(r) = reg {
+            self.store_raw_value_to_register(r, literal);
+        } else {
+            panic!("failed to unpack register and its value")
+        }
+    }
+
+    fn store_raw_value_to_register(&mut self, register_number: usize, value: u16) {
+        assert!(register_number < 8);
+        assert!(value < MAX + 8);
+        if value >= MAX
+            && !self.check_value(FaultKind::InvalidRegisterValue {
+                register: register_number,
+                value,
+            })
+        {
+            return;
+        }
+        trace!("storing value {} to register {}", value, register_number);
+        self.registers[register_number] = value;
+    }
+
+    fn add(&mut self, a: Address, b: Address, c: Address) {
+        self.do_arithmetic_operation(a, b, c, ArithmeticOperations::Add);
+    }
+
+    fn do_arithmetic_on_values(
+        &mut self,
+        reg: Data,
+        v1: Data,
+        v2: Option<Data>,
+        op: ArithmeticOperations,
+    ) {
+        // operations add mult mod and or not
+        trace!(
+            "   storing result of {} operation on {} and {:?} to {}",
+            op.get_instruction_name(),
+            v1,
+            v2,
+            reg
+        );
+
+        assert!(
+            reg.is_register(),
+            "first argument value cannot be used as register"
+        );
+        let val1 = self.unpack_data(v1);
+        if let Data::Register(r) = reg {
+            let second = || {
+                self.unpack_data(v2.unwrap_or_else(|| {
+                    panic!(
+                        "second argumemnt for {} operation is required, but None was provided",
+                        op
+                    )
+                }))
+            };
+            let result = match op {
+                ArithmeticOperations::Add => (val1 + second()) % MAX,
+                ArithmeticOperations::Multiply => (val1 as u64 * second() as u64) as u16 % MAX,
+                ArithmeticOperations::And => (val1 & second()) % MAX,
+                ArithmeticOperations::Or => (val1 | second()) % MAX,
+                ArithmeticOperations::Not => {
+                    trace!(
+                        "   performint bitwise negation operation ~ (!) on {} ({:#b})",
+                        val1, val1
+                    );
+                    let result = (!val1) % MAX;
+                    trace!("   got negation result {} ({:#b})", result, result);
+                    result
+                }
+                ArithmeticOperations::Modulo => (val1 % second()) % MAX,
+            };
+            trace!(
+                "   got arithmetic ops result {} {:#x} {:#b}",
+                result, result, result
+            );
+            self.store_raw_value_to_register(r, result);
+        } else {
+            panic!("cannot unpack values and register for add operation");
+        }
+    }
+
+    fn do_arithmetic_operation(
+        &mut self,
+        a: Address,
+        b: Address,
+        c: Address,
+        op: ArithmeticOperations,
+    ) {
+        debug!(
+            "{} {}: {} {} {}",
+            &self.current_address,
+            op.get_instruction_name().magenta(),
+            &a,
+            &b,
+            &c
+        );
+        let reg = pack_raw_value(self.get_value_from_addr(&a));
+        let value1 = pack_raw_value(self.get_value_from_addr(&b));
+        let value2 = pack_raw_value(self.get_value_from_addr(&c));
+        self.do_arithmetic_on_values(reg, value1, Some(value2), op);
+        self.step_n(4);
+    }
+    fn mult(&mut self, a: Address, b: Address, c: Address) {
+        self.do_arithmetic_operation(a, b, c, ArithmeticOperations::Multiply);
+    }
+    fn modulo(&mut self, a: Address, b: Address, c: Address) {
+        self.do_arithmetic_operation(a, b, c, ArithmeticOperations::Modulo);
+    }
+    fn and(&mut self, a: Address, b: Address, c: Address) {
+        self.do_arithmetic_operation(a, b, c, ArithmeticOperations::And);
+    }
+    fn or(&mut self, a: Address, b: Address, c: Address) {
+        self.do_arithmetic_operation(a, b, c, ArithmeticOperations::Or);
+    }
+    fn not(&mut self, a: Address, b: Address) {
+        debug!(
+            "{} {}: {} {}",
+            &self.current_address,
+            "not".magenta(),
+            &a,
+            &b
+        );
+        let reg = pack_raw_value(self.get_value_from_addr(&a));
+        let value1 = pack_raw_value(self.get_value_from_addr(&b));
+        self.do_arithmetic_on_values(reg, value1, None, ArithmeticOperations::Not);
+        self.step_n(3);
+    }
+
+    fn eq(&mut self, a: Address, b: Address, c: Address) {
+        debug!(
+            "{} {}: {} {} {}",
+            &self.current_address,
+            "eq".magenta(),
+            &a,
+            &b,
+            &c
+        );
+        let reg = pack_raw_value(self.get_value_from_addr(&a));
+        let value1 = pack_raw_value(self.get_value_from_addr(&b));
+        let value2 = pack_raw_value(self.get_value_from_addr(&c));
+        if self.store_equality(reg, value1, value2) {
+            trace!("successfully stored positive result of comparison");
+        } else {
+            trace!("successfully stored negative result of comparison");
+        }
+        self.step_n(4);
+    }
+
+    fn store_equality(&mut self, reg: Data, v1: Data, v2: Data) -> bool {
+        trace!(
+            " storing result of eq operation of {} and {} to {}",
+            v1, v2, reg
+        );
+        assert!(
+            reg.is_register(),
+            "first argument value cannot be used as register"
+        );
+        let val1 = self.unpack_data(v1);
+        let val2 = self.unpack_data(v2);
+        trace!(" comparing values {} and {}", val1, val2);
+        if let Data::Register(r) = reg {
+            if val1 == val2 {
+                self.store_raw_value_to_register(r, 1);
+                true
+            } else {
+                self.store_raw_value_to_register(r, 0);
+                false
+            }
+        } else {
+            panic!("cannot unpack values and register for add operation");
+        }
+    }
+
+    /// This method applies the strictness to the invalid value. It returns whether the value may be
+    /// stored
+    fn check_value(&mut self, kind: FaultKind) -> bool {
+        match self.strictness {
+            ValueStrictness::Permit => true,
+            ValueStrictness::Warn => {
+                let violation = self.fault_at(kind);
+                warn!("{}", violation);
+                if self.violations.len() < MAX_VIOLATIONS {
+                    self.violations.push(violation);
+                }
+                true
+            }
+            ValueStrictness::Reject => {
+                self.raise_fault(kind);
+                false
+            }
+        }
+    }
+    fn fault_at(&self, kind: FaultKind) -> Fault {
+        Fault {
+            kind,
+            position: self.current_address.0,
+            backtrace: self
+                .calls
+                .iter()
+                .rev()
+                .take(BACKTRACE_DEPTH)
+                .copied()
+                .collect(),
+        }
+    }
+    /// This method halts the machine because of the spec violation
+    fn raise_fault(&mut self, kind: FaultKind) {
+        let fault = self.fault_at(kind);
+        error!("{}", fault);
+        self.fault = Some(fault);
+        self.halt = true;
+    }
+    fn push_to_stack(&mut self, val: u16) -> bool {
+        if let Some(limit) = self.stack_limit
+            && self.stack.len() >= limit
+        {
+            self.raise_fault(FaultKind::Overflow(limit));
+            return false;
+        }
+        trace!("    pushing {} to stack", val);
+        self.stack.push_back(val);
+        self.stats.stack_high_water = self.stats.stack_high_water.max(self.stack.len());
+        true
+    }
+    fn pop_from_stack(&mut self) -> Option<u16> {
+        let val = self.stack.pop_back()?;
+        trace!("    popped value {} from stack", val);
+        Some(val)
+    }
+    fn push(&mut self, a: Address) {
+        debug!("{} {}: {}", &self.current_address, "push".magenta(), &a);
+        // Here used to be a stack bug.
+        // IMPORTANT! Befor pushing data to stack the data should be resolved from registers!
+        let val = self.get_data_from_addr(a);
+        if !self.push_to_stack(val) {
+            return;
+        }
+        trace!("pushed value {} to stack", val);
+        self.step_n(2);
+    }
+
+    fn pop(&mut self, a: Address) {
+        debug!("{} {}: {}", &self.current_address, "pop".magenta(), &a);
+        // empty stack = error
+        let Some(val) = self.pop_from_stack() else {
+            self.raise_fault(FaultKind::Underflow);
+            return;
+        };
+        trace!("popped value {} from stack", val);
+        self.calls.truncate(self.stack.len());
+        self.set_memory_by_address(a, val);
+        self.step_n(2);
+    }
+
+    fn set_memory_by_address(&mut self, a: Address, val: u16) {
+        trace!(" setting memory by address {} to {}", &a, val);
+        let r_data = pack_raw_value(self.get_value_from_addr(&a));
+        let v_data = pack_raw_value(val);
+        match r_data {
+            Data::Register(r) => {
+                trace!(
+                    " following mem address and setting register {} to value {}",
+                    r, val
+                );
+                self.set_value_to_register(r_data, v_data);
+            }
+            Data::LiteralValue(_) => {
+                let ptr: Ptr = (&a).into();
+                let raw_value = self.unpack_data(v_data);
+                trace!(
+                    "setting literal value {} (orig: {}) to memory address {} (Ptr: {})",
+                    raw_value, val, a, ptr
+                );
+                self.set_memory(ptr, raw_value);
+            }
+        }
+    }
+    fn set_memory(&mut self, ptr: Ptr, val: u16) {
+        trace!(
+            "  setting value: {} to memory raw ptr: {}({:#x})",
+            val, ptr, ptr
+        );
+        assert!(
+            validate_value(val),
+            "value bigger than 32768 + 8 is invalid"
+        );
+        assert_eq!(
+            (ptr % 2),
+            0,
+            "first pointer must point to an even address"
+        );
+        if val >= MAX
+            && !self.check_value(FaultKind::InvalidMemoryValue {
+                address: ptr / 2,
+                value: val,
+            })
+        {
+            return;
+        }
+        let (lb, hb) = decompose_value(val);
+        self.memory[ptr as usize] = lb;
+        self.memory[ptr as usize + 1] = hb;
+    }
+
+    fn gt(&mut self, a: Address, b: Address, c: Address) {
+        debug!(
+            "{} {}: {} {} {}",
+            &self.current_address,
+            "gt".magenta(),
+            &a,
+            &b,
+            &c
+        );
+        let reg = pack_raw_value(self.get_value_from_addr(&a));
+        let value1 = pack_raw_value(self.get_value_from_addr(&b));
+        let value2 = pack_raw_value(self.get_value_from_addr(&c));
+        if self.store_greater_than(reg, value1, value2) {
+            trace!("successfully stored positive result of comparison");
+        } else {
+            trace!("successfully stored negative result of comparison");
+        }
+        self.step_n(4);
+    }
+
+    fn store_greater_than(&mut self, reg: Data, v1: Data, v2: Data) -> bool {
+        trace!(
+            " storing result of gt operation of {} and {} to {}",
+            v1, v2, reg
+        );
+        assert!(
+            reg.is_register(),
+            "first argument value cannot be used as register"
+        );
+        let val1 = self.unpack_data(v1);
+        let val2 = self.unpack_data(v2);
+        trace!(" comparing values {} and {}", val1, val2);
+        if let Data::Register(r) = reg {
+            if val1 > val2 {
+                self.store_raw_value_to_register(r, 1);
+                true
+            } else {
+                self.store_raw_value_to_register(r, 0);
+                false
+            }
+        } else {
+            panic!("cannot unpack values and register for add operation");
+        }
+    }
+    fn call(&mut self, a: Address) {
+        debug!("{} {}: {}", &self.current_address, "call".magenta(), &a);
+        let next_addr = a.next();
+
+        trace!("got address {} and push it to stack", next_addr);
+        if !self.push_to_stack(next_addr.0) {
+            return;
+        }
+        self.calls.push(self.current_address.0);
+        let pos = Address::new(self.get_data_from_addr(a));
+        self.set_position(pos);
+    }
+    fn ret(&mut self) {
+        debug!("{} {}:", &self.current_address, "ret".magenta());
+        // empty stack = halt
+        let Some(addr) = self.pop_from_stack() else {
+            info!("{} returns with the empty stack", &self.current_address);
+            self.halt();
+            return;
+        };
+        self.calls.pop();
+        self.set_position(Address::new(addr));
+    }
+    fn rmem(&mut self, a: Address, b: Address) {
+        debug!(
+            "{} {}: {} {}",
+            &self.current_address,
+            "rmem".magenta(),
+            &a,
+            &b
+        );
+        let val_address = pack_raw_value(self.get_value_from_addr(&b));
+        let reg = pack_raw_value(self.get_value_from_addr(&a));
+        let address = Address::new(self.unpack_data(val_address));
+        let raw_value = self.get_value_from_addr(&address);
+        if raw_value >= MAX
+            && !self.check_value(FaultKind::InvalidMemoryValue {
+                address: address.0,
+                value: raw_value,
+            })
+        {
+            return;
+        }
+        let val = self.get_data(raw_value);
+        trace!("got {} and {} after packing", reg, val);
+        self.set_value_to_register(reg, pack_raw_value(val));
+        self.step_n(3);
+    }
+    fn wmem(&mut self, a: Address, b: Address) {
+        debug!(
+            "{} {}: {} {}",
+            &self.current_address,
+            "wmem".magenta(),
+            &a,
+            &b
+        );
+        let val = self.get_data_from_addr(b); //30000
+        let val_addr = self.get_data_from_addr(a); //20000
+        trace!(" value of b {} value of address from a {}", val, val_addr);
+        self.set_memory_by_address(Address::new(val_addr), val);
+        self.step_n(3);
+    }
+    /// This function is an implementation of the 'in' operational instruction
+    fn read_in(&mut self, a: Address, input: impl FnOnce() -> Option<u8>) -> StepEvent {
+        debug!("{} {}: {}", &self.current_address, "in".magenta(), &a);
+        let Some(c) = input() else {
+            // The instruction will be executed again, once the input is provided
+            trace!("there is no input available, waiting for input");
+            return StepEvent::AwaitingInput;
+        };
+        let reg = pack_raw_value(self.get_value_from_addr(&a));
+        let val = pack_raw_value(c.into());
+        self.set_value_to_register(reg, val);
+        self.at_prompt = false;
+        self.step_n(2);
+        StepEvent::Input(c as char)
+    }
+    /// This method executes a single instruction at the current address. The input callback is
+    /// called only by the 'in' instruction
+    pub fn execute(&mut self, input: impl FnOnce() -> Option<u8>) -> StepEvent {
+        if self.journal_size == 0 {
+            return self.execute_opcode(input);
+        }
+        let entry = self.journal_entry();
+        let event = self.execute_opcode(input);
+        // The instruction waiting for the input has changed nothing
+        if event != StepEvent::AwaitingInput {
+            if self.journal.len() >= self.journal_size {
+                self.journal.pop_front();
+            }
+            self.journal.push_back(entry);
+        }
+        event
+    }
+    /// This method saves the state, which the instruction at the current address may overwrite
+    fn journal_entry(&self) -> JournalEntry {
+        let opcode = self.word(self.current_address.0);
+        // wmem
+        let memory = (opcode == 16).then(|| {
+            let address = self.get_data_from_addr(self.current_address.add(1)) % MAX;
+            (address, self.word(address))
+        });
+        JournalEntry {
+            position: self.current_address.0,
+            registers: self.registers,
+            halt: self.halt,
+            fault: self.fault.clone(),
+            stack_len: self.stack.len(),
+            stack_top: self.stack.back().copied(),
+            calls_len: self.calls.len(),
+            // pop, ret
+            calls: matches!(opcode, 3 | 18).then(|| self.calls.clone()),
+            memory,
+            prompt_matched: self.prompt_matched,
+            at_prompt: self.at_prompt,
+            read_input: opcode == 20,
+        }
+    }
+    fn execute_opcode(&mut self, input: impl FnOnce() -> Option<u8>) -> StepEvent {
+        let current_val = self.get_value_from_addr(&self.current_address);
+        let v = self.get_data(current_val);
+        if let Some(count) = self.stats.opcodes.get_mut(v as usize) {
+            *count += 1;
+        }
+        match v {
+            0 => {
+                /*
+                halt: 0
+                  stop execution and terminate the program
+                */
+                self.halt();
+                return StepEvent::Halted;
+            }
+            1 => {
+                /*
+                set: 1 a b
+                  set register <a> to the value of <b>
+                */
+                self.set_register(self.current_address.add(1), self.current_address.add(2));
+            }
+            2 => {
+                /*
+                push: 2 a
+                  push <a> onto the stack
+                */
+                self.push(self.current_address.add(1));
+            }
+            3 => {
+                /*
+                pop: 3 a
+                  remove the top element from the stack and write it into <a>; empty stack = error
+                */
+                self.pop(self.current_address.add(1));
+            }
+            4 => {
+                /*
+                eq: 4 a b c
+                  set <a> to 1 if <b> is equal to <c>; set it to 0 otherwise
+                */
+                self.eq(
+                    self.current_address.add(1),
+                    self.current_address.add(2),
+                    self.current_address.add(3),
+                );
+            }
+            5 => {
+                /*
+                gt: 5 a b c
+                  set <a> to 1 if <b> is greater than <c>; set it to 0 otherwise
+                */
+                self.gt(
+                    self.current_address.add(1),
+                    self.current_address.add(2),
+                    self.current_address.add(3),
+                );
+            }
+            6 => {
+                /*
+                jmp: 6 a
+                  jump to <a>
+                */
+                self.jmp(self.current_address.add(1));
+            }
+            7 => {
+                /*
+                jt: 7 a b
+                  if <a> is nonzero, jump to <b>
+                */
+                self.jmp_true(self.current_address.add(1), self.current_address.add(2));
+            }
+            8 => {
+                /*
+                jf: 8 a b
+                  if <a> is zero, jump to <b>
+                */
+                self.jmp_false(self.current_address.add(1), self.current_address.add(2));
+            }
+            9 => {
+                /*
+                                    add: 9 a b c
+                  assign into <a> the sum of <b> and <c> (modulo 32768)
+                */
+                self.add(
+                    self.current_address.add(1),
+                    self.current_address.add(2),
+                    self.current_address.add(3),
+                );
+            }
+            10 => {
+                /*
+                                    mult: 10 a b c
+                  store into <a> the product of <b> and <c> (modulo 32768)
+                */
+
+                self.mult(
+                    self.current_address.add(1),
+                    self.current_address.add(2),
+                    self.current_address.add(3),
+                );
+            }
+            11 => {
+                /*
+                                    mod: 11 a b c
+                  store into <a> the remainder of <b> divided by <c>
+                */
+                self.modulo(
+                    self.current_address.add(1),
+                    self.current_address.add(2),
+                    self.current_address.add(3),
+                );
+            }
+            12 => {
+                /*
+                                    and: 12 a b c
+                  stores into <a> the bitwise and of <b> and <c>
+                */
+                self.and(
+                    self.current_address.add(1),
+                    self.current_address.add(2),
+                    self.current_address.add(3),
+                );
+            }
+            13 => {
+                /*
+                                    or: 13 a b c
+                  stores into <a> the bitwise or of <b> and <c>
+                */
+                self.or(
+                    self.current_address.add(1),
+                    self.current_address.add(2),
+                    self.current_address.add(3),
+                );
+            }
+            14 => {
+                /*
+                                    not: 14 a b
+                  stores 15-bit bitwise inverse of <b> in <a>
+                */
+                self.not(self.current_address.add(1), self.current_address.add(2));
+            }
+            15 => {
+                /*
+                                    rmem: 15 a b
+                  read memory at address <b> and write it to <a>
+                */
+                self.rmem(self.current_address.add(1), self.current_address.add(2));
+            }
+            16 => {
+                /*
+                                    wmem: 16 a b
+                  write the value from <b> into memory at address <a>
+                */
+                self.wmem(self.current_address.add(1), self.current_address.add(2));
+            }
+            17 => {
+                /*
+                    call: 17 a
+                  write the address of the next instruction to the stack and jump to <a>
+                */
+                self.call(self.current_address.add(1));
+            }
+            18 => {
+                /*
+                    ret: 18
+                  remove the top element from the stack and jump to it; empty stack = halt
+                */
+                self.ret();
+            }
+            19 => {
+                /*
+                    out: 19 a
+                  write the character represented by ascii code <a> to the terminal
+                */
+                return self.out(self.current_address.add(1));
+            }
+            20 => {
+                /*
+                    in: 20 a
+                  read a character from the terminal and write its ascii code to <a>; it can be assumed that once input starts, it will continue until a newline is encountered; this means that you can safely read whole lines from the keyboard and trust that they will be fully read
+                */
+                let event = self.read_in(self.current_address.add(1), input);
+                // The instruction is retried, once the input is available
+                if event == StepEvent::AwaitingInput {
+                    self.stats.opcodes[20] -= 1;
+                }
+                return event;
+            }
+            21 => {
+                /*
+                    noop: 21
+                  no operation
+
+                            unimplemented!("main loop is not implemented yet");
+                */
+                // TODO: Probably it worth to add fuctions for each operation...
+                self.noop();
+            }
+            instruction => panic!("got invalid instruction {}", instruction),
+        }
+        // The instructions halt the machine on the faults
+        if self.halt {
+            return StepEvent::Halted;
+        }
+        StepEvent::None
+        /*
+        == hints ==
+        - Start with operations 0, 19, and 21.
+        - Here's a code for the challenge website: ZjuGobDBMEiN
+        - The program "9,32768,32769,4,19,32768" occupies six memory addresses and should:
+          - Store into register 0 the sum of 4 and the value contained in register 1.
+          - Output to the terminal the character with the ascii code contained in register 0.
+
+        == opcode listing ==
+        halt: 0
+          stop execution and terminate the program
+        set: 1 a b
+          set register <a> to the value of <b>
+        push: 2 a
+          push <a> onto the stack
+        pop: 3 a
+          remove the top element from the stack and write it into <a>; empty stack = error
+        eq: 4 a b c
+          set <a> to 1 if <b> is equal to <c>; set it to 0 otherwise
+        gt: 5 a b c
+          set <a> to 1 if <b> is greater than <c>; set it to 0 otherwise
+        jmp: 6 a
+          jump to <a>
+        jt: 7 a b
+          if <a> is nonzero, jump to <b>
+        jf: 8 a b
+          if <a> is zero, jump to <b>
+        add: 9 a b c
+          assign into <a> the sum of <b> and <c> (modulo 32768)
+        mult: 10 a b c
+          store into <a> the product of <b> and <c> (modulo 32768)
+        mod: 11 a b c
+          store into <a> the remainder of <b> divided by <c>
+        and: 12 a b c
+          stores into <a> the bitwise and of <b> and <c>
+        or: 13 a b c
+          stores into <a> the bitwise or of <b> and <c>
+        not: 14 a b
+          stores 15-bit bitwise inverse of <b> in <a>
+        rmem: 15 a b
+          read memory at address <b> and write it to <a>
+        wmem: 16 a b
+          write the value from <b> into memory at address <a>
+        call: 17 a
+          write the address of the next instruction to the stack and jump to <a>
+        ret: 18
+          remove the top element from the stack and jump to it; empty stack = halt
+        out: 19 a
+          write the character represented by ascii code <a> to the terminal
+        in: 20 a
+          read a character from the terminal and write its ascii code to <a>; it can be assumed that once input starts, it will continue until a newline is encountered; this means that you can safely read whole lines from the keyboard and trust that they will be fully read
+        noop: 21
+          no operation
+        */
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_the_multibyte_input_once_it_is_complete() {
+        let mut decoder = InputDecoder::default();
+        let decoded: Vec<Vec<char>> = "aé€"
+            .bytes()
+            .map(|b| decoder.push(char::from(b)))
+            .collect();
+        assert_eq!(
+            decoded,
+            [vec!['a'], vec![], vec!['é'], vec![], vec![], vec!['€']]
+        );
+    }
+
+    #[test]
+    fn replaces_the_invalid_input() {
+        let mut decoder = InputDecoder::default();
+        assert_eq!(decoder.push('\u{ff}'), ['\u{fffd}']);
+        assert_eq!(decoder.push('a'), ['a']);
+    }
+
+    #[test]
+    fn steps_back_up_to_the_input_read() {
+        // in r0, add r1 r1 1, add r1 r1 1, halt
+        let rom: Vec<u8> = [20, MAX, 9, MAX + 1, MAX + 1, 1, 9, MAX + 1, MAX + 1, 1, 0]
+            .iter()
+            .flat_map(|w: &u16| w.to_le_bytes())
+            .collect();
+        let mut machine = Machine::new();
+        machine.set_journal_size(10);
+        machine.load_rom(&rom).unwrap();
+        assert_eq!(machine.step_back(), Err(StepBackLimit::JournalExhausted));
+        let mut input = Some(b'x');
+        while !machine.is_halted() {
+            machine.execute(|| input.take());
+        }
+        assert_eq!(machine.journal_len(), 3);
+        assert_eq!(machine.step_back(), Ok(()));
+        assert_eq!(machine.step_back(), Ok(()));
+        assert_eq!(machine.step_back(), Ok(()));
+        assert_eq!(machine.registers()[1], 0);
+        assert_eq!(machine.step_back(), Err(StepBackLimit::InputRead));
+        assert_eq!(machine.position(), 2);
+        assert_eq!(machine.registers()[0], u16::from(b'x'));
+    }
+}
@@ -4,36 +4,46 @@ use synacor_challenge_v1::config::*;
 use synacor_challenge_v1::*;
 
 fn main() {
-    println!(
-        "{}",
-        "Welcome to maskimko's SYNACOR challenge solution!"
-            .green()
-            .underline()
-    );
-    env_logger::init();
-    // load configuration
+    // load configuration, the logger is started once the profile gives its level
+    let policy = ErrorPolicy::from_args();
     let conf: Configuration = match parse_args() {
         Ok(c) => c,
         Err(e) => {
-            let mut c = Configuration::default();
+            // The log would get mixed with the JSON report
+            init_logging(policy.json.then_some("off"), None);
             error!("Failed to parse configuration. Error: {}", e);
-            warn!(
-                "Failed to parse configuration. Fallback to default value {:?}",
-                c
-            );
-            if let Err(read_error) = c.read_in() {
-                error!(
-                    "Failed to load the default configuration. Aborting execution. Error: {}",
-                    read_error
-                );
-                std::process::exit(2);
+            eprintln!("{}", policy.report(&*e, ExitStatus::ConfigError.code()));
+            if !policy.lenient {
+                std::process::exit(ExitStatus::ConfigError.code());
+            }
+            warn!("falling back to the default configuration");
+            match fallback_configuration() {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("{}", policy.report(&*e, ExitStatus::ConfigError.code()));
+                    std::process::exit(ExitStatus::ConfigError.code());
+                }
             }
-            c
         }
     };
+    init_logging(conf.log_level().as_deref(), Some(conf.color()));
+    // Nothing but the JSON lines goes to stdout in the JSON output format
+    let quiet = conf.quiet() || conf.output_format() == OutputFormat::Json;
+    // The banner is colored once the color choice is known. It goes to stderr, so stdout holds
+    // only the output of the subcommand, e.g. the map or the disassembly
+    if !quiet {
+        eprintln!(
+            "{}",
+            "Welcome to maskimko's SYNACOR challenge solution!"
+                .green()
+                .underline()
+        );
+    }
     // launch VM
-    match run(conf) {
-        Ok(()) => println!(
+    let result = run(conf);
+    match &result {
+        Ok(()) if quiet => {}
+        Ok(()) => eprintln!(
             "{}",
             "Challenge program finished successfully"
                 .green()
@@ -41,4 +51,5 @@ fn main() {
         ),
         Err(e) => eprintln!("Error: {}", e),
     };
+    std::process::exit(ExitStatus::of(&result).code());
 }
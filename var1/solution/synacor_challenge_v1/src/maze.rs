@@ -0,0 +1,1701 @@
+use crate::events::VmEvent;
+use crate::parser::{ResponsePartsRef, UseEffect};
+use crate::plugin::VmPlugin;
+use crate::style::Colorize;
+use log::{debug, info, trace};
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+#[cfg(feature = "state")]
+pub mod export;
+pub mod parallel;
+
+/// A room of the game world discovered during the play session
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub struct Node {
+    pub id: u16,
+    pub title: String,
+    pub description: String,
+    pub exits: Vec<String>,
+    pub items: Vec<String>,
+    /// The annotations made by the player with /note, e.g. the riddle hints
+    #[cfg_attr(feature = "state", serde(default))]
+    pub notes: Vec<String>,
+    /// All the ways the room is shown, once the rooms shown differently are merged with
+    /// /merge_nodes. Empty for the room shown in the single way
+    #[cfg_attr(feature = "state", serde(default))]
+    pub looks: Vec<RoomLook>,
+}
+
+/// The way the room is shown by the game, e.g. before and after the lantern is lit
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoomLook {
+    pub title: String,
+    pub description: String,
+    pub exits: Vec<String>,
+}
+
+impl Node {
+    fn matches(&self, response: &ResponsePartsRef) -> bool {
+        let matches = |title: &str, description: &str, exits: &[String]| {
+            response.title == Some(title)
+                && response.description == description
+                && response.exits == exits
+        };
+        if self.looks.is_empty() {
+            return matches(&self.title, &self.description, &self.exits);
+        }
+        self.looks
+            .iter()
+            .any(|l| matches(&l.title, &l.description, &l.exits))
+    }
+
+    fn look(&self) -> RoomLook {
+        RoomLook {
+            title: self.title.clone(),
+            description: self.description.clone(),
+            exits: self.exits.clone(),
+        }
+    }
+
+    /// Returns the signatures of all the ways the room is shown
+    fn signatures(&self) -> Vec<u64> {
+        if self.looks.is_empty() {
+            return vec![room_signature(&self.title, &self.description, &self.exits)];
+        }
+        self.looks
+            .iter()
+            .map(|l| room_signature(&l.title, &l.description, &l.exits))
+            .collect()
+    }
+}
+
+/// This function hashes the parts of the response, which identify the room. The exits hash the same
+/// both borrowed and owned, so the stored nodes match the parsed responses
+fn room_signature<S: Hash>(title: &str, description: &str, exits: &[S]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    title.hash(&mut hasher);
+    description.hash(&mut hasher);
+    exits.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// This function hashes the text with FNV-1a. Unlike the room signature, the hash is stable between
+/// builds, so it can be saved together with the map
+fn stable_hash<'a>(parts: impl IntoIterator<Item = &'a str>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        // The separator keeps ["ab", "c"] and ["a", "bc"] apart
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// This function hashes the inventory regardless of the order of the items, the aliases are
+/// counted as the items they stand for
+fn inventory_hash(items: &[String], aliases: &ItemAliases) -> u64 {
+    let sorted: BTreeSet<&str> = items.iter().map(|item| aliases.resolve(item)).collect();
+    stable_hash(sorted)
+}
+
+/// The index of the interned exit name. The same few exit names are shared by all the rooms, so
+/// the passages are keyed by it instead of the name itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+struct ExitId(u16);
+
+/// The known passage between two rooms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge<'a> {
+    pub from: u16,
+    pub exit: &'a str,
+    pub to: u16,
+}
+
+/// The jump between two rooms caused by using the item, e.g. the teleporter. It is not a passage,
+/// so it is never taken by the exploration and there is no way back along it
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub struct Teleport {
+    pub from: u16,
+    pub item: String,
+    pub to: u16,
+}
+
+/// The long text found in the game, e.g. the book read in the room
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub struct Document {
+    pub item: String,
+    /// The room, where the text was read
+    pub room: Option<u16>,
+    pub text: String,
+}
+
+/// The arrival to the room along the path actually taken by the player
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrailStep {
+    pub room: u16,
+    /// The exit of the previous room, None for the starting room and for the arrivals which are
+    /// not caused by the movement commands
+    pub exit: Option<String>,
+}
+
+/// The priorities of the automatic exploration. It can be parsed from the comma separated
+/// 'key=value' pairs, e.g. 'take_items=false,edge_visit_limit=10'
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExplorationStrategy {
+    /// Take the unexplored exits first and head to the closest room with them, otherwise just
+    /// wander around randomly
+    pub prefer_unexplored: bool,
+    /// Take the items lying in the room before leaving it
+    pub take_items: bool,
+    /// Do not return to the previous room while wandering, unless there is no other way
+    pub avoid_backtracking: bool,
+    /// The exit is not used anymore once it has been taken this number of times
+    pub edge_visit_limit: u32,
+    /// Plan the tour over all unexplored exits at once instead of looking for the closest one
+    /// after every move
+    pub plan_route: bool,
+    /// Take only the exits proven to be safe: the ones taken before, or any of them while the light
+    /// is carried or the room does not warn of the danger
+    #[cfg_attr(feature = "state", serde(default))]
+    pub safe_only: bool,
+}
+
+impl Default for ExplorationStrategy {
+    fn default() -> Self {
+        ExplorationStrategy {
+            prefer_unexplored: true,
+            take_items: true,
+            avoid_backtracking: true,
+            edge_visit_limit: 25,
+            plan_route: true,
+            safe_only: false,
+        }
+    }
+}
+
+impl fmt::Display for ExplorationStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "prefer_unexplored={},take_items={},avoid_backtracking={},edge_visit_limit={},plan_route={},safe_only={}",
+            self.prefer_unexplored,
+            self.take_items,
+            self.avoid_backtracking,
+            self.edge_visit_limit,
+            self.plan_route,
+            self.safe_only
+        )
+    }
+}
+
+impl FromStr for ExplorationStrategy {
+    type Err = String;
+
+    /// The keys which are not mentioned keep their default values
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut strategy = ExplorationStrategy::default();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let Some((key, value)) = pair.split_once('=') else {
+                return Err(format!("expected 'key=value', got '{}'", pair));
+            };
+            let value = value.trim();
+            let flag = || {
+                value
+                    .parse::<bool>()
+                    .map_err(|_| format!("'{}' expects true or false, got '{}'", key, value))
+            };
+            match key.trim() {
+                "prefer_unexplored" => strategy.prefer_unexplored = flag()?,
+                "take_items" => strategy.take_items = flag()?,
+                "avoid_backtracking" => strategy.avoid_backtracking = flag()?,
+                "plan_route" => strategy.plan_route = flag()?,
+                "safe_only" => strategy.safe_only = flag()?,
+                "edge_visit_limit" => {
+                    strategy.edge_visit_limit = value
+                        .parse()
+                        .map_err(|_| format!("'{}' expects a number, got '{}'", key, value))?
+                }
+                unknown => return Err(format!("unknown exploration strategy key '{}'", unknown)),
+            }
+        }
+        Ok(strategy)
+    }
+}
+
+/// The items counted as the other ones by the inventory, e.g. the lit lantern as the lantern, so
+/// the transformed item does not look like the new one. It can be parsed from the comma separated
+/// 'alias=item' pairs, e.g. 'lit lantern=lantern'
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "state", derive(serde::Serialize, serde::Deserialize))]
+pub struct ItemAliases(BTreeMap<String, String>);
+
+impl ItemAliases {
+    /// Returns the item the given one is counted as
+    pub fn resolve<'a>(&'a self, item: &'a str) -> &'a str {
+        self.0.get(item).map_or(item, String::as_str)
+    }
+}
+
+impl Default for ItemAliases {
+    fn default() -> Self {
+        ItemAliases(BTreeMap::from([(
+            "lit lantern".to_string(),
+            "lantern".to_string(),
+        )]))
+    }
+}
+
+impl fmt::Display for ItemAliases {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs: Vec<String> = self
+            .0
+            .iter()
+            .map(|(alias, item)| format!("{}={}", alias, item))
+            .collect();
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+impl FromStr for ItemAliases {
+    type Err = String;
+
+    /// The given aliases replace the default ones, so the empty string turns them off
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut aliases = BTreeMap::new();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let Some((alias, item)) = pair.split_once('=') else {
+                return Err(format!("expected 'alias=item', got '{}'", pair));
+            };
+            aliases.insert(alias.trim().to_string(), item.trim().to_string());
+        }
+        Ok(ItemAliases(aliases))
+    }
+}
+
+/// This structure builds the map of the game world from the parsed game responses. When it is
+/// restored from the saved state, the random generator starts over from the seed
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "state",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(remote = "Self")
+)]
+pub struct MazeAnalyzer {
+    nodes: Vec<Node>,
+    // room signature -> the rooms with it. Different rooms may share the hash, but hardly ever.
+    // The hash is not guaranteed to be stable between builds, so it is rebuilt after restoring
+    #[cfg_attr(feature = "state", serde(skip))]
+    rooms_by_signature: HashMap<u64, Vec<u16>>,
+    exit_names: Vec<String>,
+    exit_ids: HashMap<String, ExitId>,
+    // (source node, exit) -> destination node
+    #[cfg_attr(feature = "state", serde(with = "crate::state::pairs"))]
+    edges: HashMap<(u16, ExitId), u16>,
+    // The jumps are kept apart from the passages, so they are not used to find the paths
+    #[cfg_attr(feature = "state", serde(default))]
+    teleports: Vec<Teleport>,
+    #[cfg_attr(feature = "state", serde(default))]
+    documents: Vec<Document>,
+    current: Option<u16>,
+    previous: Option<u16>,
+    pending_command: Option<String>,
+    // The item, which has just teleported the player
+    #[cfg_attr(feature = "state", serde(skip))]
+    pending_teleport: Option<String>,
+    items_seen: BTreeSet<String>,
+    strategy: ExplorationStrategy,
+    // How many times every exit was taken
+    #[cfg_attr(feature = "state", serde(with = "crate::state::pairs"))]
+    edge_visits: HashMap<(u16, ExitId), u32>,
+    // (source node, exit, inventory hash) -> the hash of the response, which has not shown any room,
+    // e.g. the door does not open without the key. The exit is not tried again with the same items
+    #[cfg_attr(feature = "state", serde(default, with = "crate::state::pairs"))]
+    edge_outcomes: HashMap<(u16, ExitId, u64), u64>,
+    // The hash of the items carried, the aliases counted as the items they stand for
+    #[cfg_attr(feature = "state", serde(default))]
+    inventory_hash: u64,
+    #[cfg_attr(feature = "state", serde(default))]
+    item_aliases: ItemAliases,
+    // Whether the items carried include the light, e.g. the lit lantern
+    #[cfg_attr(feature = "state", serde(default))]
+    light: bool,
+    // The exits skipped as not proven to be safe, each of them is logged once
+    #[cfg_attr(feature = "state", serde(skip))]
+    unsafe_exits: HashSet<(u16, ExitId)>,
+    // The cost of taking the exit set with /set_edge_cost, e.g. for the dangerous passages. The
+    // passages not listed here cost one move
+    #[cfg_attr(feature = "state", serde(default, with = "crate::state::pairs"))]
+    edge_costs: HashMap<(u16, ExitId), u32>,
+    // The items the exploration has already tried to take in the room
+    take_attempts: HashSet<(u16, String)>,
+    // The planned tour as (expected room, exit) steps. The room is unknown right after taking the
+    // unexplored exit, so the way back is checked only by the exit presence
+    route: VecDeque<(Option<u16>, String)>,
+    // Every arrival to a room in the order of the play session
+    trail: Vec<TrailStep>,
+    // The exploration picks the unexplored exits randomly, the seed makes it reproducible
+    seed: u64,
+    #[cfg_attr(feature = "state", serde(skip, default = "unseeded_rng"))]
+    rng: StdRng,
+}
+
+#[cfg(feature = "state")]
+fn unseeded_rng() -> StdRng {
+    StdRng::seed_from_u64(0)
+}
+
+#[cfg(feature = "state")]
+impl serde::Serialize for MazeAnalyzer {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        MazeAnalyzer::serialize(self, s)
+    }
+}
+
+#[cfg(feature = "state")]
+impl<'de> serde::Deserialize<'de> for MazeAnalyzer {
+    /// The skipped fields are restored after the derived deserialization
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let mut maze = MazeAnalyzer::deserialize(d)?;
+        maze.index_signatures();
+        maze.rng = StdRng::seed_from_u64(maze.seed);
+        Ok(maze)
+    }
+}
+
+impl Default for MazeAnalyzer {
+    fn default() -> Self {
+        MazeAnalyzer::with_seed(rand::rng().random())
+    }
+}
+
+/// This function converts the movement command to the exit name, e.g. 'go north' -> 'north'
+pub fn exit_of_command(command: &str) -> &str {
+    let command = command.trim();
+    command.strip_prefix("go ").map(str::trim).unwrap_or(command)
+}
+
+// The words of the rooms, where the player may not move in the dark, e.g. 'It is pitch black. You
+// are likely to be eaten by a grue.'
+const DANGER_MARKS: [&str; 3] = ["grue", "pitch black", "hopelessly lost"];
+/// The prefix of the items giving the light
+pub const LIGHT_PREFIX: &str = "lit ";
+// The exit leading the way the player has come
+const RETREAT: &str = "back";
+
+// The cost of the passage, which is not set with /set_edge_cost
+const DEFAULT_EDGE_COST: u32 = 1;
+// How many times the sequence has to repeat at the end, before it is reported as the loop
+const LOOP_REPEATS: usize = 3;
+// The longest loop looked for
+const LOOP_MAX_PERIOD: usize = 8;
+
+/// This function finds the shortest sequence repeated at least LOOP_REPEATS times in a row at the
+/// end of the given one, e.g. the player going back and forth between two rooms, or lighting the
+/// lantern and putting it out over and over again
+pub fn find_loop<T: PartialEq>(sequence: &[T]) -> Option<&[T]> {
+    (1..=LOOP_MAX_PERIOD).find_map(|period| {
+        let len = period * LOOP_REPEATS;
+        let tail = sequence.get(sequence.len().checked_sub(len)?..)?;
+        let cycle = &tail[..period];
+        tail.chunks(period)
+            .all(|chunk| chunk == cycle)
+            .then_some(cycle)
+    })
+}
+
+impl MazeAnalyzer {
+    pub fn new() -> Self {
+        MazeAnalyzer::default()
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        MazeAnalyzer {
+            nodes: vec![],
+            rooms_by_signature: HashMap::new(),
+            exit_names: vec![],
+            exit_ids: HashMap::new(),
+            edges: HashMap::new(),
+            teleports: vec![],
+            documents: vec![],
+            current: None,
+            previous: None,
+            pending_command: None,
+            pending_teleport: None,
+            items_seen: BTreeSet::new(),
+            strategy: ExplorationStrategy::default(),
+            edge_visits: HashMap::new(),
+            edge_outcomes: HashMap::new(),
+            inventory_hash: inventory_hash(&[], &ItemAliases::default()),
+            item_aliases: ItemAliases::default(),
+            light: false,
+            unsafe_exits: HashSet::new(),
+            edge_costs: HashMap::new(),
+            take_attempts: HashSet::new(),
+            route: VecDeque::new(),
+            trail: vec![],
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// This method restarts the random generator of the exploration with the given seed
+    pub fn set_seed(&mut self, seed: u64) {
+        debug!("seeding maze exploration with {}", seed);
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn current_node(&self) -> Option<&Node> {
+        self.current.map(|id| &self.nodes[id as usize])
+    }
+
+    pub fn node_by_id(&self, id: u16) -> Option<&Node> {
+        self.nodes.get(id as usize)
+    }
+
+    /// Returns the known passages leading out of the room in the order of its exits
+    pub fn neighbors(&self, id: u16) -> Vec<Edge<'_>> {
+        let Some(node) = self.node_by_id(id) else {
+            return vec![];
+        };
+        node.exits
+            .iter()
+            .filter_map(|exit| {
+                self.destination(id, exit).map(|to| Edge {
+                    from: id,
+                    exit: exit.as_str(),
+                    to,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the first known passage from one room to the other
+    pub fn edge_between(&self, from: u16, to: u16) -> Option<Edge<'_>> {
+        self.neighbors(from).into_iter().find(|e| e.to == to)
+    }
+
+    /// Returns all known passages as (source, exit, destination) triples
+    pub fn edges(&self) -> impl Iterator<Item = (u16, &str, u16)> {
+        self.edges
+            .iter()
+            .map(|((from, exit), to)| (*from, self.exit_name(*exit), *to))
+    }
+
+    /// Returns all known jumps between the rooms
+    pub fn teleports(&self) -> &[Teleport] {
+        &self.teleports
+    }
+
+    /// Returns the long texts in the order they were read
+    pub fn documents(&self) -> &[Document] {
+        &self.documents
+    }
+
+    /// This method attaches the long text of the item to the current room. The text read again is
+    /// not stored twice
+    pub fn attach_document(&mut self, item: &str, text: String) {
+        let document = Document {
+            item: item.to_string(),
+            room: self.current,
+            text,
+        };
+        if self.documents.contains(&document) {
+            return;
+        }
+        debug!(
+            "attaching document '{}' of {} bytes to room {:?}",
+            item,
+            document.text.len(),
+            document.room
+        );
+        self.documents.push(document);
+    }
+
+    fn index_signatures(&mut self) {
+        self.rooms_by_signature.clear();
+        for node in &self.nodes {
+            for signature in node.signatures() {
+                self.rooms_by_signature
+                    .entry(signature)
+                    .or_default()
+                    .push(node.id);
+            }
+        }
+    }
+
+    /// This method replaces every room id known to the analyzer with the mapped one. The passages,
+    /// visits and outcomes of the rooms mapped to the same id are joined
+    fn remap_rooms(&mut self, map: impl Fn(u16) -> u16) {
+        let mut edges = HashMap::new();
+        for ((from, exit), to) in self.edges.drain() {
+            edges.entry((map(from), exit)).or_insert(map(to));
+        }
+        self.edges = edges;
+        let mut edge_visits = HashMap::new();
+        for ((from, exit), visits) in self.edge_visits.drain() {
+            *edge_visits.entry((map(from), exit)).or_default() += visits;
+        }
+        self.edge_visits = edge_visits;
+        let mut edge_costs = HashMap::new();
+        for ((from, exit), cost) in self.edge_costs.drain() {
+            edge_costs.entry((map(from), exit)).or_insert(cost);
+        }
+        self.edge_costs = edge_costs;
+        self.edge_outcomes = self
+            .edge_outcomes
+            .drain()
+            .map(|((from, exit, inventory), outcome)| ((map(from), exit, inventory), outcome))
+            .collect();
+        self.take_attempts = self
+            .take_attempts
+            .drain()
+            .map(|(room, item)| (map(room), item))
+            .collect();
+        let mut teleports: Vec<Teleport> = vec![];
+        for mut teleport in self.teleports.drain(..) {
+            teleport.from = map(teleport.from);
+            teleport.to = map(teleport.to);
+            if !teleports.contains(&teleport) {
+                teleports.push(teleport);
+            }
+        }
+        self.teleports = teleports;
+        self.documents
+            .iter_mut()
+            .for_each(|d| d.room = d.room.map(&map));
+        self.trail.iter_mut().for_each(|s| s.room = map(s.room));
+        self.route
+            .iter_mut()
+            .for_each(|(room, _)| *room = room.map(&map));
+        self.current = self.current.map(&map);
+        self.previous = self.previous.map(&map);
+    }
+
+    /// This method joins two rooms, which are actually the same one, e.g. the room shown differently
+    /// with the other items carried. The room with the lower id is kept: it gets the exits, items,
+    /// notes and passages of the other one and is recognized by both looks. The rooms after the
+    /// removed one move one id down
+    pub fn merge_nodes(&mut self, a: u16, b: u16) -> Result<u16, String> {
+        let (into, gone) = (a.min(b), a.max(b));
+        if into == gone {
+            return Err(format!("room #{} cannot be merged with itself", a));
+        }
+        if gone as usize >= self.nodes.len() {
+            return Err(format!("room #{} is unknown", gone));
+        }
+        debug!("merging room #{} into room #{}", gone, into);
+        let removed = self.nodes.remove(gone as usize);
+        let node = &mut self.nodes[into as usize];
+        if node.looks.is_empty() {
+            node.looks.push(node.look());
+        }
+        let removed_looks = if removed.looks.is_empty() {
+            vec![removed.look()]
+        } else {
+            removed.looks.clone()
+        };
+        for look in removed_looks {
+            if !node.looks.contains(&look) {
+                node.looks.push(look);
+            }
+        }
+        for exit in removed.exits {
+            if !node.exits.contains(&exit) {
+                node.exits.push(exit);
+            }
+        }
+        for item in removed.items {
+            if !node.items.contains(&item) {
+                node.items.push(item);
+            }
+        }
+        node.notes.extend(removed.notes);
+        self.remap_rooms(|id| match id {
+            id if id == gone => into,
+            id if id > gone => id - 1,
+            id => id,
+        });
+        for (n, node) in self.nodes.iter_mut().enumerate() {
+            node.id = n as u16;
+        }
+        self.index_signatures();
+        Ok(into)
+    }
+
+    /// This method tells apart the room the player is in from the rest of the visits to it, e.g.
+    /// when two different rooms look the same. The new room gets the passage the player has come
+    /// along, while the other passages stay with the old one. Returns the id of the new room
+    pub fn split_node(&mut self, id: u16) -> Result<u16, String> {
+        if self.current != Some(id) {
+            return Err(format!("enter room #{} to split it", id));
+        }
+        let new = self.nodes.len() as u16;
+        debug!("splitting room #{} off room #{}", new, id);
+        let node = &self.nodes[id as usize];
+        let split = Node {
+            id: new,
+            title: node.title.clone(),
+            description: node.description.clone(),
+            exits: node.exits.clone(),
+            items: node.items.clone(),
+            notes: vec![],
+            looks: node.looks.clone(),
+        };
+        for signature in split.signatures() {
+            self.rooms_by_signature
+                .entry(signature)
+                .or_default()
+                .push(new);
+        }
+        self.nodes.push(split);
+        let arrival = self.trail.len().checked_sub(2).and_then(|n| {
+            let from = self.trail[n].room;
+            let exit = self.trail[n + 1].exit.as_deref()?;
+            Some((from, self.exit_id(exit)?))
+        });
+        if let Some(edge) = arrival
+            && self.edges.get(&edge) == Some(&id)
+        {
+            self.edges.insert(edge, new);
+        }
+        if let Some(step) = self.trail.last_mut() {
+            step.room = new;
+        }
+        self.current = Some(new);
+        self.route.clear();
+        Ok(new)
+    }
+
+    /// This method attaches the note of the player to the current room and returns the room
+    pub fn add_note(&mut self, text: &str) -> Option<u16> {
+        let id = self.current?;
+        debug!("attaching note to room #{}", id);
+        self.nodes[id as usize].notes.push(text.to_string());
+        Some(id)
+    }
+
+    fn exit_name(&self, exit: ExitId) -> &str {
+        &self.exit_names[exit.0 as usize]
+    }
+
+    fn exit_id(&self, exit: &str) -> Option<ExitId> {
+        self.exit_ids.get(exit).copied()
+    }
+
+    fn intern_exit(&mut self, exit: &str) -> ExitId {
+        if let Some(id) = self.exit_id(exit) {
+            return id;
+        }
+        let id = ExitId(self.exit_names.len() as u16);
+        trace!("interning exit '{}' as {:?}", exit, id);
+        self.exit_names.push(exit.to_string());
+        self.exit_ids.insert(exit.to_string(), id);
+        id
+    }
+
+    pub fn strategy(&self) -> &ExplorationStrategy {
+        &self.strategy
+    }
+
+    pub fn set_strategy(&mut self, strategy: ExplorationStrategy) {
+        debug!("using exploration strategy {}", strategy);
+        self.strategy = strategy;
+    }
+
+    /// The aliases are applied from the next change of the inventory on
+    pub fn set_item_aliases(&mut self, aliases: ItemAliases) {
+        debug!("counting the items by the aliases {}", aliases);
+        self.item_aliases = aliases;
+    }
+
+    /// Returns all items ever seen lying in the rooms
+    pub fn items_seen(&self) -> &BTreeSet<String> {
+        &self.items_seen
+    }
+
+    /// Returns the number of exits, which were never taken
+    pub fn unexplored_exits(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|n| {
+                n.exits
+                    .iter()
+                    .filter(|e| self.destination(n.id, e).is_none())
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Returns the destination of the exit if it was already used
+    pub fn destination(&self, from: u16, exit: &str) -> Option<u16> {
+        let exit = self.exit_id(exit)?;
+        self.edges.get(&(from, exit)).copied()
+    }
+
+    /// Returns the room next to the given one in the given direction. Unlike the destination, the
+    /// reverse edges are taken into account as well, e.g. if 'b' is to the north of 'a', then 'a'
+    /// is to the south of 'b'
+    fn neighbor(&self, id: u16, direction: &str) -> Option<u16> {
+        self.destination(id, direction).or_else(|| {
+            let opposite = self.exit_id(opposite_direction(direction)?)?;
+            self.edges
+                .iter()
+                .filter(|((_, exit), to)| **to == id && *exit == opposite)
+                .map(|((from, _), _)| *from)
+                .min()
+        })
+    }
+
+    /// This method should be called every time the player issues a command
+    pub fn command(&mut self, command: &str) {
+        trace!("maze analyzer got command '{}'", command);
+        self.pending_command = Some(command.trim().to_lowercase());
+    }
+
+    /// This method should be called when using the item moves the player to another place. The
+    /// next observed room is recorded as the teleport destination
+    pub fn teleported(&mut self, item: &str) {
+        trace!("maze analyzer expects teleport by '{}'", item);
+        self.pending_teleport = Some(item.to_string());
+    }
+
+    /// This method should be called every time the inventory changes, the exits blocked with the
+    /// former items are tried again with the new ones
+    pub fn set_inventory(&mut self, items: &[String]) {
+        self.inventory_hash = inventory_hash(items, &self.item_aliases);
+        let light = items.iter().any(|i| i.starts_with(LIGHT_PREFIX));
+        if light != self.light {
+            // The exits skipped before are reported again, once they are skipped for another reason
+            self.unsafe_exits.clear();
+            self.light = light;
+        }
+    }
+
+    /// Returns the hash of the items carried. It stays the same, when the item is only
+    /// transformed into its alias, e.g. the lantern is lit
+    pub fn inventory_hash(&self) -> u64 {
+        self.inventory_hash
+    }
+
+    /// Returns why the exit is not proven to be safe, None if it is. The exit taken before is
+    /// safe, as well as the way back and any exit while the light is carried or the room does not
+    /// warn of the danger
+    pub fn exit_danger(&self, from: u16, exit: &str) -> Option<&'static str> {
+        if self.destination(from, exit).is_some() || self.light {
+            return None;
+        }
+        let arrival = self
+            .trail
+            .iter()
+            .rev()
+            .find(|s| s.room == from)
+            .and_then(|s| s.exit.as_deref());
+        if exit == RETREAT || arrival.and_then(opposite_direction) == Some(exit) {
+            return None;
+        }
+        let node = &self.nodes[from as usize];
+        let text = format!("{} {}", node.title, node.description).to_lowercase();
+        DANGER_MARKS
+            .iter()
+            .any(|mark| text.contains(mark))
+            .then_some("the room warns of the danger in the dark and no light is carried")
+    }
+
+    /// Returns the number of the exits skipped as not proven to be safe
+    pub fn unsafe_exits(&self) -> usize {
+        self.unsafe_exits.len()
+    }
+
+    /// Whether the exploration may take the exit: it is unexplored and, with the safe_only
+    /// strategy, proven to be safe
+    fn is_explorable(&self, from: u16, exit: &str) -> bool {
+        self.is_unexplored(from, exit) && self.is_allowed(from, exit)
+    }
+
+    fn is_allowed(&self, from: u16, exit: &str) -> bool {
+        !self.strategy.safe_only || self.exit_danger(from, exit).is_none()
+    }
+
+    /// Returns the exits of the room the exploration may take
+    pub fn explorable_exits(&self, id: u16) -> Vec<String> {
+        self.nodes[id as usize]
+            .exits
+            .iter()
+            .filter(|e| self.is_explorable(id, e))
+            .cloned()
+            .collect()
+    }
+
+    fn has_explorable(&self, id: u16) -> bool {
+        self.nodes[id as usize]
+            .exits
+            .iter()
+            .any(|e| self.is_explorable(id, e))
+    }
+
+    /// This method logs every exit of the known rooms, which is skipped as not proven to be safe
+    fn log_unsafe_exits(&mut self) {
+        if !self.strategy.safe_only {
+            return;
+        }
+        let mut skipped = vec![];
+        for node in &self.nodes {
+            for exit in node.exits.iter().filter(|e| self.is_unexplored(node.id, e)) {
+                if let Some(reason) = self.exit_danger(node.id, exit) {
+                    skipped.push((node.id, exit.clone(), reason));
+                }
+            }
+        }
+        for (from, exit, reason) in skipped {
+            let id = self.intern_exit(&exit);
+            if self.unsafe_exits.insert((from, id)) {
+                info!("skipping exit '{}' of room #{}: {}", exit, from, reason);
+            }
+        }
+    }
+
+    /// Returns whether taking the exit with the current inventory is known to lead nowhere
+    pub fn is_edge_blocked(&self, from: u16, exit: &str) -> bool {
+        self.exit_id(exit).is_some_and(|exit| {
+            self.edge_outcomes
+                .contains_key(&(from, exit, self.inventory_hash))
+        })
+    }
+
+    /// Returns the number of the exits known to lead nowhere with the current inventory
+    pub fn blocked_exits(&self) -> usize {
+        self.edge_outcomes
+            .keys()
+            .filter(|(_, _, inventory)| *inventory == self.inventory_hash)
+            .count()
+    }
+
+    /// Whether the exit is worth exploring: it was never taken and it is not blocked
+    fn is_unexplored(&self, from: u16, exit: &str) -> bool {
+        self.destination(from, exit).is_none() && !self.is_edge_blocked(from, exit)
+    }
+
+    /// Returns the fraction of the exits of the room, which are either taken or blocked with the
+    /// current inventory. The room without exits is complete
+    pub fn completion(&self, id: u16) -> f64 {
+        let exits = &self.nodes[id as usize].exits;
+        if exits.is_empty() {
+            return 1.0;
+        }
+        let explored = exits.iter().filter(|e| !self.is_unexplored(id, e)).count();
+        explored as f64 / exits.len() as f64
+    }
+
+    /// Returns the fraction of the exits of all known rooms, which are either taken or blocked
+    pub fn total_completion(&self) -> f64 {
+        let (explored, total) = self.nodes.iter().fold((0, 0), |(explored, total), n| {
+            let done = n
+                .exits
+                .iter()
+                .filter(|e| !self.is_unexplored(n.id, e))
+                .count();
+            (explored + done, total + n.exits.len())
+        });
+        if total == 0 {
+            return 1.0;
+        }
+        explored as f64 / total as f64
+    }
+
+    fn has_unexplored(&self, id: u16) -> bool {
+        self.completion(id) < 1.0
+    }
+
+    /// Returns the rooms which still have the exits worth exploring
+    pub fn frontier(&self) -> Vec<u16> {
+        self.nodes
+            .iter()
+            .map(|n| n.id)
+            .filter(|id| self.has_unexplored(*id))
+            .collect()
+    }
+
+    /// Returns the completed rooms, whose passages lead back only to the completed rooms, so there
+    /// is nothing left to explore beyond them
+    pub fn dead_ends(&self) -> Vec<u16> {
+        self.nodes
+            .iter()
+            .map(|n| n.id)
+            .filter(|id| {
+                self.path_to(*id, |to| self.has_unexplored(to), false)
+                    .is_none()
+            })
+            .collect()
+    }
+
+    /// Returns the rooms the player keeps going round at the end of the trail, if any
+    pub fn trail_loop(&self) -> Option<Vec<u16>> {
+        let rooms: Vec<u16> = self.trail.iter().map(|s| s.room).collect();
+        find_loop(&rooms).map(<[u16]>::to_vec)
+    }
+
+    /// This method records the response to taking the exit, which has not shown any room
+    fn record_blocked(&mut self, command: &str, response: &ResponsePartsRef) {
+        let Some(from) = self.current else {
+            return;
+        };
+        let exit = exit_of_command(command);
+        if !self.nodes[from as usize].exits.iter().any(|e| e == exit) {
+            return;
+        }
+        debug!(
+            "exit '{}' of room #{} is blocked with the current items",
+            exit, from
+        );
+        let exit = self.intern_exit(exit);
+        let outcome = stable_hash([response.pretext.as_ref(), response.description.as_ref()]);
+        self.edge_outcomes
+            .insert((from, exit, self.inventory_hash), outcome);
+    }
+
+    /// Whether the room looking the same as the one the exit has led to is rather the room left,
+    /// e.g. the dark passages of the same look one after another. Moving in the direction never
+    /// leads back to the same room
+    fn is_loop(&self, from: u16, exit: &str, id: u16) -> bool {
+        opposite_direction(exit).is_some() && id == from
+    }
+
+    /// Whether the opposite direction of the room looking the same leads back to the room left, if
+    /// it is known. The passages of the game are not always two-way, e.g. on the beach, so such a
+    /// room is only preferred to the other ones of the same look
+    fn leads_back(&self, from: u16, exit: &str, id: u16) -> bool {
+        opposite_direction(exit)
+            .is_none_or(|back| self.destination(id, back).is_none_or(|to| to == from))
+    }
+
+    /// This method should be called every time the game response is parsed
+    pub fn observe(&mut self, response: &ResponsePartsRef) {
+        let command = self.pending_command.take();
+        let teleport = self.pending_teleport.take();
+        let Some(title) = response.title else {
+            if let Some(command) = command
+                && teleport.is_none()
+                && response.inventory.is_none()
+            {
+                self.record_blocked(&command, response);
+            }
+            return;
+        };
+        let signature = room_signature(title, &response.description, &response.exits);
+        // The passage already taken tells apart the rooms which look the same (see split_node)
+        let along_passage = self
+            .current
+            .zip(command.as_deref())
+            .and_then(|(from, command)| self.destination(from, exit_of_command(command)))
+            .filter(|id| self.nodes[*id as usize].matches(response));
+        let arrival = self
+            .current
+            .zip(command.as_deref().map(exit_of_command))
+            .filter(|_| teleport.is_none());
+        let known = along_passage.or_else(|| {
+            let candidates: Vec<u16> = self
+                .rooms_by_signature
+                .get(&signature)?
+                .iter()
+                .copied()
+                .filter(|id| {
+                    self.nodes[*id as usize].matches(response)
+                        && arrival.is_none_or(|(from, exit)| !self.is_loop(from, exit, *id))
+                })
+                .collect();
+            candidates
+                .iter()
+                .copied()
+                .find(|id| arrival.is_none_or(|(from, exit)| self.leads_back(from, exit, *id)))
+                .or(candidates.first().copied())
+        });
+        let id = match known {
+            Some(id) => id,
+            None => {
+                let id = self.nodes.len() as u16;
+                debug!("discovered new room #{} '{}'", id, title);
+                self.rooms_by_signature.entry(signature).or_default().push(id);
+                self.nodes.push(Node {
+                    id,
+                    title: title.to_string(),
+                    description: response.description.to_string(),
+                    exits: response.exits.iter().map(|e| e.to_string()).collect(),
+                    items: vec![],
+                    notes: vec![],
+                    looks: vec![],
+                });
+                id
+            }
+        };
+        // Items can be taken or dropped, so always keep the latest view
+        let items = &mut self.nodes[id as usize].items;
+        if *items != response.items {
+            *items = response.items.iter().map(|i| i.to_string()).collect();
+        }
+        for item in &response.items {
+            if !self.items_seen.contains(*item) {
+                self.items_seen.insert(item.to_string());
+            }
+        }
+        let mut arrival = None;
+        if let (Some(from), Some(command)) = (self.current, command) {
+            let exit = exit_of_command(&command);
+            if self.nodes[from as usize].exits.iter().any(|e| e == exit) {
+                arrival = Some(exit.to_string());
+                trace!("recording edge #{} --{}--> #{}", from, exit, id);
+                let exit = self.intern_exit(exit);
+                self.edges.insert((from, exit), id);
+                *self.edge_visits.entry((from, exit)).or_default() += 1;
+            }
+        }
+        if let (Some(from), Some(item)) = (self.current, teleport)
+            && from != id
+        {
+            let teleport = Teleport { from, item, to: id };
+            if !self.teleports.contains(&teleport) {
+                debug!(
+                    "recording teleport #{} --{}--> #{}",
+                    from, teleport.item, id
+                );
+                self.teleports.push(teleport);
+            }
+            // There is no going back along the teleport
+            self.current = None;
+        }
+        if self.current != Some(id) {
+            self.previous = self.current;
+            self.trail.push(TrailStep {
+                room: id,
+                exit: arrival,
+            });
+        }
+        self.current = Some(id);
+    }
+
+    /// This method records the response to taking the exit of the room by somebody else than the
+    /// player, e.g. the forked machine. The player stays where they are. Returns the room the exit
+    /// has led to, if any
+    pub fn record_passage(
+        &mut self,
+        from: u16,
+        exit: &str,
+        response: &ResponsePartsRef,
+    ) -> Option<u16> {
+        let (current, previous, trail) = (self.current, self.previous, self.trail.len());
+        let pending = self.pending_command.take();
+        self.current = Some(from);
+        self.command(exit);
+        self.observe(response);
+        self.current = current;
+        self.previous = previous;
+        self.trail.truncate(trail);
+        self.pending_command = pending;
+        self.destination(from, exit)
+    }
+
+    /// This method returns the next command to explore the map according to the strategy: take an
+    /// item lying in the room, one of the unexplored exits of the current room, or the first step
+    /// towards the closest room which still has unexplored exits. None means that there is nothing
+    /// left to explore among the reachable rooms
+    pub fn next_exploration_command(&mut self) -> Option<String> {
+        let current = self.current?;
+        self.log_unsafe_exits();
+        if self.strategy.take_items
+            && let Some(item) = self.nodes[current as usize]
+                .items
+                .iter()
+                .find(|i| !self.take_attempts.contains(&(current, i.to_string())))
+                .cloned()
+        {
+            trace!("taking item '{}' in room #{}", item, current);
+            self.take_attempts.insert((current, item.clone()));
+            return Some(format!("take {}", item));
+        }
+        if !self.strategy.prefer_unexplored {
+            return self.wander(current);
+        }
+        if self.strategy.plan_route {
+            if let Some(exit) = self.next_route_step(current) {
+                return Some(format!("go {}", exit));
+            }
+            self.plan_tour(current);
+            if let Some(exit) = self.next_route_step(current) {
+                return Some(format!("go {}", exit));
+            }
+        }
+        let unexplored: Vec<&String> = self.nodes[current as usize]
+            .exits
+            .iter()
+            .filter(|e| self.is_explorable(current, e))
+            .collect();
+        if let Some(exit) = unexplored.choose(&mut self.rng) {
+            trace!("exploring exit '{}' of room #{}", exit, current);
+            return Some(format!("go {}", exit));
+        }
+        let path = self.path_to_frontier(current)?;
+        trace!("heading to the frontier by path {:?}", path);
+        path.first().map(|exit| format!("go {}", exit))
+    }
+
+    /// Returns the path actually taken by the player since the start of the session
+    pub fn trail(&self) -> &[TrailStep] {
+        &self.trail
+    }
+
+    /// Returns the cost of taking the exit of the room
+    pub fn edge_cost(&self, from: u16, exit: &str) -> u32 {
+        self.exit_id(exit)
+            .and_then(|exit| self.edge_costs.get(&(from, exit)))
+            .copied()
+            .unwrap_or(DEFAULT_EDGE_COST)
+    }
+
+    /// This method sets the cost of taking the exit of the room, the default cost removes it
+    pub fn set_edge_cost(&mut self, from: u16, exit: &str, cost: u32) -> Result<(), String> {
+        let node = self
+            .node_by_id(from)
+            .ok_or(format!("room #{} is unknown", from))?;
+        if !node.exits.iter().any(|e| e == exit) {
+            return Err(format!("room #{} has no exit '{}'", from, exit));
+        }
+        debug!("exit '{}' of room #{} costs {}", exit, from, cost);
+        let exit = self.intern_exit(exit);
+        if cost == DEFAULT_EDGE_COST {
+            self.edge_costs.remove(&(from, exit));
+        } else {
+            self.edge_costs.insert((from, exit), cost);
+        }
+        Ok(())
+    }
+
+    /// Returns the total cost of the path starting in the room
+    pub fn path_cost(&self, from: u16, path: &[String]) -> u32 {
+        let mut step = from;
+        let mut cost = 0;
+        for exit in path {
+            cost += self.edge_cost(step, exit);
+            step = self.destination(step, exit).unwrap_or(step);
+        }
+        cost
+    }
+
+    /// Returns the exits of the cheapest known path between the rooms, found with Dijkstra's
+    /// algorithm over the costs of the passages
+    pub fn shortest_path(&self, from: u16, to: u16) -> Option<Vec<String>> {
+        if from as usize >= self.nodes.len() {
+            return None;
+        }
+        let mut costs: HashMap<u16, u32> = HashMap::from([(from, 0)]);
+        let mut previous: HashMap<u16, (u16, &str)> = HashMap::new();
+        let mut queue = BinaryHeap::from([Reverse((0, from))]);
+        while let Some(Reverse((cost, id))) = queue.pop() {
+            if id == to {
+                let mut path = vec![];
+                let mut step = id;
+                while step != from {
+                    let (prev, exit) = previous[&step];
+                    path.push(exit.to_string());
+                    step = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if costs.get(&id).is_some_and(|c| cost > *c) {
+                continue;
+            }
+            for exit in &self.nodes[id as usize].exits {
+                let Some(next) = self.destination(id, exit) else {
+                    continue;
+                };
+                let next_cost = cost.saturating_add(self.edge_cost(id, exit));
+                if costs.get(&next).is_none_or(|c| next_cost < *c) {
+                    costs.insert(next, next_cost);
+                    previous.insert(next, (id, exit.as_str()));
+                    queue.push(Reverse((next_cost, next)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the exits of the planned route, which are not taken yet
+    pub fn planned_route(&self) -> impl Iterator<Item = &str> {
+        self.route.iter().map(|(_, exit)| exit.as_str())
+    }
+
+    /// This method drops the rest of the planned route
+    pub fn clear_route(&mut self) {
+        self.route.clear();
+    }
+
+    /// This method pops the next step of the planned route. The whole route is dropped, once the
+    /// player is not where the route expects or the step is not allowed by the safe_only strategy
+    fn next_route_step(&mut self, current: u16) -> Option<String> {
+        let (expected, exit) = self.route.pop_front()?;
+        let on_track = expected.is_none_or(|room| room == current)
+            && self.nodes[current as usize].exits.contains(&exit)
+            && self.is_allowed(current, &exit);
+        if !on_track {
+            debug!(
+                "room #{} is off the planned route, dropping {} steps",
+                current,
+                self.route.len() + 1
+            );
+            self.route.clear();
+            return None;
+        }
+        Some(exit)
+    }
+
+    /// This method plans the tour over the unexplored exits of all reachable rooms. The rooms are
+    /// visited in the greedy nearest first order, and every unexplored exit is followed by the
+    /// opposite one to get back. The tour ends at the exit without the opposite one, since there is
+    /// no telling where the player is after it
+    fn plan_tour(&mut self, from: u16) {
+        let mut route = VecDeque::new();
+        let mut visited: HashSet<u16> = HashSet::new();
+        let mut position = from;
+        'tour: loop {
+            let frontier = |id: u16| !visited.contains(&id) && self.has_explorable(id);
+            let Some((target, path)) = self.path_to(position, frontier, true) else {
+                break;
+            };
+            let mut step = position;
+            for exit in path {
+                route.push_back((Some(step), exit.clone()));
+                step = self.destination(step, &exit).unwrap_or(step);
+            }
+            visited.insert(target);
+            position = target;
+            let unexplored: Vec<String> = self.nodes[target as usize]
+                .exits
+                .iter()
+                .filter(|e| self.is_explorable(target, e))
+                .cloned()
+                .collect();
+            for exit in unexplored {
+                route.push_back((Some(target), exit.clone()));
+                match opposite_direction(&exit) {
+                    Some(back) => route.push_back((None, back.to_string())),
+                    None => break 'tour,
+                }
+            }
+        }
+        debug!(
+            "planned route of {} moves over {} rooms",
+            route.len(),
+            visited.len()
+        );
+        self.route = route;
+    }
+
+    /// Returns the number of times the exit was taken
+    pub fn edge_visits(&self, from: u16, exit: &str) -> u32 {
+        self.exit_id(exit)
+            .and_then(|exit| self.edge_visits.get(&(from, exit)))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn is_edge_exhausted(&self, from: u16, exit: &str) -> bool {
+        self.edge_visits(from, exit) >= self.strategy.edge_visit_limit
+    }
+
+    /// This method picks a random exit of the room, which is not exhausted yet. The exits into the
+    /// dead ends and into the loop the player keeps going round are left for the last resort
+    fn wander(&mut self, current: u16) -> Option<String> {
+        let exits: Vec<&String> = self.nodes[current as usize]
+            .exits
+            .iter()
+            .filter(|e| !self.is_edge_exhausted(current, e) && !self.is_edge_blocked(current, e))
+            .filter(|e| self.is_allowed(current, e))
+            .collect();
+        let dead_ends = self.dead_ends();
+        let looping = self.trail_loop().unwrap_or_default();
+        let pruned: Vec<&String> = exits
+            .iter()
+            .copied()
+            .filter(|e| {
+                self.destination(current, e)
+                    .is_none_or(|to| !dead_ends.contains(&to) && !looping.contains(&to))
+            })
+            .collect();
+        if pruned.len() < exits.len() && !pruned.is_empty() {
+            trace!(
+                "pruned {} exits of room #{}",
+                exits.len() - pruned.len(),
+                current
+            );
+        }
+        let exits = if pruned.is_empty() { exits } else { pruned };
+        let forward: Vec<&String> = exits
+            .iter()
+            .copied()
+            .filter(|e| self.previous.is_none() || self.destination(current, e) != self.previous)
+            .collect();
+        let candidates = if self.strategy.avoid_backtracking && !forward.is_empty() {
+            forward
+        } else {
+            exits
+        };
+        let exit = candidates.choose(&mut self.rng)?;
+        trace!("wandering through exit '{}' of room #{}", exit, current);
+        Some(format!("go {}", exit))
+    }
+
+    /// Returns the exits leading from the room to the closest room with unexplored exits
+    fn path_to_frontier(&self, from: u16) -> Option<Vec<String>> {
+        let path = self.path_to(from, |id| id != from && self.has_explorable(id), true);
+        if path.is_none() {
+            info!("there are no reachable rooms with unexplored exits");
+        }
+        path.map(|(_, path)| path)
+    }
+
+    /// Returns the closest room matching the predicate together with the exits leading to it.
+    /// Optionally the exhausted exits are not used
+    fn path_to(
+        &self,
+        from: u16,
+        target: impl Fn(u16) -> bool,
+        skip_exhausted: bool,
+    ) -> Option<(u16, Vec<String>)> {
+        let mut previous: HashMap<u16, (u16, String)> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(id) = queue.pop_front() {
+            let node = &self.nodes[id as usize];
+            if target(id) {
+                let mut path = vec![];
+                let mut step = id;
+                while step != from {
+                    let (prev, exit) = &previous[&step];
+                    path.push(exit.clone());
+                    step = *prev;
+                }
+                path.reverse();
+                return Some((id, path));
+            }
+            for exit in &node.exits {
+                if let Some(next) = self.destination(id, exit)
+                    && !(skip_exhausted && self.is_edge_exhausted(id, exit))
+                    && next != from
+                    && !previous.contains_key(&next)
+                {
+                    previous.insert(next, (id, exit.clone()));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// This method renders the rooms around the current one on the grid, using only the
+    /// north/south/east/west exits. The layout stops at the given distance from the current room
+    pub fn render_map(&self, radius: usize) -> String {
+        let Some(current) = self.current else {
+            return "no rooms have been visited yet\n".to_string();
+        };
+        let mut positions: HashMap<u16, (i32, i32)> = HashMap::new();
+        let mut occupied: HashMap<(i32, i32), u16> = HashMap::new();
+        let mut queue = VecDeque::from([(current, (0, 0), 0)]);
+        positions.insert(current, (0, 0));
+        occupied.insert((0, 0), current);
+        while let Some((id, (x, y), distance)) = queue.pop_front() {
+            if distance >= radius {
+                continue;
+            }
+            for (dx, dy, exit) in DIRECTIONS {
+                let Some(next) = self.neighbor(id, exit) else {
+                    continue;
+                };
+                let position = (x + dx, y + dy);
+                // The maze is not always euclidean, so the conflicting rooms are skipped
+                if positions.contains_key(&next) || occupied.contains_key(&position) {
+                    continue;
+                }
+                positions.insert(next, position);
+                occupied.insert(position, next);
+                queue.push_back((next, position, distance + 1));
+            }
+        }
+        let min_x = occupied.keys().map(|p| p.0).min().unwrap_or(0);
+        let max_x = occupied.keys().map(|p| p.0).max().unwrap_or(0);
+        let min_y = occupied.keys().map(|p| p.1).min().unwrap_or(0);
+        let max_y = occupied.keys().map(|p| p.1).max().unwrap_or(0);
+        let mut map = String::new();
+        for y in min_y..=max_y {
+            let mut rooms_row = String::new();
+            let mut links_row = String::new();
+            for x in min_x..=max_x {
+                match occupied.get(&(x, y)) {
+                    Some(id) => {
+                        rooms_row.push_str(&self.render_cell(*id, *id == current));
+                        let east = self.neighbor(*id, "east");
+                        if east.is_some() && east == occupied.get(&(x + 1, y)).copied() {
+                            rooms_row.push('-');
+                        } else {
+                            rooms_row.push(' ');
+                        }
+                        let south = self.neighbor(*id, "south");
+                        if south.is_some() && south == occupied.get(&(x, y + 1)).copied() {
+                            links_row.push_str(" | ");
+                        } else {
+                            links_row.push_str("   ");
+                        }
+                        links_row.push(' ');
+                    }
+                    None => {
+                        rooms_row.push_str("    ");
+                        links_row.push_str("    ");
+                    }
+                }
+            }
+            map.push_str(rooms_row.trim_end());
+            map.push('\n');
+            if y != max_y {
+                map.push_str(links_row.trim_end());
+                map.push('\n');
+            }
+        }
+        map.push_str(&format!(
+            "{} - you are here ({}), {} - unexplored exits\n",
+            "[@]".green().bold(),
+            self.nodes[current as usize].title,
+            "[?]".yellow()
+        ));
+        map
+    }
+
+    /// This method renders both the path actually taken to the room (up to the latest arrival) and
+    /// the shortest known path to it from the starting room
+    pub fn render_paths(&self, to: u16) -> String {
+        let Some(last) = self.trail.iter().rposition(|s| s.room == to) else {
+            return format!("room #{} has not been visited\n", to);
+        };
+        let taken = &self.trail[..=last];
+        let mut actual = String::new();
+        for step in taken {
+            if let Some(exit) = &step.exit {
+                actual.push_str(&format!(" -{}-> ", exit));
+            } else if !actual.is_empty() {
+                actual.push_str(" ~~> ");
+            }
+            actual.push_str(&format!("#{}", step.room));
+        }
+        let moves = taken.iter().filter(|s| s.exit.is_some()).count();
+        let mut report = format!("actual path   ({} moves): {}\n", moves, actual);
+        let start = taken[0].room;
+        match self.shortest_path(start, to) {
+            Some(path) => {
+                let mut shortest = format!("#{}", start);
+                let mut step = start;
+                for exit in &path {
+                    step = self.destination(step, exit).unwrap_or(step);
+                    shortest.push_str(&format!(" -{}-> #{}", exit, step));
+                }
+                report.push_str(&format!(
+                    "shortest path ({} moves, cost {}): {}\n",
+                    path.len(),
+                    self.path_cost(start, &path),
+                    shortest
+                ));
+            }
+            None => report.push_str("shortest path: none of the known passages lead there\n"),
+        }
+        report
+    }
+
+    /// This method renders everything known about the room
+    pub fn render_node(&self, id: u16) -> Option<String> {
+        let node = self.node_by_id(id)?;
+        let mut report = format!("***  Room #{}: {}  ***\n", node.id, node.title);
+        report.push_str(&format!("{}\n", node.description));
+        if self.current == Some(id) {
+            report.push_str("(you are here)\n");
+        }
+        let items = if node.items.is_empty() {
+            "none".to_string()
+        } else {
+            node.items.join(", ")
+        };
+        report.push_str(&format!("{:<9}: {}\n", "items", items));
+        report.push_str(&format!(
+            "{:<9}: {:.0}%\n",
+            "complete",
+            self.completion(id) * 100.0
+        ));
+        if !node.notes.is_empty() {
+            report.push_str("notes    :\n");
+            node.notes
+                .iter()
+                .for_each(|n| report.push_str(&format!("  * {}\n", n)));
+        }
+        report.push_str("exits    :\n");
+        for exit in &node.exits {
+            match self.destination(id, exit) {
+                Some(to) => report.push_str(&format!(
+                    "  - {} -> #{} {} (taken {} times, cost {})\n",
+                    exit,
+                    to,
+                    self.nodes[to as usize].title,
+                    self.edge_visits(id, exit),
+                    self.edge_cost(id, exit)
+                )),
+                None => report.push_str(&format!("  - {} -> ?\n", exit)),
+            }
+        }
+        for teleport in self.teleports.iter().filter(|t| t.from == id) {
+            report.push_str(&format!(
+                "  ~ use {} ~> #{} {}\n",
+                teleport.item, teleport.to, self.nodes[teleport.to as usize].title
+            ));
+        }
+        let mut incoming: Vec<(u16, &str)> = self
+            .edges()
+            .filter(|(_, _, to)| *to == id)
+            .map(|(from, exit, _)| (from, exit))
+            .collect();
+        incoming.sort();
+        report.push_str("entries  :\n");
+        for (from, exit) in incoming {
+            report.push_str(&format!(
+                "  - #{} {} --{}-->\n",
+                from, self.nodes[from as usize].title, exit
+            ));
+        }
+        for teleport in self.teleports.iter().filter(|t| t.to == id) {
+            report.push_str(&format!(
+                "  ~ #{} {} ~~use {}~~>\n",
+                teleport.from, self.nodes[teleport.from as usize].title, teleport.item
+            ));
+        }
+        let documents: Vec<&str> = self
+            .documents
+            .iter()
+            .filter(|d| d.room == Some(id))
+            .map(|d| d.item.as_str())
+            .collect();
+        if !documents.is_empty() {
+            report.push_str(&format!("{:<9}: {}\n", "documents", documents.join(", ")));
+        }
+        let visits = self.trail.iter().filter(|s| s.room == id).count();
+        report.push_str(&format!("{:<9}: {}\n", "visits", visits));
+        Some(report)
+    }
+
+    fn render_cell(&self, id: u16, is_current: bool) -> String {
+        let node = &self.nodes[id as usize];
+        if is_current {
+            return "[@]".green().bold().to_string();
+        }
+        if node.exits.iter().any(|e| self.destination(id, e).is_none()) {
+            return "[?]".yellow().to_string();
+        }
+        "[ ]".to_string()
+    }
+}
+
+impl VmPlugin for MazeAnalyzer {
+    fn name(&self) -> &str {
+        "maze analyzer"
+    }
+
+    fn on_command(&mut self, command: &str) {
+        if !command.starts_with('/') {
+            self.command(command);
+        }
+    }
+
+    fn on_event(&mut self, event: &VmEvent) {
+        match event {
+            VmEvent::ItemUsed(item, effects) if effects.contains(&UseEffect::Teleported) => {
+                self.teleported(item)
+            }
+            VmEvent::ResponseParsed(response) => self.observe(response),
+            _ => {}
+        }
+    }
+}
+
+// The offset on the map grid for every exit, which can be used for the layout
+const DIRECTIONS: [(i32, i32, &str); 4] = [
+    (0, -1, "north"),
+    (0, 1, "south"),
+    (1, 0, "east"),
+    (-1, 0, "west"),
+];
+
+fn opposite_direction(direction: &str) -> Option<&'static str> {
+    match direction {
+        "north" => Some("south"),
+        "south" => Some("north"),
+        "east" => Some("west"),
+        "west" => Some("east"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(items: &[&str]) -> Vec<String> {
+        items.iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn counts_the_transformed_item_as_the_former_one() {
+        let mut maze = MazeAnalyzer::new();
+        maze.set_inventory(&items(&["tablet", "lantern"]));
+        let before = maze.inventory_hash();
+        maze.set_inventory(&items(&["lit lantern", "tablet"]));
+        assert_eq!(maze.inventory_hash(), before);
+        maze.set_inventory(&items(&["lit lantern", "tablet", "can"]));
+        assert_ne!(maze.inventory_hash(), before);
+    }
+
+    #[test]
+    fn replaces_the_default_aliases_with_the_given_ones() {
+        let aliases: ItemAliases = "empty lantern = lantern, lit lantern=lantern"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            aliases.to_string(),
+            "empty lantern=lantern,lit lantern=lantern"
+        );
+        assert_eq!(aliases.resolve("empty lantern"), "lantern");
+        assert_eq!(aliases.resolve("tablet"), "tablet");
+        let none: ItemAliases = "".parse().unwrap();
+        assert_eq!(none.resolve("lit lantern"), "lit lantern");
+        assert_eq!(
+            "lit lantern".parse::<ItemAliases>().unwrap_err(),
+            "expected 'alias=item', got 'lit lantern'"
+        );
+    }
+}
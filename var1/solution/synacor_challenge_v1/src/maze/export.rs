@@ -0,0 +1,256 @@
+use super::MazeAnalyzer;
+use crate::state::VmState;
+use log::debug;
+use serde_json::{Value, json};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The format the map is exported in
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MapFormat {
+    /// The Graphviz graph, e.g. for 'dot -Tsvg'
+    #[default]
+    Dot,
+    Json,
+    /// The standalone page with the rooms linked along the passages
+    Html,
+}
+
+impl MapFormat {
+    /// Returns the format matching the extension of the file, if any
+    pub fn of_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for MapFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" | "gv" => Ok(MapFormat::Dot),
+            "json" => Ok(MapFormat::Json),
+            "html" => Ok(MapFormat::Html),
+            other => Err(format!(
+                "unknown map format '{}', expected dot, json or html",
+                other
+            )),
+        }
+    }
+}
+
+/// This function loads the map from the state saved by /save_state or from the serialized maze
+/// alone. The directory, e.g. the one of the save slots, stands for the latest state saved in it
+pub fn load(path: &Path) -> Result<MazeAnalyzer, Box<dyn Error>> {
+    let path = if path.is_dir() {
+        latest_state(path)?
+    } else {
+        path.to_path_buf()
+    };
+    debug!("loading the map from {}", path.display());
+    let text = fs::read_to_string(&path)?;
+    if let Ok(state) = VmState::from_json(&text) {
+        return state
+            .maze
+            .ok_or_else(|| format!("the state {} has no map", path.display()).into());
+    }
+    serde_json::from_str(&text).map_err(|e| {
+        format!(
+            "{} is neither the saved state nor the map: {}",
+            path.display(),
+            e
+        )
+        .into()
+    })
+}
+
+fn latest_state(dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let mut latest = None;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.path().extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if latest.as_ref().is_none_or(|(m, _)| modified > *m) {
+            latest = Some((modified, entry.path()));
+        }
+    }
+    latest
+        .map(|(_, path)| path)
+        .ok_or_else(|| format!("there are no saved states in {}", dir.display()).into())
+}
+
+/// This function exports the map in the given format
+pub fn export(maze: &MazeAnalyzer, format: MapFormat) -> String {
+    match format {
+        MapFormat::Dot => to_dot(maze),
+        MapFormat::Json => {
+            serde_json::to_string_pretty(&to_json(maze)).expect("map is always serializable")
+        }
+        MapFormat::Html => to_html(maze),
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The passages are solid edges, the teleports dashed ones, the current room is filled. Every room
+/// is labeled with the share of its exits explored
+pub fn to_dot(maze: &MazeAnalyzer) -> String {
+    let mut dot = String::from("digraph maze {\n    node [shape=box];\n");
+    let current = maze.current_node().map(|n| n.id);
+    for node in maze.nodes() {
+        let style = if Some(node.id) == current {
+            ", style=filled"
+        } else {
+            ""
+        };
+        dot.push_str(&format!(
+            "    n{} [label=\"{}\\n{:.0}%\"{}];\n",
+            node.id,
+            escape_dot(&node.title),
+            maze.completion(node.id) * 100.0,
+            style
+        ));
+    }
+    // The notes are pinned to their rooms, but they are not the passages
+    for node in maze.nodes().iter().filter(|n| !n.notes.is_empty()) {
+        let label: Vec<String> = node.notes.iter().map(|n| escape_dot(n)).collect();
+        dot.push_str(&format!(
+            "    note{} [shape=note, label=\"{}\"];\n    n{} -> note{} [style=dotted, arrowhead=none];\n",
+            node.id,
+            label.join("\\n"),
+            node.id,
+            node.id
+        ));
+    }
+    let mut edges: Vec<(u16, &str, u16)> = maze.edges().collect();
+    edges.sort();
+    for (from, exit, to) in edges {
+        dot.push_str(&format!(
+            "    n{} -> n{} [label=\"{}\"];\n",
+            from,
+            to,
+            escape_dot(exit)
+        ));
+    }
+    for teleport in maze.teleports() {
+        dot.push_str(&format!(
+            "    n{} -> n{} [label=\"use {}\", style=dashed];\n",
+            teleport.from,
+            teleport.to,
+            escape_dot(&teleport.item)
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// The rooms, the passages and the teleports, the same as GET /map of the server
+pub fn to_json(maze: &MazeAnalyzer) -> Value {
+    let nodes: Vec<Value> = maze
+        .nodes()
+        .iter()
+        .map(|n| {
+            json!({
+                "id": n.id,
+                "title": n.title,
+                "description": n.description,
+                "exits": n.exits,
+                "items": n.items,
+                "notes": n.notes,
+                "completion": maze.completion(n.id),
+            })
+        })
+        .collect();
+    let mut edges: Vec<(u16, &str, u16)> = maze.edges().collect();
+    edges.sort();
+    let edges: Vec<Value> = edges
+        .into_iter()
+        .map(|(from, exit, to)| json!({ "from": from, "exit": exit, "to": to }))
+        .collect();
+    json!({
+        "current": maze.current_node().map(|n| n.id),
+        "nodes": nodes,
+        "edges": edges,
+        "teleports": maze.teleports(),
+    })
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Every room is the section with its description, items and the exits linking to the rooms they
+/// lead to
+pub fn to_html(maze: &MazeAnalyzer) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Synacor Challenge Map</title>\n</head>\n<body>\n",
+    );
+    html.push_str(&format!(
+        "<h1>Synacor Challenge Map</h1>\n<p>{} rooms, {} passages.</p>\n",
+        maze.nodes().len(),
+        maze.edges().count()
+    ));
+    let current = maze.current_node().map(|n| n.id);
+    for node in maze.nodes() {
+        let marker = if Some(node.id) == current {
+            " (you are here)"
+        } else {
+            ""
+        };
+        html.push_str(&format!(
+            "<section id=\"room-{}\">\n<h2>{}{}</h2>\n<p>{}</p>\n",
+            node.id,
+            escape_html(&node.title),
+            marker,
+            escape_html(&node.description)
+        ));
+        if !node.items.is_empty() {
+            html.push_str(&format!(
+                "<p>Items: {}</p>\n",
+                escape_html(&node.items.join(", "))
+            ));
+        }
+        if !node.notes.is_empty() {
+            html.push_str("<h3>Notes</h3>\n<ul class=\"notes\">\n");
+            for note in &node.notes {
+                html.push_str(&format!("<li>{}</li>\n", escape_html(note)));
+            }
+            html.push_str("</ul>\n");
+        }
+        html.push_str("<ul>\n");
+        for exit in &node.exits {
+            match maze.destination(node.id, exit) {
+                Some(to) => html.push_str(&format!(
+                    "<li>{} &rarr; <a href=\"#room-{}\">{}</a></li>\n",
+                    escape_html(exit),
+                    to,
+                    escape_html(maze.node_by_id(to).map_or("?", |n| n.title.as_str()))
+                )),
+                None => html.push_str(&format!("<li>{} (unexplored)</li>\n", escape_html(exit))),
+            }
+        }
+        for teleport in maze.teleports().iter().filter(|t| t.from == node.id) {
+            html.push_str(&format!(
+                "<li>use {} &rarr; <a href=\"#room-{}\">{}</a></li>\n",
+                escape_html(&teleport.item),
+                teleport.to,
+                escape_html(
+                    maze.node_by_id(teleport.to)
+                        .map_or("?", |n| n.title.as_str())
+                )
+            ));
+        }
+        html.push_str("</ul>\n</section>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
@@ -0,0 +1,211 @@
+use super::MazeAnalyzer;
+use crate::machine::{Machine, StepEvent};
+use crate::parser::OutputParser;
+use log::{debug, trace, warn};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The number of the instructions a worker may execute to reach the exit and take it, before the
+// task is given up, e.g. the game got stuck in a loop
+const TASK_CYCLE_LIMIT: u64 = 20_000_000;
+
+/// How the parallel exploration has gone
+#[derive(Debug, Clone)]
+pub struct ParallelReport {
+    pub workers: usize,
+    /// The number of the exits taken by the workers
+    pub tasks: usize,
+    /// The tasks given up: the known passages do not lead to the exit or the game has not responded
+    pub failed: usize,
+    pub new_rooms: usize,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for ParallelReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "***        Parallel Exploration           ***")?;
+        writeln!(f, "{}", "=".repeat(44))?;
+        writeln!(f, "{:<9}: {}", "workers", self.workers)?;
+        writeln!(f, "{:<9}: {}", "exits", self.tasks)?;
+        writeln!(f, "{:<9}: {}", "failed", self.failed)?;
+        writeln!(f, "{:<9}: {}", "new rooms", self.new_rooms)?;
+        writeln!(f, "{:<9}: {:.2?}", "elapsed", self.elapsed)?;
+        write!(f, "{}", "=".repeat(45))
+    }
+}
+
+/// The state shared by the workers: the map, the exits waiting to be taken and the number of the
+/// workers taking them at the moment. The workers add the exits of the rooms they discover
+struct Shared {
+    maze: MazeAnalyzer,
+    queue: VecDeque<(u16, String)>,
+    queued: HashSet<(u16, String)>,
+    busy: usize,
+    tasks: usize,
+    failed: usize,
+}
+
+impl Shared {
+    fn enqueue(&mut self, room: u16) {
+        for exit in self.maze.explorable_exits(room) {
+            if self.queued.insert((room, exit.clone())) {
+                self.queue.push_back((room, exit));
+            }
+        }
+    }
+}
+
+/// This function explores the unexplored exits of the map with the given number of workers. Every
+/// worker takes the next exit from the shared queue, forks the machine, walks there along the known
+/// passages from the current room and takes the exit. The response is recorded in the shared map,
+/// and the exits of the newly discovered room are queued for any idle worker. The forks are
+/// dropped, so the game itself stays where it is, and the worker dying in the dark costs nothing
+pub fn explore(
+    machine: &mut Machine,
+    maze: MazeAnalyzer,
+    workers: usize,
+    max_tasks: usize,
+) -> (MazeAnalyzer, ParallelReport) {
+    let started = Instant::now();
+    let workers = workers.max(1);
+    let rooms = maze.nodes().len();
+    let Some(start) = maze.current_node().map(|n| n.id) else {
+        let report = ParallelReport {
+            workers,
+            tasks: 0,
+            failed: 0,
+            new_rooms: 0,
+            elapsed: started.elapsed(),
+        };
+        return (maze, report);
+    };
+    let base = machine.checkpoint();
+    let mut shared = Shared {
+        maze,
+        queue: VecDeque::new(),
+        queued: HashSet::new(),
+        busy: 0,
+        tasks: 0,
+        failed: 0,
+    };
+    for room in 0..rooms as u16 {
+        shared.enqueue(room);
+    }
+    debug!(
+        "exploring {} exits with {} workers",
+        shared.queue.len(),
+        workers
+    );
+    let shared = Mutex::new(shared);
+    let ready = Condvar::new();
+    thread::scope(|scope| {
+        for worker in 0..workers {
+            let (shared, ready, base) = (&shared, &ready, &base);
+            scope.spawn(move || work(worker, start, base, shared, ready, max_tasks));
+        }
+    });
+    let shared = shared.into_inner().unwrap_or_else(PoisonError::into_inner);
+    let report = ParallelReport {
+        workers,
+        tasks: shared.tasks,
+        failed: shared.failed,
+        new_rooms: shared.maze.nodes().len() - rooms,
+        elapsed: started.elapsed(),
+    };
+    (shared.maze, report)
+}
+
+/// The loop of the worker: it takes the exits from the queue until there are none left and no
+/// other worker may add more, or the limit of the tasks is reached
+fn work(
+    worker: usize,
+    start: u16,
+    base: &Machine,
+    shared: &Mutex<Shared>,
+    ready: &Condvar,
+    max_tasks: usize,
+) {
+    let parser = OutputParser::new();
+    loop {
+        let (from, exit, path) = {
+            let mut state = shared.lock().unwrap_or_else(PoisonError::into_inner);
+            while state.queue.is_empty() && state.busy > 0 {
+                state = ready.wait(state).unwrap_or_else(PoisonError::into_inner);
+            }
+            if state.tasks >= max_tasks {
+                break;
+            }
+            let Some((from, exit)) = state.queue.pop_front() else {
+                break;
+            };
+            state.tasks += 1;
+            let Some(path) = state.maze.shortest_path(start, from) else {
+                state.failed += 1;
+                continue;
+            };
+            state.busy += 1;
+            (from, exit, path)
+        };
+        trace!(
+            "worker {} takes exit '{}' of room #{} in {} moves",
+            worker,
+            exit,
+            from,
+            path.len() + 1
+        );
+        let commands: Vec<String> = path
+            .iter()
+            .chain(std::iter::once(&exit))
+            .map(|e| format!("go {}", e))
+            .collect();
+        let response = run_fork(base, &commands);
+        let mut state = shared.lock().unwrap_or_else(PoisonError::into_inner);
+        match response {
+            Some(text) => {
+                let known = state.maze.nodes().len();
+                let to = state
+                    .maze
+                    .record_passage(from, &exit, &parser.parse_ref(&text));
+                if let Some(to) = to.filter(|to| *to as usize >= known) {
+                    debug!("worker {} has discovered room #{}", worker, to);
+                    state.enqueue(to);
+                }
+            }
+            None => {
+                warn!(
+                    "worker {} has given up exit '{}' of room #{}",
+                    worker, exit, from
+                );
+                state.failed += 1;
+            }
+        }
+        state.busy -= 1;
+        ready.notify_all();
+    }
+    // The idle workers wake up to see there is nothing left
+    ready.notify_all();
+}
+
+/// This function runs the copy of the machine with the commands and returns the response to the
+/// last of them. The output is collected after the last input character is read, so the response
+/// to the command still being processed by the original machine is skipped
+fn run_fork(base: &Machine, commands: &[String]) -> Option<String> {
+    let mut machine = base.clone();
+    let mut input: VecDeque<u8> = commands
+        .iter()
+        .flat_map(|c| c.bytes().chain(std::iter::once(b'\n')))
+        .collect();
+    let mut output = String::new();
+    for _ in 0..TASK_CYCLE_LIMIT {
+        match machine.execute(|| input.pop_front()) {
+            StepEvent::Output(c) => output.push(c),
+            StepEvent::Input(_) => output.clear(),
+            StepEvent::AwaitingInput | StepEvent::Halted => return Some(output),
+            StepEvent::None => {}
+        }
+    }
+    None
+}
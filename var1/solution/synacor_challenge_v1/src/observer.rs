@@ -0,0 +1,85 @@
+use crate::events::VmEvent;
+use crate::parser::PROMPT;
+use crate::plugin::VmPlugin;
+use log::trace;
+use std::sync::mpsc::{self, Receiver};
+
+/// The game output as seen by the observer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    /// The complete line printed by the game, including the trailing new line. The prompt line is
+    /// delivered without it, once the game starts reading the input
+    Line(String),
+    /// The complete response up to the prompt (excluding it)
+    Response(ResponseBlock),
+}
+
+/// The lines printed by the game between two prompts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseBlock {
+    /// The title of the room the response belongs to: the room shown by it, or else the room
+    /// shown last, e.g. for the response to 'take tablet'
+    pub room: Option<String>,
+    pub text: String,
+}
+
+/// This plugin passes the game output to the callback, both the single lines and the whole
+/// responses, so the consumers are kept apart from the VM internals. Every consumer registers its
+/// own observer, e.g. with `run_with_plugins`
+pub struct OutputObserver {
+    callback: Box<dyn FnMut(Output) + Send>,
+    block: String,
+    room: Option<String>,
+}
+
+impl OutputObserver {
+    pub fn new(callback: impl FnMut(Output) + Send + 'static) -> Self {
+        OutputObserver {
+            callback: Box::new(callback),
+            block: String::new(),
+            room: None,
+        }
+    }
+
+    /// Returns the observer sending the output to the channel together with its receiving end.
+    /// The output is dropped silently, once the receiver is gone
+    pub fn channel() -> (Self, Receiver<Output>) {
+        let (sender, receiver) = mpsc::channel();
+        let observer = OutputObserver::new(move |output| {
+            if sender.send(output).is_err() {
+                trace!("output receiver has been dropped");
+            }
+        });
+        (observer, receiver)
+    }
+}
+
+impl VmPlugin for OutputObserver {
+    fn name(&self) -> &str {
+        "output observer"
+    }
+
+    fn on_output(&mut self, line: &str) {
+        // The prompt closes the response, so its line is not a part of the next one
+        if line.trim_end() != PROMPT {
+            self.block.push_str(line);
+        }
+        (self.callback)(Output::Line(line.to_string()));
+    }
+
+    fn on_prompt(&mut self) {
+        let block = ResponseBlock {
+            room: self.room.clone(),
+            text: std::mem::take(&mut self.block),
+        };
+        (self.callback)(Output::Response(block));
+    }
+
+    fn on_event(&mut self, event: &VmEvent) {
+        if let VmEvent::ResponseParsed(response) = event
+            && let Some(title) = response.title
+        {
+            self.room = Some(title.to_string());
+        }
+    }
+}
@@ -0,0 +1,534 @@
+use log::{debug, trace};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// The game prints this line every time it is ready to receive the next command
+pub const PROMPT: &str = "What do you do?";
+const DONT_UNDERSTAND: &str = "I don't understand";
+const ITEMS_HEADER: &str = "Things of interest here:";
+const INVENTORY_HEADER: &str = "Your inventory:";
+const NOT_CARRIED: &str = "You can't find that in your pack.";
+const TELEPORTER_ACTIVATED: &str = "You activate the teleporter";
+const ITEM_PLACED: &str = "You place the ";
+// The response with at least this number of paragraphs is a narrative, e.g. the strange book
+const NARRATIVE_PARAGRAPHS: usize = 3;
+// The messages printed when the item cannot be used yet
+const NOTHING_HAPPENS: [&str; 2] = ["You'll have to find something", "You aren't sure"];
+// The messages of the 'use' command, which change the inventory: (message, consumed item, from, to)
+const TRANSFORMATIONS: [(&str, Option<&str>, &str, &str); 2] = [
+    (
+        "You fill your lantern with oil.",
+        Some("can"),
+        "empty lantern",
+        "lantern",
+    ),
+    ("You light your lantern.", None, "lantern", "lit lantern"),
+];
+
+/// This structure holds the parsed pieces of a single game response, i.e. everything the game
+/// printed between two prompts
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ResponseParts {
+    pub title: Option<String>,
+    pub description: String,
+    pub items: Vec<String>,
+    pub exits: Vec<String>,
+    pub inventory: Option<Vec<String>>,
+    pub dont_understand: bool,
+    // Any text which is not a part of the room description (e.g. "Taken." or help messages)
+    pub pretext: String,
+}
+
+impl ResponseParts {
+    pub fn is_room(&self) -> bool {
+        self.title.is_some()
+    }
+}
+
+/// The borrowed variant of the ResponseParts, which points into the raw text of the response
+/// instead of allocating the strings. The description and pretext are only allocated, when they
+/// are joined from several lines
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ResponsePartsRef<'a> {
+    pub title: Option<&'a str>,
+    pub description: Cow<'a, str>,
+    pub items: Vec<&'a str>,
+    pub exits: Vec<&'a str>,
+    pub inventory: Option<Vec<&'a str>>,
+    pub dont_understand: bool,
+    pub pretext: Cow<'a, str>,
+}
+
+impl ResponsePartsRef<'_> {
+    pub fn is_room(&self) -> bool {
+        self.title.is_some()
+    }
+
+    pub fn into_owned(self) -> ResponseParts {
+        let owned = |list: Vec<&str>| list.into_iter().map(str::to_string).collect();
+        ResponseParts {
+            title: self.title.map(str::to_string),
+            description: self.description.into_owned(),
+            items: owned(self.items),
+            exits: owned(self.exits),
+            inventory: self.inventory.map(owned),
+            dont_understand: self.dont_understand,
+            pretext: self.pretext.into_owned(),
+        }
+    }
+}
+
+impl From<ResponsePartsRef<'_>> for ResponseParts {
+    fn from(parts: ResponsePartsRef<'_>) -> Self {
+        parts.into_owned()
+    }
+}
+
+/// The best-effort result of the lossy parsing together with the problems found on the way
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LossyParse {
+    pub parts: ResponseParts,
+    pub diagnostics: Vec<String>,
+}
+
+/// The game response completed by the prompt, as collected by the streaming parser
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompletedResponse {
+    /// The raw text of the response including the prompt
+    pub text: String,
+    pub diagnostics: Vec<String>,
+    builder: ResponseBuilder,
+}
+
+impl CompletedResponse {
+    /// Returns the parts of the response borrowed from its text
+    pub fn parts(&self) -> ResponsePartsRef<'_> {
+        self.builder.parts(&self.text)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+enum Section {
+    #[default]
+    Pretext,
+    Description,
+    Items,
+    Exits,
+    Inventory,
+}
+
+// The byte range of the response text
+type Span = Range<usize>;
+
+/// This structure assembles the parts of the response line by line. It only remembers where the
+/// parts are in the text, so the text can keep growing while the response is being parsed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct ResponseBuilder {
+    title: Option<Span>,
+    description: Vec<Span>,
+    items: Vec<Span>,
+    exits: Vec<Span>,
+    inventory: Option<Vec<Span>>,
+    dont_understand: bool,
+    pretext: Vec<Span>,
+    section: Section,
+    // Nothing after the prompt belongs to the response
+    done: bool,
+}
+
+impl ResponseBuilder {
+    /// This method consumes the line of the response, which starts at the given byte of the text
+    fn line(&mut self, line: &str, start: usize) {
+        let line = line.trim_end();
+        if self.done {
+            return;
+        }
+        if line == PROMPT {
+            self.done = true;
+            return;
+        }
+        if let Some(title) = line.strip_prefix("== ").and_then(|l| l.strip_suffix(" =="))
+            && !title.is_empty()
+        {
+            self.title = Some(start + 3..start + 3 + title.len());
+            self.section = Section::Description;
+            return;
+        }
+        if line.starts_with(DONT_UNDERSTAND) {
+            self.dont_understand = true;
+        }
+        if line == ITEMS_HEADER {
+            self.section = Section::Items;
+            return;
+        }
+        if line == INVENTORY_HEADER {
+            self.inventory = Some(vec![]);
+            self.section = Section::Inventory;
+            return;
+        }
+        if is_exits_header(line) {
+            self.section = Section::Exits;
+            return;
+        }
+        let whole = start..start + line.len();
+        let entry = start + 2..start + line.len();
+        match (&self.section, line.starts_with("- ")) {
+            (Section::Items, true) => self.items.push(entry),
+            (Section::Exits, true) => self.exits.push(entry),
+            (Section::Inventory, true) => {
+                if let Some(inventory) = self.inventory.as_mut() {
+                    inventory.push(entry)
+                }
+            }
+            (_, _) if line.is_empty() => {
+                // An empty line closes the list sections
+                if self.section != Section::Description {
+                    self.section = Section::Pretext;
+                }
+            }
+            (Section::Description, _) => self.description.push(whole),
+            (_, _) => self.pretext.push(whole),
+        }
+    }
+
+    /// This method resolves the parts in the text the lines were taken from
+    fn parts<'a>(&self, text: &'a str) -> ResponsePartsRef<'a> {
+        let slice = |span: &Span| &text[span.clone()];
+        let list = |spans: &[Span]| spans.iter().map(slice).collect();
+        let join = |spans: &[Span]| match spans {
+            [] => Cow::Borrowed(""),
+            [line] => Cow::Borrowed(slice(line)),
+            lines => Cow::Owned(lines.iter().map(slice).collect::<Vec<&str>>().join("\n")),
+        };
+        let parts = ResponsePartsRef {
+            title: self.title.as_ref().map(slice),
+            description: join(&self.description),
+            items: list(&self.items),
+            exits: list(&self.exits),
+            inventory: self.inventory.as_deref().map(list),
+            dont_understand: self.dont_understand,
+            pretext: join(&self.pretext),
+        };
+        debug!(
+            "parsed response: title {:?}, {} items, {} exits",
+            parts.title,
+            parts.items.len(),
+            parts.exits.len()
+        );
+        parts
+    }
+}
+
+/// This structure parses the game output. It either parses the whole response at once, or
+/// consumes the output character by character and completes the response once the prompt arrives
+#[derive(Debug, Default)]
+pub struct OutputParser {
+    // The response in progress
+    text: String,
+    line_start: usize,
+    builder: ResponseBuilder,
+}
+
+impl OutputParser {
+    pub fn new() -> Self {
+        OutputParser::default()
+    }
+
+    /// This method splits the raw text of the game response into the ResponseParts
+    pub fn parse(&self, response: &str) -> ResponseParts {
+        self.parse_ref(response).into_owned()
+    }
+
+    /// This method splits the raw text of the game response into the parts borrowed from it
+    pub fn parse_ref<'a>(&self, response: &'a str) -> ResponsePartsRef<'a> {
+        trace!("parsing response of {} bytes", response.len());
+        let mut builder = ResponseBuilder::default();
+        let mut start = 0;
+        for line in response.split_inclusive('\n') {
+            builder.line(line.trim_end_matches('\n'), start);
+            start += line.len();
+        }
+        builder.parts(response)
+    }
+
+    /// This method consumes the next character of the game output. Every line is parsed once it is
+    /// completed, so the response is ready as soon as the prompt is printed
+    pub fn push(&mut self, c: char) -> Option<CompletedResponse> {
+        let end = self.text.len();
+        self.text.push(c);
+        if c == '\n' {
+            self.builder
+                .line(&self.text[self.line_start..end], self.line_start);
+            self.line_start = self.text.len();
+            return None;
+        }
+        if c != '?' || !self.text[self.line_start..].ends_with(PROMPT) {
+            return None;
+        }
+        self.builder
+            .line(&self.text[self.line_start..], self.line_start);
+        let response = CompletedResponse {
+            text: std::mem::take(&mut self.text),
+            diagnostics: vec![],
+            builder: std::mem::take(&mut self.builder),
+        };
+        self.line_start = 0;
+        let diagnostics = validate(&response.text, &response.parts());
+        if !diagnostics.is_empty() {
+            debug!("streaming parser found problems: {:?}", diagnostics);
+        }
+        Some(CompletedResponse {
+            diagnostics,
+            ..response
+        })
+    }
+
+    /// This method drops the response in progress
+    pub fn reset(&mut self) {
+        trace!(
+            "dropping {} bytes of the response in progress",
+            self.text.len()
+        );
+        *self = OutputParser::default();
+    }
+
+    /// This method parses any bytes without panicking. The invalid UTF-8 sequences are replaced,
+    /// and everything suspicious is reported in the diagnostics instead of failing, so it suits
+    /// both the fuzzing and the automatic exploration
+    pub fn parse_lossy(&self, response: &[u8]) -> LossyParse {
+        let text = String::from_utf8_lossy(response);
+        let mut diagnostics = vec![];
+        if let Cow::Owned(_) = text {
+            diagnostics.push(
+                "the response is not valid UTF-8, the invalid bytes are replaced".to_string(),
+            );
+        }
+        if text
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+        {
+            diagnostics.push("the response contains control characters".to_string());
+        }
+        let parts = self.parse_ref(&text);
+        diagnostics.extend(validate(&text, &parts));
+        if !diagnostics.is_empty() {
+            debug!("lossy parsing found problems: {:?}", diagnostics);
+        }
+        LossyParse {
+            parts: parts.into_owned(),
+            diagnostics,
+        }
+    }
+}
+
+/// What has happened after the player used the item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UseEffect {
+    /// The code for the challenge website has appeared
+    CodeRevealed(String),
+    /// The item in the inventory has turned into another one, e.g. the lantern into the lit lantern
+    Transformed { from: String, to: String },
+    /// The item has left the inventory, e.g. the oil is poured into the lantern
+    Consumed(String),
+    /// The player has been moved to another place
+    Teleported,
+    /// The item is carried, but it cannot be used yet
+    NothingHappens,
+    /// The item is not in the inventory
+    NotCarried,
+    /// The text is not recognised
+    Unknown(String),
+}
+
+/// This function classifies the response to the 'use <item>' command
+pub fn use_effects(item: &str, parts: &ResponsePartsRef) -> Vec<UseEffect> {
+    let text = parts.pretext.as_ref();
+    if text.starts_with(NOT_CARRIED) {
+        return vec![UseEffect::NotCarried];
+    }
+    let mut effects = vec![];
+    if text.contains(TELEPORTER_ACTIVATED) {
+        effects.push(UseEffect::Teleported);
+    }
+    for (message, consumed, from, to) in TRANSFORMATIONS {
+        if !text.contains(message) {
+            continue;
+        }
+        if let Some(consumed) = consumed {
+            effects.push(UseEffect::Consumed(consumed.to_string()));
+        }
+        effects.push(UseEffect::Transformed {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+    }
+    if text.starts_with(ITEM_PLACED) {
+        effects.push(UseEffect::Consumed(item.to_string()));
+    }
+    effects.extend(find_codes(text).into_iter().map(UseEffect::CodeRevealed));
+    if effects.is_empty() {
+        if text.is_empty() || NOTHING_HAPPENS.iter().any(|m| text.starts_with(m)) {
+            effects.push(UseEffect::NothingHappens);
+        } else {
+            effects.push(UseEffect::Unknown(text.to_string()));
+        }
+    }
+    effects
+}
+
+/// This function returns the text of the long multi-paragraph response (e.g. the strange book or
+/// the journal) with the paragraphs kept apart. The ordinary responses give None
+pub fn narrative(response: &str, parts: &ResponsePartsRef) -> Option<String> {
+    if parts.is_room() || parts.inventory.is_some() {
+        return None;
+    }
+    let text = response.split(PROMPT).next().unwrap_or_default();
+    let paragraphs: Vec<&str> = text
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect();
+    (paragraphs.len() >= NARRATIVE_PARAGRAPHS).then(|| paragraphs.join("\n\n"))
+}
+
+/// This function looks for the codes of the challenge website in the text. The codes are 12
+/// characters long alphanumeric words, which mix the letter case unlike the ordinary words
+pub fn find_codes(text: &str) -> Vec<String> {
+    const CODE_LENGTH: usize = 12;
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| w.len() == CODE_LENGTH)
+        .filter(|w| {
+            w.chars()
+                .skip(1)
+                .any(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        })
+        .map(|w| w.to_string())
+        .collect()
+}
+
+// Matches both "There is 1 exit:" and "There are 3 exits:"
+fn is_exits_header(line: &str) -> bool {
+    (line.starts_with("There is ") || line.starts_with("There are "))
+        && (line.ends_with(" exit:") || line.ends_with(" exits:"))
+}
+
+// Returns the number of exits announced by the exits header
+fn announced_exits(line: &str) -> Option<usize> {
+    if !is_exits_header(line) {
+        return None;
+    }
+    line.split_whitespace().nth(2)?.parse().ok()
+}
+
+/// This function checks the parsed response against the raw text and returns the descriptions of
+/// the problems found, e.g. the list lines which did not get into any list. The empty result means
+/// the response is parsed consistently
+pub fn validate(response: &str, parts: &ResponsePartsRef) -> Vec<String> {
+    let mut problems = vec![];
+    if !response.contains(PROMPT) {
+        problems.push("the response is not terminated by the prompt".to_string());
+    }
+    let announced: Vec<usize> = response.lines().filter_map(announced_exits).collect();
+    match announced.as_slice() {
+        [] if parts.is_room() => problems.push("the room has no exits header".to_string()),
+        [] => {}
+        [count] if *count != parts.exits.len() => problems.push(format!(
+            "{} exits are announced, but {} are parsed",
+            count,
+            parts.exits.len()
+        )),
+        [_] => {}
+        _ => problems.push(format!("{} exits headers are found", announced.len())),
+    }
+    if response.lines().any(|l| l.trim_end() == ITEMS_HEADER) && parts.items.is_empty() {
+        problems.push("the items header is not followed by any items".to_string());
+    }
+    if let Some(line) = parts.pretext.lines().find(|l| l.starts_with("- ")) {
+        problems.push(format!("list line '{}' is out of any list", line));
+    }
+    if parts.is_room() && parts.description.is_empty() {
+        problems.push("the room has no description".to_string());
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::rom;
+    use crate::machine::{Machine, StepEvent};
+
+    const ROOM: &str = "== Foothills ==\nYou find yourself standing at the base of an enormous mountain.\n\nThings of interest here:\n- tablet\n\nThere are 2 exits:\n- doorway\n- south\n\nWhat do you do?";
+
+    // Returns the output of the small ROM printing the text and halting, as read by the VM
+    fn game_output(text: &str) -> Vec<u8> {
+        let mut program: Vec<u16> = text.bytes().flat_map(|b| [19, u16::from(b)]).collect();
+        program.push(0);
+        let mut machine = Machine::new();
+        machine.load_rom(&rom(&program)).unwrap();
+        let mut output = String::new();
+        while !machine.is_halted() {
+            if let StepEvent::Output(c) = machine.execute(|| None) {
+                output.push(c);
+            }
+        }
+        output.into_bytes()
+    }
+
+    #[test]
+    fn parses_the_room_printed_by_the_game() {
+        let parsed = OutputParser::new().parse_lossy(&game_output(ROOM));
+        assert_eq!(parsed.diagnostics, Vec::<String>::new());
+        assert_eq!(parsed.parts.title.as_deref(), Some("Foothills"));
+        assert_eq!(parsed.parts.items, ["tablet"]);
+        assert_eq!(parsed.parts.exits, ["doorway", "south"]);
+        assert!(!parsed.parts.dont_understand);
+    }
+
+    #[test]
+    fn reports_the_invalid_bytes() {
+        let mut response = game_output(ROOM);
+        response.splice(3..3, [0xff, 0x07]);
+        let parsed = OutputParser::new().parse_lossy(&response);
+        assert_eq!(
+            parsed.diagnostics,
+            [
+                "the response is not valid UTF-8, the invalid bytes are replaced",
+                "the response contains control characters",
+            ]
+        );
+        assert_eq!(parsed.parts.exits, ["doorway", "south"]);
+    }
+
+    #[test]
+    fn reports_the_truncated_response() {
+        let response = game_output(ROOM);
+        let parsed = OutputParser::new().parse_lossy(&response[..response.len() / 2]);
+        assert!(
+            parsed
+                .diagnostics
+                .contains(&"the response is not terminated by the prompt".to_string())
+        );
+    }
+
+    // The same property as the fuzz target checks, on the corpus derived from the room
+    #[test]
+    fn survives_the_corrupted_responses() {
+        let response = game_output(ROOM);
+        let parser = OutputParser::new();
+        let mut seed: u32 = 0x2545_f491;
+        for cut in 0..=response.len() {
+            let _ = parser.parse_lossy(&response[..cut]);
+            let _ = parser.parse_lossy(&response[cut..]);
+            let mut corrupted = response.clone();
+            for _ in 0..8 {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let at = seed as usize % corrupted.len();
+                corrupted[at] = (seed >> 24) as u8;
+            }
+            let _ = parser.parse_lossy(&corrupted);
+        }
+    }
+}
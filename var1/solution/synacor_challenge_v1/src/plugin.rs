@@ -0,0 +1,48 @@
+use crate::events::VmEvent;
+use std::any::Any;
+use std::time::Duration;
+
+/// The extension of the VM. All hooks do nothing by default, so a plugin implements only the ones
+/// it is interested in. The plugins are called in the order of registration
+pub trait VmPlugin: Any + Send {
+    /// The name of the plugin, which is used in the logs
+    fn name(&self) -> &str;
+
+    /// This hook is called once, before the VM executes the first instruction
+    fn on_start(&mut self) {}
+
+    /// This hook is called before the instruction at the address is executed
+    fn on_instruction(&mut self, _address: u16) {}
+
+    /// This hook is called for every line printed by the game. The prompt line comes without the
+    /// trailing new line, once the game starts reading the input
+    fn on_output(&mut self, _line: &str) {}
+
+    /// This hook is called for every command entered by the player, including slash '/' commands
+    fn on_command(&mut self, _command: &str) {}
+
+    /// This hook is called once the game has printed the prompt and the response before it has
+    /// been parsed. Unlike the 'in' instruction waiting for the empty input buffer, it tells the
+    /// game is ready for the next command, even while the queued input is still there to read
+    fn on_prompt(&mut self) {}
+
+    /// This hook is called once the program halts with the number of the executed instructions
+    fn on_halt(&mut self, _cycles: u64) {}
+
+    /// This hook is called when the user has not typed anything for the input timeout of the async
+    /// main loop. The returned command is sent to the game instead of the user input
+    fn on_idle(&mut self, _idle: Duration) -> Option<String> {
+        None
+    }
+
+    /// This hook is called once the game waits for the command and there is no input left. The
+    /// returned commands are queued the same way as the replayed ones, before the user is asked,
+    /// so the plugin (e.g. the test driving the game) feeds the game without simulating stdin
+    fn queued_input(&mut self) -> Vec<String> {
+        vec![]
+    }
+
+    /// This hook receives every event, including the ones without a dedicated hook (e.g. the
+    /// parsed responses or the found codes)
+    fn on_event(&mut self, _event: &VmEvent) {}
+}
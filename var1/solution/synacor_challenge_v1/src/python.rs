@@ -0,0 +1,177 @@
+use crate::machine::{Machine, StepEvent};
+use crate::maze;
+use crate::parser::{OutputParser, PROMPT};
+use log::{debug, trace};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use std::collections::VecDeque;
+
+// (id, title, description, exits, items) of the room, as it is seen from Python
+type RoomTuple = (u16, String, String, Vec<String>, Vec<String>);
+
+/// The map of the game world, which is built from the game responses
+#[pyclass(name = "MazeAnalyzer", unsendable)]
+pub struct PyMazeAnalyzer {
+    maze: maze::MazeAnalyzer,
+    parser: OutputParser,
+}
+
+#[pymethods]
+impl PyMazeAnalyzer {
+    #[new]
+    fn new() -> Self {
+        PyMazeAnalyzer {
+            maze: maze::MazeAnalyzer::new(),
+            parser: OutputParser::new(),
+        }
+    }
+
+    /// This method should be called every time the player issues a command
+    fn command(&mut self, command: &str) {
+        self.maze.command(command);
+    }
+
+    /// This method parses the game response and records the room, if there is any
+    fn observe(&mut self, response: &str) {
+        let parts = self.parser.parse_ref(response);
+        self.maze.observe(&parts);
+    }
+
+    /// Returns all discovered rooms as (id, title, description, exits, items) tuples
+    fn rooms(&self) -> Vec<RoomTuple> {
+        self.maze
+            .nodes()
+            .iter()
+            .map(|n| {
+                (
+                    n.id,
+                    n.title.clone(),
+                    n.description.clone(),
+                    n.exits.clone(),
+                    n.items.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns all known passages as (source, exit, destination) tuples
+    fn edges(&self) -> Vec<(u16, String, u16)> {
+        self.maze
+            .edges()
+            .map(|(from, exit, to)| (from, exit.to_string(), to))
+            .collect()
+    }
+
+    #[getter]
+    fn current_room(&self) -> Option<u16> {
+        self.maze.current_node().map(|n| n.id)
+    }
+
+    #[pyo3(signature = (radius = 3))]
+    fn render_map(&self, radius: usize) -> String {
+        self.maze.render_map(radius)
+    }
+}
+
+/// The virtual machine, which can be driven from Python one command at a time
+#[pyclass(name = "SynacorVm", unsendable)]
+pub struct PyVm {
+    machine: Machine,
+    input: VecDeque<u8>,
+    maze: Py<PyMazeAnalyzer>,
+    intro: String,
+}
+
+impl PyVm {
+    /// This method executes the program until the game asks for the next command or halts, and
+    /// returns the produced text
+    fn run_until_input(&mut self) -> String {
+        let mut output = String::new();
+        while !self.machine.is_halted() {
+            match self.machine.execute(|| self.input.pop_front()) {
+                StepEvent::Output(c) => output.push(c),
+                StepEvent::AwaitingInput => break,
+                StepEvent::Input(_) | StepEvent::Halted | StepEvent::None => {}
+            }
+        }
+        trace!("collected {} characters of output", output.len());
+        output
+    }
+}
+
+#[pymethods]
+impl PyVm {
+    #[new]
+    fn new(py: Python<'_>, rom: &[u8]) -> PyResult<Self> {
+        let mut machine = Machine::new();
+        machine
+            .load_rom(rom)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let mut vm = PyVm {
+            machine,
+            input: VecDeque::new(),
+            maze: Py::new(py, PyMazeAnalyzer::new())?,
+            intro: String::new(),
+        };
+        vm.intro = vm.run_until_input();
+        vm.maze.borrow_mut(py).observe(&vm.intro);
+        Ok(vm)
+    }
+
+    /// This method sends the command to the game and returns the response. The response is also
+    /// passed to the maze analyzer
+    fn command(&mut self, py: Python<'_>, command: &str) -> PyResult<String> {
+        if self.machine.is_halted() {
+            return Err(PyRuntimeError::new_err("the program has halted"));
+        }
+        debug!("python command '{}'", command);
+        self.input.extend(command.trim_end().bytes());
+        self.input.push_back(b'\n');
+        let output = self.run_until_input();
+        let mut maze = self.maze.borrow_mut(py);
+        maze.command(command);
+        if output.contains(PROMPT) {
+            maze.observe(&output);
+        }
+        Ok(output)
+    }
+
+    /// The text printed by the game before the first prompt
+    #[getter]
+    fn intro(&self) -> &str {
+        &self.intro
+    }
+
+    #[getter]
+    fn maze(&self, py: Python<'_>) -> Py<PyMazeAnalyzer> {
+        self.maze.clone_ref(py)
+    }
+
+    #[getter]
+    fn registers(&self) -> [u16; 8] {
+        self.machine.registers()
+    }
+
+    #[getter]
+    fn position(&self) -> u16 {
+        self.machine.position()
+    }
+
+    #[getter]
+    fn halted(&self) -> bool {
+        self.machine.is_halted()
+    }
+
+    /// True, once the game has printed the prompt and waits for the next command
+    #[getter]
+    fn awaiting_input(&self) -> bool {
+        self.machine.is_awaiting_input() && self.input.is_empty()
+    }
+}
+
+#[pymodule(name = "synacor_challenge_v1")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyVm>()?;
+    m.add_class::<PyMazeAnalyzer>()?;
+    Ok(())
+}
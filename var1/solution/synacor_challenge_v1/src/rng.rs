@@ -0,0 +1,195 @@
+use crate::disasm;
+use crate::machine::{MAX, Machine};
+use log::debug;
+use std::fmt;
+
+/// The first line of every symbols file
+pub const SYMBOLS_HEADER: &str = "# synacor symbols v1";
+// The opcodes of the update: the state is read, multiplied, increased and written back
+const RMEM_OPCODE: u16 = 15;
+const MULT_OPCODE: u16 = 10;
+const ADD_OPCODE: u16 = 9;
+const WMEM_OPCODE: u16 = 16;
+// The words of 'rmem', 'mult' and 'add' before the 'wmem' of the update
+const STORE_OFFSET: u16 = 11;
+// The words of the whole update
+const UPDATE_LENGTH: usize = 14;
+
+/// The linear congruential generator of the ROM, updating its state in the memory with
+/// 'rmem r s; mult r r m; add r r c; wmem s r'
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generator {
+    /// The address of the 'rmem' reading the state
+    pub address: u16,
+    /// The address of the 'wmem' storing the next state
+    pub store: u16,
+    /// The register computing the next state
+    pub register: usize,
+    /// The operand of 'wmem' with the address of the state: the literal one or the register
+    /// holding it
+    pub state: u16,
+    pub multiplier: u16,
+    pub increment: u16,
+}
+
+impl Generator {
+    /// Returns the address of the state, unless it is computed at runtime
+    pub fn state_address(&self) -> Option<u16> {
+        (self.state < MAX).then_some(self.state)
+    }
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>5} ({:#06x}): state * {} + {}, stored by {} at {:>5} ({:#06x})",
+            self.address,
+            self.address,
+            self.multiplier,
+            self.increment,
+            disasm::format(&[WMEM_OPCODE, self.state, MAX + self.register as u16]),
+            self.store,
+            self.store
+        )
+    }
+}
+
+/// This function tells whether the word is the operand naming the register
+fn register(word: u16) -> Option<usize> {
+    (MAX..MAX + 8)
+        .contains(&word)
+        .then(|| (word - MAX) as usize)
+}
+
+/// This function recognizes the update of the generator at the address
+fn update_at(machine: &Machine, address: u16) -> Option<Generator> {
+    let words: [u16; UPDATE_LENGTH] = std::array::from_fn(|i| machine.word(address + i as u16));
+    let [
+        RMEM_OPCODE,
+        r,
+        state,
+        MULT_OPCODE,
+        r1,
+        r2,
+        multiplier,
+        ADD_OPCODE,
+        r3,
+        r4,
+        increment,
+        WMEM_OPCODE,
+        target,
+        value,
+    ] = words
+    else {
+        return None;
+    };
+    let same = [r1, r2, r3, r4, value].iter().all(|w| *w == r);
+    if !same || state != target || multiplier >= MAX || increment >= MAX {
+        return None;
+    }
+    Some(Generator {
+        address,
+        store: address + STORE_OFFSET,
+        register: register(r)?,
+        state,
+        multiplier,
+        increment,
+    })
+}
+
+/// This function scans the memory for the linear congruential updates of the pseudo-random state.
+/// Every address is tried, so the update is found even where the linear disassembly would lose
+/// the sync
+pub fn find(machine: &Machine) -> Vec<Generator> {
+    let generators: Vec<Generator> = (0..MAX - UPDATE_LENGTH as u16)
+        .filter_map(|address| update_at(machine, address))
+        .collect();
+    debug!("found {} pseudo-random generators", generators.len());
+    generators
+}
+
+/// This function lists the generators in the symbols file, one 'address name' line per symbol.
+/// The state held at the address computed at runtime has the comment naming its register
+pub fn symbols(generators: &[Generator]) -> String {
+    let mut lines = vec![SYMBOLS_HEADER.to_string()];
+    for (n, generator) in generators.iter().enumerate() {
+        let name = if n == 0 {
+            "rng".to_string()
+        } else {
+            format!("rng{}", n)
+        };
+        lines.push(format!("{:#06x} {}_update", generator.address, name));
+        lines.push(format!("{:#06x} {}_store", generator.store, name));
+        match generator.state_address() {
+            Some(address) => lines.push(format!("{:#06x} {}_state", address, name)),
+            None => lines.push(format!(
+                "# {}_state is the address held in r{} at {}_store",
+                name,
+                generator.state - MAX,
+                name
+            )),
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::rom;
+
+    const R5: u16 = MAX + 5;
+    const R6: u16 = MAX + 6;
+
+    /// The machine with the update of r6, which adds the given operand to the multiplied state
+    fn machine(prefix: &[u16], state: u16, added: u16) -> Machine {
+        let mut program = prefix.to_vec();
+        program.extend([
+            15, R6, state, 10, R6, R6, 5249, 9, R6, added, 12345, 16, state, R6, 0,
+        ]);
+        let mut machine = Machine::new();
+        machine.load_rom(&rom(&program)).unwrap();
+        machine
+    }
+
+    #[test]
+    fn finds_the_update_of_the_state() {
+        let generators = find(&machine(&[21], 100, R6));
+        assert_eq!(
+            generators,
+            vec![Generator {
+                address: 1,
+                store: 12,
+                register: 6,
+                state: 100,
+                multiplier: 5249,
+                increment: 12345,
+            }]
+        );
+        assert_eq!(
+            generators[0].to_string(),
+            "    1 (0x0001): state * 5249 + 12345, stored by wmem 100 r6 at    12 (0x000c)"
+        );
+        assert_eq!(
+            symbols(&generators),
+            "# synacor symbols v1\n0x0001 rng_update\n0x000c rng_store\n0x0064 rng_state\n"
+        );
+    }
+
+    #[test]
+    fn skips_the_update_mixing_the_other_register() {
+        assert!(find(&machine(&[], 100, MAX + 1)).is_empty());
+    }
+
+    #[test]
+    fn names_the_register_holding_the_address_of_the_state() {
+        let generators = find(&machine(&[], R5, R6));
+        assert_eq!(generators.len(), 1);
+        assert_eq!(generators[0].state_address(), None);
+        assert!(
+            symbols(&generators)
+                .ends_with("\n# rng_state is the address held in r5 at rng_store\n")
+        );
+    }
+}
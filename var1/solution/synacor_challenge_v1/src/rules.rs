@@ -0,0 +1,60 @@
+use log::{debug, trace};
+use regex::Regex;
+use std::error::Error;
+
+// The separator between the pattern and the response in the rules file
+const SEPARATOR: &str = "=>";
+
+/// A single automation rule: when the game output matches the pattern, the response is sent
+#[derive(Debug)]
+pub struct Rule {
+    pub pattern: Regex,
+    pub response: String,
+}
+
+/// This structure holds the expect-style rules. Every line of the rules file looks like
+/// 'pattern => command', where the pattern is a regular expression applied to the game output.
+/// Empty lines and lines starting with '#' are ignored
+#[derive(Debug, Default)]
+pub struct ExpectRules {
+    rules: Vec<Rule>,
+}
+
+impl ExpectRules {
+    pub fn parse(lines: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut rules = vec![];
+        for (n, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((pattern, response)) = line.rsplit_once(SEPARATOR) else {
+                return Err(format!(
+                    "rule at line {} has no '{}' separator: {}",
+                    n + 1,
+                    SEPARATOR,
+                    line
+                )
+                .into());
+            };
+            let pattern = Regex::new(pattern.trim())
+                .map_err(|e| format!("rule at line {} has invalid pattern. Error: {}", n + 1, e))?;
+            trace!("parsed rule '{}' => '{}'", pattern, response.trim());
+            rules.push(Rule {
+                pattern,
+                response: response.trim().to_string(),
+            });
+        }
+        debug!("parsed {} expect rules", rules.len());
+        Ok(ExpectRules { rules })
+    }
+
+    /// Returns the responses of all rules matching the game output, in the order of the file
+    pub fn responses(&self, output: &str) -> Vec<String> {
+        self.rules
+            .iter()
+            .filter(|r| r.pattern.is_match(output))
+            .map(|r| r.response.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,47 @@
+use log::trace;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// This function writes the file as a whole or not at all: the contents go to the temporary file
+/// next to it, which then replaces the file, so the error on the way leaves the former file as it
+/// was. The missing directories are created. The existing file is only replaced when it may be
+/// overwritten, e.g. with --force of the command writing it
+pub fn write(path: &Path, contents: impl AsRef<[u8]>, overwrite: bool) -> io::Result<()> {
+    if !overwrite && path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists, add --force to overwrite it",
+                path.display()
+            ),
+        ));
+    }
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = temporary_path(path);
+    trace!("writing {} through {}", path.display(), temporary.display());
+    let result =
+        write_synced(&temporary, contents.as_ref()).and_then(|_| fs::rename(&temporary, path));
+    if result.is_err() {
+        // The partial file is of no use, and the former one is still there
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+// The hidden file in the same directory, so the rename does not cross the file systems
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map_or("file".into(), |n| n.to_string_lossy());
+    path.with_file_name(format!(".{}.{}.tmp", name, process::id()))
+}
@@ -0,0 +1,117 @@
+use crate::history::HistoryEntry;
+use crate::machine::Machine;
+use crate::maze::MazeAnalyzer;
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+mod diff;
+pub use diff::{MemoryRange, changed_memory, diff_states};
+
+/// The version of the state format. It is bumped, whenever the older files cannot be read anymore
+pub const STATE_FORMAT: u32 = 1;
+
+/// The complete state of the play session: the machine itself together with everything the VM has
+/// learned about the game. It is the single format for saving, resuming and forking the sessions
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VmState {
+    pub format: u32,
+    pub machine: Machine,
+    pub cycles: u64,
+    pub commands_history: Vec<HistoryEntry>,
+    pub inventory: Vec<String>,
+    pub codes: Vec<String>,
+    pub breakpoints: BTreeSet<u16>,
+    pub maze: Option<MazeAnalyzer>,
+}
+
+impl VmState {
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        let state: VmState = serde_json::from_str(json)?;
+        if state.format != STATE_FORMAT {
+            return Err(format!(
+                "state format {} is not supported (expected {})",
+                state.format, STATE_FORMAT
+            )
+            .into());
+        }
+        Ok(state)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        trace!("saving VM state to {}", path.display());
+        fs::write(path, self.to_json()?)?;
+        debug!("saved VM state to {}", path.display());
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        trace!("loading VM state from {}", path.display());
+        let state = VmState::from_json(&fs::read_to_string(path)?)?;
+        debug!(
+            "loaded VM state from {} ({} cycles executed)",
+            path.display(),
+            state.cycles
+        );
+        Ok(state)
+    }
+}
+
+/// The memory is saved as the list of 16-bit words, the same way the ROM is addressed
+pub(crate) mod memory_words {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(memory: &[u8; 1 << 16], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(
+            memory
+                .chunks_exact(2)
+                .map(|w| u16::from_le_bytes([w[0], w[1]])),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<[u8; 1 << 16], D::Error> {
+        let words = Vec::<u16>::deserialize(d)?;
+        if words.len() != 1 << 15 {
+            return Err(D::Error::invalid_length(words.len(), &"32768 memory words"));
+        }
+        let mut memory = [0; 1 << 16];
+        for (i, word) in words.iter().enumerate() {
+            memory[i * 2..i * 2 + 2].copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(memory)
+    }
+}
+
+/// JSON objects can only have string keys, so the maps with the compound keys are saved as the
+/// lists of (key, value) pairs
+pub(crate) mod pairs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+    use std::hash::Hash;
+
+    pub fn serialize<K, V, S>(map: &HashMap<K, V>, s: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        s.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, K, V, D>(d: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(d)?.into_iter().collect())
+    }
+}
@@ -0,0 +1,159 @@
+use super::VmState;
+use crate::history;
+use crate::machine::Address;
+
+// The changed words closer than this are reported as a single memory range
+const RANGE_GAP: usize = 4;
+// The hexdump excerpt shows at most this number of lines per range
+const EXCERPT_LINES: usize = 4;
+const WORDS_PER_LINE: usize = 8;
+
+/// A range of the memory words, which differ between two states
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryRange {
+    /// The address of the first word
+    pub start: u16,
+    /// The number of words, including the unchanged ones in the gaps
+    pub len: u16,
+}
+
+fn word(memory: &[u8; 1 << 16], address: usize) -> u16 {
+    u16::from_le_bytes([memory[address * 2], memory[address * 2 + 1]])
+}
+
+/// Returns the ranges of the memory words, which differ between the states
+pub fn changed_memory(a: &VmState, b: &VmState) -> Vec<MemoryRange> {
+    let mut ranges: Vec<MemoryRange> = vec![];
+    for address in 0..1 << 15 {
+        if word(&a.machine.memory, address) == word(&b.machine.memory, address) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(range) if address - (range.start + range.len) as usize <= RANGE_GAP => {
+                range.len = (address - range.start as usize + 1) as u16;
+            }
+            _ => ranges.push(MemoryRange {
+                start: address as u16,
+                len: 1,
+            }),
+        }
+    }
+    ranges
+}
+
+fn hexdump(memory: &[u8; 1 << 16], range: MemoryRange, sign: char) -> String {
+    let end = range.start as usize + range.len as usize;
+    let mut dump = String::new();
+    for (n, line_start) in (range.start as usize..end)
+        .step_by(WORDS_PER_LINE)
+        .enumerate()
+    {
+        if n == EXCERPT_LINES {
+            dump.push_str(&format!("  {} ...\n", sign));
+            break;
+        }
+        let words: Vec<String> = (line_start..end.min(line_start + WORDS_PER_LINE))
+            .map(|address| format!("{:04x}", word(memory, address)))
+            .collect();
+        dump.push_str(&format!(
+            "  {} {:#06x}: {}\n",
+            sign,
+            line_start,
+            words.join(" ")
+        ));
+    }
+    dump
+}
+
+/// This function reports what has changed between the states: the position, registers, stack,
+/// memory ranges (with the hexdump excerpts) and the commands entered in between
+pub fn diff_states(a: &VmState, b: &VmState) -> String {
+    let mut report = String::new();
+    report.push_str("***            Snapshot Diff              ***\n");
+    report.push_str(&format!("{}\n", "=".repeat(44)));
+    let (pos_a, pos_b) = (&a.machine.current_address, &b.machine.current_address);
+    if pos_a.0 == pos_b.0 {
+        report.push_str(&format!("{:<9}: {}\n", "position", pos_a));
+    } else {
+        report.push_str(&format!("{:<9}: {} -> {}\n", "position", pos_a, pos_b));
+    }
+    if a.machine.halt != b.machine.halt {
+        report.push_str(&format!(
+            "{:<9}: {} -> {}\n",
+            "halt", a.machine.halt, b.machine.halt
+        ));
+    }
+    report.push_str(&format!(
+        "{:<9}: {} -> {} (+{})\n",
+        "cycles",
+        a.cycles,
+        b.cycles,
+        b.cycles as i128 - a.cycles as i128
+    ));
+    report.push_str(&format!("{:<9}:\n", "registers"));
+    let changed_registers: Vec<usize> = (0..8)
+        .filter(|r| a.machine.registers[*r] != b.machine.registers[*r])
+        .collect();
+    if changed_registers.is_empty() {
+        report.push_str("  no changes\n");
+    }
+    for r in changed_registers {
+        report.push_str(&format!(
+            "  reg {}: {} -> {}\n",
+            r, a.machine.registers[r], b.machine.registers[r]
+        ));
+    }
+    report.push_str(&format!(
+        "{:<9}  (size: {} -> {}):\n",
+        "stack",
+        a.machine.stack.len(),
+        b.machine.stack.len()
+    ));
+    let depth = a.machine.stack.len().max(b.machine.stack.len());
+    let mut stack_changed = false;
+    for n in (0..depth).rev() {
+        let (old, new) = (a.machine.stack.get(n), b.machine.stack.get(n));
+        if old != new {
+            stack_changed = true;
+            let show = |v: Option<&u16>| v.map_or("-".to_string(), u16::to_string);
+            report.push_str(&format!("  [{}: {} -> {}]\n", n, show(old), show(new)));
+        }
+    }
+    if !stack_changed {
+        report.push_str("  no changes\n");
+    }
+    let ranges = changed_memory(a, b);
+    report.push_str(&format!("{:<9}  ({} ranges):\n", "memory", ranges.len()));
+    for range in ranges {
+        report.push_str(&format!(
+            "  {} - {} ({} words)\n",
+            Address(range.start),
+            Address(range.start + range.len - 1),
+            range.len
+        ));
+        report.push_str(&hexdump(&a.machine.memory, range, '-'));
+        report.push_str(&hexdump(&b.machine.memory, range, '+'));
+    }
+    let (a_commands, b_commands) = (history::commands(&a.commands_history), history::commands(&b.commands_history));
+    if b_commands.starts_with(&a_commands) {
+        let entered = &b_commands[a_commands.len()..];
+        report.push_str(&format!("{:<9}: {}\n", "commands", entered.join(", ")));
+    } else {
+        report.push_str(&format!(
+            "{:<9}: the histories diverge ({} vs {} commands)\n",
+            "commands",
+            a.commands_history.len(),
+            b.commands_history.len()
+        ));
+    }
+    if a.inventory != b.inventory {
+        report.push_str(&format!(
+            "{:<9}: [{}] -> [{}]\n",
+            "inventory",
+            a.inventory.join(", "),
+            b.inventory.join(", ")
+        ));
+    }
+    report.push_str(&format!("{}\n", "=".repeat(45)));
+    report
+}
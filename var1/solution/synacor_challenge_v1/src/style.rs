@@ -0,0 +1,38 @@
+// The colors are used only for the logs and the terminal output. Without the 'colors' feature
+// the same methods leave the text as is, so the core of the VM can be built without the terminal
+// related dependencies
+#[cfg(feature = "colors")]
+pub(crate) use colored::Colorize;
+
+#[cfg(not(feature = "colors"))]
+#[allow(dead_code)] // the maze analyzer uses more colors than the core
+pub(crate) trait Colorize {
+    fn red(&self) -> String;
+    fn green(&self) -> String;
+    fn yellow(&self) -> String;
+    fn magenta(&self) -> String;
+    fn bold(&self) -> String;
+    fn italic(&self) -> String;
+}
+
+#[cfg(not(feature = "colors"))]
+impl<T: std::fmt::Display + ?Sized> Colorize for T {
+    fn red(&self) -> String {
+        self.to_string()
+    }
+    fn green(&self) -> String {
+        self.to_string()
+    }
+    fn yellow(&self) -> String {
+        self.to_string()
+    }
+    fn magenta(&self) -> String {
+        self.to_string()
+    }
+    fn bold(&self) -> String {
+        self.to_string()
+    }
+    fn italic(&self) -> String {
+        self.to_string()
+    }
+}
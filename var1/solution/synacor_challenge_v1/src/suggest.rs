@@ -0,0 +1,90 @@
+use log::trace;
+
+/// Verbs which the game understands (taken from the output of the 'help' command)
+pub const KNOWN_VERBS: [&str; 7] = ["look", "go", "inv", "take", "drop", "use", "help"];
+
+/// This function calculates the Levenshtein edit distance between two strings
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b_chars.len()).collect();
+    let mut current: Vec<usize> = vec![0; b_chars.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b_chars.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b_chars.len()]
+}
+
+/// This function picks the closest candidate to the rejected command. The candidate is returned
+/// only if it is close enough to be considered a typo (roughly one mistake per 3 characters)
+pub fn closest_match(rejected: &str, candidates: &[String]) -> Option<String> {
+    let rejected = rejected.trim().to_lowercase();
+    if rejected.is_empty() {
+        return None;
+    }
+    let threshold = (rejected.chars().count() / 3).max(1);
+    let best = candidates
+        .iter()
+        .map(|c| (edit_distance(&rejected, c), c))
+        .filter(|(d, _)| *d > 0 && *d <= threshold)
+        .min_by_key(|(d, _)| *d);
+    trace!("closest match for '{}' is {:?}", rejected, best);
+    best.map(|(_, c)| c.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verbs() -> Vec<String> {
+        KNOWN_VERBS.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn counts_the_edits_between_the_words() {
+        assert_eq!(edit_distance("look", "look"), 0);
+        assert_eq!(edit_distance("lok", "look"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "inv"), 3);
+    }
+
+    #[test]
+    fn suggests_the_verb_for_the_typo() {
+        assert_eq!(closest_match("lok", &verbs()), Some("look".to_string()));
+        assert_eq!(closest_match(" TAKR ", &verbs()), Some("take".to_string()));
+    }
+
+    #[test]
+    fn allows_one_mistake_in_the_short_word() {
+        assert_eq!(closest_match("gp", &verbs()), Some("go".to_string()));
+        assert_eq!(closest_match("xy", &verbs()), None);
+    }
+
+    #[test]
+    fn allows_one_mistake_per_three_characters() {
+        let candidates = vec!["teleporter".to_string()];
+        assert_eq!(
+            closest_match("telaportr", &candidates),
+            Some("teleporter".to_string())
+        );
+        assert_eq!(closest_match("telaport", &candidates), None);
+    }
+
+    #[test]
+    fn suggests_nothing_for_the_exact_match_or_the_empty_command() {
+        assert_eq!(closest_match("look", &verbs()), None);
+        assert_eq!(closest_match("  ", &verbs()), None);
+    }
+
+    #[test]
+    fn prefers_the_first_candidate_of_the_tie() {
+        let candidates = vec!["use".to_string(), "uso".to_string()];
+        assert_eq!(closest_match("usa", &candidates), Some("use".to_string()));
+        let candidates = vec!["uso".to_string(), "use".to_string()];
+        assert_eq!(closest_match("usa", &candidates), Some("uso".to_string()));
+    }
+}
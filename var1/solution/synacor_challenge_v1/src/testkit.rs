@@ -0,0 +1,282 @@
+use crate::machine::{InputDecoder, Machine, RomTooLarge, StepEvent};
+use crate::parser;
+use log::{debug, info, trace};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The environment variable, which makes the checks write the golden files
+pub const UPDATE_GOLDEN_VAR: &str = "SYNACOR_UPDATE_GOLDEN";
+/// The game is stopped after this number of instructions, if it still runs
+pub const DEFAULT_MAX_CYCLES: u64 = 1_000_000_000;
+
+/// The output of the played session. The commands are echoed in it, the same way they are
+/// recorded by the VM
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub output: String,
+    pub cycles: u64,
+    pub halted: bool,
+}
+
+/// This function plays the ROM with the commands until the game halts, asks for more input than
+/// given, or executes the given number of instructions
+pub fn play(rom: &[u8], commands: &[String], max_cycles: u64) -> Result<Transcript, RomTooLarge> {
+    let mut machine = Machine::new();
+    machine.load_rom(rom)?;
+    let mut input: VecDeque<u8> = commands
+        .iter()
+        .flat_map(|c| c.bytes().chain(std::iter::once(b'\n')))
+        .collect();
+    let mut input_decoder = InputDecoder::default();
+    let mut output = String::new();
+    let mut cycles = 0;
+    while cycles < max_cycles {
+        cycles += 1;
+        match machine.execute(|| input.pop_front()) {
+            StepEvent::Output(c) => output.push(c),
+            StepEvent::Input(c) => output.extend(input_decoder.push(c)),
+            StepEvent::AwaitingInput => {
+                trace!("the commands are over after {} cycles", cycles);
+                break;
+            }
+            StepEvent::Halted => break,
+            StepEvent::None => {}
+        }
+    }
+    debug!("played {} commands in {} cycles", commands.len(), cycles);
+    Ok(Transcript {
+        output,
+        cycles,
+        halted: machine.is_halted(),
+    })
+}
+
+/// This function plays the ROM file with the commands of the replay file
+pub fn play_files(rom: &Path, replay: &Path) -> Result<Transcript, Box<dyn Error>> {
+    let rom = fs::read(rom)?;
+    let commands: Vec<String> = fs::read_to_string(replay)?
+        .lines()
+        .map(str::to_string)
+        .collect();
+    Ok(play(&rom, &commands, DEFAULT_MAX_CYCLES)?)
+}
+
+/// The differences between the transcripts, which are not worth failing the test
+#[derive(Debug, Clone)]
+pub struct Normalization {
+    pub trim_trailing_whitespace: bool,
+    pub collapse_blank_lines: bool,
+    /// Replace the codes of the challenge website with '<CODE>', since they differ between ROMs
+    pub mask_codes: bool,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Normalization {
+            trim_trailing_whitespace: true,
+            collapse_blank_lines: true,
+            mask_codes: false,
+        }
+    }
+}
+
+impl Normalization {
+    pub fn apply(&self, text: &str) -> String {
+        let mut normalized = String::new();
+        let mut blank = false;
+        for line in text.lines() {
+            let line = if self.trim_trailing_whitespace {
+                line.trim_end()
+            } else {
+                line
+            };
+            if self.collapse_blank_lines && blank && line.trim().is_empty() {
+                continue;
+            }
+            blank = line.trim().is_empty();
+            let mut line = line.to_string();
+            if self.mask_codes {
+                for code in parser::find_codes(&line) {
+                    line = line.replace(&code, "<CODE>");
+                }
+            }
+            normalized.push_str(&line);
+            normalized.push('\n');
+        }
+        normalized
+    }
+}
+
+/// The first line, where the transcript differs from the golden one
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptMismatch {
+    /// The line number starting from 1
+    pub line: usize,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for TranscriptMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |l: &Option<String>| l.clone().unwrap_or("<end of transcript>".to_string());
+        write!(
+            f,
+            "transcript differs at line {}\n  expected: {}\n  actual  : {}",
+            self.line,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+impl Error for TranscriptMismatch {}
+
+/// Returns the first difference between the normalized transcripts
+pub fn compare(
+    expected: &str,
+    actual: &str,
+    normalization: &Normalization,
+) -> Option<TranscriptMismatch> {
+    let (expected, actual) = (normalization.apply(expected), normalization.apply(actual));
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 0;
+    loop {
+        line += 1;
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return None,
+            (e, a) if e == a => continue,
+            (e, a) => {
+                return Some(TranscriptMismatch {
+                    line,
+                    expected: e.map(str::to_string),
+                    actual: a.map(str::to_string),
+                });
+            }
+        }
+    }
+}
+
+/// This function plays the replay file and compares the output with the golden transcript. With
+/// the `SYNACOR_UPDATE_GOLDEN` environment variable set, the golden file is written instead
+pub fn check_transcript(
+    rom: &Path,
+    replay: &Path,
+    golden: &Path,
+    normalization: &Normalization,
+) -> Result<(), Box<dyn Error>> {
+    let transcript = play_files(rom, replay)?;
+    if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        info!("updating golden transcript {}", golden.display());
+        fs::write(golden, normalization.apply(&transcript.output))?;
+        return Ok(());
+    }
+    let expected = fs::read_to_string(golden)?;
+    match compare(&expected, &transcript.output, normalization) {
+        Some(mismatch) => Err(Box::new(mismatch)),
+        None => Ok(()),
+    }
+}
+
+/// This function is the panicking variant of 'check_transcript' for the tests
+pub fn assert_transcript(rom: &Path, replay: &Path, golden: &Path, normalization: &Normalization) {
+    if let Err(e) = check_transcript(rom, replay, golden, normalization) {
+        panic!("{} (replay {})", e, replay.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::rom;
+    use crate::machine::{MAX, MEMORY_SIZE};
+
+    const R0: u16 = MAX;
+    const R1: u16 = MAX + 1;
+
+    // The game printing the '>' prompt and answering 'ok' to every command
+    fn echo_rom() -> Vec<u8> {
+        rom(&[
+            19, 62, // out '>'
+            20, R0, // in r0
+            4, R1, R0, 10, // eq r1 r0 '\n'
+            8, R1, 2, // jf r1 2
+            19, 111, 19, 107, 19, 10, // out "ok\n"
+            6, 0, // jmp 0
+        ])
+    }
+
+    fn commands(commands: &[&str]) -> Vec<String> {
+        commands.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn plays_the_commands_until_the_game_asks_for_more() {
+        let transcript = play(
+            &echo_rom(),
+            &commands(&["look", "take tablet"]),
+            DEFAULT_MAX_CYCLES,
+        )
+        .unwrap();
+        assert_eq!(transcript.output, ">look\nok\n>take tablet\nok\n>");
+        assert!(!transcript.halted);
+    }
+
+    #[test]
+    fn stops_once_the_game_halts() {
+        let transcript = play(&rom(&[19, 65, 0]), &[], DEFAULT_MAX_CYCLES).unwrap();
+        assert_eq!(transcript.output, "A");
+        assert_eq!(transcript.cycles, 2);
+        assert!(transcript.halted);
+    }
+
+    #[test]
+    fn rejects_the_rom_larger_than_the_memory() {
+        let rom = vec![0; MEMORY_SIZE + 2];
+        assert_eq!(play(&rom, &[], 1), Err(RomTooLarge(MEMORY_SIZE + 2)));
+    }
+
+    #[test]
+    fn normalizes_the_whitespace() {
+        let normalization = Normalization::default();
+        assert_eq!(normalization.apply("a  \n\n\n\nb\t\n"), "a\n\nb\n");
+        assert_eq!(compare("a \n\n\nb", "a\n\nb\n", &normalization), None);
+    }
+
+    #[test]
+    fn checks_the_scripted_session_against_the_golden_transcript() {
+        let dir = std::env::temp_dir().join(format!("synacor-testkit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (rom_file, replay, golden) = (
+            dir.join("echo.bin"),
+            dir.join("replay.txt"),
+            dir.join("golden.txt"),
+        );
+        fs::write(&rom_file, echo_rom()).unwrap();
+        fs::write(&replay, "look\ntake tablet\n").unwrap();
+        let normalization = Normalization::default();
+
+        fs::write(&golden, ">look  \nok\n>take tablet\nok\n>").unwrap();
+        let matched = check_transcript(&rom_file, &replay, &golden, &normalization);
+
+        fs::write(&golden, ">look\nok\n>take tablet\nTaken.\n>").unwrap();
+        let mismatch = check_transcript(&rom_file, &replay, &golden, &normalization)
+            .unwrap_err()
+            .downcast::<TranscriptMismatch>()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matched.is_ok(), "{:?}", matched);
+        assert_eq!(
+            *mismatch,
+            TranscriptMismatch {
+                line: 4,
+                expected: Some("Taken.".to_string()),
+                actual: Some("ok".to_string()),
+            }
+        );
+    }
+}
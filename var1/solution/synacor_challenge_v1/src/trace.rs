@@ -0,0 +1,518 @@
+use crate::disasm;
+use crate::machine::MAX;
+use log::{debug, trace};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The first line of every trace file
+pub const HEADER: &str = "# synacor trace v1";
+// The first bytes of the gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+// The output loop of the game executes at most this number of instructions between the characters
+const OUTPUT_LOOP_LENGTH: usize = 64;
+const COMPRESSION_DISABLED: &str = "the compressed trace requires the 'compression' feature";
+
+/// The instruction executed by the VM together with the state of the machine before it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    pub cycle: u64,
+    pub address: u16,
+    /// The opcode and the operands
+    pub words: Vec<u16>,
+    pub registers: [u16; 8],
+    pub stack: usize,
+}
+
+fn join(words: &[u16]) -> String {
+    words
+        .iter()
+        .map(|w| w.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// The line of the trace file: the cycle, the address, the instruction words, the registers and
+/// the stack depth separated with tabs
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            self.cycle,
+            self.address,
+            join(&self.words),
+            join(&self.registers),
+            self.stack
+        )
+    }
+}
+
+impl FromStr for TraceRecord {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split('\t').collect();
+        let [cycle, address, words, registers, stack] = fields[..] else {
+            return Err(format!("expected 5 fields, found {}", fields.len()).into());
+        };
+        let words = words
+            .split_whitespace()
+            .map(|w| w.parse())
+            .collect::<Result<Vec<u16>, _>>()?;
+        let registers: Vec<u16> = registers
+            .split_whitespace()
+            .map(|w| w.parse())
+            .collect::<Result<Vec<u16>, _>>()?;
+        Ok(TraceRecord {
+            cycle: cycle.parse()?,
+            address: address.parse()?,
+            words,
+            registers: registers
+                .try_into()
+                .map_err(|r: Vec<u16>| format!("expected 8 registers, found {}", r.len()))?,
+            stack: stack.parse()?,
+        })
+    }
+}
+
+impl TraceRecord {
+    /// This method shows the record for reading, with the disassembled instruction
+    pub fn pretty(&self) -> String {
+        format!(
+            "{:>10}  {:>5}  {:<22} [{}] stack: {}",
+            self.cycle,
+            self.address,
+            disasm::format(&self.words),
+            self.registers
+                .iter()
+                .map(|r| format!("{:>5}", r))
+                .collect::<Vec<String>>()
+                .join(" "),
+            self.stack
+        )
+    }
+}
+
+/// The writer of the trace file. The file with the .gz extension is compressed with gzip
+/// (the `compression` feature)
+pub struct TraceWriter {
+    writer: Box<dyn Write + Send>,
+    records: u64,
+}
+
+impl TraceWriter {
+    pub fn create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let gzip = path.extension().is_some_and(|e| e == "gz");
+        if gzip && !cfg!(feature = "compression") {
+            return Err(COMPRESSION_DISABLED.into());
+        }
+        let file = File::create(path)?;
+        let mut writer: Box<dyn Write + Send> = if gzip {
+            compressed(file)?
+        } else {
+            Box::new(BufWriter::new(file))
+        };
+        writeln!(writer, "{}", HEADER)?;
+        debug!("writing the trace to {} (gzip: {})", path.display(), gzip);
+        Ok(TraceWriter { writer, records: 0 })
+    }
+
+    pub fn write(&mut self, record: &TraceRecord) -> std::io::Result<()> {
+        self.records += 1;
+        writeln!(self.writer, "{}", record)
+    }
+
+    /// This method flushes the trace and returns the number of the records written. The gzip
+    /// stream is finished, when the writer is dropped
+    pub fn finish(mut self) -> std::io::Result<u64> {
+        self.writer.flush()?;
+        Ok(self.records)
+    }
+}
+
+#[cfg(feature = "compression")]
+fn compressed(file: File) -> Result<Box<dyn Write + Send>, Box<dyn Error>> {
+    use flate2::{Compression, write::GzEncoder};
+    // The encoder finishes the gzip stream when it is dropped
+    Ok(Box::new(BufWriter::new(GzEncoder::new(
+        file,
+        Compression::fast(),
+    ))))
+}
+
+#[cfg(not(feature = "compression"))]
+fn compressed(_file: File) -> Result<Box<dyn Write + Send>, Box<dyn Error>> {
+    Err(COMPRESSION_DISABLED.into())
+}
+
+#[cfg(feature = "compression")]
+fn decompressed(reader: BufReader<File>) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    Ok(Box::new(BufReader::new(flate2::bufread::GzDecoder::new(
+        reader,
+    ))))
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompressed(_reader: BufReader<File>) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    Err(COMPRESSION_DISABLED.into())
+}
+
+/// This function opens the trace file, the gzip compressed one is recognized by its content
+pub fn open(path: &Path) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let gzip = reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+    trace!("opening the trace {} (gzip: {})", path.display(), gzip);
+    if gzip {
+        decompressed(reader)
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// This function reads the records of the trace file one by one
+pub fn records(
+    path: &Path,
+) -> Result<impl Iterator<Item = Result<TraceRecord, Box<dyn Error>>>, Box<dyn Error>> {
+    let path = path.to_path_buf();
+    Ok(open(&path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.as_ref().is_ok_and(|l| l.starts_with('#')))
+        .map(move |(n, line)| {
+            line.map_err(|e| e.into()).and_then(|l| {
+                l.parse::<TraceRecord>()
+                    .map_err(|e| format!("{} line {}: {}", path.display(), n + 1, e).into())
+            })
+        }))
+}
+
+/// The 'out' instructions printing the consecutive characters, either from the same address (the
+/// output loop of the game) or from the adjacent ones (the literal characters)
+struct PrintGroup {
+    /// The records from the first 'out' to the last one
+    records: Vec<TraceRecord>,
+    text: String,
+    outs: usize,
+}
+
+impl PrintGroup {
+    fn new(record: TraceRecord, c: char) -> Self {
+        PrintGroup {
+            records: vec![record],
+            text: c.to_string(),
+            outs: 1,
+        }
+    }
+
+    fn last(&self) -> &TraceRecord {
+        &self.records[self.records.len() - 1]
+    }
+
+    /// Tells whether the 'out' instruction continues printing the text of the group
+    fn continues(&self, record: &TraceRecord) -> bool {
+        let last = self.last().address;
+        record.address == last || record.address == last.wrapping_add(2)
+    }
+
+    /// This method writes the group as a single 'print' pseudo-instruction, unless there is only
+    /// one character in it
+    fn write(self, out: &mut impl Write) -> std::io::Result<usize> {
+        if self.outs < 2 {
+            for record in &self.records {
+                writeln!(out, "{}", record.pretty())?;
+            }
+            return Ok(self.records.len());
+        }
+        let first = &self.records[0];
+        writeln!(
+            out,
+            "{:>10}  {:>5}  print {:?} ({} instructions up to the cycle {})",
+            first.cycle,
+            first.address,
+            self.text,
+            self.records.len(),
+            self.last().cycle
+        )?;
+        Ok(1)
+    }
+}
+
+/// Returns the character printed by the 'out' instruction of the record
+fn printed(record: &TraceRecord) -> Option<char> {
+    let (&opcode, operands) = record.words.split_first()?;
+    if opcode != disasm::OUT_OPCODE {
+        return None;
+    }
+    let value = match *operands.first()? {
+        w if w < MAX => w,
+        w => *record.registers.get((w - MAX) as usize)?,
+    };
+    char::from_u32(value as u32)
+}
+
+/// This function writes the records of the trace within the range of cycles (inclusive) in the
+/// readable form. Unless `raw` is set, the characters printed one by one are collapsed into the
+/// single 'print' line
+pub fn dump(
+    path: &Path,
+    from: Option<u64>,
+    to: Option<u64>,
+    raw: bool,
+    out: &mut impl Write,
+) -> Result<usize, Box<dyn Error>> {
+    let mut shown = 0;
+    let mut group: Option<PrintGroup> = None;
+    // The records after the last 'out' of the group
+    let mut pending: Vec<TraceRecord> = vec![];
+    for record in records(path)? {
+        let record = record?;
+        if from.is_some_and(|from| record.cycle < from) {
+            continue;
+        }
+        if to.is_some_and(|to| record.cycle > to) {
+            break;
+        }
+        let c = if raw { None } else { printed(&record) };
+        match (c, group.as_mut()) {
+            (Some(c), Some(g)) if g.continues(&record) => {
+                g.records.append(&mut pending);
+                g.records.push(record);
+                g.text.push(c);
+                g.outs += 1;
+            }
+            (Some(c), _) => {
+                if let Some(g) = group.take() {
+                    shown += g.write(out)?;
+                }
+                shown += write_records(&mut pending, out)?;
+                group = Some(PrintGroup::new(record, c));
+            }
+            (None, Some(_)) => {
+                pending.push(record);
+                if pending.len() > OUTPUT_LOOP_LENGTH {
+                    shown += group.take().map_or(Ok(0), |g| g.write(out))?;
+                    shown += write_records(&mut pending, out)?;
+                }
+            }
+            (None, None) => {
+                writeln!(out, "{}", record.pretty())?;
+                shown += 1;
+            }
+        }
+    }
+    shown += group.map_or(Ok(0), |g| g.write(out))?;
+    shown += write_records(&mut pending, out)?;
+    Ok(shown)
+}
+
+fn write_records(records: &mut Vec<TraceRecord>, out: &mut impl Write) -> std::io::Result<usize> {
+    for record in records.iter() {
+        writeln!(out, "{}", record.pretty())?;
+    }
+    Ok(std::mem::take(records).len())
+}
+
+/// The first difference of two traces with the records around it
+#[derive(Debug, Default)]
+pub struct TraceDiff {
+    /// The number of the records compared before the divergence (or the end of both traces)
+    pub compared: u64,
+    /// The cycle of the first record, which differs
+    pub divergence: Option<u64>,
+    pub problems: Vec<String>,
+    /// The matching records before the divergence
+    pub before: Vec<TraceRecord>,
+    pub after_a: Vec<TraceRecord>,
+    pub after_b: Vec<TraceRecord>,
+}
+
+impl TraceDiff {
+    pub fn is_same(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+/// This function lists the differences of two records of the same cycle
+fn compare(a: &TraceRecord, b: &TraceRecord) -> Vec<String> {
+    let mut problems = vec![];
+    if a.cycle != b.cycle {
+        problems.push(format!("cycle {} vs {}", a.cycle, b.cycle));
+    }
+    if a.address != b.address {
+        problems.push(format!("address {} vs {}", a.address, b.address));
+    }
+    if a.words != b.words {
+        problems.push(format!(
+            "instruction '{}' vs '{}'",
+            disasm::format(&a.words),
+            disasm::format(&b.words)
+        ));
+    }
+    for (r, (x, y)) in a.registers.iter().zip(b.registers.iter()).enumerate() {
+        if x != y {
+            problems.push(format!("register {} is {} vs {}", r, x, y));
+        }
+    }
+    if a.stack != b.stack {
+        problems.push(format!("stack depth {} vs {}", a.stack, b.stack));
+    }
+    problems
+}
+
+/// This function reads two traces record by record and stops at the first difference, keeping
+/// the given number of records before and after it
+pub fn diff(a: &Path, b: &Path, context: usize) -> Result<TraceDiff, Box<dyn Error>> {
+    let mut records_a = records(a)?;
+    let mut records_b = records(b)?;
+    let mut result = TraceDiff::default();
+    let mut before = VecDeque::with_capacity(context + 1);
+    loop {
+        let (x, y) = (records_a.next().transpose()?, records_b.next().transpose()?);
+        let (x, y) = match (x, y) {
+            (None, None) => break,
+            (Some(x), Some(y)) => (x, y),
+            (Some(x), None) => {
+                result.divergence = Some(x.cycle);
+                result
+                    .problems
+                    .push(format!("{} ends at this cycle", b.display()));
+                result.after_a.push(x);
+                break;
+            }
+            (None, Some(y)) => {
+                result.divergence = Some(y.cycle);
+                result
+                    .problems
+                    .push(format!("{} ends at this cycle", a.display()));
+                result.after_b.push(y);
+                break;
+            }
+        };
+        let problems = compare(&x, &y);
+        if !problems.is_empty() {
+            result.divergence = Some(x.cycle.min(y.cycle));
+            result.problems = problems;
+            result.after_a.push(x);
+            result.after_b.push(y);
+            break;
+        }
+        result.compared += 1;
+        before.push_back(x);
+        if before.len() > context {
+            before.pop_front();
+        }
+    }
+    if result.divergence.is_some() {
+        for _ in 1..=context {
+            match records_a.next().transpose()? {
+                Some(x) => result.after_a.push(x),
+                None => break,
+            }
+        }
+        for _ in 1..=context {
+            match records_b.next().transpose()? {
+                Some(y) => result.after_b.push(y),
+                None => break,
+            }
+        }
+    }
+    debug!("compared {} records of the traces", result.compared);
+    result.before = before.into();
+    Ok(result)
+}
+
+impl fmt::Display for TraceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "***              Trace Diff               ***")?;
+        writeln!(f, "{}", "=".repeat(44))?;
+        writeln!(f, "{:<9}: {}", "compared", self.compared)?;
+        let Some(cycle) = self.divergence else {
+            writeln!(f, "the traces are the same")?;
+            return write!(f, "{}", "=".repeat(45));
+        };
+        writeln!(f, "{:<9}: {}", "diverged", cycle)?;
+        for problem in &self.problems {
+            writeln!(f, "  ! {}", problem)?;
+        }
+        writeln!(f, "{}", "_".repeat(44))?;
+        for record in &self.before {
+            writeln!(f, "  {}", record.pretty())?;
+        }
+        for (name, records) in [("a", &self.after_a), ("b", &self.after_b)] {
+            writeln!(f, "{}", "_".repeat(44))?;
+            for (i, record) in records.iter().enumerate() {
+                let marker = if i == 0 { '>' } else { ' ' };
+                writeln!(f, "{}{}{}", name, marker, record.pretty())?;
+            }
+        }
+        write!(f, "{}", "=".repeat(45))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The record of 'add r0 r0 1' at the given cycle, with r0 counting the cycles
+    fn record(cycle: u64) -> TraceRecord {
+        let mut registers = [0; 8];
+        registers[0] = cycle as u16;
+        TraceRecord {
+            cycle,
+            address: 0,
+            words: vec![9, MAX, MAX, 1],
+            registers,
+            stack: 0,
+        }
+    }
+
+    fn write_trace(path: &Path, records: &[TraceRecord]) {
+        let mut writer = TraceWriter::create(path).unwrap();
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn reads_back_the_written_records() {
+        let line = record(7).to_string();
+        assert_eq!(line, "7\t0\t9 32768 32768 1\t7 0 0 0 0 0 0 0\t0");
+        assert_eq!(line.parse::<TraceRecord>().unwrap(), record(7));
+        assert!("7\t0\t9".parse::<TraceRecord>().is_err());
+    }
+
+    #[test]
+    fn reports_the_divergence_at_the_first_different_record() {
+        let dir = std::env::temp_dir().join(format!("synacor-trace-diff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.trace"), dir.join("b.trace"));
+        let same: Vec<TraceRecord> = (0..10).map(record).collect();
+        let mut diverged = same.clone();
+        diverged[6].registers[3] = 42;
+        write_trace(&a, &same);
+        write_trace(&b, &diverged);
+        let result = diff(&a, &b, 2).unwrap();
+        let identical = diff(&a, &a, 2).unwrap();
+        write_trace(&b, &same[..4]);
+        let shorter = diff(&a, &b, 2).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.divergence, Some(6));
+        assert_eq!(result.compared, 6);
+        assert_eq!(result.problems, vec!["register 3 is 0 vs 42".to_string()]);
+        assert_eq!(result.before, vec![record(4), record(5)]);
+        assert_eq!(result.after_a, vec![record(6), record(7), record(8)]);
+        assert_eq!(result.after_b[0], diverged[6]);
+        assert!(identical.is_same());
+        assert_eq!(identical.compared, 10);
+        assert_eq!(shorter.divergence, Some(4));
+        assert_eq!(shorter.compared, 4);
+        assert!(shorter.problems[0].ends_with("ends at this cycle"));
+    }
+}
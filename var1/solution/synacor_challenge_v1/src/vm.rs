@@ -0,0 +1,3449 @@
+use colored::Colorize;
+use log::{Level, debug, error, info, trace};
+use log::{log_enabled, warn};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::error::Error;
+use std::{fmt, fs};
+use std::io::{self, IsTerminal, Write};
+use std::iter;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::aux::{self, Commander};
+use crate::bench;
+use crate::config::{self, ConfigError, FlushPolicy, OutputFormat};
+use crate::conformance;
+use crate::corpus;
+use crate::disasm;
+use crate::events::{EventBus, VmEvent};
+use crate::history::{self, CommandResult, HistoryEntry, HistoryFilter, HistoryRange};
+use crate::input_log::{self, InputEvent, InputStats};
+use crate::lockstep::{Lockstep, load_machine};
+use crate::machine::{Address, InputDecoder, MAX, MNEMONICS, Machine, RomTooLarge, StepEvent};
+use crate::maze::{self, ExplorationStrategy, MazeAnalyzer, export};
+use crate::parser::{self, OutputParser, ResponseParts, ResponsePartsRef, UseEffect};
+use crate::plugin::VmPlugin;
+use crate::rng::{self, Generator};
+use crate::rules::ExpectRules;
+use crate::safe_file;
+use regex::{Regex, RegexBuilder};
+use crate::state::{self, STATE_FORMAT, VmState};
+use crate::suggest;
+use crate::trace::{self, TraceRecord, TraceWriter};
+use capabilities::Capabilities;
+use flush::FlushingWriter;
+use json_lines::JsonLines;
+use notifier::Notifier;
+use recorder::{InputRecorder, OutputRecorder};
+use script::{ScriptAction, ScriptHooks};
+use ring::SnapshotRing;
+use taint::Taint;
+use walkthrough::Walkthrough;
+use watch::Watch;
+use workspace::Workspace;
+
+#[cfg(feature = "async")]
+mod async_loop;
+mod capabilities;
+mod coins;
+mod recorder;
+mod script;
+#[cfg(feature = "dap")]
+mod dap;
+mod flush;
+mod json_lines;
+mod notifier;
+mod playthrough;
+#[cfg(feature = "gdb")]
+mod gdb;
+#[cfg(feature = "server")]
+mod server;
+mod ring;
+mod taint;
+mod walkthrough;
+#[cfg(feature = "tui")]
+mod keys;
+#[cfg(feature = "tui")]
+mod tui;
+mod watch;
+mod workspace;
+
+// The default file of /save_state and /load_state
+const STATE_JSON_FILE: &str = "vm_state.json";
+// The default trace file of /replay_last
+const REPLAY_TRACE_FILE: &str = "replay_last.trace";
+// The default file of /save_history
+const HISTORY_FILE: &str = "history.txt";
+// The default file of /export_markdown
+const WALKTHROUGH_FILE: &str = "walkthrough.md";
+// The default file of /capabilities
+const CAPABILITIES_FILE: &str = "capabilities.json";
+// The default file of /dump_state, also written once --max-cycles or --timeout stops the VM
+const STATE_FILE: &str = "vm_state.txt";
+// The default file of /save_symbols
+const SYMBOLS_FILE: &str = "symbols.txt";
+// The default file of the partial transcript of the batch run
+const TRANSCRIPT_FILE: &str = "transcript.txt";
+// The directory keeping the slots of the 'save <name>' and 'load <name>' typed at the game prompt
+const SAVES_DIR: &str = "saves";
+// The number of instructions executed between the checks of the --timeout deadline
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+// The opcode of the 'in' instruction
+const IN_OPCODE: u16 = 20;
+// The opcode of the 'wmem' instruction
+const WMEM_OPCODE: u16 = 16;
+
+// The number of instructions shown by /list before and after the current one
+const LIST_INSTRUCTIONS: usize = 5;
+
+// The slash command output of this number of lines is shown through the pager
+const PAGER_MIN_LINES: usize = 40;
+
+// The game's own help is asked from the copy of the machine, which gives up after this many cycles
+const GAME_HELP_CYCLES: u64 = 1_000_000;
+
+// Set by the Ctrl+C handler, the VM pauses before the next instruction
+static PAUSE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// This function makes Ctrl+C pause the VM instead of killing the process, so the runaway replay
+/// or /solve can be inspected and resumed. Pressing it once again before the VM pauses (e.g. while
+/// it waits for the input) quits as usual
+fn install_pause_handler() {
+    let result = ctrlc::set_handler(|| {
+        if PAUSE_REQUESTED.swap(true, Ordering::SeqCst) {
+            eprintln!();
+            std::process::exit(130);
+        }
+        eprintln!("\n{}", "pausing the VM (press Ctrl+C again to quit)".yellow());
+    });
+    if let Err(e) = result {
+        warn!("failed to install the Ctrl+C handler. Error: {}", e);
+    }
+}
+
+/// The session state saved on entering the room, so it can be restored after the unexpected death
+struct Autosave {
+    room: String,
+    state: VmState,
+}
+
+/// The goal ending /solve before the moves are used up
+#[derive(Debug, Clone, PartialEq)]
+enum SolveGoal {
+    /// A code appears, which is not among the given number of the codes found so far
+    Code(usize),
+    /// The item is carried
+    Item(String),
+    /// The player is in the room with the title
+    Room(String),
+}
+
+impl fmt::Display for SolveGoal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveGoal::Code(_) => write!(f, "the new code is found"),
+            SolveGoal::Item(item) => write!(f, "the {} is taken", item),
+            SolveGoal::Room(title) => write!(f, "the {} is reached", title),
+        }
+    }
+}
+
+/// This function parses the arguments of /solve: the number of the moves and the goals
+/// '--until-code', '--until-item <name>' and '--until-room <title>'. The names and titles may be
+/// several words long
+fn parse_solve_args(
+    args: &[&str],
+    codes: usize,
+) -> Result<(Option<u32>, Vec<SolveGoal>), Box<dyn Error>> {
+    let mut steps = None;
+    let mut goals = vec![];
+    let mut words = args.iter().peekable();
+    while let Some(word) = words.next() {
+        let mut name = || {
+            let mut name = vec![];
+            while let Some(word) = words.next_if(|w| !w.starts_with("--")) {
+                name.push(*word);
+            }
+            if name.is_empty() {
+                Err(format!("{} expects the name", word))
+            } else {
+                Ok(name.join(" "))
+            }
+        };
+        match *word {
+            "--until-code" => goals.push(SolveGoal::Code(codes)),
+            "--until-item" => goals.push(SolveGoal::Item(name()?)),
+            "--until-room" => goals.push(SolveGoal::Room(name()?)),
+            option if option.starts_with("--") => return Err(format!("unknown option {}", option).into()),
+            number if steps.is_none() => steps = Some(number.parse()?),
+            _ => return Err("at most one number of the moves is expected".into()),
+        }
+    }
+    Ok((steps, goals))
+}
+
+struct VM {
+    machine: Machine,
+
+    // Auxiliary stuff
+    replay_commands: Option<Vec<String>>,
+    commands_history: Vec<HistoryEntry>,
+    slash_history: Vec<String>, // kept apart from the game commands, so they are not replayed
+    current_command_buf: String, //used to store user input until the newline character
+    input_decoder: InputDecoder, // the characters of the command typed, decoded from the bytes read
+    output_parser: OutputParser,
+    game_help: Option<String>, // the response to 'help', asked once by /help
+    last_room: Option<ResponseParts>,
+    inventory: Vec<String>,
+    status_line: bool,
+    status_pending: bool, // the status line is shown once per prompt
+    events: EventBus,
+    line_buffer: String, // the output line, which is not completed yet
+    codes: Vec<String>,
+    coin_values: BTreeMap<String, u16>, // the values of the coins looked at
+    coins_planned: bool,                // /coins has queued the commands and runs once again
+    solving: bool,
+    solve_steps_left: u32,
+    solve_moves: u32,
+    solve_started: Instant,
+    solve_paused: Option<Instant>, // the moment /pause_solve has paused the exploration
+    solve_command: Option<String>, // the latest command issued by /solve
+    solve_goals: Vec<SolveGoal>,   // any of them reached ends /solve
+    quit_after_solve: bool,        // the end of /solve halts the program, e.g. for the solve subcommand
+    solve_progress_interval: u32, // the moves between the progress lines of /solve, 0 for none
+    solve_report_file: Option<PathBuf>,
+    cycles: u64,
+    input_buffer: VecDeque<(u8, bool)>, // pending input characters and whether to echo them
+    stdin_input: bool,                  // read the input from stdin, when the buffer is empty
+    batch: bool,                        // end the run instead of waiting for the input
+    transcript: String,                 // the game output and the commands of the batch run
+    pager: bool,                        // show the long slash command output through $PAGER
+    #[cfg(feature = "tui")]
+    keys: bool, // read stdin in the raw terminal mode with the key shortcuts
+    quiet: bool,                        // show only the game output and the output asked for
+    json_lines: bool,                   // the game output is printed as JSON lines instead of text
+    started: Instant,
+    stdout_output: bool,                // print the output to stdout instead of the output buffer
+    stdout: FlushingWriter<io::Stdout>,
+    flush_policy: FlushPolicy, // of stdout and the output recording
+    output_buffer: String,
+    awaiting_input: bool,
+    breakpoints: BTreeSet<u16>,
+    output_breakpoints: Vec<Regex>, // pause, once the completed output line matches
+    break_on_room: bool,            // pause, once the game shows another room
+    break_on_input: bool,           // pause before every 'in' instruction
+    watches: Vec<Watch>,
+    protected: Vec<(u16, u16)>, // the memory ranges, pausing the VM before 'wmem' into them
+    frozen_rng: Option<(Vec<Generator>, u16)>, // the generators of the ROM and the state stored by all of them
+    taint: Option<Taint>,       // the values derived from the input, while the tracking is on
+    paused: bool,
+    resuming: bool, // used to step over the breakpoint after resuming
+    scripts: Option<ScriptHooks>,
+    rules: ExpectRules,
+    cycle_limit: Option<u64>, // the VM is stopped, once the cycles counter reaches it
+    deadline: Option<(Instant, Duration)>, // the instant to stop the VM at and the timeout itself
+    stdin_lines: Option<Receiver<io::Result<String>>>, // the lines read by the thread, once the deadline is set
+    stdin_failure: Option<io::Error>, // the error of reading stdin, which has halted the VM
+    interruption: Option<Interruption>,
+    trace: Option<TraceWriter>, // every executed instruction is written to it
+    workspace: Option<Workspace>, // the directory of --session-dir
+    rom_hash: u64,
+    options: Vec<(&'static str, String)>, // the options the session is started with, as shown by /version
+    autosaves: VecDeque<Autosave>, // the states saved on entering the latest rooms, the oldest first
+    autosave_limit: usize,
+    snapshots: SnapshotRing, // the rolling snapshots for /replay_last
+    timed_input: VecDeque<InputEvent>, // fed to the game at the times they have been recorded at
+}
+
+/// The reason why the VM has been stopped before the program halted by itself
+#[derive(Debug, Clone, PartialEq)]
+pub enum Interruption {
+    /// The budget of instructions given by --max-cycles is exhausted at the given cycle
+    CycleLimit(u64),
+    /// The time given by --timeout is over
+    Timeout(Duration),
+    /// The batch run needs the input, but the replay commands are used up
+    InputExhausted,
+    /// The batch run is paused at the given address, e.g. at the breakpoint
+    Paused(u16),
+}
+
+impl fmt::Display for Interruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interruption::CycleLimit(limit) => {
+                write!(f, "the budget of instructions is exhausted at cycle {}", limit)
+            }
+            Interruption::Timeout(timeout) => write!(f, "the timeout of {:?} is over", timeout),
+            Interruption::InputExhausted => write!(f, "the replay commands are used up"),
+            Interruption::Paused(address) => {
+                write!(f, "the VM is paused at {} in the batch mode", address)
+            }
+        }
+    }
+}
+
+impl Error for Interruption {}
+
+/// The exploration by /solve has ended with the program halted, e.g. the player has been eaten,
+/// or the playthrough of solve-all has not got as far as the last code
+#[derive(Debug, Clone)]
+pub struct SolveFailure {
+    pub moves: u32,
+    pub reason: String,
+}
+
+impl fmt::Display for SolveFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} after {} moves", self.reason, self.moves)
+    }
+}
+
+impl Error for SolveFailure {}
+
+/// The exit codes of the binaries, so the shell pipelines and supervisors can tell why the VM
+/// has stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Halted = 0,
+    RuntimeError = 1,
+    ConfigError = 2,
+    CycleLimit = 3,
+    Timeout = 4,
+    SolveFailure = 5,
+    InputExhausted = 6,
+    Paused = 7,
+}
+
+impl ExitStatus {
+    /// This function tells the exit status of the result of the run
+    pub fn of(result: &Result<(), Box<dyn Error>>) -> Self {
+        let Err(e) = result else {
+            return ExitStatus::Halted;
+        };
+        if e.is::<config::ConfigError>() {
+            ExitStatus::ConfigError
+        } else if e.is::<SolveFailure>() {
+            ExitStatus::SolveFailure
+        } else {
+            match e.downcast_ref::<Interruption>() {
+                Some(Interruption::CycleLimit(_)) => ExitStatus::CycleLimit,
+                Some(Interruption::Timeout(_)) => ExitStatus::Timeout,
+                Some(Interruption::InputExhausted) => ExitStatus::InputExhausted,
+                Some(Interruption::Paused(_)) => ExitStatus::Paused,
+                None => ExitStatus::RuntimeError,
+            }
+        }
+    }
+
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+/// The reason why the VM has stopped executing instructions
+#[derive(Debug, PartialEq)]
+enum RunState {
+    Running,
+    Halted,
+    Paused,
+    AwaitingInput,
+}
+
+fn slash_command_help() -> String {
+    [
+        "*** Available slash '/' commands: ***",
+        "/help - show this help together with the game's own help, the key shortcuts, the breakpoints and the watches",
+        "/version - show the version, the cargo features, the ROM hash, the plugins and the options of the session",
+        "/capabilities [file] [--force] - show the same as /version in JSON or write it to the file, e.g. for the bug report (--force overwrites it)",
+        "/show_state - show state of the VM",
+        "/dump_state [--force] - save VM state information to file (vm_state.txt, --force overwrites it)",
+        "/save_state [file] - save the complete session state to the JSON file (vm_state.json by default)",
+        "/load_state [file] - restore the session state saved by /save_state",
+        "/autosaves [n] - list the states saved on entering the latest rooms or restore the n-th latest one",
+        "/diff_snapshot <a> <b> - compare two states saved by /save_state",
+        "/dump_memory [--force] - save VM RAM to file (vm_memory_dump.bin, --force overwrites it)",
+        "/show_history [all|moves|items|failed] [n|a..b] - show the commands (the last n or from a up to b, either bound may be left out) with their rooms, cycles and outcomes",
+        "/history_grep <regex> - show the commands of the history matching the regular expression",
+        "/replay_from <n> - rewind to the moment before the n-th command of the history, replaying it from the autosave",
+        "/slash_history [text] - show the slash commands entered so far (containing the text); !! repeats the last one",
+        "/save_history [file] [--moves-only] [--force] - save the commands one per line, replayable with --replay (history.txt by default)",
+        "/export_markdown [file] [--force] - write the walkthrough of the session in Markdown (walkthrough.md by default, --force overwrites it)",
+        "/record_output [file] - start output recording (to output.txt by default)",
+        "/stop_recording - stop output recording, flushing it to the file",
+        "/rotate_recording <file> - continue output recording in another file",
+        "/recording_status - show where the output is recorded",
+        "/map - show the map of the visited rooms around",
+        "/solve [steps] [--until-code] [--until-item <name>] [--until-room <title>] - explore the map automatically for the given number of moves (100 by default) or until the goal is reached",
+        "/stop_solve - stop /solve and show what it has explored",
+        "/explore_parallel [workers] [exits] - take the unexplored exits of the map in the forked machines running in parallel, leaving the game where it is (500 exits at most by default)",
+        "/coins - take the coins of the ruins, look at them and put them into the monument in the order solving its equation",
+        "/pause_solve - pause /solve, /resume_solve continues it",
+        "/resume_solve - continue the paused /solve",
+        "/strategy [key=value ...] - show or change the exploration priorities of /solve",
+        "/route - show the route planned by /solve",
+        "/frontier - show the rooms left to explore, the dead ends and the loop the player keeps going round",
+        "/node <room> - show everything known about the room",
+        "/goto <room> - walk to the room along the cheapest known path",
+        "/set_edge_cost <room> <exit> <cost> - make the exit of the room cost more (or less) for /goto and /show_path",
+        "/merge_nodes <a> <b> - join two rooms, which are actually the same one",
+        "/split_node <room> - tell the room the player is in apart from the other visits to it",
+        "/note <text> - attach the note to the current room, it is shown by /node and in the map export",
+        "/inject \"<command>\" - queue the command for the game as if it were replayed",
+        "/read_notes [item] - show the long texts read so far, e.g. the books",
+        "/show_path [room] - show the path taken to the room and the shortest one (the current room by default)",
+        "/break <addr> - pause the VM before executing the instruction at the address",
+        "/delete_break <addr> - delete the breakpoint",
+        "/break_output <regex> - pause the VM, once the printed line matches the regular expression",
+        "/delete_break_output <regex> - delete the breakpoint on the output",
+        "/break_room [on|off] - toggle pausing the VM every time another room is shown",
+        "/break_input [on|off] - toggle pausing the VM before every 'in' instruction, showing where the character goes",
+        "/watch_expr <expr> - report every change of the expression over r0..r7, mem[addr], + - * / % & | and == != < <= > >=",
+        "/watches - show the watch expressions and their values",
+        "/delete_watch <n> - delete the watch expression",
+        "/protect <start> <end> - pause the VM before any 'wmem' into the memory range, reporting the instruction",
+        "/unprotect <start> <end> - delete the protection of the memory range",
+        "/rng - show the pseudo-random generators found in the ROM: the update of the state and where it is stored",
+        "/freeze_rng <value>|off - store the value as the next state of every generator, so the 'random' values repeat",
+        "/save_symbols [file] [--force] - save the addresses of the generators as 'address name' lines (symbols.txt by default)",
+        "/taint [on|off] - toggle tracking the registers and the memory derived from the input characters",
+        "/tainted [rN|addr] - show the tainted registers, memory and the instructions using them, or query one of them",
+        "/breakpoints - show all breakpoints",
+        "/stats - show the number of the executed instructions per opcode, the branches and the stack depth",
+        "/pause - pause the VM before the next instruction (Ctrl+C does the same during the replay or /solve)",
+        "/continue - resume the paused VM",
+        "/step - execute a single instruction",
+        "/replay_last <cycles> [file] - execute the latest cycles once again, writing their trace to the file",
+        "/stepback [n] - undo the last executed instruction (or n of them), as long as the journal keeps them",
+        "/list [n] - disassemble n instructions (5 by default) before and after the current one",
+    ]
+    .join("\n")
+}
+
+/// This function pipes the text to $PAGER (less by default) and waits until the user quits it
+fn run_pager(text: &str) -> io::Result<()> {
+    let pager = std::env::var("PAGER").unwrap_or("less -R".to_string());
+    let mut words = pager.split_whitespace();
+    let program = words.next().ok_or(io::Error::other("PAGER is empty"))?;
+    let mut child = Command::new(program).args(words).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The user may quit the pager before reading everything
+        match stdin.write_all(text.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// This function parses a memory address given either as a decimal or a hex (0x prefixed) number
+fn parse_address(s: &str) -> Result<u16, Box<dyn Error>> {
+    let value = match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16)?,
+        None => s.parse::<u16>()?,
+    };
+    if value >= MAX {
+        return Err(format!("address {} is out of the address space", value).into());
+    }
+    Ok(value)
+}
+
+fn parse_address_argument(args: &[&str]) -> Result<u16, Box<dyn Error>> {
+    match args {
+        [addr] => parse_address(addr),
+        _ => Err("exactly one address argument is expected".into()),
+    }
+}
+
+fn char_is_printable(c: char) -> bool {
+    !c.is_control()
+}
+
+/// This function writes the control character the way it is kept in the command, e.g. '\t'
+fn escape_control(c: char) -> String {
+    c.escape_default().collect()
+}
+
+// Repeats the last slash command
+const REPEAT_SLASH_COMMAND: &str = "!!";
+
+/// This function tells whether the line is addressed to the VM rather than to the game
+fn is_slash_command(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with('/') || line == REPEAT_SLASH_COMMAND
+}
+
+/// This function starts the thread reading stdin line by line, until it is closed or the VM
+/// reading the lines is gone. The empty line stands for the end of the input
+fn spawn_stdin_reader() -> Receiver<io::Result<String>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        loop {
+            let mut line = String::new();
+            let read = io::stdin().read_line(&mut line).map(|_| line);
+            let end = !matches!(&read, Ok(line) if !line.is_empty());
+            if sender.send(read).is_err() || end {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// This function recognizes 'save <name>' and 'load <name>', which are handled by the VM instead
+/// of the game, like in the save systems of the interactive fiction games
+fn parse_save_command(line: &str) -> Option<(&str, &str)> {
+    let mut words = line.split_whitespace();
+    let verb = words.next().filter(|v| *v == "save" || *v == "load")?;
+    let name = words.next()?;
+    words.next().is_none().then_some((verb, name))
+}
+
+/// The slot is saved to the file named after it, so only the letters, digits, '-' and '_' are allowed.
+/// Returns the name of the file relative to the directory of the session
+fn save_slot_file(name: &str) -> Result<String, String> {
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("invalid slot name '{}', use letters, digits, '-' and '_'", name));
+    }
+    Ok(format!("{}/{}.json", SAVES_DIR, name))
+}
+
+/// Returns the names of the slots saved to the directory
+fn list_save_slots(dir: &Path) -> Vec<String> {
+    let mut slots: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| e.file_name().to_str()?.strip_suffix(".json").map(String::from))
+        .collect();
+    slots.sort();
+    slots
+}
+
+impl<'b> aux::Commander<'b> for VM {
+    fn show_state(&self) {
+        trace!("showing VM state to stderr");
+        eprintln!("{}", self.get_state());
+    }
+    fn dump_state(&self, p: &std::path::Path, overwrite: bool) -> Result<(), std::io::Error> {
+        trace!("dumping VM state to {}", p.display());
+        safe_file::write(p, self.get_state(), overwrite)
+    }
+    fn dump_memory(&self, p: &std::path::Path, overwrite: bool) -> Result<(), std::io::Error> {
+        trace!("dumping VM memory to {}", p.display());
+        safe_file::write(p, self.machine.memory.as_ref(), overwrite)
+    }
+    fn record_output(&mut self, p: &std::path::Path) -> Result<(), Box<dyn Error>> {
+        if self.is_recording_active() {
+            return Err(
+                "recording is already enabled to another file, use /rotate_recording to switch it".into(),
+            );
+        }
+        trace!("starting recording VM output to {}", p.display());
+        // The recorder may be already subscribed, but stopped because of an error
+        match self.events.plugin_mut::<OutputRecorder>() {
+            Some(recorder) => *recorder = OutputRecorder::new(p, self.flush_policy),
+            None => self.events.register(Box::new(OutputRecorder::new(p, self.flush_policy))),
+        }
+        Ok(())
+    }
+    fn commands_history(&self) -> &[HistoryEntry] {
+        trace!(
+            "returning {} elements of command history",
+            self.commands_history.len()
+        );
+        self.commands_history.as_ref()
+    }
+    fn get_replay_commands(&self) -> Vec<String> {
+        match &self.replay_commands {
+            Some(rc) => rc.clone(),
+            None => vec![],
+        }
+    }
+    /// This method stops the active recording, flushing everything recorded so far to the file
+    fn stop_recording(&mut self) -> Result<PathBuf, Box<dyn Error>> {
+        let recorder = self
+            .events
+            .plugin_mut::<OutputRecorder>()
+            .filter(|r| r.is_active())
+            .ok_or("the output is not being recorded")?;
+        recorder.stop()?;
+        Ok(recorder.path().to_path_buf())
+    }
+    fn get_recording_status(&self) -> String {
+        match self.events.plugin::<OutputRecorder>() {
+            Some(r) if r.is_active() => format!(
+                "recording the output to {} ({} bytes written)",
+                r.path().display(),
+                r.written()
+            ),
+            Some(r) => format!("not recording, the last record is {}", r.path().display()),
+            None => "not recording".to_string(),
+        }
+    }
+    fn is_recording_active(&self) -> bool {
+        self.events
+            .plugin::<OutputRecorder>()
+            .is_some_and(|r| r.is_active())
+    }
+    /// The commands are saved one per line, so the file can be replayed with --replay
+    fn save_commands_history(&self, dst: &Path, filter: HistoryFilter, overwrite: bool) -> Result<(), io::Error> {
+        trace!("saving commands history to file {}", dst.display());
+        let commands: String = self
+            .commands_history
+            .iter()
+            .filter(|e| filter.matches(e))
+            .map(|e| format!("{}\n", e.command))
+            .collect();
+        safe_file::write(dst, commands, overwrite)
+    }
+    fn process_command(&mut self, command: &str) -> Result<(), Box<dyn Error>> {
+        debug!("processing command {}", command);
+        let repeated;
+        let command = if command.trim() == REPEAT_SLASH_COMMAND {
+            repeated = self
+                .slash_history
+                .last()
+                .cloned()
+                .ok_or("there is no slash command to repeat")?;
+            self.message(format!("repeating {}", repeated));
+            repeated.as_str()
+        } else {
+            command
+        };
+        if command.starts_with("/") {
+            self.slash_history.push(command.to_string());
+            trace!("processing slash '/' command");
+            let lowercase_command = command.to_lowercase();
+            let mut words = lowercase_command.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let args: Vec<&str> = words.collect();
+            // The file names are case sensitive
+            let raw_args: Vec<&str> = command.split_whitespace().skip(1).collect();
+            // The whole text after the name, e.g. the pattern with the spaces
+            let raw_text = command
+                .trim_start()
+                .split_once(char::is_whitespace)
+                .map_or("", |(_, text)| text.trim());
+            match name {
+                "/help" => {
+                    let help = self.get_help();
+                    self.page(help)
+                }
+                "/show_state" => self.page(self.get_state()),
+                "/version" => self.page(self.capabilities().report()),
+                "/capabilities" => match raw_args.iter().find(|a| !a.starts_with("--")) {
+                    Some(file) => {
+                        let path = self.write_capabilities(Some(file), raw_args.contains(&"--force"))?;
+                        self.message(format!("written the capabilities to {}", path.display()));
+                    }
+                    None => self.message(serde_json::to_string_pretty(&self.capabilities().to_json())?),
+                },
+                "/show_history" => {
+                    trace!("showing history of commands by demand");
+                    let (mut filter, mut range) = (HistoryFilter::All, None);
+                    for arg in &args {
+                        if arg.starts_with(|c: char| c.is_ascii_digit() || c == '.' || c == '-') {
+                            range = Some(arg.parse::<HistoryRange>()?);
+                        } else {
+                            filter = arg.parse()?;
+                        }
+                    }
+                    let range = range.map_or(0..self.commands_history.len(), |r| r.indices(self.commands_history.len()));
+                    self.page(self.get_commands_history(0, filter, range));
+                },
+                "/history_grep" => {
+                    let pattern = raw_text;
+                    if pattern.is_empty() {
+                        return Err("the pattern is expected".into());
+                    }
+                    let regex = RegexBuilder::new(pattern).case_insensitive(true).build()?;
+                    let found: Vec<String> = self
+                        .commands_history
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, e)| regex.is_match(&e.command))
+                        .map(|(n, e)| self.get_history_entry_info(n, e))
+                        .collect();
+                    if found.is_empty() {
+                        self.message(format!("no commands match /{}/", pattern));
+                    } else {
+                        self.page(found.join("\n"));
+                    }
+                }
+                "/replay_from" => {
+                    let index: usize = match args.first() {
+                        Some(n) => n.parse().map_err(|e| format!("invalid number '{}': {}", n, e))?,
+                        None => return Err("the index of the command in the history is expected".into()),
+                    };
+                    self.replay_from(index)?;
+                }
+                "/slash_history" => {
+                    // The search is case insensitive, like the command names
+                    let pattern = raw_text.to_lowercase();
+                    let history: Vec<String> = self
+                        .slash_history
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, c)| c.to_lowercase().contains(&pattern))
+                        .map(|(n, c)| format!("[{}: {}]", n, c))
+                        .collect();
+                    if history.is_empty() {
+                        self.message("no slash commands found");
+                    } else {
+                        self.page(history.join("\n"));
+                    }
+                }
+                "/save_history" => {
+                    trace!("saving history of commands by demand");
+                    let moves_only = raw_args.contains(&"--moves-only");
+                    let force = raw_args.contains(&"--force");
+                    let file = raw_args.iter().find(|a| !a.starts_with("--")).copied();
+                    let path = self.artifact_path("history", file, HISTORY_FILE);
+                    let filter = if moves_only { HistoryFilter::Moves } else { HistoryFilter::All };
+                    match self.save_commands_history(&path, filter, force) {
+                        Ok(_) => {
+                            self.record_artifact("history", &path);
+                            self.message(format!("successfully saved commands history to file {}", path.display()))
+                        }
+                        Err(sh_err) => error!("failed to save commands history to file {} Error: {}", path.display(), sh_err),
+                    };
+
+                },
+                "/export_markdown" => {
+                    let file = raw_args.iter().find(|a| !a.starts_with("--")).copied();
+                    let path = self.artifact_path("walkthrough", file, WALKTHROUGH_FILE);
+                    let walkthrough = Walkthrough {
+                        commands: &self.commands_history,
+                        maze: self.maze(),
+                        inventory: &self.inventory,
+                        codes: &self.codes,
+                    };
+                    safe_file::write(&path, walkthrough.to_markdown(), raw_args.contains(&"--force"))?;
+                    self.record_artifact("walkthrough", &path);
+                    self.message(format!("written the walkthrough to {}", path.display()));
+                }
+                "/record_output" => {
+                    trace!("enabling output record by demand");
+                    const OUTPUT_FILE : &str = "output.txt";
+                    let path = self.artifact_path("output", raw_args.first().copied(), OUTPUT_FILE);
+                    match self.record_output(&path) {
+                        Ok(()) => {
+                            self.record_artifact("output", &path);
+                            self.message(format!("output recording to {} started", path.display()))
+                        }
+                        Err(e_err) => error!("failed to start output recording. Error: {}", e_err),
+                    }
+                },
+                "/stop_recording" => {
+                    let path = self.stop_recording()?;
+                    self.message(format!("output recording to {} stopped", path.display()));
+                }
+                "/rotate_recording" => {
+                    let Some(file) = raw_args.first() else {
+                        return Err("usage: /rotate_recording <file>".into());
+                    };
+                    let path = self.artifact_path("output", Some(file), file);
+                    let previous = self.stop_recording()?;
+                    self.record_output(&path)?;
+                    self.record_artifact("output", &path);
+                    self.message(format!(
+                        "output recording switched from {} to {}",
+                        previous.display(),
+                        path.display()
+                    ));
+                }
+                "/recording_status" => self.message(self.get_recording_status()),
+                "/map" => {
+                    trace!("rendering the map by demand");
+                    const MAP_RADIUS: usize = 4;
+                    self.message(self.maze().render_map(MAP_RADIUS));
+                }
+                "/solve" => {
+                    const DEFAULT_SOLVE_STEPS: u32 = 100;
+                    let (steps, goals) = parse_solve_args(&args, self.codes.len())?;
+                    let steps = steps.unwrap_or(DEFAULT_SOLVE_STEPS);
+                    let until: Vec<String> = goals.iter().map(|g| format!(" until {}", g)).collect();
+                    self.message(format!(
+                        "exploring the map for {} moves (seed {}){}",
+                        steps,
+                        self.maze().seed(),
+                        until.join(" or")
+                    ));
+                    self.solve_goals = goals;
+                    // The exploration continues once the game responds to this command
+                    self.solving = true;
+                    self.solve_steps_left = steps;
+                    self.solve_moves = 0;
+                    self.solve_started = Instant::now();
+                    self.solve_paused = None;
+                }
+                "/stop_solve" => {
+                    if !self.solving && self.solve_paused.is_none() {
+                        return Err("/solve is not running".into());
+                    }
+                    self.drop_solver_input();
+                    self.maze_mut().clear_route();
+                    self.solve_paused = None;
+                    self.finish_solving("stopped by the user");
+                }
+                "/pause_solve" => {
+                    if !self.solving {
+                        return Err("/solve is not running".into());
+                    }
+                    self.drop_solver_input();
+                    self.solving = false;
+                    self.solve_paused = Some(Instant::now());
+                    self.message(format!(
+                        "/solve paused after {} moves with {} moves left (/resume_solve continues it)",
+                        self.solve_moves, self.solve_steps_left
+                    ));
+                }
+                "/resume_solve" => {
+                    let Some(paused) = self.solve_paused.take() else {
+                        return Err("/solve is not paused".into());
+                    };
+                    // The time of the pause does not count for the estimated time left
+                    self.solve_started += paused.elapsed();
+                    self.message(format!("resuming /solve with {} moves left", self.solve_steps_left));
+                    // The exploration continues once the game responds to this command
+                    self.solving = true;
+                }
+                "/strategy" => {
+                    if !args.is_empty() {
+                        // The keys which are not given keep their current values
+                        let current = self.maze().strategy().to_string();
+                        let strategy: ExplorationStrategy =
+                            format!("{},{}", current, args.join(",")).parse()?;
+                        self.maze_mut().set_strategy(strategy);
+                    }
+                    self.message(format!("exploration strategy: {}", self.maze().strategy()));
+                }
+                "/show_path" => {
+                    let room = match args.as_slice() {
+                        [] => match self.maze().current_node() {
+                            Some(node) => node.id,
+                            None => return Err("no rooms have been visited yet".into()),
+                        },
+                        [room] => room.trim_start_matches('#').parse()?,
+                        _ => return Err("at most one argument is expected".into()),
+                    };
+                    self.page(self.maze().render_paths(room));
+                }
+                "/goto" => {
+                    let [room] = args.as_slice() else {
+                        return Err("room number is expected".into());
+                    };
+                    let room: u16 = room.trim_start_matches('#').parse()?;
+                    let Some(from) = self.maze().current_node().map(|n| n.id) else {
+                        return Err("no rooms have been visited yet".into());
+                    };
+                    let path = self
+                        .maze()
+                        .shortest_path(from, room)
+                        .ok_or(format!("none of the known passages lead to room #{}", room))?;
+                    self.message(format!(
+                        "going to room #{} in {} moves (cost {})",
+                        room,
+                        path.len(),
+                        self.maze().path_cost(from, &path)
+                    ));
+                    path.iter().for_each(|exit| self.feed_input(&format!("go {}", exit), true));
+                }
+                "/set_edge_cost" => {
+                    let [room, exit @ .., cost] = args.as_slice() else {
+                        return Err("room number, exit and cost are expected".into());
+                    };
+                    if exit.is_empty() {
+                        return Err("room number, exit and cost are expected".into());
+                    }
+                    let room: u16 = room.trim_start_matches('#').parse()?;
+                    let exit = exit.join(" ");
+                    let cost: u32 = cost.parse()?;
+                    self.maze_mut().set_edge_cost(room, &exit, cost)?;
+                    self.message(format!("exit '{}' of room #{} costs {}", exit, room, cost));
+                }
+                "/node" => {
+                    let [room] = args.as_slice() else {
+                        return Err("room number is expected".into());
+                    };
+                    let room: u16 = room.trim_start_matches('#').parse()?;
+                    match self.maze().render_node(room) {
+                        Some(report) => self.page(report),
+                        None => return Err(format!("room #{} is unknown", room).into()),
+                    }
+                }
+                "/merge_nodes" => {
+                    let [a, b] = args.as_slice() else {
+                        return Err("two room numbers are expected".into());
+                    };
+                    let a: u16 = a.trim_start_matches('#').parse()?;
+                    let b: u16 = b.trim_start_matches('#').parse()?;
+                    let into = self.maze_mut().merge_nodes(a, b)?;
+                    self.message(format!("rooms #{} and #{} merged into room #{}", a, b, into));
+                }
+                "/split_node" => {
+                    let [room] = args.as_slice() else {
+                        return Err("room number is expected".into());
+                    };
+                    let room: u16 = room.trim_start_matches('#').parse()?;
+                    let new = self.maze_mut().split_node(room)?;
+                    self.message(format!("room #{} split off room #{}", new, room));
+                }
+                "/note" => {
+                    let text = raw_text;
+                    if text.is_empty() {
+                        return Err("note text is expected".into());
+                    }
+                    match self.maze_mut().add_note(text) {
+                        Some(room) => self.message(format!("note attached to room #{}", room)),
+                        None => return Err("no rooms have been visited yet".into()),
+                    }
+                }
+                "/inject" => {
+                    // The command is taken as it is, the quotes around it are optional
+                    let text = raw_text;
+                    let text = text
+                        .strip_prefix('"')
+                        .and_then(|t| t.strip_suffix('"'))
+                        .unwrap_or(text);
+                    if text.is_empty() {
+                        return Err("usage: /inject \"<command>\"".into());
+                    }
+                    self.queue_input(text);
+                    self.message(format!("queued '{}' for the game", text));
+                }
+                "/read_notes" => {
+                    let item = args.join(" ");
+                    let notes: Vec<String> = self
+                        .maze()
+                        .documents()
+                        .iter()
+                        .filter(|d| item.is_empty() || d.item == item)
+                        .map(|d| {
+                            let room = d.room.map_or("?".to_string(), |r| format!("#{}", r));
+                            format!("***  {} (read in room {})  ***\n{}", d.item, room, d.text)
+                        })
+                        .collect();
+                    if notes.is_empty() {
+                        self.message("there are no notes");
+                    } else {
+                        self.page(notes.join("\n\n"));
+                    }
+                }
+                "/frontier" => {
+                    let report = self.get_frontier_report();
+                    self.page(report);
+                }
+                "/coins" => self.plan_coins()?,
+                "/explore_parallel" => {
+                    // The number of the exits taken, unless told otherwise
+                    const DEFAULT_PARALLEL_EXITS: usize = 500;
+                    let workers = match args.first() {
+                        Some(n) => n.parse()?,
+                        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+                    };
+                    let exits = match args.get(1) {
+                        Some(n) => n.parse()?,
+                        None => DEFAULT_PARALLEL_EXITS,
+                    };
+                    let maze = std::mem::take(self.maze_mut());
+                    let (maze, report) = maze::parallel::explore(&mut self.machine, maze, workers, exits);
+                    *self.maze_mut() = maze;
+                    self.page(report.to_string());
+                }
+                "/route" => {
+                    let route: Vec<&str> = self.maze().planned_route().collect();
+                    if route.is_empty() {
+                        self.message("there is no planned route");
+                    } else {
+                        self.message(format!("planned route of {} moves: {}", route.len(), route.join(", ")));
+                    }
+                }
+                "/break" => {
+                    let addr = parse_address_argument(&args)?;
+                    self.breakpoints.insert(addr);
+                    self.message(format!("breakpoint set at {}", Address::new(addr)));
+                }
+                "/delete_break" => {
+                    let addr = parse_address_argument(&args)?;
+                    if self.breakpoints.remove(&addr) {
+                        self.message(format!("breakpoint at {} deleted", Address::new(addr)));
+                    } else {
+                        return Err(format!("there is no breakpoint at {}", Address::new(addr)).into());
+                    }
+                }
+                "/break_output" => {
+                    // The pattern is case sensitive and may contain spaces
+                    let pattern = raw_text;
+                    if pattern.is_empty() {
+                        return Err("usage: /break_output <regex>".into());
+                    }
+                    let regex = Regex::new(pattern)?;
+                    self.output_breakpoints.push(regex);
+                    self.message(format!("breakpoint set on the output matching /{}/", pattern));
+                }
+                "/delete_break_output" => {
+                    let pattern = raw_text;
+                    let before = self.output_breakpoints.len();
+                    self.output_breakpoints.retain(|r| r.as_str() != pattern);
+                    if self.output_breakpoints.len() == before {
+                        return Err(format!("there is no breakpoint on the output /{}/", pattern).into());
+                    }
+                    self.message(format!("breakpoint on the output /{}/ deleted", pattern));
+                }
+                "/break_room" => {
+                    self.break_on_room = match args.as_slice() {
+                        [] => !self.break_on_room,
+                        ["on"] => true,
+                        ["off"] => false,
+                        _ => return Err("usage: /break_room [on|off]".into()),
+                    };
+                    let state = if self.break_on_room { "on" } else { "off" };
+                    self.message(format!("breaking on the room change is {}", state));
+                }
+                "/break_input" => {
+                    self.break_on_input = match args.as_slice() {
+                        [] => !self.break_on_input,
+                        ["on"] => true,
+                        ["off"] => false,
+                        _ => return Err("usage: /break_input [on|off]".into()),
+                    };
+                    let state = if self.break_on_input { "on" } else { "off" };
+                    self.message(format!("breaking on the 'in' instruction is {}", state));
+                }
+                "/protect" | "/unprotect" => {
+                    let [start, end] = args[..] else {
+                        return Err(format!("usage: {} <start> <end>", name).into());
+                    };
+                    let range = (parse_address(start)?, parse_address(end)?);
+                    if range.0 > range.1 {
+                        return Err("the start of the range is after its end".into());
+                    }
+                    let text = format!("{}..{}", Address::new(range.0), Address::new(range.1));
+                    if name == "/protect" {
+                        if !self.protected.contains(&range) {
+                            self.protected.push(range);
+                        }
+                        self.message(format!("memory {} is protected", text));
+                    } else if let Some(n) = self.protected.iter().position(|r| *r == range) {
+                        self.protected.remove(n);
+                        self.message(format!("memory {} is not protected anymore", text));
+                    } else {
+                        return Err(format!("memory {} is not protected", text).into());
+                    }
+                }
+                "/taint" => {
+                    let on = match args.as_slice() {
+                        [] => self.taint.is_none(),
+                        ["on"] => true,
+                        ["off"] => false,
+                        _ => return Err("usage: /taint [on|off]".into()),
+                    };
+                    if on != self.taint.is_some() {
+                        self.taint = on.then(|| Taint::new(&self.machine));
+                    }
+                    let state = if on { "on" } else { "off" };
+                    self.message(format!("taint tracking is {}", state));
+                }
+                "/tainted" => {
+                    let Some(taint) = &self.taint else {
+                        return Err("taint tracking is off, turn it on with /taint on".into());
+                    };
+                    let tainted = match args.as_slice() {
+                        [] => {
+                            let report = taint.to_string();
+                            self.page(report);
+                            return Ok(());
+                        }
+                        [register] if register.starts_with('r') => {
+                            let r: usize = register[1..]
+                                .parse()
+                                .ok()
+                                .filter(|r| *r < 8)
+                                .ok_or(format!("unknown register '{}'", register))?;
+                            taint.register(r)
+                        }
+                        [address] => taint.memory(parse_address(address)?),
+                        _ => return Err("usage: /tainted [rN|addr]".into()),
+                    };
+                    let verdict = if tainted { "derived from the input" } else { "not tainted" };
+                    self.message(format!("{} is {}", args[0], verdict));
+                }
+                "/watch_expr" => {
+                    let text = raw_text.trim_matches('"');
+                    if text.is_empty() {
+                        return Err("usage: /watch_expr <expression>, e.g. mem[0x0aa7] + r1".into());
+                    }
+                    let watch = Watch::new(text, &self.machine)?;
+                    self.message(format!("watching [{}] {}", self.watches.len(), watch));
+                    self.watches.push(watch);
+                }
+                "/watches" => {
+                    if self.watches.is_empty() {
+                        self.message("no watch expressions");
+                    } else {
+                        let watches: Vec<String> = self
+                            .watches
+                            .iter()
+                            .enumerate()
+                            .map(|(n, w)| format!("[{}] {}", n, w))
+                            .collect();
+                        self.message(watches.join("\n"));
+                    }
+                }
+                "/delete_watch" => {
+                    let [n] = args.as_slice() else {
+                        return Err("the number of the watch expression is expected".into());
+                    };
+                    let n: usize = n.parse()?;
+                    if n >= self.watches.len() {
+                        return Err(format!("there is no watch expression [{}]", n).into());
+                    }
+                    let watch = self.watches.remove(n);
+                    self.message(format!("watch expression {} deleted", watch.text()));
+                }
+                "/rng" => {
+                    let generators = self.generators()?;
+                    let list: Vec<String> = generators.iter().map(|g| g.to_string()).collect();
+                    self.message(format!("pseudo-random generators:\n{}", list.join("\n")));
+                }
+                "/freeze_rng" => match args.as_slice() {
+                    ["off"] => {
+                        self.frozen_rng = None;
+                        self.message("the pseudo-random generators are not frozen anymore");
+                    }
+                    [value] => {
+                        let value = parse_address(value)
+                            .map_err(|_| format!("the state '{}' is not a number below {}", value, MAX))?;
+                        let generators = self.generators()?;
+                        self.message(format!("{} pseudo-random generator(s) frozen at {}", generators.len(), value));
+                        self.frozen_rng = Some((generators, value));
+                    }
+                    _ => return Err("usage: /freeze_rng <value>|off".into()),
+                },
+                "/save_symbols" => {
+                    let file = raw_args.iter().find(|a| !a.starts_with("--")).copied();
+                    let path = self.artifact_path("symbols", file, SYMBOLS_FILE);
+                    let symbols = rng::symbols(&self.generators()?);
+                    safe_file::write(&path, symbols, raw_args.contains(&"--force"))?;
+                    self.record_artifact("symbols", &path);
+                    self.message(format!("saved the symbols to {}", path.display()));
+                }
+                "/breakpoints" => self.message(self.get_breakpoints_info()),
+                "/stats" => self.page(self.get_execution_stats()),
+                "/pause" => {
+                    trace!("pausing execution by demand");
+                    self.paused = true;
+                }
+                "/continue" | "/step" | "/stepback" if !self.paused => {
+                    return Err(format!("{} is available only while the VM is paused", name).into());
+                }
+                "/continue" => {
+                    trace!("resuming execution by demand");
+                    self.resume();
+                }
+                "/step" => {
+                    trace!("executing single instruction by demand");
+                    self.execute_instruction();
+                    self.message(format!("stopped at {}", self.machine.current_address));
+                }
+                "/stepback" => {
+                    let n: usize = match args.first() {
+                        Some(n) => n.parse().map_err(|e| format!("invalid number '{}': {}", n, e))?,
+                        None => 1,
+                    };
+                    let mut undone = 0;
+                    let mut limit = None;
+                    while undone < n && limit.is_none() {
+                        match self.machine.step_back() {
+                            Ok(()) => undone += 1,
+                            Err(e) => limit = Some(e),
+                        }
+                    }
+                    self.cycles -= undone as u64;
+                    self.snapshots.clear();
+                    // Do not stop at the breakpoint we have stepped back to
+                    self.resuming = true;
+                    if let Some(limit) = limit {
+                        warn!("stopped stepping back after {} instructions: {}", undone, limit);
+                        self.message(format!("cannot step back any further: {}", limit));
+                    }
+                    self.message(format!(
+                        "stepped back {} instructions to {} ({} more can be undone)",
+                        undone,
+                        self.machine.current_address,
+                        self.machine.journal_len()
+                    ));
+                }
+                "/replay_last" => {
+                    let cycles: u64 = match args.first() {
+                        Some(n) => n.parse().map_err(|e| format!("invalid number '{}': {}", n, e))?,
+                        None => return Err("the number of cycles is expected".into()),
+                    };
+                    let path = self.artifact_path("replay", raw_args.get(1).copied(), REPLAY_TRACE_FILE);
+                    let replay = self.snapshots.replay(self.cycles, cycles, &path)?;
+                    self.record_artifact("replay", &path);
+                    let machine = &replay.machine;
+                    if machine.position() != self.machine.position()
+                        || machine.registers() != self.machine.registers()
+                        || machine.stack != self.machine.stack
+                    {
+                        warn!("the replay has ended at {}", machine.position());
+                        self.message("the replay has diverged, the state has been changed since the snapshot");
+                    }
+                    self.message(format!(
+                        "replayed from the snapshot at cycle {}, {} records are written to {}",
+                        replay.start,
+                        replay.records,
+                        path.display()
+                    ));
+                }
+                "/list" => {
+                    let n = match args.first() {
+                        Some(n) => n.parse().map_err(|e| format!("invalid number '{}': {}", n, e))?,
+                        None => LIST_INSTRUCTIONS,
+                    };
+                    self.page(self.get_listing(n));
+                }
+                "/save_state" => {
+                    let path = self.artifact_path("state", raw_args.first().copied(), STATE_JSON_FILE);
+                    self.snapshot().save(&path)?;
+                    self.record_artifact("state", &path);
+                    self.message(format!("saved session state to {}", path.display()));
+                }
+                "/load_state" => {
+                    // The session directory loads the state saved to it last by default
+                    let latest = self.workspace.as_ref().and_then(|w| w.latest("state"));
+                    let path = match (raw_args.first(), latest) {
+                        (None, Some(latest)) => latest,
+                        (file, _) => self.artifact_path("state", file.copied(), STATE_JSON_FILE),
+                    };
+                    self.restore(VmState::load(&path)?);
+                    self.message(format!(
+                        "restored session state from {} at {}",
+                        path.display(),
+                        self.machine.current_address
+                    ));
+                }
+                "/autosaves" => match args.first() {
+                    Some(n) => {
+                        let n: usize = n.parse().map_err(|e| format!("invalid number '{}': {}", n, e))?;
+                        let index = self.autosaves.len().checked_sub(n).filter(|_| n > 0);
+                        let autosave = index
+                            .and_then(|i| self.autosaves.get(i))
+                            .ok_or_else(|| format!("there are {} autosaves", self.autosaves.len()))?;
+                        let room = autosave.room.clone();
+                        self.restore(autosave.state.clone());
+                        self.message(format!("restored the state saved on entering {}", room));
+                    }
+                    None => self.page(self.get_autosaves_info()),
+                },
+                "/diff_snapshot" => {
+                    let [a, b] = raw_args.as_slice() else {
+                        return Err("two state files are expected".into());
+                    };
+                    // The relative names are taken inside the session directory, as for /load_state
+                    let a = VmState::load(&self.artifact_path("state", Some(a), STATE_JSON_FILE))?;
+                    let b = VmState::load(&self.artifact_path("state", Some(b), STATE_JSON_FILE))?;
+                    self.message(state::diff_states(&a, &b));
+                }
+                "/dump_state" => {
+                    trace!("dumping VM state by demand");
+                    // TODO: Provide an argument to this command
+                    let path = self.artifact_path("dump", None, STATE_FILE);
+                    match self.dump_state(&path, raw_args.contains(&"--force")) {
+                        Ok(()) => {
+                            self.record_artifact("dump", &path);
+                            self.message(format!("saved VM state to {}", path.display()))
+                        }
+                        Err(st_err) => error!("failed to save VM state to {} Error: {}", path.display(), st_err),
+                    }
+                    
+                }
+                "/dump_memory" => {
+                    // TODO: Provide an argument to this command
+                    const RAM_FILE : &str = "vm_memory_dump.bin";
+                    let path = self.artifact_path("memory", None, RAM_FILE);
+                    match self.dump_memory(&path, raw_args.contains(&"--force")) {
+                        Ok(()) => {
+                            self.record_artifact("memory", &path);
+                            self.message(format!("saved VM RAM to {}", path.display()))
+                        }
+                        Err(m_err) => error!("failed to save VM RAM to {} Error: {}", path.display(), m_err),
+                    }
+
+                }
+                user_command => {
+                    return Err(format!("unsupported slash command {}", user_command).into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VM {
+    fn new() -> Self {
+        VM {
+            machine: Machine::new(),
+            commands_history: vec![],
+            slash_history: vec![],
+            current_command_buf: String::new(),
+            input_decoder: InputDecoder::default(),
+            replay_commands: None,
+            output_parser: OutputParser::new(),
+            game_help: None,
+            last_room: None,
+            inventory: vec![],
+            status_line: false,
+            status_pending: false,
+            events: Self::default_event_bus(),
+            line_buffer: String::new(),
+            codes: vec![],
+            coin_values: BTreeMap::new(),
+            coins_planned: false,
+            solving: false,
+            solve_steps_left: 0,
+            solve_moves: 0,
+            solve_started: Instant::now(),
+            solve_paused: None,
+            solve_command: None,
+            quit_after_solve: false,
+            solve_goals: vec![],
+            solve_progress_interval: 0,
+            solve_report_file: None,
+            cycles: 0,
+            input_buffer: VecDeque::new(),
+            stdin_input: true,
+            batch: false,
+            transcript: String::new(),
+            pager: true,
+            #[cfg(feature = "tui")]
+            keys: false,
+            quiet: false,
+            json_lines: false,
+            started: Instant::now(),
+            stdout_output: true,
+            stdout: FlushingWriter::new(io::stdout(), FlushPolicy::default()),
+            flush_policy: FlushPolicy::default(),
+            output_buffer: String::new(),
+            awaiting_input: false,
+            breakpoints: BTreeSet::new(),
+            output_breakpoints: vec![],
+            break_on_room: false,
+            break_on_input: false,
+            watches: vec![],
+            protected: vec![],
+            frozen_rng: None,
+            taint: None,
+            paused: false,
+            resuming: false,
+            scripts: None,
+            rules: ExpectRules::default(),
+            cycle_limit: None,
+            deadline: None,
+            stdin_lines: None,
+            stdin_failure: None,
+            interruption: None,
+            trace: None,
+            workspace: None,
+            rom_hash: capabilities::rom_hash(&[]),
+            options: vec![],
+            autosaves: VecDeque::new(),
+            autosave_limit: 0,
+            snapshots: SnapshotRing::new(0),
+            timed_input: VecDeque::new(),
+        }
+    }
+    /// The maze analyzer and other built-in plugins are always registered
+    fn default_event_bus() -> EventBus {
+        let mut events = EventBus::new();
+        events.register(Box::new(MazeAnalyzer::new()));
+        events
+    }
+    /// This method captures the complete state of the session
+    /// Returns the state of the session to save or to restore later. The journal of /stepback is
+    /// left out, it is not saved and the autosaves would copy it on every room
+    fn snapshot(&mut self) -> VmState {
+        VmState {
+            format: STATE_FORMAT,
+            machine: self.machine.checkpoint(),
+            cycles: self.cycles,
+            commands_history: self.commands_history.clone(),
+            inventory: self.inventory.clone(),
+            codes: self.codes.clone(),
+            breakpoints: self.breakpoints.clone(),
+            maze: Some(self.maze().clone()),
+        }
+    }
+    /// This method replaces the session state with the saved one. The partially collected output
+    /// belongs to the replaced session, so it is dropped
+    fn restore(&mut self, state: VmState) {
+        debug!("restoring VM state at {}", state.machine.current_address);
+        // The journal is not saved, but it keeps recording after the restored state
+        let journal_size = self.machine.journal_size();
+        self.machine = state.machine;
+        self.machine.set_journal_size(journal_size);
+        self.cycles = state.cycles;
+        self.snapshots.clear();
+        self.commands_history = state.commands_history;
+        self.inventory = state.inventory;
+        self.codes = state.codes;
+        self.breakpoints = state.breakpoints;
+        if let Some(maze) = state.maze {
+            *self.maze_mut() = maze;
+        }
+        let inventory = self.inventory.clone();
+        self.maze_mut().set_inventory(&inventory);
+        self.output_parser.reset();
+        self.line_buffer.clear();
+        self.last_room = None;
+        self.solving = false;
+        self.solve_paused = None;
+    }
+    /// This method saves the state on entering the room, dropping the oldest autosave over the limit
+    fn autosave(&mut self, room: &str) {
+        debug!("autosaving on entering {}", room);
+        if self.autosaves.len() >= self.autosave_limit {
+            self.autosaves.pop_front();
+        }
+        let state = self.snapshot();
+        self.autosaves.push_back(Autosave {
+            room: room.to_string(),
+            state,
+        });
+    }
+    fn get_autosaves_info(&self) -> String {
+        let mut info = String::new();
+        info.push_str("***               Autosaves               ***\n");
+        info.push_str(&format!("{}\n", "=".repeat(44)));
+        // The latest autosave is the first one, as it is restored with /autosaves 1
+        for (n, autosave) in self.autosaves.iter().rev().enumerate() {
+            info.push_str(&format!(
+                "{:>3}: {} (after {} commands, cycle {})\n",
+                n + 1,
+                autosave.room,
+                autosave.state.commands_history.len(),
+                autosave.state.cycles
+            ));
+        }
+        info.push_str(&"=".repeat(45));
+        info
+    }
+    fn maze(&self) -> &MazeAnalyzer {
+        self.events
+            .plugin::<MazeAnalyzer>()
+            .expect("maze analyzer is always registered")
+    }
+    fn maze_mut(&mut self) -> &mut MazeAnalyzer {
+        self.events
+            .plugin_mut::<MazeAnalyzer>()
+            .expect("maze analyzer is always registered")
+    }
+    fn get_state(&self) -> String {
+        let mut state = String::new();
+        state.push_str("***         Virtual Machine State         ***\n");
+        state.push_str(&format!(
+            "{}\n",
+            "=".repeat(44)
+        ));
+        state.push_str(&format!("{:<9}: {}\n", "halt", self.machine.halt));
+        if let Some(fault) = self.machine.fault() {
+            state.push_str(&format!("{:<9}: {}\n", "fault", fault));
+        }
+        if let Some(interruption) = &self.interruption {
+            state.push_str(&format!("{:<9}: {}\n", "stopped", interruption));
+        }
+        if !self.machine.violations().is_empty() {
+            state.push_str(&format!("{:<9}:\n", "violation"));
+            for violation in self.machine.violations() {
+                state.push_str(&format!("  {}\n", violation));
+            }
+        }
+        state.push_str(&format!("{:<9}: {}\n", "rom size", self.machine.memory.len()));
+        state.push_str(&self.get_registers_info(1));
+        state.push_str(&self.get_stack_info(1));
+        state.push_str(&format!("{:<9}: {}\n", "position", self.machine.current_address));
+        state.push_str(&format!(
+            "{}\n",
+            "_".repeat(44)
+        ));
+        state.push_str(&format!(
+            "{:<9}: {}\n",
+            "# to replay",
+            self.replay_commands
+                .clone()
+                .map_or("N/A".to_string(), |v| v.iter().len().to_string())
+        ));
+        state.push_str(&format!(
+            "{:<9}: {}\n",
+            "record out",
+            self.events
+                .plugin::<OutputRecorder>()
+                .filter(|r| r.is_active())
+                .map_or("N/A".to_string(), |r| r.path().display().to_string())
+        ));
+        state.push_str(&format!(
+            "{:<9}: {}\n",
+            "# cmd. hist",
+            self.commands_history.len()
+        ));
+        state.push_str(&self.get_completion_info());
+        state.push_str("=============================================\n");
+        state
+    }
+    /// This method shows how much of the map is explored, overall and in the rooms left to explore
+    fn get_completion_info(&self) -> String {
+        let maze = self.maze();
+        let mut info = format!(
+            "{:<9}: {} rooms, {:.0}% explored\n",
+            "map",
+            maze.nodes().len(),
+            maze.total_completion() * 100.0
+        );
+        for id in maze.frontier() {
+            let title = maze.node_by_id(id).map_or("", |n| n.title.as_str());
+            info.push_str(&format!(
+                "  #{:<4} {:>3.0}% {}\n",
+                id,
+                maze.completion(id) * 100.0,
+                title
+            ));
+        }
+        info
+    }
+    fn get_registers_info(&self, indent: usize) -> String {
+        let mut registers = String::new();
+        let indentation = "  ".repeat(indent);
+        registers.push_str(&format!("{:<9}:\n", "registers"));
+        registers.push_str(&format!(
+            "{}{}\n",
+            indentation,
+            "-".repeat(44 - indent)
+        ));
+        self.machine.registers.iter().enumerate().for_each(|(n, r)| {
+            registers.push_str(&format!("{}{}{}: {:<10}\n", indentation, "reg ", n, r))
+        });
+        registers.push_str(&format!(
+            "{}{}\n",
+            indentation,
+            "-".repeat(44 - indent)
+        ));
+        registers
+    }
+    fn get_stack_info(&self, indent: usize) -> String {
+        let mut stack = String::new();
+        let indentation = "  ".repeat(indent);
+        stack.push_str(&format!(
+            "{:<9}  (size: {:3}):\n",
+            "stack",
+            self.machine.stack.len()
+        ));
+        stack.push_str(&format!(
+            "{}{}\n",
+            indentation,
+            "+".repeat(44 - indent)
+        ));
+        self.machine.stack
+            .iter()
+            .enumerate()
+            .rev()
+            .for_each(|(n, r)| stack.push_str(&format!("{}[{}: {:<10}]\n", indentation, n, r)));
+        stack.push_str(&format!(
+            "{}{}\n",
+            indentation,
+            "+".repeat(44 - indent)
+        ));
+        stack
+    }
+    /// This method shows the commands in the range matching the filter with the room they have been entered in,
+    /// the cycle and the outcome, e.g. the room they have led to
+    fn get_commands_history(&self, indent: usize, filter: HistoryFilter, range: Range<usize>) -> String {
+        let mut commands = String::new();
+        let indentation = "  ".repeat(indent);
+        commands.push_str(&format!(
+            "{:<9}  (size: {:3}):\n",
+            "commands history",
+            self.commands_history.len()
+        ));
+        commands.push_str(&format!(
+            "{}{}\n",
+            indentation,
+            ".".repeat(44 - indent)
+        ));
+        let entries = self.commands_history().iter().enumerate().skip(range.start).take(range.len());
+        for (n, entry) in entries.filter(|(_, e)| filter.matches(e)) {
+            commands.push_str(&indentation);
+            commands.push_str(&self.get_history_entry_info(n, entry));
+            commands.push('\n');
+        }
+        commands.push_str(&format!(
+            "{}{}\n",
+            indentation,
+            ".".repeat(44 - indent)
+        ));
+        commands
+    }
+    fn get_history_entry_info(&self, n: usize, entry: &HistoryEntry) -> String {
+        let title = |id: Option<u16>| {
+            id.and_then(|id| self.maze().node_by_id(id))
+                .map_or("unknown location", |n| n.title.as_str())
+        };
+        let mut line = format!("[{}: {:<10}] in {} at cycle {}", n, entry.command, title(entry.room), entry.cycle);
+        if let Some(to) = entry.moved_to() {
+            line.push_str(&format!(" -> {}", title(Some(to))));
+        }
+        if !entry.is_understood() {
+            line.push_str(" (not understood)");
+        }
+        if !entry.codes.is_empty() {
+            line.push_str(&format!(" codes: {}", entry.codes.join(", ")));
+        }
+        line
+    }
+    /// This method rewinds the session to the moment before the command of the history was entered:
+    /// it restores the latest autosave taken before it and replays the commands up to it
+    fn replay_from(&mut self, index: usize) -> Result<(), Box<dyn Error>> {
+        if index >= self.commands_history.len() {
+            return Err(format!("there are {} commands in the history", self.commands_history.len()).into());
+        }
+        let autosave = self
+            .autosaves
+            .iter()
+            .rev()
+            .find(|a| a.state.commands_history.len() <= index)
+            .ok_or_else(|| format!("there is no autosave taken before the command {}", index))?;
+        let (room, state) = (autosave.room.clone(), autosave.state.clone());
+        let commands: Vec<String> = self.commands_history[state.commands_history.len()..index]
+            .iter()
+            .map(|e| e.command.clone())
+            .collect();
+        let target = self.commands_history[index].command.clone();
+        // The autosaves taken after the command lead to the future, which is going to be rewritten
+        self.autosaves.retain(|a| a.state.commands_history.len() <= index);
+        self.restore(state);
+        self.input_buffer.clear();
+        commands.iter().for_each(|command| self.feed_input(command, true));
+        self.message(format!(
+            "restored the state saved on entering {}, replaying {} commands up to '{}'",
+            room,
+            commands.len(),
+            target
+        ));
+        Ok(())
+    }
+    fn new_from_rom(rom: Vec<u8>) -> Result<Self, RomTooLarge> {
+        let mut vm = Self::new();
+        vm.machine.load_rom(&rom)?;
+        vm.rom_hash = capabilities::rom_hash(&rom);
+        Ok(vm)
+    }
+    fn new_from_rom_with_options(
+        rom: Vec<u8>,
+        replay_commands: Option<Vec<String>>,
+        record_output: Option<PathBuf>,
+    ) -> Result<Self, RomTooLarge> {
+        let mut vm = VM {
+            replay_commands,
+            ..Self::new_from_rom(rom)?
+        };
+        if let Some(path) = record_output {
+            vm.events.register(Box::new(OutputRecorder::new(&path, vm.flush_policy)));
+        }
+        vm.get_replay_commands()
+            .iter()
+            .for_each(|command| vm.feed_input(command, true));
+        Ok(vm)
+    }
+    /// This method appends the command to the input buffer
+    fn feed_input(&mut self, command: &str, echo: bool) {
+        trace!("queueing input '{}'", command);
+        command
+            .bytes()
+            .chain(iter::once(b'\n'))
+            .for_each(|b| self.input_buffer.push_back((b, echo)));
+    }
+    /// This method appends the command to the replayed ones at runtime, e.g. for the scripts, the
+    /// plugins or /inject
+    fn queue_input(&mut self, command: &str) {
+        debug!("injecting input '{}'", command);
+        self.replay_commands
+            .get_or_insert_with(Vec::new)
+            .push(command.to_string());
+        self.feed_input(command, true);
+    }
+    /// This method handles the line typed by the user, when the input does not come from stdin
+    #[cfg_attr(not(any(feature = "tui", feature = "server")), allow(dead_code))]
+    fn submit_line(&mut self, line: &str) {
+        if self.machine.halt {
+            self.message("VM is halted");
+        } else if self.paused {
+            // The game cannot consume any input, while the VM is frozen
+            if !is_slash_command(line) {
+                self.message("only slash '/' commands are available while the VM is paused");
+            } else if let Err(e) = self.process_command(line.trim()) {
+                warn!("processing command returned an error: {}", e);
+                self.message(format!("Error: {}", e));
+            }
+        } else if !self.intercept_save_command(line) {
+            self.feed_input(line, false);
+        }
+    }
+    /// This method shows the game output either on stdout or collects it in the output buffer
+    fn emit_output(&mut self, c: char) {
+        if self.json_lines {
+            return;
+        }
+        if self.stdout_output {
+            self.write_stdout(c.encode_utf8(&mut [0; 4]));
+        } else {
+            self.output_buffer.push(c);
+        }
+    }
+    /// This method writes the game output to stdout, flushed according to --flush
+    fn write_stdout(&mut self, text: &str) {
+        if let Err(e) = self.stdout.write(text) {
+            warn!("failed to write to stdout. Error: {}", e);
+        }
+    }
+    /// This method flushes the game output written to stdout so far, e.g. once the game waits for
+    /// the command
+    fn flush_stdout(&mut self) {
+        if let Err(e) = self.stdout.prompt() {
+            warn!("failed to flush stdout. Error: {}", e);
+        }
+    }
+    /// This method sets when the game output is flushed to stdout and to the recording
+    fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.flush_policy = policy;
+        self.stdout.set_policy(policy);
+        if let Some(recorder) = self.events.plugin_mut::<OutputRecorder>() {
+            recorder.set_policy(policy);
+        }
+    }
+    /// This method shows the auxiliary (non game) information to the user
+    fn message(&mut self, text: impl fmt::Display) {
+        if self.stdout_output {
+            // The game output comes first, as it has been printed before
+            self.flush_stdout();
+            eprintln!("{}", text);
+        } else {
+            self.output_buffer.push_str(&format!("{}\n", text));
+        }
+    }
+    /// This method shows the information nobody has asked for, e.g. the reports at the end of the
+    /// run, unless the VM is quiet
+    fn chatter(&mut self, text: impl fmt::Display) {
+        if !self.quiet {
+            self.message(text);
+        }
+    }
+    /// This method shows the long output of the slash command through the pager, so it does not
+    /// scroll the game text away. The output of the commands from the replay or /solve is not paged
+    fn page(&mut self, text: impl fmt::Display) {
+        let text = text.to_string();
+        let interactive =
+            self.stdout_output && self.input_buffer.is_empty() && !self.solving && io::stderr().is_terminal();
+        if !self.pager || !interactive || text.lines().count() < PAGER_MIN_LINES {
+            self.message(text);
+            return;
+        }
+        if let Err(e) = run_pager(&text) {
+            warn!("failed to show the output in the pager. Error: {}", e);
+            self.message(text);
+        }
+    }
+    /// This method describes the 'in' instruction to be executed: where the character is stored
+    /// and which character it is going to be
+    fn get_input_info(&self) -> String {
+        let position = self.machine.position();
+        let target = self.machine.word(position + 1);
+        let destination = match target.checked_sub(MAX) {
+            Some(register) if register < 8 => format!(
+                "register {} (now {})",
+                register,
+                self.machine.registers()[register as usize]
+            ),
+            _ => format!("invalid operand {}", target),
+        };
+        let next = match self.input_buffer.front() {
+            Some((c, _)) => format!("{:?}", *c as char),
+            None => "not typed yet".to_string(),
+        };
+        format!(
+            "{} at {} stores the next character ({}) to {}",
+            "in".yellow(),
+            Address::new(position),
+            next,
+            destination
+        )
+    }
+    /// This method describes the 'wmem' instruction to be executed, if it writes into the
+    /// protected memory
+    fn get_protected_write_info(&self) -> Option<String> {
+        let position = self.machine.position();
+        if self.machine.word(position) != WMEM_OPCODE {
+            return None;
+        }
+        let target = self.machine.operand(self.machine.word(position + 1));
+        let value = self.machine.operand(self.machine.word(position + 2));
+        let (start, end) = self
+            .protected
+            .iter()
+            .find(|(start, end)| (*start..=*end).contains(&target))?;
+        Some(format!(
+            "{} at {} writes {} into the protected {} ({}..{})",
+            "wmem".yellow(),
+            Address::new(position),
+            value,
+            Address::new(target),
+            Address::new(*start),
+            Address::new(*end)
+        ))
+    }
+    /// Returns the pseudo-random generators found in the memory, failing if there is none
+    fn generators(&self) -> Result<Vec<Generator>, Box<dyn Error>> {
+        let generators = rng::find(&self.machine);
+        if generators.is_empty() {
+            return Err("no pseudo-random generator is found in the memory".into());
+        }
+        Ok(generators)
+    }
+    /// This method puts the frozen state into the register of the generator, which is about to
+    /// store it
+    fn pin_rng(&mut self) {
+        let Some((generators, value)) = &self.frozen_rng else {
+            return;
+        };
+        let position = self.machine.position();
+        if let Some(generator) = generators.iter().find(|g| g.store == position) {
+            trace!("pinning the state of the generator at {} to {}", generator.address, value);
+            self.machine.registers[generator.register] = *value;
+        }
+    }
+    /// This method reports the watch expressions, which have changed after the instruction
+    fn check_watches(&mut self) {
+        let mut changes = vec![];
+        for (n, watch) in self.watches.iter_mut().enumerate() {
+            if let Some(previous) = watch.update(&self.machine) {
+                let show = |v: Option<i64>| v.map_or("undefined".to_string(), |v| v.to_string());
+                changes.push(format!(
+                    "{} [{}] {}: {} -> {} at {}",
+                    "watch".yellow(),
+                    n,
+                    watch.text(),
+                    show(previous),
+                    show(watch.value()),
+                    self.machine.current_address
+                ));
+            }
+        }
+        changes.into_iter().for_each(|c| self.message(c));
+    }
+    /// This method disassembles the instructions around the current one, marking the breakpoints
+    fn get_listing(&self, n: usize) -> String {
+        let position = self.machine.position();
+        let listing = disasm::around(&self.machine, position, n, n);
+        disasm::annotate(&listing)
+            .iter()
+            .map(|line| {
+                let breakpoint = if self.breakpoints.iter().any(|b| line.contains(*b)) {
+                    "*".red().bold().to_string()
+                } else {
+                    " ".to_string()
+                };
+                let text = format!("{:>5} ({:#06x}): {}", line.address, line.address, line.text);
+                if line.contains(position) {
+                    format!("=>{} {}", breakpoint, text.yellow().bold())
+                } else {
+                    format!("  {} {}", breakpoint, text)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+    fn get_breakpoints_info(&self) -> String {
+        let toggles = [
+            (self.break_on_room, "room change"),
+            (self.break_on_input, "every 'in' instruction"),
+        ];
+        let toggled = toggles.iter().any(|t| t.0);
+        if self.breakpoints.is_empty()
+            && self.output_breakpoints.is_empty()
+            && self.protected.is_empty()
+            && !toggled
+        {
+            return "no breakpoints".to_string();
+        }
+        self.breakpoints
+            .iter()
+            .map(|b| format!("{}", Address::new(*b)))
+            .chain(self.output_breakpoints.iter().map(|r| format!("output /{}/", r)))
+            .chain(self.protected.iter().map(|(start, end)| {
+                format!("write into {}..{}", Address::new(*start), Address::new(*end))
+            }))
+            .chain(toggles.iter().filter(|t| t.0).map(|t| t.1.to_string()))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+    fn store_command_to_history(&mut self) {
+        debug!(
+            "storing command {} to command history",
+            self.current_command_buf.as_str()
+        );
+        let command = std::mem::take(&mut self.current_command_buf);
+        self.events.publish(VmEvent::CommandEntered(&command));
+        if let Err(process_error) = self.process_command(&command) {
+            warn!("processing command returned an error: {}", process_error);
+        }
+        // The slash commands are kept in their own history
+        if !is_slash_command(&command) {
+            let room = self.maze().current_node().map(|n| n.id);
+            self.commands_history.push(HistoryEntry::new(&command, room, self.cycles));
+        }
+        debug!("history size now is {}", self.commands_history.len());
+    }
+    fn grab_input(&mut self, c: char) {
+        match c {
+            '\n' => self.store_command_to_history(),
+            c if char_is_printable(c) => self.current_command_buf.push(c),
+            c => {
+                warn!("storing the control character {:?} of the command escaped", c);
+                self.current_command_buf.push_str(&escape_control(c));
+            }
+        }
+    }
+    /// This method accumulates the game output line and publishes it, once it is completed
+    fn collect_line(&mut self, c: char) {
+        self.line_buffer.push(c);
+        if c == '\n' {
+            self.publish_line();
+        }
+    }
+    fn publish_line(&mut self) {
+        if self.line_buffer.is_empty() {
+            return;
+        }
+        let line = std::mem::take(&mut self.line_buffer);
+        self.events.publish(VmEvent::OutputLineCompleted(&line));
+        for code in parser::find_codes(&line) {
+            if !self.codes.contains(&code) {
+                info!("found code '{}'", code);
+                if let Some(entry) = self.commands_history.last_mut() {
+                    entry.codes.push(code.clone());
+                }
+                self.events.publish(VmEvent::CodeFound(&code));
+                self.codes.push(code);
+            }
+        }
+        if let Some(pattern) = self.output_breakpoints.iter().find(|r| r.is_match(&line)) {
+            info!("output line matches /{}/ at {}", pattern, self.machine.current_address);
+            let message = format!("{} /{}/", "output matched".yellow(), pattern);
+            self.message(message);
+            self.paused = true;
+        }
+    }
+    /// This method feeds the game output to the parser and handles the response once the prompt is
+    /// printed
+    fn collect_response(&mut self, c: char) {
+        if let Some(response) = self.output_parser.push(c) {
+            self.run_script_hook(|s| s.on_output(&response.text));
+            self.apply_rules(&response.text);
+            let parts = response.parts();
+            self.store_narrative(&response.text, &parts);
+            self.handle_response(&parts);
+            self.events.publish(VmEvent::Prompt);
+        }
+    }
+    fn handle_response(&mut self, response: &ResponsePartsRef) {
+        if let Some(inventory) = &response.inventory {
+            trace!("updating inventory with {} items", inventory.len());
+            self.inventory = inventory.iter().map(|i| i.to_string()).collect();
+        }
+        self.apply_take_drop(response);
+        self.read_coin_value(response);
+        if let Some(item) = self
+            .commands_history
+            .last()
+            .and_then(|e| e.command.trim().strip_prefix("use "))
+            .map(|i| i.trim().to_string())
+        {
+            let effects = parser::use_effects(&item, response);
+            debug!("using '{}' has effects {:?}", item, effects);
+            self.apply_use_effects(&effects);
+            self.events.publish(VmEvent::ItemUsed(&item, &effects));
+        }
+        // The exits blocked with the former items are worth trying again
+        let inventory = self.inventory.clone();
+        self.maze_mut().set_inventory(&inventory);
+        self.events.publish(VmEvent::ResponseParsed(response));
+        // The maze analyzer has seen the response, so it knows the room shown by it
+        let room = response
+            .is_room()
+            .then(|| self.maze().current_node().map(|n| n.id))
+            .flatten();
+        if let Some(entry) = self.commands_history.last_mut()
+            && entry.result.is_none()
+        {
+            entry.result = Some(CommandResult {
+                room,
+                understood: !response.dont_understand,
+            });
+        }
+        if response.dont_understand
+            && let Some(suggestion) = self
+                .commands_history
+                .last()
+                .map(|e| e.command.as_str())
+                .filter(|c| !c.starts_with('/'))
+                .and_then(|c| self.suggest_command(c))
+        {
+            self.chatter(format!("{} did you mean '{}'?", "hint:".yellow(), suggestion));
+        }
+        if response.is_room() {
+            let room_changed = self.last_room.as_ref().is_none_or(|r| {
+                r.title.as_deref() != response.title || r.description != response.description
+            });
+            if room_changed {
+                self.events.publish(VmEvent::RoomChanged(response));
+            }
+            if room_changed && let Some(title) = response.title {
+                self.run_script_hook(|s| s.on_room_change(title));
+            }
+            if room_changed && self.autosave_limit > 0 {
+                self.autosave(response.title.unwrap_or("unknown location"));
+            }
+            if room_changed && self.break_on_room {
+                let title = response.title.unwrap_or("unknown location");
+                info!("entered '{}' at {}", title, self.machine.current_address);
+                self.message(format!("{} {}", "entered".yellow(), title));
+                self.paused = true;
+            }
+            // The owned copy is only made, when the room or its items are different
+            if room_changed || self.last_room.as_ref().is_some_and(|r| r.items != response.items) {
+                self.last_room = Some(response.clone().into_owned());
+            }
+        }
+        self.status_pending = true;
+        self.continue_solving();
+    }
+    /// This method keeps the long texts of the items, so they can be read again with /read_notes
+    fn store_narrative(&mut self, text: &str, parts: &ResponsePartsRef) {
+        let Some(narrative) = parser::narrative(text, parts) else {
+            return;
+        };
+        let command = self.commands_history.last().map_or("", |e| e.command.trim());
+        let item = command.strip_prefix("look ").unwrap_or(command).trim().to_string();
+        self.maze_mut().attach_document(&item, narrative);
+    }
+    /// This method keeps the inventory up to date after the item is taken or dropped, so the items
+    /// are known before the inventory is shown again
+    fn apply_take_drop(&mut self, response: &ResponsePartsRef) {
+        let Some(command) = self.commands_history.last().map(|e| e.command.trim()) else {
+            return;
+        };
+        if let Some(item) = command.strip_prefix("take ").map(str::trim)
+            && response.pretext.trim() == "Taken."
+            && !self.inventory.iter().any(|i| i == item)
+        {
+            self.inventory.push(item.to_string());
+        } else if let Some(item) = command.strip_prefix("drop ").map(str::trim)
+            && response.pretext.trim().starts_with("Dropped.")
+        {
+            let item = item.to_string();
+            self.inventory.retain(|i| *i != item);
+        }
+    }
+    /// This method remembers the value of the coin shown by 'look <coin>'
+    fn read_coin_value(&mut self, response: &ResponsePartsRef) {
+        let Some(item) = self
+            .commands_history
+            .last()
+            .and_then(|e| e.command.trim().strip_prefix("look "))
+            .map(str::trim)
+            .filter(|i| coins::is_coin(i))
+        else {
+            return;
+        };
+        if let Some(value) = coins::coin_value(&response.pretext) {
+            debug!("'{}' is worth {}", item, value);
+            self.coin_values.insert(item.to_string(), value);
+        }
+    }
+    /// This method queues the plan of the coin puzzle: it walks to the coins which are not carried
+    /// yet, takes them and looks at them, then runs /coins once again. Once every coin is carried and
+    /// its value is known, it walks to the monument and puts the coins into it in the order solving
+    /// the equation
+    fn plan_coins(&mut self) -> Result<(), Box<dyn Error>> {
+        let second_pass = std::mem::take(&mut self.coins_planned);
+        let maze = self.maze();
+        let Some(mut at) = maze.current_node().map(|n| n.id) else {
+            return Err("no rooms have been visited yet".into());
+        };
+        let carried: BTreeSet<String> = self
+            .inventory
+            .iter()
+            .filter(|i| coins::is_coin(i))
+            .cloned()
+            .collect();
+        let seen: BTreeSet<String> = maze
+            .items_seen()
+            .iter()
+            .filter(|i| coins::is_coin(i))
+            .cloned()
+            .chain(carried.iter().cloned())
+            .collect();
+        if seen.len() < coins::COIN_SLOTS {
+            return Err(format!(
+                "only {} of {} coins have been seen, explore the ruins first",
+                seen.len(),
+                coins::COIN_SLOTS
+            )
+            .into());
+        }
+        let mut commands = vec![];
+        for coin in seen.iter().filter(|c| !carried.contains(*c)) {
+            let room = maze
+                .nodes()
+                .iter()
+                .find(|n| n.items.contains(coin))
+                .map(|n| n.id)
+                .ok_or(format!("the room of the {} is unknown", coin))?;
+            let path = maze
+                .shortest_path(at, room)
+                .ok_or(format!("none of the known passages lead to the {}", coin))?;
+            commands.extend(path.iter().map(|exit| format!("go {}", exit)));
+            commands.push(format!("take {}", coin));
+            at = room;
+        }
+        commands.extend(
+            seen.iter()
+                .filter(|c| !self.coin_values.contains_key(*c))
+                .map(|c| format!("look {}", c)),
+        );
+        if !commands.is_empty() {
+            if second_pass {
+                return Err("the coins could not be taken or their values are unknown".into());
+            }
+            self.message(format!(
+                "collecting {} coins in {} commands",
+                seen.len() - carried.len(),
+                commands.len()
+            ));
+            commands.push("/coins".to_string());
+            self.coins_planned = true;
+            commands.iter().for_each(|c| self.feed_input(c, true));
+            return Ok(());
+        }
+        let values: Vec<(String, u16)> = seen
+            .iter()
+            .map(|c| (c.clone(), self.coin_values[c]))
+            .collect();
+        let order = coins::solve(&values).ok_or("no order of the coins solves the equation")?;
+        let monument = maze
+            .nodes()
+            .iter()
+            .find(|n| n.description.contains(coins::MONUMENT_MARK))
+            .map(|n| n.id)
+            .ok_or("the monument has not been found yet")?;
+        let path = maze
+            .shortest_path(at, monument)
+            .ok_or("none of the known passages lead to the monument")?;
+        let values: Vec<String> = order
+            .iter()
+            .map(|c| format!("{} ({})", c, self.coin_values[c]))
+            .collect();
+        self.message(format!("the order of the coins: {}", values.join(", ")));
+        let commands: Vec<String> = path
+            .iter()
+            .map(|exit| format!("go {}", exit))
+            .chain(order.iter().map(|c| format!("use {}", c)))
+            .collect();
+        commands.iter().for_each(|c| self.feed_input(c, true));
+        Ok(())
+    }
+    /// This method keeps the inventory up to date after the item is used, since the game does not
+    /// show it
+    fn apply_use_effects(&mut self, effects: &[UseEffect]) {
+        for effect in effects {
+            match effect {
+                UseEffect::Consumed(item) => self.inventory.retain(|i| i != item),
+                UseEffect::Transformed { from, to } => {
+                    if let Some(item) = self.inventory.iter_mut().find(|i| *i == from) {
+                        *item = to.clone();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    /// This method issues the next exploration command, while the /solve is in progress
+    fn continue_solving(&mut self) {
+        if !self.solving {
+            return;
+        }
+        if let Some(goal) = self.reached_goal() {
+            self.finish_solving(&format!("the goal is reached: {}", goal));
+            return;
+        }
+        if self.solve_steps_left == 0 {
+            self.finish_solving("the limit of moves is reached");
+            return;
+        }
+        match self.maze_mut().next_exploration_command() {
+            Some(command) => {
+                self.solve_steps_left -= 1;
+                self.solve_moves += 1;
+                debug!("solver issues '{}', {} moves left", command, self.solve_steps_left);
+                self.events.publish(VmEvent::SolverCommand(&command));
+                self.solve_command = Some(command.clone());
+                if self.solve_progress_interval > 0
+                    && self.solve_moves.is_multiple_of(self.solve_progress_interval)
+                {
+                    let progress = self.get_solve_progress();
+                    self.chatter(progress);
+                }
+                self.feed_input(&command, true);
+            }
+            None => self.finish_solving("there are no reachable unexplored exits"),
+        }
+    }
+    /// Returns the first goal of /solve, which is reached
+    fn reached_goal(&self) -> Option<&SolveGoal> {
+        self.solve_goals.iter().find(|goal| match goal {
+            SolveGoal::Code(known) => self.codes.len() > *known,
+            SolveGoal::Item(item) => self.inventory.iter().any(|i| i.eq_ignore_ascii_case(item)),
+            SolveGoal::Room(title) => self
+                .maze()
+                .current_node()
+                .is_some_and(|n| n.title.eq_ignore_ascii_case(title)),
+        })
+    }
+    /// This method takes back the latest command of /solve, unless the game has started reading it
+    fn drop_solver_input(&mut self) {
+        let Some(command) = self.solve_command.take() else {
+            return;
+        };
+        let queued: Vec<u8> = command.bytes().chain(iter::once(b'\n')).collect();
+        let Some(start) = self.input_buffer.len().checked_sub(queued.len()) else {
+            return;
+        };
+        if self.input_buffer.range(start..).map(|(b, _)| *b).eq(queued) {
+            debug!("dropping the queued solver command '{}'", command);
+            self.input_buffer.truncate(start);
+        }
+    }
+    /// This method reports the exploration, which is still in progress, when the program halts
+    fn check_solving(&mut self) -> Result<(), SolveFailure> {
+        if !self.solving {
+            return Ok(());
+        }
+        let moves = self.solve_moves;
+        self.finish_solving("the program has halted");
+        Err(SolveFailure {
+            moves,
+            reason: "the program halted during the exploration".to_string(),
+        })
+    }
+    fn finish_solving(&mut self, reason: &str) {
+        self.solving = false;
+        self.solve_steps_left = 0;
+        self.events.publish(VmEvent::SolveFinished(reason));
+        if self.quit_after_solve {
+            info!("the exploration is over, quitting the game");
+            self.machine.halt = true;
+        }
+        let report = self.get_solve_report(reason);
+        self.chatter(&report);
+        if let Some(file) = &self.solve_report_file {
+            let path = match &self.workspace {
+                Some(workspace) => workspace.path("report", file.to_str(), "txt"),
+                None => file.clone(),
+            };
+            // Every run replaces the report of the previous one
+            match safe_file::write(&path, &report, true) {
+                Ok(()) => {
+                    debug!("saved exploration report to {}", path.display());
+                    self.record_artifact("report", &path);
+                }
+                Err(e) => error!("failed to save exploration report to {}. Error: {}", path.display(), e),
+            }
+        }
+    }
+    /// This method describes the exploration in progress in one line. The time left is estimated
+    /// from the average time of the moves made so far
+    fn get_solve_progress(&self) -> String {
+        let maze = self.maze();
+        let elapsed = self.solve_started.elapsed();
+        let eta = elapsed.mul_f64(self.solve_steps_left as f64 / self.solve_moves.max(1) as f64);
+        format!(
+            "{} {}/{} moves, {} rooms, frontier {}, {:.0}% explored, ETA {:.1?}",
+            "solving:".dimmed(),
+            self.solve_moves,
+            self.solve_moves + self.solve_steps_left,
+            maze.nodes().len(),
+            maze.frontier().len(),
+            maze.total_completion() * 100.0,
+            eta
+        )
+    }
+    fn get_solve_report(&self, reason: &str) -> String {
+        let maze = self.maze();
+        let mut report = String::new();
+        report.push_str("***          Exploration Report           ***\n");
+        report.push_str(&format!("{}\n", "=".repeat(44)));
+        report.push_str(&format!("{:<9}: {}\n", "stopped", reason));
+        report.push_str(&format!("{:<9}: {}\n", "moves", self.solve_moves));
+        report.push_str(&format!("{:<9}: {}\n", "rooms", maze.nodes().len()));
+        report.push_str(&format!(
+            "{:<9}: {} visited, {} unexplored, {} blocked, {} unsafe\n",
+            "passages",
+            maze.edges().count(),
+            maze.unexplored_exits(),
+            maze.blocked_exits(),
+            maze.unsafe_exits()
+        ));
+        let items: Vec<&str> = maze.items_seen().iter().map(String::as_str).collect();
+        report.push_str(&format!("{:<9}: {}\n", "items", items.join(", ")));
+        report.push_str(&format!("{:<9}: {}\n", "codes", self.codes.join(", ")));
+        report.push_str(&format!(
+            "{:<9}: {}\n",
+            "# cmd. hist",
+            self.commands_history.len()
+        ));
+        report.push_str("=============================================\n");
+        report
+    }
+    /// Returns the game's own help. The game is not disturbed: the command is sent to the copy of
+    /// the machine, and its response (the text before the last prompt) is kept for the next time
+    fn game_help(&mut self) -> Option<String> {
+        if self.game_help.is_none() {
+            let mut machine = self.machine.clone();
+            let mut input = "help\n".bytes();
+            let mut output = String::new();
+            for _ in 0..GAME_HELP_CYCLES {
+                match machine.execute(|| input.next()) {
+                    StepEvent::Output(c) => output.push(c),
+                    StepEvent::AwaitingInput => break,
+                    StepEvent::Halted => return None,
+                    StepEvent::Input(_) | StepEvent::None => {}
+                }
+            }
+            let help = output.rsplit(parser::PROMPT).nth(1).unwrap_or_default().trim();
+            debug!("the game help has {} bytes", help.len());
+            self.game_help = Some(help.to_string()).filter(|h| !h.is_empty());
+        }
+        self.game_help.clone()
+    }
+    fn get_help(&mut self) -> String {
+        let watches: Vec<String> = self
+            .watches
+            .iter()
+            .enumerate()
+            .map(|(n, w)| format!("[{}] {}", n, w))
+            .collect();
+        let mut help = String::new();
+        help.push_str("***                 Help                  ***\n");
+        help.push_str(&format!("{}\n", "=".repeat(44)));
+        help.push_str(&format!("{}\n", slash_command_help()));
+        help.push_str(&format!("{}\n", "_".repeat(44)));
+        help.push_str("*** The game's own help: ***\n");
+        let game_help = self.game_help();
+        help.push_str(&format!("{}\n", game_help.as_deref().unwrap_or("not available now")));
+        #[cfg(feature = "tui")]
+        if self.keys {
+            help.push_str(&format!("{}\n", "_".repeat(44)));
+            help.push_str("*** Key shortcuts at the empty line: ***\n");
+            for (key, command) in keys::SHORTCUTS {
+                help.push_str(&format!("{:<9}: {}\n", key, command));
+            }
+        }
+        help.push_str(&format!("{}\n", "_".repeat(44)));
+        help.push_str(&format!("{:<9}: {}\n", "breaks", self.get_breakpoints_info().replace('\n', ", ")));
+        let watches = if watches.is_empty() { "none".to_string() } else { watches.join(", ") };
+        help.push_str(&format!("{:<9}: {}\n", "watches", watches));
+        help.push_str("=============================================\n");
+        help
+    }
+    fn get_execution_stats(&self) -> String {
+        let stats = self.machine.stats();
+        let total: u64 = stats.opcodes.iter().sum();
+        let mut report = String::new();
+        report.push_str("***          Execution Statistics         ***\n");
+        report.push_str(&format!("{}\n", "=".repeat(44)));
+        report.push_str(&format!("{:<9}: {}\n", "cycles", self.cycles));
+        for (opcode, count) in stats.opcodes.iter().enumerate().filter(|(_, c)| **c > 0) {
+            report.push_str(&format!(
+                "  {:<7}: {:>12} {:>6.2}%\n",
+                MNEMONICS[opcode],
+                count,
+                *count as f64 * 100.0 / total as f64
+            ));
+        }
+        report.push_str(&format!("{}\n", "_".repeat(44)));
+        report.push_str(&format!(
+            "{:<9}: {} taken, {} not taken\n",
+            "branches", stats.branches_taken, stats.branches_not_taken
+        ));
+        report.push_str(&format!("{:<9}: {} values at most\n", "stack", stats.stack_high_water));
+        report.push_str("=============================================\n");
+        report
+    }
+    fn get_frontier_report(&self) -> String {
+        let maze = self.maze();
+        let rooms = |ids: &[u16]| {
+            let titles: Vec<String> = ids
+                .iter()
+                .filter_map(|id| maze.node_by_id(*id))
+                .map(|n| format!("#{} {}", n.id, n.title))
+                .collect();
+            if titles.is_empty() { "none".to_string() } else { titles.join(", ") }
+        };
+        let mut report = String::new();
+        report.push_str("***               Frontier                ***\n");
+        report.push_str(&format!("{}\n", "=".repeat(44)));
+        report.push_str(&format!("{:<9}: {}\n", "frontier", rooms(&maze.frontier())));
+        report.push_str(&format!("{:<9}: {}\n", "dead ends", rooms(&maze.dead_ends())));
+        report.push_str(&format!("{:<9}: {}\n", "blocked", maze.blocked_exits()));
+        let room_loop = maze.trail_loop().map_or("none".to_string(), |ids| rooms(&ids));
+        report.push_str(&format!("{:<9}: {}\n", "room loop", room_loop));
+        let commands = history::commands(&self.commands_history);
+        let command_loop = maze::find_loop(&commands).map_or("none".to_string(), |c| c.join(", "));
+        report.push_str(&format!("{:<9}: {}\n", "cmd. loop", command_loop));
+        report.push_str("=============================================\n");
+        report
+    }
+    fn get_session_summary(&self) -> String {
+        let mut summary = String::new();
+        summary.push_str("***            Session Summary            ***\n");
+        summary.push_str(&format!("{}\n", "=".repeat(44)));
+        summary.push_str(&format!("{:<9}: {}\n", "rooms", self.maze().nodes().len()));
+        summary.push_str(&format!("{:<9}: {}\n", "items", self.inventory.join(", ")));
+        summary.push_str(&format!("{:<9}: {}\n", "codes", self.codes.join(", ")));
+        summary.push_str(&format!("{:<9}: {}\n", "commands", self.commands_history.len()));
+        summary.push_str(&format!("{:<9}: {}\n", "cycles", self.cycles));
+        summary.push_str(&format!("{:<9}: {:.1?}\n", "elapsed", self.started.elapsed()));
+        summary.push_str("=============================================\n");
+        summary
+    }
+    /// This method shows the summary of the session and keeps it in the sessions directory, so the
+    /// playthroughs can be compared later
+    fn finish_session(&mut self) {
+        if let Some(writer) = self.trace.take() {
+            match writer.finish() {
+                Ok(records) => info!("written {} instructions to the trace", records),
+                Err(e) => error!("failed to finish the trace. Error: {}", e),
+            }
+        }
+        let summary = self.get_session_summary();
+        self.chatter(&summary);
+        // The summary is saved only to the session directory, the run without it leaves no files
+        let Some(workspace) = &self.workspace else {
+            return;
+        };
+        let path = workspace.path("summary", None, "txt");
+        match safe_file::write(&path, &summary, true) {
+            Ok(()) => {
+                debug!("saved session summary to {}", path.display());
+                self.record_artifact("summary", &path);
+            }
+            Err(e) => error!("failed to save session summary to {}. Error: {}", path.display(), e),
+        }
+    }
+    fn get_status_line(&self) -> String {
+        let title = self
+            .last_room
+            .as_ref()
+            .and_then(|r| r.title.clone())
+            .unwrap_or("unknown location".to_string());
+        format!(
+            "{} {} {} {}",
+            "[".dimmed(),
+            title.cyan().bold(),
+            format!("| inventory: {} |", self.inventory.len()).dimmed(),
+            format!("cmd. #{} ]", self.commands_history.len() + 1).dimmed()
+        )
+    }
+    fn show_status_line(&mut self) {
+        if self.status_line && self.status_pending && self.current_command_buf.is_empty() {
+            // Make sure that the prompt is already on the screen
+            self.flush_stdout();
+            eprintln!("{}", self.get_status_line());
+        }
+        self.status_pending = false;
+    }
+    /// This method looks for a known command which is the most similar to the rejected one
+    fn suggest_command(&self, rejected: &str) -> Option<String> {
+        let mut candidates: Vec<String> = suggest::KNOWN_VERBS
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        if let Some(room) = &self.last_room {
+            for exit in &room.exits {
+                candidates.push(exit.clone());
+                candidates.push(format!("go {}", exit));
+            }
+            for item in &room.items {
+                candidates.push(format!("take {}", item));
+                candidates.push(format!("look {}", item));
+            }
+        }
+        for item in &self.inventory {
+            candidates.push(format!("use {}", item));
+            candidates.push(format!("drop {}", item));
+            candidates.push(format!("look {}", item));
+        }
+        suggest::closest_match(rejected, &candidates)
+    }
+    fn read_stdin_line(&mut self) {
+        let mut line = String::new();
+        match self.read_line(&mut line) {
+            Ok(0) => {
+                info!("stdin is closed, quitting the game");
+                self.machine.halt = true;
+            }
+            Ok(_) if self.intercept_save_command(&line) => {}
+            Ok(_) => {
+                line.bytes()
+                    .for_each(|b| self.input_buffer.push_back((b, false)));
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => self.time_out(),
+            Err(e) => self.fail_on_stdin(e),
+        }
+    }
+    /// This method reads the line typed by the user, with the key shortcuts of --keys at the terminal.
+    /// The reading is given up with the TimedOut error, once the --timeout is over
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        #[cfg(feature = "tui")]
+        if self.keys && io::stdin().is_terminal() {
+            return keys::read_line(line, self.deadline.map(|(instant, _)| instant));
+        }
+        self.read_stdin(line)
+    }
+    /// This method reads the line from stdin. The blocking read cannot be given up, so with the
+    /// --timeout the lines are read by the thread, while the VM waits for them until the deadline
+    fn read_stdin(&mut self, line: &mut String) -> io::Result<usize> {
+        let Some((instant, _)) = self.deadline else {
+            return io::stdin().read_line(line);
+        };
+        let lines = self.stdin_lines.get_or_insert_with(spawn_stdin_reader);
+        match lines.recv_timeout(instant.saturating_duration_since(Instant::now())) {
+            Ok(read) => {
+                let read = read?;
+                line.push_str(&read);
+                Ok(read.len())
+            }
+            Err(RecvTimeoutError::Timeout) => Err(io::ErrorKind::TimedOut.into()),
+            Err(RecvTimeoutError::Disconnected) => Ok(0),
+        }
+    }
+    /// This method halts the VM, which cannot read stdin any more, e.g. once it is not valid UTF-8.
+    /// The run ends with the error
+    fn fail_on_stdin(&mut self, e: io::Error) {
+        error!("failed to read from stdin. Error: {}", e);
+        self.stdin_failure = Some(e);
+        self.machine.halt = true;
+    }
+    /// This method stops the VM waiting for the input, once the --timeout is over
+    fn time_out(&mut self) {
+        if let Some((_, timeout)) = self.deadline {
+            warn!("the timeout is over while waiting for the input at {}", self.machine.current_address);
+            self.interruption = Some(Interruption::Timeout(timeout));
+        }
+    }
+    /// This method handles the save-game command typed at the game prompt, so the game never sees
+    /// it. Returns false for the line, which goes to the game
+    fn intercept_save_command(&mut self, line: &str) -> bool {
+        let Some((verb, name)) = parse_save_command(line) else {
+            return false;
+        };
+        let result = match verb {
+            "save" => self.save_slot(name),
+            _ => self.load_slot(name),
+        };
+        if let Err(e) = result {
+            warn!("failed to {} the slot '{}'. Error: {}", verb, name, e);
+            self.message(format!("Error: {}", e));
+        }
+        true
+    }
+    /// Returns the path of the file of the kind the command writes: the given one or the default one,
+    /// or the one in the session directory (see Workspace::path)
+    fn artifact_path(&self, kind: &str, file: Option<&str>, default: &str) -> PathBuf {
+        match &self.workspace {
+            Some(workspace) => {
+                let extension = Path::new(default).extension().and_then(|e| e.to_str()).unwrap_or("txt");
+                workspace.path(kind, file, extension)
+            }
+            None => PathBuf::from(file.unwrap_or(default)),
+        }
+    }
+    /// This method lists the file written in the index of the session directory, if any
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            rom_hash: self.rom_hash,
+            plugins: self.events.names(),
+            options: self.options.clone(),
+        }
+    }
+    /// This method writes the capabilities in JSON to the file, or to the next one of the session
+    /// directory. The existing file is replaced only when it may be overwritten
+    fn write_capabilities(&mut self, file: Option<&str>, overwrite: bool) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.artifact_path("capabilities", file, CAPABILITIES_FILE);
+        let json = serde_json::to_string_pretty(&self.capabilities().to_json())?;
+        safe_file::write(&path, json, overwrite)?;
+        self.record_artifact("capabilities", &path);
+        Ok(path)
+    }
+    fn record_artifact(&mut self, kind: &str, path: &Path) {
+        if let Some(workspace) = &mut self.workspace
+            && let Err(e) = workspace.record(kind, path)
+        {
+            error!("failed to update the index of {}. Error: {}", workspace.dir().display(), e);
+        }
+    }
+    /// Returns the path of the slot, which is kept in the session directory, if any
+    fn save_slot_path(&self, name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        Ok(self.artifact_path("slot", Some(&save_slot_file(name)?), STATE_JSON_FILE))
+    }
+    fn save_slot(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let path = self.save_slot_path(name)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        self.snapshot().save(&path)?;
+        self.record_artifact("slot", &path);
+        self.message(format!("saved the game to the slot '{}'", name));
+        Ok(())
+    }
+    fn load_slot(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let path = self.save_slot_path(name)?;
+        if !path.exists() {
+            let slots = path.parent().map(list_save_slots).unwrap_or_default();
+            return Err(match slots.is_empty() {
+                true => format!("there is no slot '{}', nothing is saved yet", name),
+                false => format!("there is no slot '{}', the saved ones: {}", name, slots.join(", ")),
+            }
+            .into());
+        }
+        self.restore(VmState::load(&path)?);
+        self.message(format!(
+            "restored the game from the slot '{}' (type look to see where you are)",
+            name
+        ));
+        Ok(())
+    }
+    fn main_loop(&mut self) -> Result<u64, Box<dyn Error>> {
+        trace!("starting the main loop");
+        loop {
+            match self.run_for(u64::MAX) {
+                RunState::Halted => {
+                    if !self.quiet {
+                        self.show_state();
+                    }
+                    if let Some(fault) = self.machine.fault() {
+                        return Err(Box::new(fault.clone()));
+                    }
+                    if let Some(e) = self.stdin_failure.take() {
+                        return Err(format!("failed to read from stdin: {}", e).into());
+                    }
+                    if let Some(interruption) = self.interruption.clone() {
+                        self.dump_interrupted_state();
+                        return Err(Box::new(interruption));
+                    }
+                    self.check_solving()?;
+                    break;
+                }
+                RunState::Paused if self.batch => {
+                    let address = self.machine.current_address.0;
+                    return Err(self.end_batch(Interruption::Paused(address)));
+                }
+                RunState::Paused => self.debug_prompt(),
+                RunState::AwaitingInput if self.batch => {
+                    return Err(self.end_batch(Interruption::InputExhausted));
+                }
+                RunState::AwaitingInput => {
+                    return Err("VM is waiting for input, but there is no input source".into());
+                }
+                RunState::Running => continue,
+            }
+        }
+        Ok(self.cycles)
+    }
+    /// This method saves the state of the VM stopped by --max-cycles, --timeout or the end of the
+    /// batch run, so the unattended run leaves it behind. The dump of the previous run is replaced
+    fn dump_interrupted_state(&mut self) {
+        let path = self.artifact_path("dump", None, STATE_FILE);
+        match self.dump_state(&path, true) {
+            Ok(()) => {
+                self.record_artifact("dump", &path);
+                self.message(format!("saved VM state to {}", path.display()));
+            }
+            Err(e) => error!("failed to save VM state to {}. Error: {}", path.display(), e),
+        }
+    }
+    /// This method ends the batch run, which cannot ask stdin for the input, before the program
+    /// halts. The state of the VM is dumped and the game output printed so far is saved as the
+    /// partial transcript of the run. Returns the interruption the run fails with
+    fn end_batch(&mut self, interruption: Interruption) -> Box<dyn Error> {
+        // The prompt of the game is not terminated by the newline
+        if !self.line_buffer.is_empty() {
+            self.emit_output('\n');
+        }
+        self.chatter(format!(
+            "{} {} after {} commands",
+            "Batch run ended:".yellow().bold(),
+            interruption,
+            self.commands_history.len()
+        ));
+        if !self.quiet {
+            self.show_state();
+        }
+        self.dump_interrupted_state();
+        let path = self.artifact_path("transcript", None, TRANSCRIPT_FILE);
+        match safe_file::write(&path, &self.transcript, true) {
+            Ok(()) => {
+                self.record_artifact("transcript", &path);
+                self.message(format!("saved the transcript to {}", path.display()));
+            }
+            Err(e) => error!("failed to save the transcript to {}. Error: {}", path.display(), e),
+        }
+        Box::new(interruption)
+    }
+    /// This method executes instructions until the VM halts, hits a breakpoint, runs out of input or
+    /// the budget of cycles is exhausted. Once the VM is interrupted, it is reported as halted,
+    /// but the machine itself is left intact
+    fn run_for(&mut self, budget: u64) -> RunState {
+        let mut executed: u64 = 0;
+        loop {
+            if self.machine.halt || self.interruption.is_some() {
+                return RunState::Halted;
+            }
+            if let Some(limit) = self.cycle_limit
+                && self.cycles >= limit
+            {
+                warn!("the budget of cycles is exhausted at {}", self.machine.current_address);
+                self.interruption = Some(Interruption::CycleLimit(limit));
+                continue;
+            }
+            // Asking for the time on every instruction would slow the VM down
+            if executed.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                && let Some((instant, timeout)) = self.deadline
+                && Instant::now() >= instant
+            {
+                warn!("the timeout is over at {}", self.machine.current_address);
+                self.interruption = Some(Interruption::Timeout(timeout));
+                continue;
+            }
+            if PAUSE_REQUESTED.swap(false, Ordering::SeqCst) {
+                info!("paused by the user at {}", self.machine.current_address);
+                self.paused = true;
+            }
+            if self.paused {
+                return RunState::Paused;
+            }
+            if self.awaiting_input {
+                if self.input_buffer.is_empty() {
+                    return RunState::AwaitingInput;
+                }
+                self.awaiting_input = false;
+            }
+            if executed >= budget {
+                return RunState::Running;
+            }
+            if !self.resuming && self.breakpoints.contains(&self.machine.current_address.0) {
+                info!("hit breakpoint at {}", self.machine.current_address);
+                self.paused = true;
+                let address = self.machine.current_address.0;
+                self.run_script_hook(|s| s.on_breakpoint(address));
+                continue;
+            }
+            if !self.resuming
+                && self.break_on_input
+                && self.machine.word(self.machine.position()) == IN_OPCODE
+            {
+                self.message(self.get_input_info());
+                self.paused = true;
+                continue;
+            }
+            if !self.resuming
+                && !self.protected.is_empty()
+                && let Some(info) = self.get_protected_write_info()
+            {
+                self.message(info);
+                self.paused = true;
+                continue;
+            }
+            self.resuming = false;
+            if log_enabled!(Level::Trace) {
+                // Debugging
+                self.show_state();
+            }
+            self.execute_instruction();
+            if !self.watches.is_empty() {
+                self.check_watches();
+            }
+            executed += 1;
+        }
+    }
+    fn resume(&mut self) {
+        if self.paused {
+            self.paused = false;
+            // Ctrl+C pressed at the paused prompt should not pause the VM once again
+            PAUSE_REQUESTED.store(false, Ordering::SeqCst);
+            // Do not stop at the breakpoint we are paused at
+            self.resuming = true;
+        }
+    }
+    /// This method reads slash commands from stdin while the VM is paused
+    fn debug_prompt(&mut self) {
+        self.message(format!(
+            "{} at {} (type /continue to resume, /step to execute one instruction)",
+            "VM paused".yellow().bold(),
+            self.machine.current_address
+        ));
+        while self.paused && !self.machine.halt {
+            eprint!("{} ", "(paused)>".yellow());
+            let mut line = String::new();
+            match self.read_stdin(&mut line) {
+                Ok(0) => {
+                    warn!("stdin is closed while the VM is paused. Halting");
+                    self.machine.halt = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                    self.time_out();
+                    break;
+                }
+                Ok(_) => {
+                    let command = line.trim();
+                    if !is_slash_command(command) {
+                        self.message("only slash '/' commands are available while the VM is paused");
+                        continue;
+                    }
+                    if let Err(e) = self.process_command(command) {
+                        warn!("processing command returned an error: {}", e);
+                    }
+                }
+                Err(e) => self.fail_on_stdin(e),
+            }
+        }
+    }
+    /// This method executes a single instruction and dispatches its input and output
+    fn execute_instruction(&mut self) {
+        self.pin_rng();
+        self.snapshots.record(self.cycles, &mut self.machine);
+        self.cycles += 1;
+        self.events
+            .publish(VmEvent::InstructionExecuted(self.machine.current_address.0));
+        let record = self.trace.is_some().then(|| self.get_trace_record());
+        if let Some(taint) = self.taint.as_mut() {
+            taint.step(&self.machine);
+        }
+        let input_buffer = &mut self.input_buffer;
+        let mut echo = false;
+        let event = self.machine.execute(|| {
+            let (c, e) = input_buffer.pop_front()?;
+            echo = e;
+            Some(c)
+        });
+        // The instruction waiting for the input is retried, so it is traced once it is executed
+        if let Some(record) = record
+            && event != StepEvent::AwaitingInput
+        {
+            self.write_trace(&record);
+        }
+        match event {
+            StepEvent::Output(c) => {
+                if self.batch {
+                    self.transcript.push(c);
+                }
+                self.emit_output(c);
+                self.collect_line(c);
+                self.collect_response(c);
+            }
+            StepEvent::Input(c) => {
+                // The prompt line is not terminated, so it is completed by the input
+                self.publish_line();
+                self.events.publish(VmEvent::InputConsumed(c));
+                self.snapshots.input(c as u8);
+                for c in self.input_decoder.push(c) {
+                    if self.batch {
+                        self.transcript.push(c);
+                    }
+                    if echo && !self.quiet {
+                        self.emit_output(c);
+                    }
+                    self.grab_input(c);
+                }
+            }
+            StepEvent::AwaitingInput => {
+                self.flush_stdout();
+                self.cycles -= 1;
+                // The instruction is retried, so do not stop at its breakpoint once again
+                self.resuming = true;
+                let queued = self.events.queued_input();
+                if !queued.is_empty() {
+                    queued.iter().for_each(|command| self.queue_input(command));
+                } else if !self.timed_input.is_empty() {
+                    self.feed_timed_input();
+                } else if self.stdin_input {
+                    self.show_status_line();
+                    self.read_stdin_line();
+                } else {
+                    trace!("input buffer is empty, waiting for input");
+                    self.awaiting_input = true;
+                }
+            }
+            StepEvent::Halted => {
+                self.flush_stdout();
+                self.events.publish(VmEvent::Halted(self.cycles));
+            }
+            StepEvent::None => {}
+        }
+    }
+    /// This method waits for the time the next recorded command has been typed at and feeds it
+    fn feed_timed_input(&mut self) {
+        if let Some(wait) = self
+            .timed_input
+            .front()
+            .and_then(|e| e.at.checked_sub(self.started.elapsed()))
+        {
+            debug!("waiting {:?} for the next recorded command", wait);
+            std::thread::sleep(wait);
+        }
+        while let Some(event) = self.timed_input.pop_front() {
+            self.input_buffer.push_back((event.byte, true));
+            if event.byte == b'\n' {
+                break;
+            }
+        }
+    }
+    /// This method describes the instruction about to be executed for the trace
+    fn get_trace_record(&self) -> TraceRecord {
+        let address = self.machine.position();
+        TraceRecord {
+            cycle: self.cycles,
+            address,
+            words: disasm::read(&self.machine, address),
+            registers: self.machine.registers(),
+            stack: self.machine.stack.len(),
+        }
+    }
+    fn write_trace(&mut self, record: &TraceRecord) {
+        let Some(writer) = self.trace.as_mut() else {
+            return;
+        };
+        if let Err(e) = writer.write(record) {
+            error!("failed to write the trace, tracing is stopped. Error: {}", e);
+            self.trace = None;
+        }
+    }
+    /// This method answers the game output with the commands of the matching expect rules
+    fn apply_rules(&mut self, output: &str) {
+        for response in self.rules.responses(output) {
+            debug!("expect rule responds with '{}'", response);
+            self.feed_input(&response, true);
+        }
+    }
+    /// This method calls the user script callback, if a script is loaded, and applies the actions
+    /// requested by it
+    fn run_script_hook(&mut self, hook: impl FnOnce(&mut ScriptHooks) -> Vec<ScriptAction>) {
+        let Some(scripts) = self.scripts.as_mut() else {
+            return;
+        };
+        for action in hook(scripts) {
+            debug!("applying script action {:?}", action);
+            match action {
+                ScriptAction::SetRegister(register, value) => {
+                    self.machine.registers[register] = value;
+                }
+                // Slash commands are executed right away, so a script can e.g. resume the VM
+                ScriptAction::FeedCommand(command) if command.starts_with('/') => {
+                    if let Err(e) = self.process_command(&command) {
+                        warn!("processing script command returned an error: {}", e);
+                    }
+                }
+                ScriptAction::FeedCommand(command) => self.queue_input(&command),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(vm: &mut VM) -> Result<u64, Box<dyn Error>> {
+    tui::run(vm)
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(_vm: &mut VM) -> Result<u64, Box<dyn Error>> {
+    Err("the terminal user interface is not available, rebuild with the 'tui' feature".into())
+}
+
+#[cfg(feature = "async")]
+fn run_async(vm: &mut VM, input_timeout: Option<Duration>) -> Result<u64, Box<dyn Error>> {
+    async_loop::run(vm, input_timeout)
+}
+
+#[cfg(not(feature = "async"))]
+fn run_async(_vm: &mut VM, _input_timeout: Option<Duration>) -> Result<u64, Box<dyn Error>> {
+    Err("the async main loop is not available, rebuild with the 'async' feature".into())
+}
+
+#[cfg(feature = "server")]
+fn serve(vm: VM, address: &str) -> Result<u64, Box<dyn Error>> {
+    server::serve(vm, address)
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_vm: VM, _address: &str) -> Result<u64, Box<dyn Error>> {
+    Err("the server mode is not available, rebuild with the 'server' feature".into())
+}
+
+#[cfg(feature = "gdb")]
+fn debug_with_gdb(vm: VM, address: &str) -> Result<VM, Box<dyn Error>> {
+    gdb::debug(vm, address)
+}
+
+#[cfg(not(feature = "gdb"))]
+fn debug_with_gdb(_vm: VM, _address: &str) -> Result<VM, Box<dyn Error>> {
+    Err("the gdb mode is not available, rebuild with the 'gdb' feature".into())
+}
+
+#[cfg(feature = "dap")]
+fn debug_with_dap(vm: VM, address: &str) -> Result<VM, Box<dyn Error>> {
+    dap::debug(vm, address)
+}
+
+#[cfg(not(feature = "dap"))]
+fn debug_with_dap(_vm: VM, _address: &str) -> Result<VM, Box<dyn Error>> {
+    Err("the DAP mode is not available, rebuild with the 'dap' feature".into())
+}
+
+/// This function ends the output of the subcommand without the error, once stdout is closed by
+/// the reader, e.g. by `head`, as the rest of the output is not wanted
+fn end_of_output(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+            debug!("stdout is closed by the reader, the rest of the output is dropped");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// This function prints the output of the subcommand to stdout, see end_of_output
+fn print_output(text: impl fmt::Display) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    end_of_output(write!(stdout, "{}", text).and_then(|_| stdout.flush()))
+}
+
+pub fn run(config: config::Configuration) -> Result<(), Box<dyn Error>> {
+    run_with_plugins(config, vec![])
+}
+
+/// This function runs two machines side by side and reports where they diverge
+fn run_lockstep(config: &config::LockstepConfig) -> Result<(), Box<dyn Error>> {
+    let mut lockstep = Lockstep::new(load_machine(&config.a)?, load_machine(&config.b)?);
+    if let Some(path) = &config.replay {
+        fs::read_to_string(path)?
+            .lines()
+            .for_each(|command| lockstep.feed(command));
+    }
+    print_output(format_args!("{}\n", lockstep.run(config.max_cycles)))?;
+    Ok(())
+}
+
+/// This function runs the VM like 'run', but registers the given plugins after the built-in ones
+pub fn run_with_plugins(
+    config: config::Configuration,
+    plugins: Vec<Box<dyn VmPlugin>>,
+) -> Result<(), Box<dyn Error>> {
+    debug!("received configuration {}", &config);
+    if let Some((a, b)) = config.diff_states() {
+        let (a, b) = (VmState::load(&a)?, VmState::load(&b)?);
+        print_output(format_args!("{}\n", state::diff_states(&a, &b)))?;
+        return Ok(());
+    }
+    if let Some(lockstep) = config.lockstep() {
+        return run_lockstep(&lockstep);
+    }
+    if let Some(dir) = config.corpus_dir() {
+        let report = corpus::parse_corpus(&dir)?;
+        print_output(format_args!("{}\n", report))?;
+        if !report.is_ok() {
+            return Err(format!("{} responses are not parsed consistently", report.failures.len()).into());
+        }
+        return Ok(());
+    }
+    if let Some(dump) = config.trace_dump() {
+        let mut stdout = io::stdout().lock();
+        match trace::dump(&dump.file, dump.from, dump.to, dump.raw, &mut stdout) {
+            Ok(shown) => debug!("shown {} records of the trace {}", shown, dump.file.display()),
+            Err(e) => match e.downcast::<io::Error>() {
+                Ok(e) => end_of_output(Err(*e))?,
+                Err(e) => return Err(e),
+            },
+        }
+        return Ok(());
+    }
+    if let Some(map) = config.map() {
+        let text = export::export(&export::load(&map.input)?, map.format);
+        match &map.output {
+            Some(path) => safe_file::write(path, text, map.force)?,
+            None => print_output(text)?,
+        }
+        return Ok(());
+    }
+    if let Some(path) = config.input_stats() {
+        print_output(format_args!("{}\n", InputStats::new(&input_log::read(&path)?)))?;
+        return Ok(());
+    }
+    if let Some(diff) = config.trace_diff() {
+        let result = trace::diff(&diff.a, &diff.b, diff.context)?;
+        print_output(format_args!("{}\n", result))?;
+        if let Some(cycle) = result.divergence {
+            return Err(format!("the traces diverge at the cycle {}", cycle).into());
+        }
+        return Ok(());
+    }
+    if config.selftest() {
+        let report = conformance::run_all();
+        print_output(format_args!("{}\n", report))?;
+        if !report.is_ok() {
+            return Err(format!("{} conformance cases failed", report.failures()).into());
+        }
+        return Ok(());
+    }
+    if !config.is_valid() {
+        return Err(Box::new(ConfigError("the ROM file is missing or empty".to_string())));
+    }
+    trace!("configuration has been successfully validated");
+    if let Some(disasm) = config.disasm() {
+        let mut machine = Machine::new();
+        machine.load_rom(&config.rom()).map_err(|e| ConfigError(e.to_string()))?;
+        // The ROM is the sequence of the little endian words
+        let end = (config.rom().len() / 2).min(MAX as usize) as u16;
+        let listing = disasm::range(&machine, disasm.from.unwrap_or(0), disasm.to.unwrap_or(end));
+        let mut stdout = io::stdout().lock();
+        let written = disasm::annotate(&listing).iter().try_for_each(|line| {
+            writeln!(stdout, "{:>5} ({:#06x}): {}", line.address, line.address, line.text)
+        });
+        end_of_output(written.and_then(|_| stdout.flush()))?;
+        return Ok(());
+    }
+    if let Some(cycles) = config.bench_cycles() {
+        let report = bench::run(&config.rom(), &config.replay(), cycles).map_err(|e| ConfigError(e.to_string()))?;
+        print_output(format_args!("{}\n", report))?;
+        return Ok(());
+    }
+    if config.solve_all() {
+        let seed = config.seed().unwrap_or_else(rand::random);
+        // The transcript goes to stdout only for the complete playthrough, so the failed one is
+        // told apart by the exit status too
+        return match playthrough::play(&config.rom(), seed) {
+            Ok(report) => {
+                print_output(format_args!("{}\n", report))?;
+                Ok(())
+            }
+            Err(failure) => {
+                eprintln!("{}", failure.report);
+                Err(SolveFailure {
+                    moves: failure.report.commands as u32,
+                    reason: failure.to_string(),
+                }
+                .into())
+            }
+        };
+    }
+    let status_line = config.status_line();
+    let use_tui = config.tui();
+    let serve_address = config.serve_address();
+    let gdb_address = config.gdb_address();
+    let dap_address = config.dap_address();
+    let async_loop = config.async_loop();
+    let batch = config.batch();
+    let quit_after_solve = config.quit_after_solve();
+    let pager = config.pager();
+    let keys = config.keys();
+    let quiet = config.quiet();
+    let output_format = config.output_format();
+    let flush_policy = config.flush_policy();
+    let notify = config.notify();
+    let input_timeout = config.input_timeout();
+    let seed = config.seed();
+    let solve_report_file = config.solve_report_file();
+    let session_dir = config.session_dir();
+    let solve_progress_interval = config.solve_progress_interval();
+    let strategy = config.strategy();
+    let item_aliases = config.item_aliases();
+    let load_state = config.load_state();
+    let stack_limit = config.stack_limit();
+    let max_cycles = config.max_cycles();
+    let timeout = config.timeout();
+    let strictness = config.strictness();
+    let trace_file = config.trace_file();
+    let record_input = config.record_input();
+    let replay_input = config.replay_input();
+    let journal_size = config.journal_size();
+    let autosave_limit = config.autosaves();
+    let snapshot_interval = config.snapshot_interval();
+    let script = config.script();
+    let options = config.options();
+    let rules = ExpectRules::parse(&config.rules()).map_err(|e| ConfigError(e.to_string()))?;
+    let (rom, replay, record_output) = config.rom_replay_record();
+    let mut vm =
+        VM::new_from_rom_with_options(rom, replay, record_output).map_err(|e| ConfigError(e.to_string()))?;
+    vm.status_line = status_line && !quiet;
+    vm.quiet = quiet;
+    vm.set_flush_policy(flush_policy);
+    if output_format == OutputFormat::Json {
+        vm.json_lines = true;
+        vm.events.register(Box::new(JsonLines::new()));
+    }
+    if let Some(spec) = notify {
+        vm.events.register(Box::new(Notifier::new(&spec)));
+    }
+    vm.batch = batch;
+    vm.quit_after_solve = quit_after_solve;
+    vm.pager = pager;
+    #[cfg(feature = "tui")]
+    {
+        vm.keys = keys;
+    }
+    #[cfg(not(feature = "tui"))]
+    if keys {
+        warn!("the key shortcuts of --keys need the 'tui' feature, reading the lines as usual");
+    }
+    vm.stdin_input = !batch;
+    vm.rules = rules;
+    if let Some(seed) = seed {
+        vm.maze_mut().set_seed(seed);
+    }
+    if let Some(strategy) = strategy {
+        vm.maze_mut().set_strategy(strategy);
+    }
+    if let Some(aliases) = item_aliases {
+        vm.maze_mut().set_item_aliases(aliases);
+    }
+    if let Some(path) = load_state {
+        vm.restore(VmState::load(&path).map_err(|e| ConfigError(e.to_string()))?);
+        info!("resuming the session saved in {}", path.display());
+    }
+    vm.machine.set_stack_limit(stack_limit);
+    vm.machine.set_strictness(strictness);
+    vm.machine.set_journal_size(journal_size);
+    vm.autosave_limit = autosave_limit;
+    vm.snapshots = SnapshotRing::new(snapshot_interval);
+    // The budget is counted from the restored state
+    vm.cycle_limit = max_cycles.map(|n| vm.cycles.saturating_add(n));
+    vm.deadline = timeout.map(|t| (Instant::now() + t, t));
+    vm.solve_report_file = solve_report_file;
+    if let Some(dir) = session_dir {
+        vm.workspace = Some(Workspace::open(&dir).map_err(|e| ConfigError(e.to_string()))?);
+        info!("writing the files of the session to {}", dir.display());
+    }
+    vm.solve_progress_interval = solve_progress_interval;
+    if let Some(path) = record_input {
+        let recorder = InputRecorder::create(&path, vm.started).map_err(|e| ConfigError(e.to_string()))?;
+        vm.events.register(Box::new(recorder));
+    }
+    if let Some(path) = replay_input {
+        vm.timed_input = input_log::read(&path)?.into();
+    }
+    if let Some(path) = trace_file {
+        vm.trace = Some(TraceWriter::create(&path).map_err(|e| ConfigError(e.to_string()))?);
+    }
+    plugins.into_iter().for_each(|p| vm.events.register(p));
+    vm.options = options;
+    // The session directory tells how the session has been played
+    if vm.workspace.is_some() {
+        let path = vm.write_capabilities(None, true).map_err(|e| ConfigError(e.to_string()))?;
+        debug!("written the capabilities to {}", path.display());
+    }
+    if let Some(path) = script {
+        vm.scripts = Some(ScriptHooks::load(&path).map_err(|e| ConfigError(e.to_string()))?);
+        vm.run_script_hook(ScriptHooks::take_actions);
+    }
+    vm.events.publish(VmEvent::Started);
+    if let Some(address) = serve_address {
+        let cycles = serve(vm, &address)?;
+        debug!("VM server exited after completing {} cycles", cycles);
+        return Ok(());
+    }
+    if let Some(address) = gdb_address {
+        vm = debug_with_gdb(vm, &address)?;
+    }
+    if let Some(address) = dap_address {
+        vm = debug_with_dap(vm, &address)?;
+    }
+    let result = if use_tui {
+        run_tui(&mut vm)
+    } else if async_loop {
+        run_async(&mut vm, input_timeout)
+    } else {
+        install_pause_handler();
+        vm.main_loop()
+    };
+    // The summary is worth showing even if the run has ended with an error
+    vm.finish_session();
+    let cycles = result?;
+    debug!("VM exited after completing {} cycles", cycles);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::rom;
+    use crate::machine::MAX;
+
+    // The game printing the '>' prompt and answering 'ok' to every command
+    fn echo_rom() -> Vec<u8> {
+        rom(&[19, 62, 20, MAX, 4, MAX + 1, MAX, 10, 8, MAX + 1, 2, 19, 111, 19, 107, 19, 10, 6, 0])
+    }
+
+    // Returns the quiet VM running the commands in the batch mode with the session directory
+    fn batch_vm(rom: Vec<u8>, commands: &[&str], dir: &Path) -> VM {
+        let commands = commands.iter().map(|c| c.to_string()).collect();
+        let mut vm = VM::new_from_rom_with_options(rom, Some(commands), None).unwrap();
+        vm.batch = true;
+        vm.stdin_input = false;
+        vm.quiet = true;
+        vm.stdout_output = false;
+        vm.workspace = Some(Workspace::open(dir).unwrap());
+        vm
+    }
+
+    #[test]
+    fn batch_run_saves_the_dump_and_the_transcript_once_the_replay_is_used_up() {
+        let dir = std::env::temp_dir().join(format!("synacor-batch-{}", std::process::id()));
+        let mut vm = batch_vm(echo_rom(), &["look", "take tablet"], &dir);
+        let result = vm.main_loop().map(|_| ());
+        let transcript = fs::read_to_string(dir.join("transcript-001.txt"));
+        let dumped = dir.join("dump-001.txt").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(ExitStatus::of(&result), ExitStatus::InputExhausted);
+        assert_eq!(transcript.unwrap(), ">look\nok\n>take tablet\nok\n>");
+        assert!(dumped);
+    }
+
+    #[test]
+    fn batch_run_ends_without_the_error_once_the_program_halts() {
+        let dir = std::env::temp_dir().join(format!("synacor-halt-{}", std::process::id()));
+        let mut vm = batch_vm(rom(&[19, 65, 0]), &[], &dir);
+        let result = vm.main_loop().map(|_| ());
+        let written = dir.join("transcript-001.txt").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(ExitStatus::of(&result), ExitStatus::Halted);
+        assert!(!written);
+    }
+
+    #[test]
+    fn save_slots_are_kept_in_the_session_directory() {
+        let dir = std::env::temp_dir().join(format!("synacor-slots-{}", std::process::id()));
+        let mut vm = batch_vm(echo_rom(), &[], &dir);
+        vm.save_slot("before-maze").unwrap();
+        let missing = vm.load_slot("after-maze").unwrap_err().to_string();
+        let index = fs::read_to_string(dir.join("index.json")).unwrap();
+        let saved = dir.join("saves/before-maze.json").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(saved);
+        assert!(index.contains("saves/before-maze.json"), "{}", index);
+        assert_eq!(missing, "there is no slot 'after-maze', the saved ones: before-maze");
+        assert!(vm.save_slot("../escape").is_err());
+    }
+
+    #[test]
+    fn autosaves_leave_the_journal_out() {
+        let dir = std::env::temp_dir().join(format!("synacor-autosave-{}", std::process::id()));
+        // noop, jmp 0
+        let mut vm = batch_vm(rom(&[21, 6, 0]), &[], &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        vm.machine.set_journal_size(100);
+        (0..10).for_each(|_| vm.execute_instruction());
+        vm.autosave("Foothills");
+        let saved = &vm.autosaves[0].state.machine;
+        assert_eq!(saved.journal_len(), 0);
+        assert_eq!(saved.position(), vm.machine.position());
+        assert_eq!(vm.machine.journal_len(), 10);
+
+        vm.restore(vm.autosaves[0].state.clone());
+        assert_eq!(vm.machine.journal_size(), 100);
+    }
+
+    #[test]
+    fn frozen_rng_stores_the_pinned_state() {
+        let dir = std::env::temp_dir().join(format!("synacor-rng-{}", std::process::id()));
+        let r6 = MAX + 6;
+        // rmem r6 100, mult r6 r6 5249, add r6 r6 12345, wmem 100 r6, jmp 0
+        let program = [15, r6, 100, 10, r6, r6, 5249, 9, r6, r6, 12345, 16, 100, r6, 6, 0];
+        let mut vm = batch_vm(rom(&program), &[], &dir);
+        (0..4).for_each(|_| vm.execute_instruction());
+        let free = vm.machine.word(100);
+        vm.process_command("/freeze_rng 7").unwrap();
+        (0..5).for_each(|_| vm.execute_instruction());
+        let frozen = vm.machine.word(100);
+        let invalid = vm.process_command("/freeze_rng 40000").is_err();
+        vm.process_command("/save_symbols").unwrap();
+        let symbols = fs::read_to_string(dir.join("symbols-001.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(free, 12345);
+        assert_eq!(frozen, 7);
+        assert!(invalid);
+        assert_eq!(
+            symbols.unwrap(),
+            "# synacor symbols v1\n0x0000 rng_update\n0x000b rng_store\n0x0064 rng_state\n"
+        );
+    }
+
+    #[test]
+    fn unreadable_stdin_ends_the_run_with_the_error() {
+        let mut vm = VM::new_from_rom(echo_rom()).unwrap();
+        vm.quiet = true;
+        vm.stdout_output = false;
+        // The lines are taken from the reading thread, once the deadline is set
+        let (sender, receiver) = mpsc::channel();
+        sender
+            .send(Err(io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8")))
+            .unwrap();
+        vm.stdin_lines = Some(receiver);
+        vm.deadline = Some((Instant::now() + Duration::from_secs(60), Duration::from_secs(60)));
+        let result = vm.main_loop().map(|_| ());
+        assert_eq!(ExitStatus::of(&result), ExitStatus::RuntimeError);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "failed to read from stdin: stream did not contain valid UTF-8"
+        );
+    }
+
+    #[test]
+    fn history_grep_keeps_the_spaces_of_the_pattern() {
+        let mut vm = VM::new_from_rom(echo_rom()).unwrap();
+        vm.stdout_output = false;
+        for command in ["use  tablet", "use tablet", "go north"] {
+            vm.commands_history.push(HistoryEntry::new(command, None, 0));
+        }
+        vm.process_command("/history_grep ^use  tab").unwrap();
+        let found = std::mem::take(&mut vm.output_buffer);
+        assert!(found.contains("use  tablet"), "{}", found);
+        assert!(!found.contains("use tablet"), "{}", found);
+    }
+
+    #[test]
+    fn closed_stdout_ends_the_output_without_the_error() {
+        assert!(end_of_output(Err(io::ErrorKind::BrokenPipe.into())).is_ok());
+        assert!(end_of_output(Err(io::ErrorKind::PermissionDenied.into())).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use super::{RunState, VM, is_slash_command};
+use crate::aux::Commander;
+use colored::Colorize;
+use log::{debug, trace, warn};
+use std::error::Error;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+use tokio::time;
+
+// The VM yields to the runtime after this number of instructions, so other tasks can progress
+const CYCLES_PER_SLICE: u64 = 100_000;
+
+type StdinLines = Lines<BufReader<Stdin>>;
+
+/// This function runs the VM on the single threaded tokio runtime
+pub(super) fn run(vm: &mut VM, input_timeout: Option<Duration>) -> Result<u64, Box<dyn Error>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()?;
+    runtime.block_on(vm.main_loop_async(input_timeout))
+}
+
+impl VM {
+    /// This method is the async variant of the main loop. The input is read from stdin without
+    /// blocking the runtime, and if the user does not type anything during the input timeout, the
+    /// plugins are asked for the commands to continue with
+    pub(super) async fn main_loop_async(
+        &mut self,
+        input_timeout: Option<Duration>,
+    ) -> Result<u64, Box<dyn Error>> {
+        trace!("starting the async main loop");
+        self.stdin_input = false;
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        loop {
+            match self.run_for(CYCLES_PER_SLICE) {
+                RunState::Halted => {
+                    self.show_state();
+                    if let Some(interruption) = &self.interruption {
+                        return Err(Box::new(interruption.clone()));
+                    }
+                    self.check_solving()?;
+                    break;
+                }
+                RunState::Paused => self.debug_prompt_async(&mut lines).await?,
+                RunState::AwaitingInput => {
+                    self.show_status_line();
+                    self.read_line_async(&mut lines, input_timeout).await?;
+                }
+                RunState::Running => tokio::task::yield_now().await,
+            }
+        }
+        Ok(self.cycles)
+    }
+
+    async fn read_line_async(
+        &mut self,
+        lines: &mut StdinLines,
+        input_timeout: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        let line = match input_timeout {
+            Some(timeout) => match time::timeout(timeout, lines.next_line()).await {
+                Ok(line) => line?,
+                Err(_) => {
+                    debug!("no input during {:?}", timeout);
+                    self.on_idle(timeout);
+                    return Ok(());
+                }
+            },
+            None => lines.next_line().await?,
+        };
+        match line {
+            Some(line) => {
+                self.feed_input(&line, false);
+                Ok(())
+            }
+            None => Err("stdin is closed".into()),
+        }
+    }
+
+    /// This method lets the plugins take over, when the user is idle
+    fn on_idle(&mut self, timeout: Duration) {
+        let commands = self.events.idle(timeout);
+        if commands.is_empty() {
+            trace!("none of the plugins has a command, waiting for the user");
+            return;
+        }
+        for command in commands {
+            debug!("plugin takes over with command '{}'", command);
+            self.feed_input(&command, true);
+        }
+    }
+
+    async fn debug_prompt_async(&mut self, lines: &mut StdinLines) -> Result<(), Box<dyn Error>> {
+        self.message(format!(
+            "{} at {} (type /continue to resume, /step to execute one instruction)",
+            "VM paused".yellow().bold(),
+            self.machine.current_address
+        ));
+        while self.paused && !self.machine.halt {
+            eprint!("{} ", "(paused)>".yellow());
+            let Some(line) = lines.next_line().await? else {
+                warn!("stdin is closed while the VM is paused. Halting");
+                self.machine.halt = true;
+                break;
+            };
+            let command = line.trim();
+            if !is_slash_command(command) {
+                self.message("only slash '/' commands are available while the VM is paused");
+                continue;
+            }
+            if let Err(e) = self.process_command(command) {
+                warn!("processing command returned an error: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use serde_json::{Map, Value, json};
+
+// The cargo features the binary is built with
+const FEATURES: [(&str, bool); 15] = [
+    ("colors", cfg!(feature = "colors")),
+    ("maze", cfg!(feature = "maze")),
+    ("state", cfg!(feature = "state")),
+    ("files", cfg!(feature = "files")),
+    ("tui", cfg!(feature = "tui")),
+    ("server", cfg!(feature = "server")),
+    ("wasm", cfg!(feature = "wasm")),
+    ("ffi", cfg!(feature = "ffi")),
+    ("testkit", cfg!(feature = "testkit")),
+    ("python", cfg!(feature = "python")),
+    ("scripting", cfg!(feature = "scripting")),
+    ("async", cfg!(feature = "async")),
+    ("compression", cfg!(feature = "compression")),
+    ("gdb", cfg!(feature = "gdb")),
+    ("dap", cfg!(feature = "dap")),
+];
+
+/// This function returns the 64-bit FNV-1a hash of the ROM. It is stable across the builds and the
+/// platforms, unlike the hasher of the standard library, so the reports of the different runs
+/// tell whether they have played the same ROM
+pub(super) fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Everything needed to reproduce the session: the build, the ROM, the plugins and the options.
+/// It is shown by /version and written as JSON by /capabilities and to the session directory
+pub(super) struct Capabilities {
+    pub(super) rom_hash: u64,
+    pub(super) plugins: Vec<String>,
+    pub(super) options: Vec<(&'static str, String)>,
+}
+
+impl Capabilities {
+    fn features() -> Vec<&'static str> {
+        FEATURES.iter().filter(|f| f.1).map(|f| f.0).collect()
+    }
+
+    pub(super) fn report(&self) -> String {
+        let mut report = String::new();
+        report.push_str("***               Version                 ***\n");
+        report.push_str(&format!("{}\n", "=".repeat(44)));
+        report.push_str(&format!(
+            "{:<9}: {}\n",
+            "version",
+            env!("CARGO_PKG_VERSION")
+        ));
+        report.push_str(&format!(
+            "{:<9}: {}\n",
+            "features",
+            Self::features().join(", ")
+        ));
+        report.push_str(&format!(
+            "{:<9}: fnv1a:{:016x}\n",
+            "rom hash", self.rom_hash
+        ));
+        report.push_str(&format!("{:<9}: {}\n", "plugins", self.plugins.join(", ")));
+        report.push_str(&format!("{}\n", "_".repeat(44)));
+        for (name, value) in &self.options {
+            report.push_str(&format!("{:<11}: {}\n", name, value));
+        }
+        report.push_str("=============================================\n");
+        report
+    }
+
+    pub(super) fn to_json(&self) -> Value {
+        let options: Map<String, Value> = self
+            .options
+            .iter()
+            .map(|(name, value)| (name.replace(' ', "_"), json!(value)))
+            .collect();
+        json!({
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "features": Self::features(),
+            "rom_hash": format!("fnv1a:{:016x}", self.rom_hash),
+            "plugins": self.plugins,
+            "options": options,
+        })
+    }
+}
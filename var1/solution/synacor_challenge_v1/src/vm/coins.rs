@@ -0,0 +1,143 @@
+/// The equation on the monument, which tells its room apart
+pub(super) const MONUMENT_MARK: &str = "_ + _ * _^2 + _^3 - _";
+// The value of the equation on the monument
+const EQUATION_RESULT: i64 = 399;
+
+// The number of the slots on the monument
+pub(super) const COIN_SLOTS: usize = 5;
+
+// The marks on the coins, either counted in words or drawn as the polygons
+const MARKS: [(&str, u16); 16] = [
+    ("one", 1),
+    ("two", 2),
+    ("three", 3),
+    ("four", 4),
+    ("five", 5),
+    ("six", 6),
+    ("seven", 7),
+    ("eight", 8),
+    ("nine", 9),
+    ("triangle", 3),
+    ("square", 4),
+    ("pentagon", 5),
+    ("hexagon", 6),
+    ("heptagon", 7),
+    ("octagon", 8),
+    ("nonagon", 9),
+];
+
+/// Whether the item is one of the coins of the monument puzzle
+pub(super) fn is_coin(item: &str) -> bool {
+    item.trim().ends_with("coin")
+}
+
+/// This function reads the value of the coin from its description, e.g. 'It has two dots on one
+/// side' is 2 and 'It has a triangle on one side' is 3
+pub(super) fn coin_value(description: &str) -> Option<u16> {
+    description
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find_map(|word| {
+            let word = word.to_lowercase();
+            MARKS
+                .iter()
+                .find(|(mark, _)| *mark == word)
+                .map(|(_, v)| *v)
+        })
+}
+
+fn satisfies(values: &[i64]) -> bool {
+    let [a, b, c, d, e] = values else {
+        return false;
+    };
+    a + b * c.pow(2) + d.pow(3) - e == EQUATION_RESULT
+}
+
+/// This function finds the order of the coins, which solves the equation on the monument, by
+/// trying all the permutations of them
+pub(super) fn solve(coins: &[(String, u16)]) -> Option<Vec<String>> {
+    if coins.len() != COIN_SLOTS {
+        return None;
+    }
+    let mut order: Vec<usize> = (0..coins.len()).collect();
+    permute(&mut order, 0, &|order| {
+        let values: Vec<i64> = order.iter().map(|i| coins[*i].1 as i64).collect();
+        satisfies(&values)
+    })
+    .then(|| order.iter().map(|i| coins[*i].0.clone()).collect())
+}
+
+/// This function permutes the indices from the given position on, until the check passes. The
+/// indices are left in the passing order
+fn permute(order: &mut [usize], from: usize, check: &dyn Fn(&[usize]) -> bool) -> bool {
+    if from == order.len() {
+        return check(order);
+    }
+    for n in from..order.len() {
+        order.swap(from, n);
+        if permute(order, from + 1, check) {
+            return true;
+        }
+        order.swap(from, n);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coins(names: &[(&str, u16)]) -> Vec<(String, u16)> {
+        names.iter().map(|(n, v)| (n.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn reads_the_value_from_the_dots_and_the_polygons() {
+        assert_eq!(
+            coin_value("This coin is made of a red metal.  It has two dots on one side."),
+            Some(2)
+        );
+        assert_eq!(
+            coin_value("This coin is somewhat corroded.  It has a triangle on one side."),
+            Some(3)
+        );
+        assert_eq!(coin_value("It has a PENTAGON on one side."), Some(5));
+        assert_eq!(coin_value("This coin is blank."), None);
+        assert!(is_coin("concave coin"));
+        assert!(!is_coin("tablet"));
+    }
+
+    #[test]
+    fn solves_the_equation_on_the_monument() {
+        let coins = coins(&[
+            ("red coin", 2),
+            ("corroded coin", 3),
+            ("shiny coin", 5),
+            ("concave coin", 7),
+            ("blue coin", 9),
+        ]);
+        assert_eq!(
+            solve(&coins),
+            Some(vec![
+                "blue coin".to_string(),
+                "red coin".to_string(),
+                "shiny coin".to_string(),
+                "concave coin".to_string(),
+                "corroded coin".to_string(),
+            ])
+        );
+        assert!(satisfies(&[9, 2, 5, 7, 3]));
+    }
+
+    #[test]
+    fn gives_no_order_for_the_wrong_coins() {
+        let four = coins(&[
+            ("red coin", 2),
+            ("corroded coin", 3),
+            ("shiny coin", 5),
+            ("blue coin", 9),
+        ]);
+        assert_eq!(solve(&four), None);
+        let unsolvable = coins(&[("a", 1), ("b", 1), ("c", 1), ("d", 1), ("e", 1)]);
+        assert_eq!(solve(&unsolvable), None);
+    }
+}
@@ -0,0 +1,427 @@
+use super::{RunState, VM};
+use crate::disasm;
+use crate::machine::MAX;
+use log::{debug, info, warn};
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, VecDeque};
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// The instructions executed between the checks of the requests sent by the client (e.g. pause)
+const REQUEST_CHECK_INTERVAL: u64 = 4096;
+// The VM has the single thread of execution
+const THREAD_ID: u64 = 1;
+// The variables references of the scopes shown by the client
+const REGISTERS_REFERENCE: u64 = 1;
+const STACK_REFERENCE: u64 = 2;
+
+/// How far the VM goes, once the client lets it run
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Motion {
+    Continue,
+    Step,
+    /// Run until the current function returns
+    StepOut,
+}
+
+/// This function reads the message framed with the Content-Length header, or None once the client
+/// has closed the connection
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() && length.is_some() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// The requests are read in the background, so the running VM can check for the pause request
+fn spawn_reader(stream: TcpStream) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        loop {
+            match read_message(&mut reader) {
+                Ok(Some(message)) => {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("failed to read the DAP message. Error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+/// The memory references are the word addresses of the VM, either hexadecimal or decimal
+fn parse_reference(reference: &Value) -> Option<u16> {
+    let reference = reference.as_str()?;
+    let address = match reference.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+        None => reference.parse().ok()?,
+    };
+    (address < MAX).then_some(address)
+}
+
+fn reference(address: u16) -> String {
+    format!("0x{:04x}", address)
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsDisassembleRequest": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsSteppingGranularity": true,
+        "supportsSetVariable": true,
+    })
+}
+
+/// The VM driven by the client of the Debug Adapter Protocol
+struct DapSession {
+    vm: VM,
+    stream: TcpStream,
+    requests: Receiver<Value>,
+    // The requests received while the VM was running
+    pending: VecDeque<Value>,
+    seq: u64,
+    // The breakpoints set by the client, the ones set with /break are left as they are
+    breakpoints: BTreeSet<u16>,
+}
+
+impl DapSession {
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(
+            self.stream,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.stream.flush()
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    /// This method answers the requests until the client disconnects
+    fn serve(&mut self) -> io::Result<()> {
+        loop {
+            let request = match self.pending.pop_front() {
+                Some(request) => request,
+                None => match self.requests.recv() {
+                    Ok(request) => request,
+                    // The client has gone without the disconnect request
+                    Err(_) => return Ok(()),
+                },
+            };
+            let command = request["command"].as_str().unwrap_or_default().to_string();
+            debug!("DAP request '{}'", command);
+            let arguments = &request["arguments"];
+            let motion = match command.as_str() {
+                "continue" => Some(Motion::Continue),
+                "next" | "stepIn" => Some(Motion::Step),
+                "stepOut" => Some(Motion::StepOut),
+                _ => None,
+            };
+            if let Some(motion) = motion {
+                self.respond(&request, Ok(json!({ "allThreadsStopped": true })))?;
+                self.run(motion)?;
+                continue;
+            }
+            let result = match command.as_str() {
+                "initialize" => Ok(capabilities()),
+                // The ROM is loaded already, so there is nothing to launch
+                "launch" | "attach" | "pause" => Ok(Value::Null),
+                "configurationDone" => {
+                    self.respond(&request, Ok(Value::Null))?;
+                    self.stopped("entry")?;
+                    continue;
+                }
+                "disconnect" => {
+                    self.respond(&request, Ok(Value::Null))?;
+                    return Ok(());
+                }
+                "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "synacor" }] })),
+                "stackTrace" => Ok(self.get_stack_trace()),
+                "scopes" => Ok(json!({ "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                    { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+                ] })),
+                "variables" => Ok(self.get_variables(arguments["variablesReference"].as_u64())),
+                "setVariable" => self.set_variable(arguments),
+                "setBreakpoints" => Ok(self.set_source_breakpoints(arguments)),
+                "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(arguments)),
+                "disassemble" => self.disassemble(arguments),
+                other => Err(format!("the request '{}' is not supported", other)),
+            };
+            self.respond(&request, result)?;
+            if command == "initialize" {
+                self.event("initialized", json!({}))?;
+            }
+        }
+    }
+
+    /// This method lets the VM run, until it stops at a breakpoint, completes the motion or is
+    /// paused by the client. The other requests received meanwhile are answered once it stops
+    fn run(&mut self, motion: Motion) -> io::Result<()> {
+        let vm = &mut self.vm;
+        vm.resume();
+        // The client asks to go on, so the breakpoint the VM is at is not hit once again
+        vm.resuming = true;
+        let depth = vm.machine.calls().len();
+        let budget = match motion {
+            Motion::Continue => REQUEST_CHECK_INTERVAL,
+            Motion::Step | Motion::StepOut => 1,
+        };
+        let mut executed = 0;
+        let reason = loop {
+            match self.vm.run_for(budget) {
+                RunState::Halted => break "exit",
+                RunState::Paused if self.breakpoints.contains(&self.vm.machine.position()) => {
+                    break "breakpoint";
+                }
+                RunState::Paused => break "pause",
+                RunState::Running | RunState::AwaitingInput => {}
+            }
+            match motion {
+                Motion::Step => break "step",
+                Motion::StepOut if self.vm.machine.calls().len() < depth => break "step",
+                _ => {}
+            }
+            executed += budget;
+            if executed.is_multiple_of(REQUEST_CHECK_INTERVAL) && self.check_pause()? {
+                break "pause";
+            }
+        };
+        self.stopped(reason)
+    }
+
+    /// This method answers the pause request received while the VM is running
+    fn check_pause(&mut self) -> io::Result<bool> {
+        let mut paused = false;
+        while let Ok(request) = self.requests.try_recv() {
+            if request["command"] == "pause" {
+                self.respond(&request, Ok(Value::Null))?;
+                paused = true;
+            } else {
+                self.pending.push_back(request);
+            }
+        }
+        Ok(paused)
+    }
+
+    fn stopped(&mut self, reason: &str) -> io::Result<()> {
+        let vm = &self.vm;
+        if vm.machine.halt || vm.interruption.is_some() {
+            let code = if vm.machine.fault().is_some() { 1 } else { 0 };
+            self.event("exited", json!({ "exitCode": code }))?;
+            return self.event("terminated", json!({}));
+        }
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )
+    }
+
+    /// The current instruction and the calls which have not returned yet are the frames
+    fn get_stack_trace(&self) -> Value {
+        let machine = &self.vm.machine;
+        let frames: Vec<Value> = std::iter::once(machine.position())
+            .chain(machine.calls().iter().rev().copied())
+            .enumerate()
+            .map(|(id, address)| {
+                json!({
+                    "id": id,
+                    "name": format!("{}: {}", address, disasm::format(&disasm::read(machine, address))),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": reference(address),
+                })
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn get_variables(&self, reference: Option<u64>) -> Value {
+        let machine = &self.vm.machine;
+        let variable = |name: String, value: u16| json!({ "name": name, "value": value.to_string(), "variablesReference": 0 });
+        let variables: Vec<Value> = match reference {
+            Some(REGISTERS_REFERENCE) => (0..8)
+                .map(|r| variable(format!("r{}", r), machine.registers[r]))
+                .chain(std::iter::once(variable(
+                    "pc".to_string(),
+                    machine.position(),
+                )))
+                .collect(),
+            // The top of the stack goes first
+            Some(STACK_REFERENCE) => machine
+                .stack
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(depth, value)| variable(format!("[{}]", depth), *value))
+                .collect(),
+            _ => vec![],
+        };
+        json!({ "variables": variables })
+    }
+
+    fn set_variable(&mut self, arguments: &Value) -> Result<Value, String> {
+        if arguments["variablesReference"].as_u64() != Some(REGISTERS_REFERENCE) {
+            return Err("only the registers can be changed".to_string());
+        }
+        let name = arguments["name"].as_str().unwrap_or_default();
+        let register = name
+            .strip_prefix('r')
+            .and_then(|r| r.parse::<usize>().ok())
+            .filter(|r| *r < 8)
+            .ok_or_else(|| format!("there is no register '{}'", name))?;
+        let value = arguments["value"]
+            .as_str()
+            .and_then(|v| v.trim().parse::<u16>().ok())
+            .filter(|v| *v < MAX)
+            .ok_or_else(|| format!("the value should be a number below {}", MAX))?;
+        self.vm.machine.registers[register] = value;
+        Ok(json!({ "value": value.to_string() }))
+    }
+
+    /// The ROM has no sources, so the breakpoints on the source lines are never verified
+    fn set_source_breakpoints(&self, arguments: &Value) -> Value {
+        let count = arguments["breakpoints"].as_array().map_or(0, Vec::len);
+        let breakpoint = json!({
+            "verified": false,
+            "message": "the ROM has no sources, set the breakpoints in the disassembly",
+        });
+        json!({ "breakpoints": vec![breakpoint; count] })
+    }
+
+    /// The client sends all its instruction breakpoints, so they replace the ones it has set before.
+    /// The offset is counted in words, as the memory references are
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Value {
+        for address in std::mem::take(&mut self.breakpoints) {
+            self.vm.breakpoints.remove(&address);
+        }
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let breakpoints: Vec<Value> = requested
+            .iter()
+            .map(|b| {
+                let offset = b["offset"].as_i64().unwrap_or(0);
+                let address = parse_reference(&b["instructionReference"])
+                    .and_then(|a| u16::try_from(a as i64 + offset).ok())
+                    .filter(|a| *a < MAX);
+                match address {
+                    Some(address) => {
+                        self.breakpoints.insert(address);
+                        self.vm.breakpoints.insert(address);
+                        json!({ "verified": true, "instructionReference": reference(address) })
+                    }
+                    None => json!({ "verified": false, "message": "invalid address" }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// The client asks for exactly the given number of the instructions, so the ones out of the
+    /// memory are sent as invalid
+    fn disassemble(&self, arguments: &Value) -> Result<Value, String> {
+        let address = parse_reference(&arguments["memoryReference"])
+            .ok_or("invalid memory reference")?
+            .saturating_add(
+                arguments["offset"]
+                    .as_i64()
+                    .unwrap_or(0)
+                    .clamp(0, MAX as i64) as u16,
+            )
+            .min(MAX - 1);
+        let offset = arguments["instructionOffset"].as_i64().unwrap_or(0);
+        let count = arguments["instructionCount"].as_u64().unwrap_or(0) as usize;
+        let before = (-offset).max(0) as usize;
+        let after = (count as i64 + offset).max(0) as usize;
+        let listing = disasm::around(&self.vm.machine, address, before, after);
+        let preceding = listing.iter().take_while(|(a, _)| *a < address).count() as i64;
+        let invalid =
+            json!({ "address": reference(0), "instruction": "", "presentationHint": "invalid" });
+        let instructions: Vec<Value> = (0..count as i64)
+            .map(|i| preceding + offset + i)
+            .map(
+                |i| match usize::try_from(i).ok().and_then(|i| listing.get(i)) {
+                    Some((address, words)) => json!({
+                        "address": reference(*address),
+                        "instruction": disasm::format(words),
+                    }),
+                    None => invalid.clone(),
+                },
+            )
+            .collect();
+        Ok(json!({ "instructions": instructions }))
+    }
+}
+
+/// This function waits for the client (e.g. VS Code) to connect and lets it drive the VM over the
+/// Debug Adapter Protocol until it disconnects. As with the gdb stub, the game reads the commands
+/// from stdin and goes on after the client has disconnected
+pub(crate) fn debug(vm: VM, listen: &str) -> Result<VM, Box<dyn Error>> {
+    let listener = TcpListener::bind(listen)?;
+    eprintln!("waiting for the DAP client on {}", listen);
+    let (stream, peer) = listener.accept()?;
+    info!("the DAP client has connected from {}", peer);
+    let mut session = DapSession {
+        vm,
+        requests: spawn_reader(stream.try_clone()?),
+        stream,
+        pending: VecDeque::new(),
+        seq: 0,
+        breakpoints: BTreeSet::new(),
+    };
+    let result = session.serve();
+    info!("the DAP client has disconnected");
+    for address in std::mem::take(&mut session.breakpoints) {
+        session.vm.breakpoints.remove(&address);
+    }
+    session.vm.resume();
+    result?;
+    Ok(session.vm)
+}
@@ -0,0 +1,37 @@
+use crate::config::FlushPolicy;
+use std::io::{self, BufWriter, Write};
+
+/// The buffered writer of the game output, which is flushed according to the policy. Both stdout
+/// and the output recording go through it, so the file is complete as often as the terminal is
+pub(super) struct FlushingWriter<W: Write> {
+    writer: BufWriter<W>,
+    policy: FlushPolicy,
+}
+
+impl<W: Write> FlushingWriter<W> {
+    pub(super) fn new(writer: W, policy: FlushPolicy) -> Self {
+        FlushingWriter {
+            writer: BufWriter::new(writer),
+            policy,
+        }
+    }
+
+    pub(super) fn set_policy(&mut self, policy: FlushPolicy) {
+        self.policy = policy;
+    }
+
+    pub(super) fn write(&mut self, text: &str) -> io::Result<()> {
+        self.writer.write_all(text.as_bytes())?;
+        match self.policy {
+            FlushPolicy::Char => self.writer.flush(),
+            FlushPolicy::Line if text.contains('\n') => self.writer.flush(),
+            FlushPolicy::Line | FlushPolicy::Prompt => Ok(()),
+        }
+    }
+
+    /// This method is called once the game waits for the command, everything written so far is
+    /// flushed whatever the policy is
+    pub(super) fn prompt(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,285 @@
+use super::{RunState, VM};
+use gdbstub::arch::{Arch, Registers};
+use gdbstub::common::Signal;
+use gdbstub::conn::ConnectionExt;
+use gdbstub::stub::{DisconnectReason, GdbStub, SingleThreadStopReason, run_blocking};
+use gdbstub::target::ext::base::BaseOps;
+use gdbstub::target::ext::base::singlethread::{
+    SingleThreadBase, SingleThreadResume, SingleThreadResumeOps, SingleThreadSingleStep,
+    SingleThreadSingleStepOps,
+};
+use gdbstub::target::ext::breakpoints::{
+    Breakpoints, BreakpointsOps, SwBreakpoint, SwBreakpointOps,
+};
+use gdbstub::target::{Target, TargetError, TargetResult};
+use log::{debug, info};
+use std::error::Error;
+use std::net::{TcpListener, TcpStream};
+
+// The instructions executed between the checks of the Ctrl+C sent by the debugger
+const INTERRUPT_CHECK_INTERVAL: u64 = 4096;
+
+/// The target description: the 8 registers and the program counter, all of them 16-bit
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.synacor.core">
+    <reg name="r0" bitsize="16" type="uint16"/>
+    <reg name="r1" bitsize="16" type="uint16"/>
+    <reg name="r2" bitsize="16" type="uint16"/>
+    <reg name="r3" bitsize="16" type="uint16"/>
+    <reg name="r4" bitsize="16" type="uint16"/>
+    <reg name="r5" bitsize="16" type="uint16"/>
+    <reg name="r6" bitsize="16" type="uint16"/>
+    <reg name="r7" bitsize="16" type="uint16"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>"#;
+
+/// The synacor architecture as the debugger sees it. The memory is addressed in bytes, so the
+/// word address of the VM is the half of the debugger address, the program counter included
+enum Synacor {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct SynacorRegisters {
+    r: [u16; 8],
+    /// The byte address of the instruction to be executed next
+    pc: u16,
+}
+
+impl Registers for SynacorRegisters {
+    type ProgramCounter = u16;
+
+    fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    fn gdb_serialize(&self, mut write_byte: impl FnMut(Option<u8>)) {
+        for value in self.r.iter().chain([self.pc].iter()) {
+            value
+                .to_le_bytes()
+                .into_iter()
+                .for_each(|b| write_byte(Some(b)));
+        }
+    }
+
+    fn gdb_deserialize(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        if bytes.len() != 18 {
+            return Err(());
+        }
+        let mut words = bytes.chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]]));
+        self.r
+            .iter_mut()
+            .for_each(|r| *r = words.next().unwrap_or(0));
+        self.pc = words.next().unwrap_or(0);
+        Ok(())
+    }
+}
+
+impl Arch for Synacor {
+    type Usize = u16;
+    type Registers = SynacorRegisters;
+    type BreakpointKind = usize;
+    type RegId = ();
+
+    fn target_description_xml() -> Option<&'static str> {
+        Some(TARGET_XML)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExecMode {
+    Step,
+    Continue,
+}
+
+/// The VM driven by the debugger
+struct GdbTarget {
+    vm: VM,
+    mode: ExecMode,
+}
+
+impl Target for GdbTarget {
+    type Arch = Synacor;
+    type Error = &'static str;
+
+    fn base_ops(&mut self) -> BaseOps<'_, Synacor, &'static str> {
+        BaseOps::SingleThread(self)
+    }
+
+    fn support_breakpoints(&mut self) -> Option<BreakpointsOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SingleThreadBase for GdbTarget {
+    fn read_registers(&mut self, regs: &mut SynacorRegisters) -> TargetResult<(), Self> {
+        regs.r = self.vm.machine.registers;
+        regs.pc = self.vm.machine.position().wrapping_mul(2);
+        Ok(())
+    }
+
+    fn write_registers(&mut self, regs: &SynacorRegisters) -> TargetResult<(), Self> {
+        if !regs.pc.is_multiple_of(2) {
+            return Err(TargetError::NonFatal);
+        }
+        self.vm.machine.registers = regs.r;
+        self.vm.machine.current_address = crate::machine::Address::new(regs.pc / 2);
+        Ok(())
+    }
+
+    fn read_addrs(&mut self, start_addr: u16, data: &mut [u8]) -> TargetResult<usize, Self> {
+        let memory = &self.vm.machine.memory;
+        let start = (start_addr as usize).min(memory.len());
+        let end = (start + data.len()).min(memory.len());
+        data[..end - start].copy_from_slice(&memory[start..end]);
+        Ok(end - start)
+    }
+
+    fn write_addrs(&mut self, start_addr: u16, data: &[u8]) -> TargetResult<(), Self> {
+        let memory = &mut self.vm.machine.memory;
+        if start_addr as usize + data.len() > memory.len() {
+            return Err(TargetError::NonFatal);
+        }
+        memory[start_addr as usize..start_addr as usize + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn support_resume(&mut self) -> Option<SingleThreadResumeOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SingleThreadResume for GdbTarget {
+    fn resume(&mut self, signal: Option<Signal>) -> Result<(), &'static str> {
+        if signal.is_some() {
+            return Err("the signals are not supported");
+        }
+        self.mode = ExecMode::Continue;
+        Ok(())
+    }
+
+    fn support_single_step(&mut self) -> Option<SingleThreadSingleStepOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SingleThreadSingleStep for GdbTarget {
+    fn step(&mut self, signal: Option<Signal>) -> Result<(), &'static str> {
+        if signal.is_some() {
+            return Err("the signals are not supported");
+        }
+        self.mode = ExecMode::Step;
+        Ok(())
+    }
+}
+
+impl Breakpoints for GdbTarget {
+    fn support_sw_breakpoint(&mut self) -> Option<SwBreakpointOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SwBreakpoint for GdbTarget {
+    fn add_sw_breakpoint(&mut self, addr: u16, _kind: usize) -> TargetResult<bool, Self> {
+        debug!("the debugger sets the breakpoint at {}", addr / 2);
+        self.vm.breakpoints.insert(addr / 2);
+        Ok(true)
+    }
+
+    fn remove_sw_breakpoint(&mut self, addr: u16, _kind: usize) -> TargetResult<bool, Self> {
+        debug!("the debugger deletes the breakpoint at {}", addr / 2);
+        Ok(self.vm.breakpoints.remove(&(addr / 2)))
+    }
+}
+
+type StopReason = SingleThreadStopReason<u16>;
+
+enum GdbEventLoop {}
+
+impl run_blocking::BlockingEventLoop for GdbEventLoop {
+    type Target = GdbTarget;
+    type Connection = TcpStream;
+    type StopReason = StopReason;
+
+    #[allow(clippy::type_complexity)]
+    fn wait_for_stop_reason(
+        target: &mut GdbTarget,
+        conn: &mut TcpStream,
+    ) -> Result<
+        run_blocking::Event<StopReason>,
+        run_blocking::WaitForStopReasonError<&'static str, std::io::Error>,
+    > {
+        let vm = &mut target.vm;
+        // The debugger has the control, so the VM is resumed from the breakpoint it stopped at
+        vm.resume();
+        if target.mode == ExecMode::Step {
+            vm.execute_instruction();
+            return Ok(run_blocking::Event::TargetStopped(stop_reason(vm, true)));
+        }
+        loop {
+            match vm.run_for(INTERRUPT_CHECK_INTERVAL) {
+                RunState::Running | RunState::AwaitingInput => {}
+                RunState::Paused | RunState::Halted => {
+                    return Ok(run_blocking::Event::TargetStopped(stop_reason(vm, false)));
+                }
+            }
+            if conn.peek().map(|b| b.is_some()).unwrap_or(true) {
+                let byte = conn
+                    .read()
+                    .map_err(run_blocking::WaitForStopReasonError::Connection)?;
+                return Ok(run_blocking::Event::IncomingData(byte));
+            }
+        }
+    }
+
+    fn on_interrupt(_target: &mut GdbTarget) -> Result<Option<StopReason>, &'static str> {
+        // The VM runs only within wait_for_stop_reason, so it is already stopped
+        Ok(Some(SingleThreadStopReason::Signal(Signal::SIGINT)))
+    }
+}
+
+/// This function tells the debugger why the VM has stopped
+fn stop_reason(vm: &VM, step: bool) -> StopReason {
+    if vm.machine.halt || vm.interruption.is_some() {
+        return match vm.machine.fault() {
+            Some(_) => SingleThreadStopReason::Terminated(Signal::SIGSEGV),
+            None => SingleThreadStopReason::Exited(0),
+        };
+    }
+    if step {
+        SingleThreadStopReason::DoneStep
+    } else {
+        SingleThreadStopReason::SwBreak(())
+    }
+}
+
+/// This function waits for the debugger to connect and lets it drive the VM over the GDB remote
+/// serial protocol until it disconnects. The game still reads the commands from stdin, and it goes
+/// on once the debugger has disconnected, so the VM is given back
+pub(crate) fn debug(vm: VM, listen: &str) -> Result<VM, Box<dyn Error>> {
+    let listener = TcpListener::bind(listen)?;
+    eprintln!(
+        "waiting for the debugger on {} (target remote {})",
+        listen, listen
+    );
+    let (stream, peer) = listener.accept()?;
+    info!("the debugger has connected from {}", peer);
+    let mut target = GdbTarget {
+        vm,
+        mode: ExecMode::Continue,
+    };
+    let reason = GdbStub::new(stream)
+        .run_blocking::<GdbEventLoop>(&mut target)
+        .map_err(|e| format!("the debugging session has failed: {}", e))?;
+    match reason {
+        DisconnectReason::Disconnect => info!("the debugger has disconnected"),
+        DisconnectReason::TargetExited(code) => info!("the program has exited with {}", code),
+        DisconnectReason::TargetTerminated(signal) => info!("the program has stopped: {}", signal),
+        DisconnectReason::Kill => {
+            info!("the debugger has killed the program");
+            target.vm.machine.halt = true;
+        }
+    }
+    Ok(target.vm)
+}
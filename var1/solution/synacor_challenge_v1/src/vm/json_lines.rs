@@ -0,0 +1,67 @@
+use crate::events::VmEvent;
+use crate::parser::ResponsePartsRef;
+use crate::plugin::VmPlugin;
+use log::error;
+use serde_json::{Value, json};
+use std::io::{self, Write};
+
+/// This plugin prints the game as JSON lines on stdout instead of the text, so the other programs
+/// drive and observe the VM without scraping the game output. Every line is the object with the
+/// "event" field naming it: response, command, solver_command, solve_finished, code or halted
+pub(super) struct JsonLines {
+    active: bool,
+}
+
+impl JsonLines {
+    pub(super) fn new() -> Self {
+        JsonLines { active: true }
+    }
+
+    fn write(&mut self, value: Value) {
+        if !self.active {
+            return;
+        }
+        let mut stdout = io::stdout().lock();
+        // The reader gets every line as soon as it is printed, even through the pipe
+        if let Err(e) = writeln!(stdout, "{}", value).and_then(|_| stdout.flush()) {
+            error!("failed to write the JSON lines to stdout. Error: {}", e);
+            self.active = false;
+        }
+    }
+}
+
+fn response_json(response: &ResponsePartsRef) -> Value {
+    json!({
+        "event": "response",
+        "title": response.title,
+        "description": response.description,
+        "items": response.items,
+        "exits": response.exits,
+        "inventory": response.inventory,
+        "dont_understand": response.dont_understand,
+        "pretext": response.pretext,
+    })
+}
+
+impl VmPlugin for JsonLines {
+    fn name(&self) -> &str {
+        "json-lines"
+    }
+
+    fn on_event(&mut self, event: &VmEvent) {
+        let value = match event {
+            VmEvent::ResponseParsed(response) => response_json(response),
+            VmEvent::CommandEntered(command) => json!({ "event": "command", "command": command }),
+            VmEvent::SolverCommand(command) => {
+                json!({ "event": "solver_command", "command": command })
+            }
+            VmEvent::SolveFinished(reason) => {
+                json!({ "event": "solve_finished", "reason": reason })
+            }
+            VmEvent::CodeFound(code) => json!({ "event": "code", "code": code }),
+            VmEvent::Halted(cycles) => json!({ "event": "halted", "cycles": cycles }),
+            _ => return,
+        };
+        self.write(value);
+    }
+}
@@ -0,0 +1,132 @@
+use super::PAUSE_REQUESTED;
+use colored::Colorize;
+use log::{trace, warn};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal;
+use std::io::{self, Write};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+/// The key shortcuts as shown by /help, the same as matched by shortcut()
+pub(super) const SHORTCUTS: [(&str, &str); 6] = [
+    ("up, w", "go north"),
+    ("down, s", "go south"),
+    ("right, d", "go east"),
+    ("left, a", "go west"),
+    ("i", "inv"),
+    ("l", "look"),
+];
+
+/// Returns the command of the key pressed at the empty line. The other keys are typed as usual,
+/// so the letters of the shortcuts still start the commands, once the line is not empty
+fn shortcut(code: KeyCode) -> Option<&'static str> {
+    match code {
+        KeyCode::Up | KeyCode::Char('w') => Some("go north"),
+        KeyCode::Down | KeyCode::Char('s') => Some("go south"),
+        KeyCode::Right | KeyCode::Char('d') => Some("go east"),
+        KeyCode::Left | KeyCode::Char('a') => Some("go west"),
+        KeyCode::Char('i') => Some("inv"),
+        KeyCode::Char('l') => Some("look"),
+        _ => None,
+    }
+}
+
+// The terminal is switched back to the cooked mode, however the reading ends
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Err(e) = terminal::disable_raw_mode() {
+            warn!("failed to restore the terminal mode. Error: {}", e);
+        }
+    }
+}
+
+/// This function reads the line typed at the terminal in the raw mode, the same way as
+/// Stdin::read_line: the line ends with the new line and Ok(0) stands for the end of the input
+/// (Ctrl+D at the empty line). The shortcut key at the empty line completes the line with its
+/// command at once, unless the line is started with the space (e.g. to type 'look tablet'), which
+/// is not a part of the command. Ctrl+C does not raise the signal in the raw mode, so it pauses the VM here,
+/// and quits the game once pressed again. The reading ends with the TimedOut error at the deadline
+pub(super) fn read_line(line: &mut String, deadline: Option<Instant>) -> io::Result<usize> {
+    let _raw = RawMode::enable()?;
+    let mut stdout = io::stdout();
+    let mut typing = false; // the leading space turns the shortcuts off
+    loop {
+        if let Some(deadline) = deadline
+            && !event::poll(deadline.saturating_duration_since(Instant::now()))?
+        {
+            write!(stdout, "\r\n")?;
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+        let control = modifiers.contains(KeyModifiers::CONTROL);
+        let plain = !control && !modifiers.contains(KeyModifiers::ALT);
+        if plain
+            && !typing
+            && line.is_empty()
+            && let Some(command) = shortcut(code)
+        {
+            trace!("key {:?} stands for '{}'", code, command);
+            line.push_str(command);
+            line.push('\n');
+            write!(stdout, "{}\r\n", command)?;
+            stdout.flush()?;
+            return Ok(line.len());
+        }
+        match code {
+            KeyCode::Char('c') if control => {
+                if PAUSE_REQUESTED.swap(true, Ordering::SeqCst) {
+                    write!(stdout, "\r\n")?;
+                    return Ok(0);
+                }
+                write!(
+                    stdout,
+                    "\r\n{}\r\n{}",
+                    "pausing the VM (press Ctrl+C again to quit)".yellow(),
+                    line
+                )?;
+            }
+            KeyCode::Char('d') if control && line.is_empty() => {
+                write!(stdout, "\r\n")?;
+                return Ok(0);
+            }
+            _ if !plain => {}
+            KeyCode::Enter => {
+                line.push('\n');
+                write!(stdout, "\r\n")?;
+                stdout.flush()?;
+                return Ok(line.len());
+            }
+            KeyCode::Char(' ') if !typing && line.is_empty() => {
+                typing = true;
+                write!(stdout, " ")?;
+            }
+            // The leading space goes last, turning the shortcuts on again
+            KeyCode::Backspace if line.pop().is_some() || std::mem::take(&mut typing) => {
+                write!(stdout, "\u{8} \u{8}")?;
+            }
+            KeyCode::Char(c) => {
+                line.push(c);
+                write!(stdout, "{}", c)?;
+            }
+            _ => {}
+        }
+        stdout.flush()?;
+    }
+}
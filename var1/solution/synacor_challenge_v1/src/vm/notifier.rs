@@ -0,0 +1,69 @@
+use crate::events::VmEvent;
+use crate::plugin::VmPlugin;
+use log::{debug, warn};
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// This plugin lets the user know about the new challenge code and the end of /solve, which is
+/// handy when the exploration runs in the background. It either rings the terminal bell or runs
+/// the shell command with SYNACOR_EVENT (code or solve_finished) and SYNACOR_DETAIL (the code or
+/// the reason the exploration has stopped) in its environment
+pub(super) enum Notifier {
+    Bell,
+    Command(String),
+}
+
+impl Notifier {
+    /// Returns the notifier given with --notify: 'bell' or the command to run
+    pub(super) fn new(spec: &str) -> Self {
+        match spec.trim() {
+            "bell" => Notifier::Bell,
+            command => Notifier::Command(command.to_string()),
+        }
+    }
+
+    fn notify(&self, event: &str, detail: &str) {
+        debug!("notifying about {} '{}'", event, detail);
+        match self {
+            Notifier::Bell => {
+                // The bell goes to stderr, so it does not get into the game output
+                let mut stderr = io::stderr();
+                if let Err(e) = stderr.write_all(b"\x07").and_then(|_| stderr.flush()) {
+                    warn!("failed to ring the bell. Error: {}", e);
+                }
+            }
+            Notifier::Command(command) => {
+                let child = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .env("SYNACOR_EVENT", event)
+                    .env("SYNACOR_DETAIL", detail)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .spawn();
+                match child {
+                    // The VM does not wait for the slow notification, but the child is reaped
+                    Ok(mut child) => {
+                        thread::spawn(move || child.wait());
+                    }
+                    Err(e) => warn!("failed to run the notification '{}'. Error: {}", command, e),
+                }
+            }
+        }
+    }
+}
+
+impl VmPlugin for Notifier {
+    fn name(&self) -> &str {
+        "notifier"
+    }
+
+    fn on_event(&mut self, event: &VmEvent) {
+        match event {
+            VmEvent::CodeFound(code) => self.notify("code", code),
+            VmEvent::SolveFinished(reason) => self.notify("solve_finished", reason),
+            _ => {}
+        }
+    }
+}
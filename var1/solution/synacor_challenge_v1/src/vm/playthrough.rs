@@ -0,0 +1,556 @@
+use super::coins;
+use crate::machine::{MAX, Machine, RomTooLarge, StepEvent};
+use crate::maze::{self, MazeAnalyzer};
+use crate::parser::{self, OutputParser, UseEffect};
+use log::{debug, info, warn};
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// The number of the instructions the game may execute to respond to the command, e.g. the
+// teleporter confirmation takes a while even when it is patched
+const COMMAND_CYCLE_LIMIT: u64 = 50_000_000;
+// The number of the commands, after which the playthrough gives up
+const MAX_COMMANDS: usize = 5_000;
+// The item reading the teleporter confirmation, the one the eighth register is set for
+const TELEPORTER: &str = "teleporter";
+// The value the teleporter confirmation has to return with the right eighth register
+const CONFIRMATION_RESULT: u16 = 6;
+// The orb weighed in the vault rooms and the number of the vault door
+const ORB: &str = "orb";
+const VAULT_DOOR_WEIGHT: i32 = 30;
+// The exit of the vault door room into the vault
+const VAULT: &str = "vault";
+// The item showing the last code, which is written mirrored
+const MIRROR: &str = "mirror";
+
+/// How the headless playthrough has gone
+#[derive(Debug, Clone)]
+pub struct PlaythroughReport {
+    /// The stages in the order they have been completed
+    pub stages: Vec<&'static str>,
+    pub commands: usize,
+    pub rooms: usize,
+    pub codes: Vec<String>,
+    pub ended: String,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for PlaythroughReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "***           Playthrough Report          ***")?;
+        writeln!(f, "{}", "=".repeat(44))?;
+        writeln!(f, "{:<9}: {}", "stages", self.stages.join(", "))?;
+        writeln!(f, "{:<9}: {}", "commands", self.commands)?;
+        writeln!(f, "{:<9}: {}", "rooms", self.rooms)?;
+        writeln!(f, "{:<9}: {}", "ended", self.ended)?;
+        writeln!(f, "{:<9}: {:.2?}", "elapsed", self.elapsed)?;
+        writeln!(f, "{:<9}:", "codes")?;
+        for (n, code) in self.codes.iter().enumerate() {
+            writeln!(f, "  {:>2}. {}", n + 1, code)?;
+        }
+        write!(f, "{}", "=".repeat(45))
+    }
+}
+
+/// The playthrough which has not found the last code. The report tells where it has stopped
+#[derive(Debug, Clone)]
+pub struct PlaythroughFailure {
+    pub report: PlaythroughReport,
+}
+
+impl fmt::Display for PlaythroughFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the playthrough has failed: {}", self.report.ended)
+    }
+}
+
+impl Error for PlaythroughFailure {}
+
+/// The game played by the bare machine without the terminal. Every command is sent to the machine
+/// copied beforehand, so the fatal one is taken back and its exit is marked as blocked
+struct Player {
+    machine: Machine,
+    parser: OutputParser,
+    maze: MazeAnalyzer,
+    inventory: Vec<String>,
+    codes: Vec<String>,
+    commands: usize,
+    // The items used with the same items carried in the same room, they are not tried again
+    used: HashSet<(u16, String, Vec<String>)>,
+}
+
+impl Player {
+    fn new(rom: &[u8], seed: u64) -> Result<Self, RomTooLarge> {
+        let mut machine = Machine::new();
+        machine.load_rom(rom)?;
+        machine.set_journal_size(0);
+        let mut maze = MazeAnalyzer::with_seed(seed);
+        let mut strategy = maze.strategy().clone();
+        strategy.safe_only = true;
+        maze.set_strategy(strategy);
+        Ok(Player {
+            machine,
+            parser: OutputParser::new(),
+            maze,
+            inventory: vec![],
+            codes: vec![],
+            commands: 0,
+            used: HashSet::new(),
+        })
+    }
+
+    /// This method runs the machine fed with the input until it waits for more. Returns the output
+    /// printed after the last input character, None if the game has not responded in time
+    fn run(&mut self, input: &str) -> Option<String> {
+        let mut input: VecDeque<u8> = input.bytes().collect();
+        let mut output = String::new();
+        for _ in 0..COMMAND_CYCLE_LIMIT {
+            match self.machine.execute(|| input.pop_front()) {
+                StepEvent::Output(c) => output.push(c),
+                StepEvent::Input(_) => output.clear(),
+                StepEvent::AwaitingInput | StepEvent::Halted => return Some(output),
+                StepEvent::None => {}
+            }
+        }
+        None
+    }
+
+    /// This method sends the command to the game and records the response. The machine halted by
+    /// the command, e.g. the player eaten by the grue, is restored as it was before it
+    fn send(&mut self, command: &str) -> Result<String, String> {
+        if self.commands >= MAX_COMMANDS {
+            return Err(format!("{} commands are sent", MAX_COMMANDS));
+        }
+        self.commands += 1;
+        debug!("playthrough sends '{}'", command);
+        let checkpoint = self.machine.checkpoint();
+        self.maze.command(command);
+        let text = self
+            .run(&format!("{}\n", command))
+            .ok_or(format!("the game has not responded to '{}'", command))?;
+        if self.machine.is_halted() {
+            warn!("'{}' has ended the game, taking it back", command);
+            self.machine = checkpoint;
+        }
+        let response = self.parser.parse_ref(&text);
+        if let Some(item) = command.strip_prefix("use ") {
+            let effects = parser::use_effects(item, &response);
+            if effects.contains(&UseEffect::Teleported) {
+                self.maze.teleported(item);
+            }
+        }
+        self.maze.observe(&response);
+        if let Some(inventory) = &response.inventory {
+            self.inventory = inventory.iter().map(|i| i.to_string()).collect();
+        }
+        for code in parser::find_codes(&text) {
+            if !self.codes.contains(&code) {
+                info!("playthrough has found code '{}'", code);
+                self.codes.push(code);
+            }
+        }
+        Ok(text)
+    }
+
+    /// This method sends the command, which may change the items carried, and looks at them
+    fn act(&mut self, command: &str) -> Result<String, String> {
+        let text = self.send(command)?;
+        self.send("inv")?;
+        self.maze.set_inventory(&self.inventory);
+        Ok(text)
+    }
+
+    fn carries(&self, item: &str) -> bool {
+        self.inventory.iter().any(|i| i == item)
+    }
+
+    fn current(&self) -> Result<u16, String> {
+        self.maze
+            .current_node()
+            .map(|n| n.id)
+            .ok_or("the player is nowhere".to_string())
+    }
+
+    /// This method walks to the room along the known passages
+    fn walk_to(&mut self, room: u16) -> Result<(), String> {
+        let path = self
+            .maze
+            .shortest_path(self.current()?, room)
+            .ok_or(format!("none of the known passages lead to room #{}", room))?;
+        for exit in path {
+            self.send(&format!("go {}", exit))?;
+        }
+        Ok(())
+    }
+
+    /// This method explores the map until there is nothing left to explore safely. The items lying
+    /// around are taken on the way. Returns whether any room has been discovered
+    fn explore(&mut self) -> Result<bool, String> {
+        let rooms = self.maze.nodes().len();
+        while let Some(command) = self.maze.next_exploration_command() {
+            if command.starts_with("take ") {
+                self.act(&command)?;
+            } else {
+                self.send(&command)?;
+            }
+        }
+        Ok(self.maze.nodes().len() > rooms)
+    }
+
+    /// This method uses every item carried, which has not been used in this room with the same
+    /// items yet, except the coins kept for the monument and the light. Returns whether the items carried or the
+    /// room have changed
+    fn use_items(&mut self) -> Result<bool, String> {
+        // Using the light again puts it out
+        let items: Vec<String> = self
+            .inventory
+            .iter()
+            .filter(|i| !coins::is_coin(i) && !i.starts_with(maze::LIGHT_PREFIX))
+            .cloned()
+            .collect();
+        for item in items {
+            let key = (self.current()?, item.clone(), self.inventory.clone());
+            if !self.used.insert(key) {
+                continue;
+            }
+            let (room, inventory) = (self.current()?, self.inventory.clone());
+            self.act(&format!("use {}", item))?;
+            if self.current()? != room || self.inventory != inventory {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// This method puts the coins into the monument in the order solving its equation, once the
+    /// coins are carried and the monument is found. Returns whether they have been put
+    fn solve_coins(&mut self) -> Result<bool, String> {
+        let carried: Vec<String> = self
+            .inventory
+            .iter()
+            .filter(|i| coins::is_coin(i))
+            .cloned()
+            .collect();
+        let monument = self
+            .maze
+            .nodes()
+            .iter()
+            .find(|n| n.description.contains(coins::MONUMENT_MARK))
+            .map(|n| n.id);
+        let Some(monument) = monument.filter(|_| carried.len() == coins::COIN_SLOTS) else {
+            return Ok(false);
+        };
+        let mut values = vec![];
+        for coin in carried {
+            let text = self.send(&format!("look {}", coin))?;
+            let value = coins::coin_value(&self.parser.parse_ref(&text).pretext)
+                .ok_or(format!("the value of the {} is unknown", coin))?;
+            values.push((coin, value));
+        }
+        let order = coins::solve(&values).ok_or("no order of the coins solves the equation")?;
+        info!("putting the coins in the order {:?}", order);
+        self.walk_to(monument)?;
+        for coin in order {
+            self.act(&format!("use {}", coin))?;
+        }
+        Ok(true)
+    }
+
+    /// This method sets the eighth register for the teleporter and skips its confirmation, which
+    /// would take ages. The register value is the one the confirmation accepts, and the
+    /// confirmation function itself is patched to return the expected value at once
+    fn solve_teleporter(&mut self) -> Result<bool, String> {
+        if !self.carries(TELEPORTER) || self.machine.registers[7] != 0 {
+            return Ok(false);
+        }
+        let function = find_confirmation(&self.machine)
+            .ok_or("the teleporter confirmation is not found in the program")?;
+        let r8 = (1..MAX)
+            .find(|r8| confirmation(*r8) == CONFIRMATION_RESULT)
+            .ok_or("no value of the eighth register passes the confirmation")?;
+        info!(
+            "the eighth register is {}, the confirmation at {} is patched",
+            r8, function
+        );
+        self.machine.registers[7] = r8;
+        // set r0 6; ret
+        for (n, word) in [1, MAX, CONFIRMATION_RESULT, 18].into_iter().enumerate() {
+            self.machine.set_word(function + n as u16, word);
+        }
+        self.act(&format!("use {}", TELEPORTER))?;
+        Ok(true)
+    }
+
+    /// This method walks the orb from the antechamber to the vault door along the rooms, which
+    /// make its weight the number of the door, found with the breadth-first search over the
+    /// vault rooms. The room of the door changes the weight too, but its own number is not shown,
+    /// so the numbers are tried one by one until the door opens. Returns whether the vault has
+    /// been entered
+    fn solve_vault(&mut self) -> Result<bool, String> {
+        let marks: Vec<Option<VaultMark>> = self
+            .maze
+            .nodes()
+            .iter()
+            .map(|n| VaultMark::of_room(&n.title, &n.description))
+            .collect();
+        let antechamber = self
+            .maze
+            .nodes()
+            .iter()
+            .find(|n| n.items.iter().any(|i| i == ORB) || n.title.contains("Antechamber"))
+            .map(|n| n.id);
+        let door = self
+            .maze
+            .nodes()
+            .iter()
+            .find(|n| n.title.contains("Vault Door"))
+            .map(|n| n.id);
+        let (Some(antechamber), Some(door)) = (antechamber, door) else {
+            return Ok(false);
+        };
+        let Some(Some(VaultMark::Number(start))) = marks.get(antechamber as usize).cloned() else {
+            return Ok(false);
+        };
+        for number in 1..=VAULT_DOOR_WEIGHT {
+            let Some(path) = self.vault_path(&marks, antechamber, start, door, number) else {
+                continue;
+            };
+            info!(
+                "walking the orb along {:?}, if the door room has number {}",
+                path, number
+            );
+            self.walk_to(antechamber)?;
+            if !self.carries(ORB) {
+                self.act(&format!("take {}", ORB))?;
+            }
+            for exit in path {
+                self.send(&format!("go {}", exit))?;
+            }
+            self.send(&format!("go {}", VAULT))?;
+            if self.current()? != door {
+                return Ok(true);
+            }
+        }
+        Err("no path of the orb opens the vault door".to_string())
+    }
+
+    /// Returns the exits leading the orb from the antechamber to the door, where it weighs the
+    /// number of the door, if the room of the door has the given number. The orb is reset in the
+    /// antechamber, so the path does not return there
+    fn vault_path(
+        &self,
+        marks: &[Option<VaultMark>],
+        antechamber: u16,
+        start: i32,
+        door: u16,
+        door_number: i32,
+    ) -> Option<Vec<String>> {
+        type State = (u16, i32, Option<char>);
+        let first: State = (antechamber, start, None);
+        let mut previous: std::collections::HashMap<State, (State, String)> =
+            std::collections::HashMap::new();
+        let mut queue = VecDeque::from([first]);
+        while let Some(state) = queue.pop_front() {
+            let (room, weight, operation) = state;
+            for edge in self.maze.neighbors(room) {
+                if edge.to == door {
+                    // Entering the door room with the wrong weight makes the orb evaporate
+                    if operation.and_then(|op| weigh(weight, op, door_number))
+                        != Some(VAULT_DOOR_WEIGHT)
+                    {
+                        continue;
+                    }
+                    let mut path = vec![edge.exit.to_string()];
+                    let mut step = state;
+                    while step != first {
+                        let (prev, exit) = previous[&step].clone();
+                        path.push(exit);
+                        step = prev;
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                let Some(Some(mark)) = marks.get(edge.to as usize) else {
+                    continue;
+                };
+                if edge.to == antechamber {
+                    continue;
+                }
+                let next = match (mark, operation) {
+                    (VaultMark::Operation(op), None) => (edge.to, weight, Some(*op)),
+                    (VaultMark::Number(n), Some(op)) => match weigh(weight, op, *n) {
+                        Some(weight) => (edge.to, weight, None),
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                if next != first && !previous.contains_key(&next) {
+                    previous.insert(next, (state, edge.exit.to_string()));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Returns the weight of the orb after the operation with the number of the room, None if the orb
+/// is too light or too heavy and shatters
+fn weigh(weight: i32, operation: char, number: i32) -> Option<i32> {
+    let weight = match operation {
+        '+' => weight + number,
+        '-' => weight - number,
+        _ => weight * number,
+    };
+    (1..=MAX as i32).contains(&weight).then_some(weight)
+}
+
+/// The mark on the floor of the vault room: the number the orb weight is changed by, or the
+/// operation applied with the number of the next room
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VaultMark {
+    Number(i32),
+    Operation(char),
+}
+
+impl VaultMark {
+    /// The vault rooms have the mark quoted in the description, e.g. "the number '8'" or "the
+    /// symbol '*'", the vault door shows the weight it opens with
+    fn of_room(title: &str, description: &str) -> Option<Self> {
+        if !title.starts_with("Vault") {
+            return None;
+        }
+        let mark = description.split('\'').nth(1)?;
+        match mark {
+            "+" | "-" | "*" => mark.chars().next().map(VaultMark::Operation),
+            number => number.parse().ok().map(VaultMark::Number),
+        }
+    }
+}
+
+/// This function finds the function confirming the teleporter destination by its first
+/// instructions: 'jt r0 <a>; add r0 r1 1; ret'
+fn find_confirmation(machine: &Machine) -> Option<u16> {
+    let pattern = [
+        Some(7),
+        Some(MAX),
+        None,
+        Some(9),
+        Some(MAX),
+        Some(MAX + 1),
+        Some(1),
+        Some(18),
+    ];
+    (0..MAX - pattern.len() as u16).find(|address| {
+        pattern
+            .iter()
+            .enumerate()
+            .all(|(n, w)| w.is_none_or(|w| machine.word(address + n as u16) == w))
+    })
+}
+
+/// This function computes the teleporter confirmation f(4, 1) for the eighth register r, where
+/// f(0, n) = n + 1, f(m, 0) = f(m - 1, r) and f(m, n) = f(m - 1, f(m, n - 1)) modulo 32768. The
+/// first rows have the closed forms: f(1, n) = n + r + 1 and f(2, n) = (n + 2)(r + 1) - 1, so only
+/// the row of f(3, n) is computed
+fn confirmation(r: u16) -> u16 {
+    let (r, max) = (r as u32, MAX as u32);
+    let f2 = |n: u32| ((n + 2) * (r + 1) - 1) % max;
+    let mut f3 = vec![0; MAX as usize];
+    f3[0] = f2(r);
+    for n in 1..f3.len() {
+        f3[n] = f2(f3[n - 1]);
+    }
+    let f4_0 = f3[r as usize];
+    f3[f4_0 as usize] as u16
+}
+
+/// This function turns the code seen in the mirror back: the letters come in the reversed order
+/// and the symmetric ones are swapped, e.g. 'b' and 'd'
+fn unmirror(code: &str) -> String {
+    code.chars()
+        .rev()
+        .map(|c| match c {
+            'b' => 'd',
+            'd' => 'b',
+            'p' => 'q',
+            'q' => 'p',
+            c => c,
+        })
+        .collect()
+}
+
+/// This function plays the whole game without the terminal: it explores the map taking the items
+/// and trying them out, solves the coin puzzle, the teleporter and the vault, and returns all the
+/// codes found on the way. The playthrough, which has not got as far as the last code, fails
+pub fn play(rom: &[u8], seed: u64) -> Result<PlaythroughReport, PlaythroughFailure> {
+    let started = Instant::now();
+    let mut player = Player::new(rom, seed).map_err(|e| PlaythroughFailure {
+        report: PlaythroughReport {
+            stages: vec![],
+            commands: 0,
+            rooms: 0,
+            codes: vec![],
+            ended: e.to_string(),
+            elapsed: started.elapsed(),
+        },
+    })?;
+    let mut stages = vec![];
+    let result = player.play(&mut stages);
+    let report = PlaythroughReport {
+        stages,
+        commands: player.commands,
+        rooms: player.maze.nodes().len(),
+        codes: player.codes,
+        ended: result.clone().unwrap_or_else(|e| e),
+        elapsed: started.elapsed(),
+    };
+    match result {
+        Ok(_) => Ok(report),
+        Err(_) => Err(PlaythroughFailure { report }),
+    }
+}
+
+impl Player {
+    /// This method chains the stages: the exploration and the items tried out are repeated until
+    /// neither of them makes any progress, then the puzzle ready to be solved is solved
+    fn play(&mut self, stages: &mut Vec<&'static str>) -> Result<String, String> {
+        let banner = self.run("").ok_or("the game has not started")?;
+        self.codes.extend(parser::find_codes(&banner));
+        self.maze.observe(&self.parser.parse_ref(&banner));
+        loop {
+            if self.explore()? {
+                continue;
+            }
+            if self.use_items()? {
+                continue;
+            }
+            if !stages.contains(&"coins") && self.solve_coins()? {
+                stages.push("coins");
+                continue;
+            }
+            if !stages.contains(&"teleporter") && self.solve_teleporter()? {
+                stages.push("teleporter");
+                continue;
+            }
+            if !stages.contains(&"vault") && self.solve_vault()? {
+                stages.push("vault");
+                continue;
+            }
+            break;
+        }
+        if !self.carries(MIRROR) {
+            return Err("there is nothing left to do, but the mirror is not found".to_string());
+        }
+        let text = self.act(&format!("use {}", MIRROR))?;
+        let code = parser::find_codes(&text)
+            .pop()
+            .ok_or("the mirror shows no code")?;
+        self.codes.retain(|c| *c != code);
+        self.codes.push(unmirror(&code));
+        stages.push(MIRROR);
+        Ok("the mirror has shown the last code".to_string())
+    }
+}
@@ -0,0 +1,174 @@
+use super::flush::FlushingWriter;
+use crate::config::FlushPolicy;
+use crate::events::VmEvent;
+use crate::input_log::{self, InputEvent};
+use crate::plugin::VmPlugin;
+use log::{error, trace};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// This plugin records the game output together with the entered commands to the file, flushed
+/// the same way as stdout
+pub(super) struct OutputRecorder {
+    path: PathBuf,
+    writer: Option<FlushingWriter<File>>,
+    policy: FlushPolicy,
+    active: bool,
+    written: usize,
+}
+
+impl OutputRecorder {
+    pub(super) fn new(path: &Path, policy: FlushPolicy) -> Self {
+        OutputRecorder {
+            path: path.to_path_buf(),
+            writer: None,
+            policy,
+            active: true,
+            written: 0,
+        }
+    }
+
+    pub(super) fn set_policy(&mut self, policy: FlushPolicy) {
+        self.policy = policy;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.set_policy(policy);
+        }
+    }
+
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// The number of bytes recorded to the file so far
+    pub(super) fn written(&self) -> usize {
+        self.written
+    }
+
+    /// This method flushes the recorded output and closes the file
+    pub(super) fn stop(&mut self) -> io::Result<()> {
+        trace!("stopping the output recording to {}", self.path.display());
+        let result = self.writer.as_mut().map_or(Ok(()), |w| w.prompt());
+        self.disable();
+        result
+    }
+
+    fn disable(&mut self) {
+        trace!("disabling the output recording to {}", self.path.display());
+        self.active = false;
+        self.writer = None;
+    }
+
+    fn write(&mut self, text: &str) {
+        if !self.active {
+            return;
+        }
+        // The file is created lazily, once there is something to record
+        if self.writer.is_none() {
+            match File::create(&self.path) {
+                Ok(f) => self.writer = Some(FlushingWriter::new(f, self.policy)),
+                Err(f_err) => {
+                    error!(
+                        "creation of the output file failed. Error: {} Recording of the output is disabled",
+                        f_err
+                    );
+                    self.disable();
+                    return;
+                }
+            }
+        }
+        if let Some(bw) = self.writer.as_mut() {
+            match bw.write(text) {
+                Ok(()) => {
+                    trace!("wrote {} bytes to the output buffer", text.len());
+                    self.written += text.len();
+                }
+                Err(buf_e) => {
+                    error!(
+                        "failed to write to the output recording buffer. Error: {} Recording stopped",
+                        buf_e
+                    );
+                    self.disable();
+                }
+            }
+        }
+    }
+}
+
+impl VmPlugin for OutputRecorder {
+    fn name(&self) -> &str {
+        "output recorder"
+    }
+
+    fn on_output(&mut self, line: &str) {
+        self.write(line);
+    }
+
+    fn on_command(&mut self, command: &str) {
+        self.write(command);
+        // The command is entered at the prompt, so the response before it is complete
+        if let Some(Err(f_err)) = self.writer.as_mut().map(|w| w.prompt()) {
+            error!("failed to flush the output record buffer. Error: {}", f_err);
+        }
+    }
+
+    fn on_halt(&mut self, _cycles: u64) {
+        if let Some(Err(f_err)) = self.writer.as_mut().map(|w| w.prompt()) {
+            error!("failed to flush the output record buffer. Error: {}", f_err);
+        }
+    }
+}
+
+/// This plugin records every character read by the game with the time since the start of the
+/// session, so the session can be replayed with the same timing
+pub(super) struct InputRecorder {
+    started: Instant,
+    writer: Option<BufWriter<File>>,
+}
+
+impl InputRecorder {
+    pub(super) fn create(path: &Path, started: Instant) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", input_log::HEADER)?;
+        Ok(InputRecorder {
+            started,
+            writer: Some(writer),
+        })
+    }
+}
+
+impl VmPlugin for InputRecorder {
+    fn name(&self) -> &str {
+        "input recorder"
+    }
+
+    fn on_event(&mut self, event: &VmEvent) {
+        let (VmEvent::InputConsumed(c), Some(writer)) = (event, self.writer.as_mut()) else {
+            return;
+        };
+        let event = InputEvent {
+            at: self.started.elapsed(),
+            byte: *c as u8,
+        };
+        // Every command is flushed, so the record survives the crash of the session
+        let result = writeln!(writer, "{}", event).and_then(|_| match c {
+            '\n' => writer.flush(),
+            _ => Ok(()),
+        });
+        if let Err(e) = result {
+            error!("failed to record the input. Error: {} Recording stopped", e);
+            self.writer = None;
+        }
+    }
+
+    fn on_halt(&mut self, _cycles: u64) {
+        if let Some(Err(e)) = self.writer.as_mut().map(|w| w.flush()) {
+            error!("failed to flush the input record. Error: {}", e);
+        }
+    }
+}
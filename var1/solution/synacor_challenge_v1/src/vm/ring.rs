@@ -0,0 +1,122 @@
+use crate::disasm;
+use crate::machine::{Machine, StepEvent};
+use crate::trace::{TraceRecord, TraceWriter};
+use log::{debug, warn};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::path::Path;
+
+// The number of the snapshots kept, so the VM can go back up to this number of the intervals
+const RING_SIZE: usize = 16;
+
+/// The machine copied at the given cycle and the input characters consumed after it
+struct Snapshot {
+    cycles: u64,
+    machine: Machine,
+    input: Vec<u8>,
+}
+
+/// The rolling snapshots of the machine taken every given number of cycles. The machine is
+/// deterministic, so the recent past is executed once again from the snapshot with the same input
+pub(super) struct SnapshotRing {
+    interval: u64,
+    snapshots: VecDeque<Snapshot>,
+}
+
+/// The result of re-executing the recent past
+pub(super) struct Replay {
+    /// The cycle of the snapshot the replay has started from
+    pub(super) start: u64,
+    pub(super) records: u64,
+    /// The machine at the end of the replay, it should be the same as the one of the VM
+    pub(super) machine: Machine,
+}
+
+impl SnapshotRing {
+    pub(super) fn new(interval: u64) -> Self {
+        SnapshotRing {
+            interval,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// This method is called before every instruction, it takes the snapshot once the interval is
+    /// over. The instruction waiting for the input is retried, so it is taken only once
+    pub(super) fn record(&mut self, cycles: u64, machine: &mut Machine) {
+        if self.interval == 0
+            || !cycles.is_multiple_of(self.interval)
+            || self.snapshots.back().is_some_and(|s| s.cycles == cycles)
+        {
+            return;
+        }
+        if self.snapshots.len() >= RING_SIZE {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot {
+            cycles,
+            machine: machine.checkpoint(),
+            input: vec![],
+        });
+    }
+
+    pub(super) fn input(&mut self, c: u8) {
+        if let Some(snapshot) = self.snapshots.back_mut() {
+            snapshot.input.push(c);
+        }
+    }
+
+    /// The snapshots do not lead to the current state any more, e.g. once another state is loaded
+    pub(super) fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    /// This method executes the instructions from the latest snapshot taken at least `cycles`
+    /// before `now` up to `now`, writing the last `cycles` of them to the trace file
+    pub(super) fn replay(
+        &self,
+        now: u64,
+        cycles: u64,
+        path: &Path,
+    ) -> Result<Replay, Box<dyn Error>> {
+        let from = now.saturating_sub(cycles);
+        let Some(index) = self.snapshots.iter().rposition(|s| s.cycles <= from) else {
+            let oldest = self.snapshots.front().map_or(now, |s| s.cycles);
+            return Err(format!(
+                "the oldest snapshot is taken at cycle {}, {} cycles ago",
+                oldest,
+                now - oldest.min(now)
+            )
+            .into());
+        };
+        let snapshot = &self.snapshots[index];
+        debug!("replaying from the snapshot at cycle {}", snapshot.cycles);
+        let mut trace = TraceWriter::create(path)?;
+        let mut machine = snapshot.machine.clone();
+        let mut input = self
+            .snapshots
+            .range(index..)
+            .flat_map(|s| s.input.iter().copied());
+        for cycle in snapshot.cycles + 1..=now {
+            let address = machine.position();
+            let record = TraceRecord {
+                cycle,
+                address,
+                words: disasm::read(&machine, address),
+                registers: machine.registers(),
+                stack: machine.stack.len(),
+            };
+            if machine.execute(|| input.next()) == StepEvent::AwaitingInput {
+                warn!("the input is used up at cycle {}", cycle);
+                break;
+            }
+            if cycle > from {
+                trace.write(&record)?;
+            }
+        }
+        Ok(Replay {
+            start: snapshot.cycles,
+            records: trace.finish()?,
+            machine,
+        })
+    }
+}
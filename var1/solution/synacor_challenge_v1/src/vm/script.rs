@@ -0,0 +1,148 @@
+/// Something the user script asked the VM to do. The actions are applied once the callback returns
+#[derive(Debug)]
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub(super) enum ScriptAction {
+    SetRegister(usize, u16),
+    FeedCommand(String),
+}
+
+#[cfg(feature = "scripting")]
+pub(super) use rhai_hooks::ScriptHooks;
+#[cfg(not(feature = "scripting"))]
+pub(super) use disabled::ScriptHooks;
+
+#[cfg(feature = "scripting")]
+mod rhai_hooks {
+    use super::ScriptAction;
+    use crate::machine::MAX;
+    use log::{debug, trace, warn};
+    use rhai::{AST, CallFnOptions, Dynamic, Engine, EvalAltResult, Scope};
+    use std::error::Error;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    /// This structure holds the compiled user script. The script may define the functions
+    /// on_output(text), on_room_change(title) and on_breakpoint(address), which are called by the
+    /// VM, and may call set_register(register, value) and feed_command(command) from them
+    pub(in crate::vm) struct ScriptHooks {
+        engine: Engine,
+        ast: AST,
+        actions: Arc<Mutex<Vec<ScriptAction>>>,
+    }
+
+    impl ScriptHooks {
+        pub(in crate::vm) fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+            let actions: Arc<Mutex<Vec<ScriptAction>>> = Arc::new(Mutex::new(vec![]));
+            let mut engine = Engine::new();
+            let queue = actions.clone();
+            engine.register_fn("feed_command", move |command: &str| {
+                trace!("script feeds command '{}'", command);
+                queue
+                    .lock()
+                    .unwrap()
+                    .push(ScriptAction::FeedCommand(command.to_string()));
+            });
+            let queue = actions.clone();
+            engine.register_fn(
+                "set_register",
+                move |register: i64, value: i64| -> Result<(), Box<EvalAltResult>> {
+                    if !(0..8).contains(&register) {
+                        return Err(format!("there is no register {}", register).into());
+                    }
+                    if !(0..MAX as i64).contains(&value) {
+                        return Err(format!("value {} does not fit into 15 bits", value).into());
+                    }
+                    trace!("script sets register {} to {}", register, value);
+                    queue
+                        .lock()
+                        .unwrap()
+                        .push(ScriptAction::SetRegister(register as usize, value as u16));
+                    Ok(())
+                },
+            );
+            let ast = engine.compile_file(path.to_path_buf())?;
+            // Top level statements are executed once, so the script can initialize itself
+            engine.run_ast(&ast)?;
+            debug!(
+                "loaded script {} with {} functions",
+                path.display(),
+                ast.iter_functions().count()
+            );
+            Ok(ScriptHooks {
+                engine,
+                ast,
+                actions,
+            })
+        }
+
+        pub(in crate::vm) fn on_output(&mut self, text: &str) -> Vec<ScriptAction> {
+            self.call("on_output", Dynamic::from(text.to_string()))
+        }
+
+        pub(in crate::vm) fn on_room_change(&mut self, title: &str) -> Vec<ScriptAction> {
+            self.call("on_room_change", Dynamic::from(title.to_string()))
+        }
+
+        pub(in crate::vm) fn on_breakpoint(&mut self, address: u16) -> Vec<ScriptAction> {
+            self.call("on_breakpoint", Dynamic::from(address as i64))
+        }
+
+        /// This method calls the callback if the script defines it and collects the requested
+        /// actions. The errors of the script are logged, but do not stop the VM
+        fn call(&mut self, name: &str, argument: Dynamic) -> Vec<ScriptAction> {
+            let defined = self
+                .ast
+                .iter_functions()
+                .any(|f| f.name == name && f.params.len() == 1);
+            if !defined {
+                return vec![];
+            }
+            trace!("calling script callback {}", name);
+            // The top level statements were already executed once the script was loaded
+            let options = CallFnOptions::new().eval_ast(false);
+            if let Err(e) = self.engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut Scope::new(),
+                &self.ast,
+                name,
+                (argument,),
+            ) {
+                warn!("script callback {} failed. Error: {}", name, e);
+            }
+            self.take_actions()
+        }
+
+        /// Returns the actions requested by the script so far, e.g. by its top level statements
+        pub(in crate::vm) fn take_actions(&mut self) -> Vec<ScriptAction> {
+            std::mem::take(&mut *self.actions.lock().unwrap())
+        }
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+mod disabled {
+    use super::ScriptAction;
+    use std::error::Error;
+    use std::path::Path;
+
+    /// This structure replaces the scripting engine, when the crate is built without it
+    pub(in crate::vm) struct ScriptHooks;
+
+    impl ScriptHooks {
+        pub(in crate::vm) fn load(_path: &Path) -> Result<Self, Box<dyn Error>> {
+            Err("scripting is not available, rebuild with the 'scripting' feature".into())
+        }
+        pub(in crate::vm) fn on_output(&mut self, _text: &str) -> Vec<ScriptAction> {
+            vec![]
+        }
+        pub(in crate::vm) fn on_room_change(&mut self, _title: &str) -> Vec<ScriptAction> {
+            vec![]
+        }
+        pub(in crate::vm) fn on_breakpoint(&mut self, _address: u16) -> Vec<ScriptAction> {
+            vec![]
+        }
+        pub(in crate::vm) fn take_actions(&mut self) -> Vec<ScriptAction> {
+            vec![]
+        }
+    }
+}
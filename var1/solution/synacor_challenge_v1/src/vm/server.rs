@@ -0,0 +1,209 @@
+use crate::history;
+use crate::maze::export;
+use super::{RunState, VM};
+use log::{debug, error, info, trace, warn};
+use serde_json::{Value, json};
+use std::error::Error;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use tungstenite::{Message, WebSocket, handshake::derive_accept_key, protocol::Role};
+
+const USAGE: &str = "SYNACOR VM server
+GET  /output  - run the VM and fetch the pending output (fetch it again, while the state is 'running')
+POST /command - send the command (request body) and fetch the output
+GET  /state   - registers, stack, position and other VM state
+GET  /map     - rooms and passages discovered so far
+GET  /ws      - WebSocket: every text message is a command, every reply is the output
+";
+
+// The instructions executed at once, the other clients get the VM in between
+const SLICE_CYCLES: u64 = 100_000;
+// The time the request runs the VM for, before it replies with the output printed so far
+const EXCHANGE_TIME: Duration = Duration::from_secs(1);
+
+/// This function hosts the VM behind the HTTP and WebSocket API until the process is stopped
+pub(crate) fn serve(mut vm: VM, listen: &str) -> Result<u64, Box<dyn Error>> {
+    vm.stdin_input = false;
+    vm.stdout_output = false;
+    vm.status_line = false;
+    colored::control::set_override(false);
+    let server = Server::http(listen).map_err(|e| format!("failed to listen on {}: {}", listen, e))?;
+    info!("serving the VM on http://{}", listen);
+    eprintln!("serving the VM on http://{} (WebSocket at ws://{}/ws)", listen, listen);
+    let vm = Arc::new(Mutex::new(vm));
+    for request in server.incoming_requests() {
+        let vm = Arc::clone(&vm);
+        if let Err(e) = handle_request(request, vm) {
+            warn!("failed to handle the request. Error: {}", e);
+        }
+    }
+    let cycles = vm.lock().unwrap_or_else(PoisonError::into_inner).cycles;
+    Ok(cycles)
+}
+
+fn handle_request(mut request: Request, vm: Arc<Mutex<VM>>) -> Result<(), Box<dyn Error>> {
+    debug!("{} {}", request.method(), request.url());
+    let (status, body) = match (request.method(), request.url()) {
+        (Method::Get, "/") => return Ok(request.respond(Response::from_string(USAGE))?),
+        (Method::Get, "/ws") => return upgrade_to_websocket(request, vm),
+        (Method::Get, "/output") => (200, exchange(&vm, None)),
+        (Method::Post, "/command") => {
+            let mut command = String::new();
+            request.as_reader().read_to_string(&mut command)?;
+            (200, exchange(&vm, Some(command.trim_end_matches(['\r', '\n']))))
+        }
+        (Method::Get, "/state") => (200, state_json(&lock(&vm))),
+        (Method::Get, "/map") => (200, export::to_json(lock(&vm).maze())),
+        (method, url) => (
+            404,
+            json!({ "error": format!("unsupported request {} {}", method, url) }),
+        ),
+    };
+    let response = Response::from_string(body.to_string())
+        .with_status_code(StatusCode(status))
+        .with_header(header("Content-Type", "application/json"));
+    request.respond(response)?;
+    Ok(())
+}
+
+fn upgrade_to_websocket(request: Request, vm: Arc<Mutex<VM>>) -> Result<(), Box<dyn Error>> {
+    let Some(key) = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+        .map(|h| h.value.to_string())
+    else {
+        let response = Response::from_string("WebSocket handshake expected").with_status_code(400);
+        return Ok(request.respond(response)?);
+    };
+    let response = Response::empty(StatusCode(101))
+        .with_header(header("Sec-WebSocket-Accept", &derive_accept_key(key.as_bytes())));
+    let stream = request.upgrade("websocket", response);
+    thread::spawn(move || {
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        debug!("WebSocket client connected");
+        // Greet the client with whatever the game has printed so far
+        let mut reply = exchange(&vm, None);
+        loop {
+            if let Err(e) = socket.send(Message::text(reply.to_string())) {
+                warn!("failed to send the WebSocket message. Error: {}", e);
+                break;
+            }
+            match socket.read() {
+                Ok(Message::Text(command)) => reply = exchange(&vm, Some(command.as_str())),
+                Ok(Message::Close(_)) => break,
+                Ok(other) => {
+                    trace!("ignoring WebSocket message {:?}", other);
+                    reply = json!({ "error": "only text messages are supported" });
+                }
+                Err(e) => {
+                    error!("failed to read the WebSocket message. Error: {}", e);
+                    break;
+                }
+            }
+        }
+        debug!("WebSocket client disconnected");
+    });
+    Ok(())
+}
+
+/// This function submits the command (if any), runs the VM until it needs more input and returns
+/// everything the VM has printed in the meantime. The VM runs in slices, unlocked in between, so
+/// the program which never asks for the input does not lock the other clients out. It is given up
+/// after a while with the state 'running'
+fn exchange(vm: &Mutex<VM>, command: Option<&str>) -> Value {
+    if let Some(command) = command {
+        lock(vm).submit_line(command);
+    }
+    let deadline = Instant::now() + EXCHANGE_TIME;
+    let mut output = String::new();
+    loop {
+        let mut vm = lock(vm);
+        let state = vm.run_for(SLICE_CYCLES);
+        output.push_str(&std::mem::take(&mut vm.output_buffer));
+        if state != RunState::Running || Instant::now() >= deadline {
+            return json!({ "output": output, "state": state_name(&state) });
+        }
+    }
+}
+
+fn lock(vm: &Mutex<VM>) -> std::sync::MutexGuard<'_, VM> {
+    // The VM state is still useful for inspection, even if some handler has panicked
+    vm.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn state_name(state: &RunState) -> &'static str {
+    match state {
+        RunState::Running => "running",
+        RunState::Halted => "halted",
+        RunState::Paused => "paused",
+        RunState::AwaitingInput => "awaiting_input",
+    }
+}
+
+fn state_json(vm: &VM) -> Value {
+    json!({
+        "halt": vm.machine.halt,
+        "paused": vm.paused,
+        "awaiting_input": vm.machine.is_awaiting_input() && vm.input_buffer.is_empty(),
+        "position": vm.machine.current_address.0,
+        "registers": vm.machine.registers,
+        "stack": vm.machine.stack,
+        "cycles": vm.cycles,
+        "room": vm.last_room.as_ref().and_then(|r| r.title.clone()),
+        "inventory": vm.inventory,
+        "commands_history": history::commands(&vm.commands_history),
+        "breakpoints": vm.breakpoints,
+    })
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("header must be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::rom;
+    use crate::machine::MAX;
+
+    fn served_vm(program: &[u16]) -> Arc<Mutex<VM>> {
+        let mut vm = VM::new_from_rom(rom(program)).unwrap();
+        vm.stdin_input = false;
+        vm.stdout_output = false;
+        vm.status_line = false;
+        Arc::new(Mutex::new(vm))
+    }
+
+    #[test]
+    fn replies_once_the_game_waits_for_the_command() {
+        // out 'A', in r0, jmp 2
+        let vm = served_vm(&[19, 65, 20, MAX, 6, 2]);
+        assert_eq!(
+            exchange(&vm, None),
+            json!({ "output": "A", "state": "awaiting_input" })
+        );
+        assert_eq!(exchange(&vm, Some("look"))["state"], "awaiting_input");
+    }
+
+    #[test]
+    fn gives_up_the_endless_loop_without_locking_the_other_clients_out() {
+        // noop, jmp 0
+        let vm = served_vm(&[21, 6, 0]);
+        let started = Instant::now();
+        let runner = {
+            let vm = Arc::clone(&vm);
+            thread::spawn(move || exchange(&vm, None))
+        };
+        thread::sleep(Duration::from_millis(100));
+        let state = state_json(&lock(&vm));
+        let queried = started.elapsed();
+        let reply = runner.join().unwrap();
+
+        assert_eq!(reply["state"], "running");
+        assert_eq!(state["halt"], false);
+        assert!(queried < EXCHANGE_TIME, "the state is queried after {:?}", queried);
+    }
+}
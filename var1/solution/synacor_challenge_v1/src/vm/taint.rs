@@ -0,0 +1,137 @@
+use crate::disasm;
+use crate::machine::{MAX, Machine};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The shadow state of the machine telling which registers, memory cells and stack values are
+/// derived from the input characters
+pub(super) struct Taint {
+    registers: [bool; 8],
+    memory: Vec<bool>,
+    stack: Vec<bool>,
+    /// The addresses of the instructions, which have used the tainted values, and how many times
+    readers: BTreeMap<u16, u64>,
+}
+
+fn register(word: u16) -> Option<usize> {
+    word.checked_sub(MAX).map(|r| r as usize).filter(|r| *r < 8)
+}
+
+impl Taint {
+    /// The values stored before the tracking has started are not tainted
+    pub(super) fn new(machine: &Machine) -> Self {
+        Taint {
+            registers: [false; 8],
+            memory: vec![false; MAX as usize],
+            stack: vec![false; machine.stack.len()],
+            readers: BTreeMap::new(),
+        }
+    }
+
+    fn operand(&self, word: u16) -> bool {
+        register(word).is_some_and(|r| self.registers[r])
+    }
+
+    fn set_register(&mut self, word: u16, tainted: bool) {
+        if let Some(r) = register(word) {
+            self.registers[r] = tainted;
+        }
+    }
+
+    /// This method propagates the taint through the instruction about to be executed: 'in'
+    /// taints its register, the other instructions pass the taint of the operands to the result
+    pub(super) fn step(&mut self, machine: &Machine) {
+        let position = machine.position();
+        let words = disasm::read(machine, position);
+        let Some((&opcode, operands)) = words.split_first() else {
+            return;
+        };
+        let tainted = |n: usize| operands.get(n).is_some_and(|w| self.operand(*w));
+        let mut used = (0..operands.len()).any(tainted);
+        let a = operands.first().copied().unwrap_or(0);
+        match opcode {
+            // set, not
+            1 | 14 => self.set_register(a, tainted(1)),
+            2 => self.stack.push(tainted(0)),
+            3 => {
+                let value = self.stack.pop().unwrap_or(false);
+                self.set_register(a, value);
+            }
+            // eq, gt, add, mult, mod, and, or
+            4 | 5 | 9..=13 => self.set_register(a, tainted(1) || tainted(2)),
+            15 => {
+                let address = machine.operand(operands[1]) % MAX;
+                let loaded = self.memory[address as usize];
+                used |= loaded;
+                self.set_register(a, loaded || tainted(1));
+            }
+            16 => {
+                let address = machine.operand(a) % MAX;
+                self.memory[address as usize] = tainted(1);
+            }
+            17 => self.stack.push(false),
+            18 => {
+                self.stack.pop();
+            }
+            20 => self.set_register(a, true),
+            _ => {}
+        }
+        if used {
+            *self.readers.entry(position).or_default() += 1;
+        }
+    }
+
+    pub(super) fn register(&self, r: usize) -> bool {
+        self.registers[r]
+    }
+
+    pub(super) fn memory(&self, address: u16) -> bool {
+        self.memory[(address % MAX) as usize]
+    }
+
+    /// Returns the ranges of the tainted memory cells (inclusive)
+    pub(super) fn ranges(&self) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = vec![];
+        for address in (0..MAX).filter(|a| self.memory[*a as usize]) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == address => *end = address,
+                _ => ranges.push((address, address)),
+            }
+        }
+        ranges
+    }
+}
+
+impl fmt::Display for Taint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registers: Vec<String> = (0..8)
+            .filter(|r| self.registers[*r])
+            .map(|r| format!("r{}", r))
+            .collect();
+        let ranges: Vec<String> = self
+            .ranges()
+            .iter()
+            .map(|(start, end)| match start == end {
+                true => start.to_string(),
+                false => format!("{}..{}", start, end),
+            })
+            .collect();
+        writeln!(f, "***           Tainted by Input            ***")?;
+        writeln!(f, "{}", "=".repeat(44))?;
+        writeln!(f, "{:<9}: {}", "registers", registers.join(", "))?;
+        writeln!(f, "{:<9}: {}", "memory", ranges.join(", "))?;
+        writeln!(
+            f,
+            "{:<9}: {} of {}",
+            "stack",
+            self.stack.iter().filter(|t| **t).count(),
+            self.stack.len()
+        )?;
+        writeln!(f, "{}", "_".repeat(44))?;
+        writeln!(f, "the instructions using the tainted values:")?;
+        for (address, count) in &self.readers {
+            writeln!(f, "{:>5}: {:>7} times", address, count)?;
+        }
+        write!(f, "{}", "=".repeat(45))
+    }
+}
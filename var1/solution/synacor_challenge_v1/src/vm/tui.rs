@@ -0,0 +1,330 @@
+use super::{RunState, VM};
+use crate::machine::Address;
+use colored::control;
+use log::{debug, trace};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+};
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+// The VM is executed in slices, so the UI stays responsive during long computations
+const CYCLES_PER_FRAME: u64 = 100_000;
+const IDLE_POLL_TIMEOUT: Duration = Duration::from_millis(100);
+const MAP_RADIUS: usize = 3;
+const INPUT_PREFIX: &str = "> ";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Pane {
+    #[default]
+    Transcript,
+    Registers,
+    Breakpoints,
+    Map,
+}
+
+impl Pane {
+    fn next(self) -> Self {
+        match self {
+            Pane::Transcript => Pane::Registers,
+            Pane::Registers => Pane::Breakpoints,
+            Pane::Breakpoints => Pane::Map,
+            Pane::Map => Pane::Transcript,
+        }
+    }
+    fn previous(self) -> Self {
+        match self {
+            Pane::Transcript => Pane::Map,
+            Pane::Registers => Pane::Transcript,
+            Pane::Breakpoints => Pane::Registers,
+            Pane::Map => Pane::Breakpoints,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct App {
+    transcript: Vec<String>,
+    partial_line: String, // the line the game is printing right now
+    input: String,
+    focus: Pane,
+    // Scroll offsets of the panes. The transcript is scrolled back from the bottom
+    transcript_scroll: usize,
+    registers_scroll: u16,
+    breakpoints_scroll: u16,
+    map_scroll: u16,
+    quit: bool,
+}
+
+/// This function runs the VM inside the terminal user interface until the user quits or the VM
+/// halts
+pub(crate) fn run(vm: &mut VM) -> Result<u64, Box<dyn Error>> {
+    debug!("starting the terminal user interface");
+    vm.stdin_input = false;
+    vm.stdout_output = false;
+    vm.status_line = false;
+    // ANSI escape sequences make no sense inside the TUI widgets
+    control::set_override(false);
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, vm);
+    ratatui::restore();
+    result?;
+    Ok(vm.cycles)
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, vm: &mut VM) -> io::Result<()> {
+    let mut app = App::default();
+    while !app.quit {
+        let state = vm.run_for(CYCLES_PER_FRAME);
+        app.consume_output(&mut vm.output_buffer);
+        terminal.draw(|frame| app.draw(frame, vm, &state))?;
+        let timeout = if state == RunState::Running {
+            Duration::ZERO
+        } else {
+            IDLE_POLL_TIMEOUT
+        };
+        if event::poll(timeout)?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            app.handle_key(key, vm, &state);
+        }
+    }
+    Ok(())
+}
+
+impl App {
+    fn consume_output(&mut self, output: &mut String) {
+        for c in output.drain(..) {
+            match c {
+                '\n' => self.transcript.push(std::mem::take(&mut self.partial_line)),
+                c => self.partial_line.push(c),
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, vm: &mut VM, state: &RunState) {
+        trace!("handling key {:?}", key);
+        match key.code {
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Tab => self.focus = self.focus.next(),
+            KeyCode::BackTab => self.focus = self.focus.previous(),
+            KeyCode::Up => self.scroll(1, true),
+            KeyCode::Down => self.scroll(1, false),
+            KeyCode::PageUp => self.scroll(10, true),
+            KeyCode::PageDown => self.scroll(10, false),
+            KeyCode::F(5) => vm.resume(),
+            KeyCode::F(10) if *state == RunState::Paused => {
+                vm.execute_instruction();
+                vm.message(format!("stopped at {}", vm.machine.current_address));
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Enter => self.submit(vm),
+            KeyCode::Char(c) => self.input.push(c),
+            _ => {}
+        }
+    }
+
+    fn submit(&mut self, vm: &mut VM) {
+        let line = std::mem::take(&mut self.input);
+        self.transcript.push(format!("{}{}", INPUT_PREFIX, line));
+        self.transcript_scroll = 0;
+        vm.submit_line(&line);
+    }
+
+    fn scroll(&mut self, lines: u16, up: bool) {
+        let offset = match self.focus {
+            Pane::Transcript => {
+                self.transcript_scroll = if up {
+                    self.transcript_scroll.saturating_add(lines as usize)
+                } else {
+                    self.transcript_scroll.saturating_sub(lines as usize)
+                };
+                return;
+            }
+            Pane::Registers => &mut self.registers_scroll,
+            Pane::Breakpoints => &mut self.breakpoints_scroll,
+            Pane::Map => &mut self.map_scroll,
+        };
+        *offset = if up {
+            offset.saturating_sub(lines)
+        } else {
+            offset.saturating_add(lines)
+        };
+    }
+
+    fn block(&self, title: &str, pane: Pane) -> Block<'static> {
+        let style = if self.focus == pane {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+        Block::bordered()
+            .title(format!(" {} ", title))
+            .border_style(style)
+    }
+
+    fn draw(&self, frame: &mut Frame, vm: &VM, state: &RunState) {
+        let [header, body, footer] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Min(5),
+                Constraint::Length(1),
+            ])
+            .areas(frame.area());
+        let [left, right] = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+            .areas(body);
+        let [transcript, input] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(3)])
+            .areas(left);
+        let [registers, breakpoints, map] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Percentage(45),
+                Constraint::Percentage(20),
+                Constraint::Percentage(35),
+            ])
+            .areas(right);
+
+        frame.render_widget(self.header(vm, state), header);
+        self.draw_transcript(frame, transcript);
+        frame.render_widget(
+            Paragraph::new(format!("{}{}", INPUT_PREFIX, self.input))
+                .block(Block::bordered().title(" command ")),
+            input,
+        );
+        frame.render_widget(
+            Paragraph::new(registers_text(vm))
+                .block(self.block("registers & stack", Pane::Registers))
+                .scroll((self.registers_scroll, 0)),
+            registers,
+        );
+        frame.render_widget(
+            Paragraph::new(breakpoints_text(vm, state))
+                .block(self.block("breakpoints", Pane::Breakpoints))
+                .scroll((self.breakpoints_scroll, 0)),
+            breakpoints,
+        );
+        frame.render_widget(
+            Paragraph::new(vm.maze().render_map(MAP_RADIUS))
+                .block(self.block("map", Pane::Map))
+                .scroll((self.map_scroll, 0)),
+            map,
+        );
+        frame.render_widget(
+            Paragraph::new(
+                "Tab: switch pane  ↑↓ PgUp/PgDn: scroll  F5: continue  F10: step  Esc: quit",
+            )
+            .style(Style::default().add_modifier(Modifier::DIM)),
+            footer,
+        );
+    }
+
+    fn header(&self, vm: &VM, state: &RunState) -> Paragraph<'static> {
+        let room = vm
+            .last_room
+            .as_ref()
+            .and_then(|r| r.title.clone())
+            .unwrap_or("unknown location".to_string());
+        let state = match state {
+            RunState::Running => "running".to_string(),
+            RunState::AwaitingInput => "awaiting input".to_string(),
+            RunState::Paused => format!("paused at {}", vm.machine.current_address),
+            RunState::Halted => "halted".to_string(),
+        };
+        Paragraph::new(Line::from(vec![
+            Span::styled(" SYNACOR VM ", Style::default().fg(Color::Black).bg(Color::Green)),
+            Span::raw(" "),
+            Span::styled(room, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Span::raw(format!(" | {} | cycles: {}", state, vm.cycles)),
+        ]))
+    }
+
+    fn draw_transcript(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(2).max(1) as usize;
+        let height = area.height.saturating_sub(2) as usize;
+        // The lines are wrapped manually, so it is known how far to scroll to see the bottom
+        let lines: Vec<Line> = self
+            .transcript
+            .iter()
+            .chain(std::iter::once(&self.partial_line))
+            .flat_map(|l| {
+                let style = if l.starts_with(INPUT_PREFIX) {
+                    Style::default().fg(Color::Cyan)
+                } else {
+                    Style::default()
+                };
+                wrap(l, width)
+                    .into_iter()
+                    .map(move |chunk| Line::styled(chunk, style))
+            })
+            .collect();
+        let bottom = lines.len().saturating_sub(height);
+        let offset = bottom.saturating_sub(self.transcript_scroll);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(self.block("game", Pane::Transcript))
+                .scroll((offset as u16, 0)),
+            area,
+        );
+    }
+}
+
+fn registers_text(vm: &VM) -> String {
+    let mut text = String::new();
+    vm.machine.registers
+        .iter()
+        .enumerate()
+        .for_each(|(n, r)| text.push_str(&format!("reg {}: {:<6} ({:#06x})\n", n, r, r)));
+    text.push_str(&format!("pc   : {}\n\n", vm.machine.current_address));
+    text.push_str(&format!("stack (size: {}):\n", vm.machine.stack.len()));
+    vm.machine.stack
+        .iter()
+        .enumerate()
+        .rev()
+        .for_each(|(n, v)| text.push_str(&format!("[{}: {}]\n", n, v)));
+    text
+}
+
+fn breakpoints_text(vm: &VM, state: &RunState) -> String {
+    if vm.breakpoints.is_empty() {
+        return "no breakpoints (use /break <addr>)".to_string();
+    }
+    vm.breakpoints
+        .iter()
+        .map(|b| {
+            let marker = if *state == RunState::Paused && *b == vm.machine.current_address.0 {
+                "> "
+            } else {
+                "  "
+            };
+            format!("{}{}", marker, Address::new(*b))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars
+        .chunks(width)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
@@ -0,0 +1,87 @@
+use crate::history::HistoryEntry;
+use crate::maze::{MazeAnalyzer, exit_of_command};
+
+/// The session to be written up: the commands entered and what the VM has learned about the game
+pub(super) struct Walkthrough<'a> {
+    pub(super) commands: &'a [HistoryEntry],
+    pub(super) maze: &'a MazeAnalyzer,
+    pub(super) inventory: &'a [String],
+    pub(super) codes: &'a [String],
+}
+
+impl Walkthrough<'_> {
+    /// Returns the room the command leads to from the given one: along the known passage, or along
+    /// the teleport of the used item. It is used for the commands restored from the older states,
+    /// which do not know the rooms
+    fn destination(&self, from: u16, command: &str) -> Option<u16> {
+        let exit = exit_of_command(command);
+        let node = self.maze.node_by_id(from)?;
+        if node.exits.iter().any(|e| e == exit) {
+            return self.maze.destination(from, exit);
+        }
+        let item = command.strip_prefix("use ")?.trim();
+        self.maze
+            .teleports()
+            .iter()
+            .find(|t| t.from == from && t.item == item)
+            .map(|t| t.to)
+    }
+
+    /// This method follows the commands across the map from the starting room, starting another
+    /// section on every arrival to the room
+    pub(super) fn to_markdown(&self) -> String {
+        let mut text = String::from("# Synacor Challenge Walkthrough\n\n");
+        text.push_str(&format!(
+            "{} commands, {} rooms discovered, {} codes found.\n",
+            self.commands.len(),
+            self.maze.nodes().len(),
+            self.codes.len()
+        ));
+        let mut current = self.maze.trail().first().map(|s| s.room);
+        let title = |id: Option<u16>| {
+            id.and_then(|id| self.maze.node_by_id(id))
+                .map_or("Unknown location", |n| n.title.as_str())
+        };
+        // The section of the room is started by the first command entered in it
+        let mut section = None;
+        for (n, entry) in self.commands.iter().enumerate() {
+            let command = entry.command.trim();
+            current = entry.room.or(current);
+            if section != Some(current) {
+                text.push_str(&format!("\n## {}\n\n", title(current)));
+                section = Some(current);
+            }
+            text.push_str(&format!("{}. `{}`", n + 1, command));
+            if let Some(item) = command.strip_prefix("take ") {
+                text.push_str(&format!(" (takes the {})", item.trim()));
+            }
+            for code in &entry.codes {
+                text.push_str(&format!(" (finds the code `{}`)", code));
+            }
+            let to = match entry.result {
+                Some(_) => entry.moved_to(),
+                None => current.and_then(|from| self.destination(from, command)),
+            };
+            match to {
+                Some(to) if Some(to) != current => {
+                    text.push_str(&format!(" leads to **{}**\n", title(Some(to))));
+                    current = Some(to);
+                }
+                _ => text.push('\n'),
+            }
+        }
+        text.push_str(&format!(
+            "\nThe walkthrough ends in **{}**.\n",
+            title(current)
+        ));
+        text.push_str("\n## Inventory\n\n");
+        self.inventory
+            .iter()
+            .for_each(|i| text.push_str(&format!("- {}\n", i)));
+        text.push_str("\n## Codes\n\n");
+        self.codes
+            .iter()
+            .for_each(|c| text.push_str(&format!("- `{}`\n", c)));
+        text
+    }
+}
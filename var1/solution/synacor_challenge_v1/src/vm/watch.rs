@@ -0,0 +1,339 @@
+use crate::machine::{MAX, Machine};
+use std::error::Error;
+use std::fmt;
+
+/// The binary operators of the watch expressions, from the lowest precedence to the highest.
+/// The comparisons give 1 if they hold and 0 otherwise
+const PRECEDENCE: [&[&str]; 5] = [
+    &["==", "!=", "<=", ">=", "<", ">"],
+    &["|"],
+    &["&"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Register(usize),
+    Memory,
+    Operator(&'static str),
+    Open(char),
+    Close(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Register(r) => write!(f, "r{}", r),
+            Token::Memory => write!(f, "mem"),
+            Token::Operator(op) => write!(f, "'{}'", op),
+            Token::Open(c) | Token::Close(c) => write!(f, "'{}'", c),
+        }
+    }
+}
+
+/// This function shows the token, which the parser has found, or the end of the expression
+fn found(token: Option<&Token>) -> String {
+    token.map_or("the end of the expression".to_string(), |t| t.to_string())
+}
+
+/// The expression over the registers and the memory of the machine, e.g. mem[0x0aa7] + r1
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Register(usize),
+    Memory(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_alphanumeric() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect::<String>().to_lowercase();
+            let token = if word == "mem" {
+                Token::Memory
+            } else if let Some(hex) = word.strip_prefix("0x") {
+                Token::Number(i64::from_str_radix(hex, 16)?)
+            } else if let Some(register) = word.strip_prefix('r') {
+                match register.parse::<usize>() {
+                    Ok(n) if n < 8 => Token::Register(n),
+                    _ => return Err(format!("unknown register '{}'", word).into()),
+                }
+            } else {
+                Token::Number(
+                    word.parse()
+                        .map_err(|_| format!("unknown word '{}'", word))?,
+                )
+            };
+            tokens.push(token);
+            continue;
+        }
+        let rest: String = chars[i..].iter().take(2).collect();
+        // The longest operator is taken, so '<=' is not read as '<' followed by '='
+        if let Some(op) = PRECEDENCE
+            .iter()
+            .flat_map(|ops| ops.iter())
+            .filter(|op| rest.starts_with(**op))
+            .max_by_key(|op| op.len())
+        {
+            tokens.push(Token::Operator(op));
+            i += op.len();
+            continue;
+        }
+        tokens.push(match c {
+            '(' | '[' => Token::Open(c),
+            ')' | ']' => Token::Close(c),
+            c => return Err(format!("unexpected character '{}'", c).into()),
+        });
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// The recursive descent parser of the token list
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), Box<dyn Error>> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(format!("expected {}, found {}", expected, found(other.as_ref())).into()),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, Box<dyn Error>> {
+        if level == PRECEDENCE.len() {
+            return self.primary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(Token::Operator(op)) = self.tokens.get(self.position)
+            && PRECEDENCE[level].contains(op)
+        {
+            let op = *op;
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn primary(&mut self) -> Result<Expr, Box<dyn Error>> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Register(r)) => Ok(Expr::Register(r)),
+            Some(Token::Memory) => {
+                self.expect(Token::Open('['))?;
+                let address = self.binary(0)?;
+                self.expect(Token::Close(']'))?;
+                Ok(Expr::Memory(Box::new(address)))
+            }
+            Some(Token::Open('(')) => {
+                let expr = self.binary(0)?;
+                self.expect(Token::Close(')'))?;
+                Ok(expr)
+            }
+            other => Err(format!("unexpected {}", found(other.as_ref())).into()),
+        }
+    }
+}
+
+impl Expr {
+    fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let expr = parser.binary(0)?;
+        if parser.position < parser.tokens.len() {
+            return Err(format!("unexpected {}", parser.tokens[parser.position]).into());
+        }
+        Ok(expr)
+    }
+
+    /// This method evaluates the expression. The address of the memory is taken modulo 32768,
+    /// the division by zero gives no value
+    fn evaluate(&self, machine: &Machine) -> Option<i64> {
+        match self {
+            Expr::Number(n) => Some(*n),
+            Expr::Register(r) => Some(machine.registers()[*r] as i64),
+            Expr::Memory(address) => {
+                let address = address.evaluate(machine)?.rem_euclid(MAX as i64);
+                Some(machine.word(address as u16) as i64)
+            }
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.evaluate(machine)?, b.evaluate(machine)?);
+                match *op {
+                    "+" => a.checked_add(b),
+                    "-" => a.checked_sub(b),
+                    "*" => a.checked_mul(b),
+                    "/" => a.checked_div(b),
+                    "%" => a.checked_rem(b),
+                    "&" => Some(a & b),
+                    "|" => Some(a | b),
+                    "==" => Some((a == b) as i64),
+                    "!=" => Some((a != b) as i64),
+                    "<" => Some((a < b) as i64),
+                    "<=" => Some((a <= b) as i64),
+                    ">" => Some((a > b) as i64),
+                    ">=" => Some((a >= b) as i64),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// The expression, which is evaluated after every instruction, and its last value
+pub(super) struct Watch {
+    text: String,
+    expr: Expr,
+    value: Option<i64>,
+}
+
+impl Watch {
+    pub(super) fn new(text: &str, machine: &Machine) -> Result<Self, Box<dyn Error>> {
+        let expr = Expr::parse(text)?;
+        let value = expr.evaluate(machine);
+        Ok(Watch {
+            text: text.to_string(),
+            expr,
+            value,
+        })
+    }
+
+    pub(super) fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// This method evaluates the expression once again and returns the previous value, if the
+    /// value has changed
+    pub(super) fn update(&mut self, machine: &Machine) -> Option<Option<i64>> {
+        let value = self.expr.evaluate(machine);
+        if value == self.value {
+            return None;
+        }
+        Some(std::mem::replace(&mut self.value, value))
+    }
+
+    pub(super) fn value(&self) -> Option<i64> {
+        self.value
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Some(value) => write!(f, "{} = {} ({:#06x})", self.text, value, value),
+            None => write!(f, "{} = undefined", self.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::rom;
+
+    /// The machine with r1 = 7 and mem[0x0aa7] = 3, about to store 4 into mem[0x0aa7]
+    fn machine() -> Machine {
+        let mut machine = Machine::new();
+        machine.load_rom(&rom(&[1, MAX + 1, 7, 16, 0x0aa7, 3, 16, 0x0aa7, 4, 0])).unwrap();
+        machine.execute(|| None);
+        machine.execute(|| None);
+        machine
+    }
+
+    fn evaluate(text: &str) -> Option<i64> {
+        Expr::parse(text).unwrap().evaluate(&machine())
+    }
+
+    fn error(text: &str) -> String {
+        Expr::parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn reads_the_registers_and_the_memory() {
+        assert_eq!(evaluate("r1"), Some(7));
+        assert_eq!(evaluate("r0"), Some(0));
+        assert_eq!(evaluate("mem[0x0aa7] + r1"), Some(10));
+        assert_eq!(evaluate("MEM[2727]"), Some(3));
+        assert_eq!(evaluate("mem[r1 + 0x0aa0]"), Some(3));
+        assert_eq!(evaluate("mem[0x0aa7 + 32768]"), Some(3));
+    }
+
+    #[test]
+    fn follows_the_precedence_of_the_operators() {
+        assert_eq!(evaluate("1 + 2 * 3"), Some(7));
+        assert_eq!(evaluate("(1 + 2) * 3"), Some(9));
+        assert_eq!(evaluate("10 - 4 - 3"), Some(3));
+        assert_eq!(evaluate("6 | 1 & 2"), Some(6));
+        assert_eq!(evaluate("17 % 5 / 2"), Some(1));
+    }
+
+    #[test]
+    fn compares_the_values() {
+        assert_eq!(evaluate("r1 == 7"), Some(1));
+        assert_eq!(evaluate("r1 != 7"), Some(0));
+        assert_eq!(evaluate("mem[0x0aa7] < r1"), Some(1));
+        assert_eq!(evaluate("r1 <= 6"), Some(0));
+        assert_eq!(evaluate("r1>=7"), Some(1));
+        assert_eq!(evaluate("r1 > 2 + 5"), Some(0));
+        assert_eq!(evaluate("r1 & 1 == 1"), Some(1));
+    }
+
+    #[test]
+    fn gives_no_value_for_the_division_by_zero() {
+        assert_eq!(evaluate("r1 / r0"), None);
+        assert_eq!(evaluate("r1 % 0"), None);
+    }
+
+    #[test]
+    fn rejects_the_invalid_expressions() {
+        assert_eq!(error("r8"), "unknown register 'r8'");
+        assert_eq!(error("pos"), "unknown word 'pos'");
+        assert_eq!(error("r1 # 2"), "unexpected character '#'");
+        assert_eq!(error("r1 +"), "unexpected the end of the expression");
+        assert_eq!(error("mem(1)"), "expected '[', found '('");
+        assert_eq!(
+            error("(r1 + 2"),
+            "expected ')', found the end of the expression"
+        );
+        assert_eq!(error("r1 r2"), "unexpected r2");
+        assert_eq!(error("r1 = 2"), "unexpected character '='");
+    }
+
+    #[test]
+    fn reports_the_changed_value_once() {
+        let mut machine = machine();
+        let mut watch = Watch::new("mem[0x0aa7] * 2", &machine).unwrap();
+        assert_eq!(watch.value(), Some(6));
+        assert_eq!(watch.update(&machine), None);
+        machine.execute(|| None);
+        assert_eq!(watch.update(&machine), Some(Some(6)));
+        assert_eq!(watch.update(&machine), None);
+        assert_eq!(watch.to_string(), "mem[0x0aa7] * 2 = 8 (0x0008)");
+    }
+}
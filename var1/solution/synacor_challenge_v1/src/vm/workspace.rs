@@ -0,0 +1,115 @@
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The manifest of the session directory, listing every file written to it
+const INDEX_FILE: &str = "index.json";
+
+/// The file written to the session directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    /// What the file keeps, e.g. 'state' or 'history'
+    pub kind: String,
+    /// The path relative to the session directory, unless the file is written outside of it
+    pub file: PathBuf,
+    /// The seconds since the epoch
+    pub written: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    started: u64,
+    artifacts: Vec<Artifact>,
+}
+
+/// The directory given with --session-dir, which keeps everything the session writes. The files
+/// written without the explicit name are named after their kind and numbered, e.g.
+/// 'state-002.json', the relative names are taken inside the directory, and every file written is
+/// listed in index.json. The directory of the former session is reused, its index is extended
+pub struct Workspace {
+    dir: PathBuf,
+    index: Index,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl Workspace {
+    pub fn open(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        let index_path = dir.join(INDEX_FILE);
+        let index = if index_path.exists() {
+            serde_json::from_str(&fs::read_to_string(&index_path)?)
+                .map_err(|e| format!("{} is not the session index: {}", index_path.display(), e))?
+        } else {
+            Index {
+                started: now(),
+                artifacts: vec![],
+            }
+        };
+        debug!(
+            "using the session directory {} with {} files",
+            dir.display(),
+            index.artifacts.len()
+        );
+        Ok(Workspace {
+            dir: dir.to_path_buf(),
+            index,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the path of the file of the kind: the given name inside the directory, unless it is
+    /// absolute, or the next numbered name with the extension
+    pub fn path(&self, kind: &str, file: Option<&str>, extension: &str) -> PathBuf {
+        match file {
+            Some(file) => self.dir.join(file),
+            None => {
+                let n = self
+                    .index
+                    .artifacts
+                    .iter()
+                    .filter(|a| a.kind == kind)
+                    .count();
+                self.dir
+                    .join(format!("{}-{:03}.{}", kind, n + 1, extension))
+            }
+        }
+    }
+
+    /// Returns the latest file of the kind written to the directory, e.g. the state to load
+    pub fn latest(&self, kind: &str) -> Option<PathBuf> {
+        self.index
+            .artifacts
+            .iter()
+            .rev()
+            .find(|a| a.kind == kind)
+            .map(|a| self.dir.join(&a.file))
+    }
+
+    /// This method lists the file written in the index and saves it
+    pub fn record(&mut self, kind: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+        trace!("recording {} file {}", kind, path.display());
+        let file = path.strip_prefix(&self.dir).unwrap_or(path).to_path_buf();
+        self.index.artifacts.retain(|a| a.file != file);
+        self.index.artifacts.push(Artifact {
+            kind: kind.to_string(),
+            file,
+            written: now(),
+        });
+        fs::write(
+            self.dir.join(INDEX_FILE),
+            serde_json::to_string_pretty(&self.index)?,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use crate::machine::{InputDecoder, Machine, StepEvent};
+use log::trace;
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
+/// This structure wraps the virtual machine for the browser. It does not touch the file system or
+/// the standard streams, the ROM bytes are passed from JavaScript, the commands are queued with
+/// 'feed' and the produced text is taken with 'take_output'
+#[wasm_bindgen]
+pub struct WasmVm {
+    machine: Machine,
+    input: VecDeque<u8>,
+    input_decoder: InputDecoder,
+    output: String,
+}
+
+#[wasm_bindgen]
+impl WasmVm {
+    /// The ROM larger than the memory is thrown as the error
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<WasmVm, JsValue> {
+        let mut machine = Machine::new();
+        machine
+            .load_rom(rom)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(WasmVm {
+            machine,
+            input: VecDeque::new(),
+            input_decoder: InputDecoder::default(),
+            output: String::new(),
+        })
+    }
+
+    /// This method queues the command for the game. The trailing new line is added automatically
+    pub fn feed(&mut self, command: &str) {
+        trace!("queueing command '{}'", command);
+        self.input.extend(command.trim_end().bytes());
+        self.input.push_back(b'\n');
+    }
+
+    /// This method executes at most 'budget' instructions and returns the state of the machine:
+    /// 'running', 'awaiting_input' or 'halted'
+    pub fn run(&mut self, budget: u32) -> String {
+        for _ in 0..budget {
+            if self.machine.is_halted() {
+                break;
+            }
+            match self.machine.execute(|| self.input.pop_front()) {
+                StepEvent::Output(c) => self.output.push(c),
+                StepEvent::Input(c) => self.output.extend(self.input_decoder.push(c)),
+                StepEvent::AwaitingInput => return "awaiting_input".to_string(),
+                StepEvent::Halted | StepEvent::None => {}
+            }
+        }
+        self.state()
+    }
+
+    /// This method returns the text produced by the game since the previous call
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Returns the state of the machine, the same as the server reports: 'halted', 'awaiting_input'
+    /// (see is_awaiting_input) or 'running'
+    pub fn state(&self) -> String {
+        if self.machine.is_halted() {
+            "halted".to_string()
+        } else if self.is_awaiting_input() {
+            "awaiting_input".to_string()
+        } else {
+            "running".to_string()
+        }
+    }
+
+    /// This method returns true, once the game has printed the prompt and all the queued input is
+    /// consumed, so it is ready for the next command
+    pub fn is_awaiting_input(&self) -> bool {
+        self.machine.is_awaiting_input() && self.input.is_empty()
+    }
+
+    pub fn registers(&self) -> Vec<u16> {
+        self.machine.registers().to_vec()
+    }
+
+    pub fn position(&self) -> u16 {
+        self.machine.position()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::rom;
+    use crate::machine::MAX;
+
+    // The game printing the prompt and reading the command until the empty line
+    fn prompt_rom() -> Vec<u8> {
+        let mut program: Vec<u16> = "What do you do?"
+            .bytes()
+            .flat_map(|b| [19, u16::from(b)])
+            .collect();
+        let read = program.len() as u16;
+        program.extend([20, MAX, 4, MAX + 1, MAX, 10, 8, MAX + 1, read, 0]);
+        rom(&program)
+    }
+
+    #[test]
+    fn reports_the_prompt_waiting_for_the_command() {
+        let mut vm = WasmVm::new(&prompt_rom()).unwrap();
+        assert_eq!(vm.state(), "running");
+        assert_eq!(vm.run(1_000), "awaiting_input");
+        assert_eq!(vm.state(), "awaiting_input");
+        vm.feed("");
+        assert_eq!(vm.state(), "running");
+        assert_eq!(vm.run(1_000), "halted");
+        assert_eq!(vm.state(), "halted");
+        assert_eq!(vm.take_output(), "What do you do?\n");
+    }
+
+    #[test]
+    fn echoes_the_utf8_input_intact() {
+        let mut vm = WasmVm::new(&prompt_rom()).unwrap();
+        vm.feed("café €");
+        assert_eq!(vm.run(1_000), "halted");
+        assert_eq!(vm.take_output(), "What do you do?café €\n");
+    }
+}